@@ -1,7 +1,7 @@
 use caeles_sdk::{log, notify};
 
-#[no_mangle]
-pub extern "C" fn caeles_main() {
+#[caeles_sdk::main]
+fn main() {
     log("hello-capsule: hello from CAELES capsule via host_log");
     notify("hello-capsule: runtime notification from capsule");
 }
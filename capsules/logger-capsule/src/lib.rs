@@ -1,7 +1,7 @@
 use caeles_sdk::{http_get, log, notify, NetworkError};
 
-#[no_mangle]
-pub extern "C" fn caeles_main() {
+#[caeles_sdk::main]
+fn main() {
     log("logger-capsule: start");
     log("logger-capsule: emitting sequential logs");
 
@@ -16,7 +16,52 @@ pub extern "C" fn caeles_main() {
         Err(NetworkError::HostFailure) => {
             log("logger-capsule: network request failed at host runtime")
         }
+        Err(NetworkError::InvalidCapability) => {
+            log("logger-capsule: network capability rejected by host runtime")
+        }
     }
 
     notify("logger-capsule: execution completed");
 }
+
+#[cfg(test)]
+mod tests {
+    use caeles_sdk::mock::{self, MockCapability};
+    use caeles_sdk::NetworkError;
+
+    #[test]
+    fn logs_successful_request_and_notifies() {
+        super::main();
+
+        assert_eq!(mock::http_requests(), ["https://example.com"]);
+        assert!(mock::logs().contains(&"logger-capsule: network request succeeded".to_string()));
+        assert_eq!(
+            mock::notifications(),
+            ["logger-capsule: execution completed"]
+        );
+    }
+
+    #[test]
+    fn reports_blocked_network_without_calling_host() {
+        mock::deny(MockCapability::Network);
+        mock::deny(MockCapability::Notifications);
+
+        super::main();
+
+        assert!(mock::http_requests().is_empty());
+        assert!(mock::logs().contains(
+            &"logger-capsule: network request blocked by manifest permission".to_string()
+        ));
+        assert!(mock::notifications().is_empty());
+    }
+
+    #[test]
+    fn reports_host_failure() {
+        mock::respond("https://example.com", Err(NetworkError::HostFailure));
+
+        super::main();
+
+        assert!(mock::logs()
+            .contains(&"logger-capsule: network request failed at host runtime".to_string()));
+    }
+}
@@ -0,0 +1,367 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Export that the runtime calls to start a capsule.
+pub const ENTRY_EXPORT: &str = "caeles_main";
+
+/// Import module of WASI preview1, linked only for `wasm32-wasip1` capsules.
+pub const WASI_P1_MODULE: &str = "wasi_snapshot_preview1";
+
+/// Command-style entrypoint used by WASI capsules built as binaries.
+pub const WASI_START_EXPORT: &str = "_start";
+
+/// Custom section where `caeles-sdk` stores the ABI version as a little-endian `u32`.
+pub const ABI_SECTION: &str = "caeles_abi";
+
+/// Versions of the host ABI, one import module per version.
+///
+/// `v0` is the original unversioned `caeles` module and is frozen. Functions may be
+/// added to the latest version, but removing or changing a signature needs a new one,
+/// so existing capsules keep linking. `v2` replaces the global permission-gated calls
+/// with capability handles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum AbiVersion {
+    #[serde(rename = "v0")]
+    V0,
+    #[serde(rename = "v1")]
+    V1,
+    #[serde(rename = "v2")]
+    V2,
+}
+
+impl AbiVersion {
+    pub const SUPPORTED: &'static [AbiVersion] = &[AbiVersion::V0, AbiVersion::V1, AbiVersion::V2];
+    pub const LATEST: AbiVersion = AbiVersion::V2;
+
+    pub fn number(self) -> u32 {
+        match self {
+            AbiVersion::V0 => 0,
+            AbiVersion::V1 => 1,
+            AbiVersion::V2 => 2,
+        }
+    }
+
+    pub fn from_number(number: u32) -> Option<Self> {
+        Self::SUPPORTED
+            .iter()
+            .copied()
+            .find(|v| v.number() == number)
+    }
+
+    /// Import module name capsules use for this version.
+    pub fn module(self) -> &'static str {
+        match self {
+            AbiVersion::V0 => "caeles",
+            AbiVersion::V1 => "caeles_v1",
+            AbiVersion::V2 => "caeles_v2",
+        }
+    }
+
+    /// Host functions linked for this version.
+    pub fn functions(self) -> impl Iterator<Item = &'static HostFunction> {
+        let number = self.number();
+        HOST_FUNCTIONS
+            .iter()
+            .filter(move |f| f.since <= number && f.until.is_none_or(|until| number <= until))
+    }
+
+    pub fn function(self, name: &str) -> Option<&'static HostFunction> {
+        self.functions().find(|f| f.name == name)
+    }
+}
+
+impl fmt::Display for AbiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.number())
+    }
+}
+
+/// Version number encoded in an import module name (`caeles` -> 0, `caeles_v3` -> 3),
+/// including versions this runtime does not support.
+pub fn module_version_number(module: &str) -> Option<u32> {
+    if module == "caeles" {
+        return Some(0);
+    }
+    module.strip_prefix("caeles_v")?.parse().ok()
+}
+
+/// Reads the version marker emitted by `caeles-sdk`, if the module carries one.
+pub fn read_marker(bytes: &[u8]) -> Option<u32> {
+    for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+        let Ok(wasmparser::Payload::CustomSection(section)) = payload else {
+            continue;
+        };
+        if section.name() == ABI_SECTION {
+            let data: [u8; 4] = section.data().get(..4)?.try_into().ok()?;
+            return Some(u32::from_le_bytes(data));
+        }
+    }
+    None
+}
+
+/// Signature and permission gate of a host function.
+#[derive(Debug)]
+pub struct HostFunction {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+    pub results: &'static [&'static str],
+    /// Manifest permission that must be `true` for the call to have any effect.
+    pub permission: Option<&'static str>,
+    /// First ABI version that provides the function.
+    pub since: u32,
+    /// Last ABI version that provides the function, if it was removed later.
+    pub until: Option<u32>,
+}
+
+/// Every host function the runtime links, kept in sync with `runtime::link_host_functions`.
+pub const HOST_FUNCTIONS: &[HostFunction] = &[
+    HostFunction {
+        name: "host_log",
+        params: &["i32", "i32"],
+        results: &[],
+        permission: None,
+        since: 0,
+        until: None,
+    },
+    HostFunction {
+        name: "host_notify",
+        params: &["i32", "i32"],
+        results: &[],
+        permission: Some("notifications"),
+        since: 0,
+        until: Some(1),
+    },
+    HostFunction {
+        name: "host_http_get",
+        params: &["i32", "i32"],
+        results: &["i32"],
+        permission: Some("network"),
+        since: 0,
+        until: Some(1),
+    },
+    HostFunction {
+        name: "host_cap_acquire",
+        params: &["i32"],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_cap_release",
+        params: &["i32"],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_notify_cap",
+        params: &["i32", "i32", "i32"],
+        results: &["i32"],
+        permission: Some("notifications"),
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_http_get_cap",
+        params: &["i32", "i32", "i32"],
+        results: &["i32"],
+        permission: Some("network"),
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_log_v2",
+        params: &["i32", "i32", "i32", "i32", "i32", "i32", "i32"],
+        results: &[],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_log_level",
+        params: &[],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_panic",
+        params: &["i32", "i32"],
+        results: &[],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_exit",
+        params: &["i32", "i32", "i32"],
+        results: &[],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_set_output",
+        params: &["i32", "i32"],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_input_len",
+        params: &[],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_input_read",
+        params: &["i32", "i32"],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_now_ms",
+        params: &[],
+        results: &["i64"],
+        permission: Some("wall_clock"),
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_monotonic_ns",
+        params: &[],
+        results: &["i64"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_sleep_ms",
+        params: &["i32"],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_random_bytes",
+        params: &["i32", "i32"],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_config_get",
+        params: &["i32", "i32", "i32", "i32"],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_secret_get",
+        params: &["i32", "i32", "i32", "i32"],
+        results: &["i32"],
+        permission: None,
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_fs_open",
+        params: &["i32", "i32", "i32"],
+        results: &["i32"],
+        permission: Some("filesystem"),
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_fs_read",
+        params: &["i32", "i32", "i32"],
+        results: &["i32"],
+        permission: Some("filesystem"),
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_fs_write",
+        params: &["i32", "i32", "i32"],
+        results: &["i32"],
+        permission: Some("filesystem"),
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_fs_close",
+        params: &["i32"],
+        results: &["i32"],
+        permission: Some("filesystem"),
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_fs_list",
+        params: &["i32", "i32", "i32", "i32"],
+        results: &["i32"],
+        permission: Some("filesystem"),
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_fs_delete",
+        params: &["i32", "i32"],
+        results: &["i32"],
+        permission: Some("filesystem"),
+        since: 2,
+        until: None,
+    },
+    HostFunction {
+        name: "host_publish",
+        params: &["i32", "i32", "i32", "i32"],
+        results: &["i32"],
+        permission: Some("publish"),
+        since: 2,
+        until: None,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{module_version_number, read_marker, AbiVersion};
+
+    #[test]
+    fn module_names_map_to_versions() {
+        assert_eq!(module_version_number("caeles"), Some(0));
+        assert_eq!(module_version_number("caeles_v1"), Some(1));
+        assert_eq!(module_version_number("caeles_v12"), Some(12));
+        assert_eq!(module_version_number("env"), None);
+        assert_eq!(AbiVersion::from_number(1), Some(AbiVersion::V1));
+        assert_eq!(AbiVersion::from_number(12), None);
+    }
+
+    #[test]
+    fn capability_abi_drops_global_permission_calls() {
+        assert!(AbiVersion::V1.function("host_http_get").is_some());
+        assert!(AbiVersion::V2.function("host_http_get").is_none());
+        assert!(AbiVersion::V2.function("host_http_get_cap").is_some());
+        assert!(AbiVersion::V1.function("host_cap_acquire").is_none());
+    }
+
+    #[test]
+    fn read_marker_uses_custom_section() {
+        let wasm = wat::parse_str(r#"(module (@custom "caeles_abi" "\01\00\00\00"))"#)
+            .expect("WAT should compile");
+        assert_eq!(read_marker(&wasm), Some(1));
+
+        let plain = wat::parse_str("(module)").expect("WAT should compile");
+        assert_eq!(read_marker(&plain), None);
+    }
+}
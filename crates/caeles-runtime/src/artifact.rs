@@ -0,0 +1,251 @@
+//! Output of `caeles build`: the wasm cargo produced, read from its JSON
+//! messages, and what it exports.
+
+use crate::component;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Path of the `.wasm` built for the crate at `manifest_path`, from cargo's
+/// `--message-format=json` output on `messages`.
+///
+/// Artifacts of other crates (dependencies, workspace members) are skipped, so
+/// `None` means the crate itself produced no wasm. The stream is read to the end.
+pub fn find_wasm(messages: impl BufRead, manifest_path: &Path) -> Result<Option<PathBuf>> {
+    let manifest_path = manifest_path
+        .canonicalize()
+        .unwrap_or_else(|_| manifest_path.to_path_buf());
+    let mut found = None;
+    for line in messages.lines() {
+        let line = line.context("Falha ao ler a saída do cargo")?;
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) else {
+            continue;
+        };
+        if message["reason"] != "compiler-artifact" {
+            continue;
+        }
+        let own = message["manifest_path"]
+            .as_str()
+            .map(Path::new)
+            .is_some_and(|path| path.canonicalize().is_ok_and(|path| path == manifest_path));
+        if !own {
+            continue;
+        }
+        if let Some(wasm) = message["filenames"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|f| f.as_str())
+            .find(|f| f.ends_with(".wasm"))
+        {
+            found = Some(PathBuf::from(wasm));
+        }
+    }
+    Ok(found)
+}
+
+/// `[package].name` of a `Cargo.toml`, if it can be read.
+pub fn package_name(manifest_path: &Path) -> Option<String> {
+    let text = fs::read_to_string(manifest_path).ok()?;
+    let parsed: toml::Table = toml::from_str(&text).ok()?;
+    parsed
+        .get("package")?
+        .get("name")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Size, exports and imports of a wasm binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WasmSummary {
+    pub size: u64,
+    pub component: bool,
+    /// Export names with their kind (`func`, `memory`, ...).
+    pub exports: Vec<(String, String)>,
+    /// Import module names, once each, in order.
+    pub import_modules: Vec<String>,
+    pub imports: usize,
+}
+
+impl WasmSummary {
+    /// Validates `bytes` and lists what the binary exports and imports.
+    pub fn read(bytes: &[u8]) -> Result<Self> {
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(bytes)
+            .context("wasm inválido")?;
+        let component = component::is_component(bytes);
+        let mut summary = WasmSummary {
+            size: bytes.len() as u64,
+            component,
+            exports: Vec::new(),
+            import_modules: Vec::new(),
+            imports: 0,
+        };
+        for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+            match payload? {
+                wasmparser::Payload::ExportSection(reader) if !component => {
+                    for export in reader {
+                        let export = export?;
+                        let kind = match export.kind {
+                            wasmparser::ExternalKind::Func => "func",
+                            wasmparser::ExternalKind::Memory => "memory",
+                            wasmparser::ExternalKind::Table => "table",
+                            wasmparser::ExternalKind::Global => "global",
+                            wasmparser::ExternalKind::Tag => "tag",
+                        };
+                        summary
+                            .exports
+                            .push((export.name.to_string(), kind.to_string()));
+                    }
+                }
+                wasmparser::Payload::ImportSection(reader) if !component => {
+                    for import in reader {
+                        let import = import?;
+                        summary.imports += 1;
+                        if !summary.import_modules.iter().any(|m| m == import.module) {
+                            summary.import_modules.push(import.module.to_string());
+                        }
+                    }
+                }
+                wasmparser::Payload::ComponentExportSection(reader) => {
+                    for export in reader {
+                        summary
+                            .exports
+                            .push((export?.name.0.to_string(), "component".to_string()));
+                    }
+                }
+                wasmparser::Payload::ComponentImportSection(reader) => {
+                    for import in reader {
+                        let name = import?.name.0.to_string();
+                        summary.imports += 1;
+                        if !summary.import_modules.contains(&name) {
+                            summary.import_modules.push(name);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Runs `wasm-opt -Oz` on `path` in place; returns the size before and after.
+pub fn optimize(path: &Path) -> Result<(u64, u64)> {
+    let before = fs::metadata(path)?.len();
+    let status = Command::new("wasm-opt")
+        .arg("-Oz")
+        .arg(path)
+        .arg("-o")
+        .arg(path)
+        .status()
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Não foi possível executar wasm-opt. Instale o binaryen para usar `--optimize`: {e}"
+            )
+        })?;
+    if !status.success() {
+        bail!("wasm-opt falhou para '{}'", path.display());
+    }
+    Ok((before, fs::metadata(path)?.len()))
+}
+
+/// The manifest text with its top-level `entry` set to `entry`.
+pub fn set_manifest_entry(text: &str, entry: &str) -> Result<String> {
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(text).context("manifest não é um JSON válido")?;
+    let Some(fields) = manifest.as_object_mut() else {
+        bail!("manifest não é um objeto JSON");
+    };
+    fields.insert(
+        "entry".to_string(),
+        serde_json::Value::String(entry.to_string()),
+    );
+    Ok(format!("{}\n", serde_json::to_string_pretty(&manifest)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_wasm, package_name, set_manifest_entry, WasmSummary};
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    #[test]
+    fn find_wasm_takes_only_the_built_crate_and_skips_other_messages() {
+        let dir = TempDir::new().expect("temp directory should be created");
+        let manifest = dir.path().join("Cargo.toml");
+        fs::write(&manifest, "[package]\nname = \"mine\"\n").expect("manifest should be written");
+        let own = format!(
+            r#"{{"reason":"compiler-artifact","manifest_path":"{}","filenames":["/t/mine.wasm"]}}"#,
+            manifest.display()
+        );
+        let other = r#"{"reason":"compiler-artifact","manifest_path":"/other/Cargo.toml","filenames":["/t/other.wasm"]}"#;
+        let messages = format!(
+            "{other}\nnot json\n{own}\n{other}\n{{\"reason\":\"build-finished\",\"success\":true}}\n"
+        );
+
+        assert_eq!(
+            find_wasm(Cursor::new(messages), &manifest).expect("messages should be read"),
+            Some(PathBuf::from("/t/mine.wasm"))
+        );
+        assert_eq!(
+            find_wasm(Cursor::new(format!("{other}\n")), &manifest)
+                .expect("messages should be read"),
+            None
+        );
+        assert_eq!(package_name(&manifest).as_deref(), Some("mine"));
+        assert_eq!(
+            find_wasm(Cursor::new(""), Path::new("/mine/Cargo.toml"))
+                .expect("messages should be read"),
+            None
+        );
+    }
+
+    #[test]
+    fn summary_lists_exports_and_import_modules() {
+        let bytes = wat::parse_str(
+            r#"(module
+  (import "caeles_v2" "host_log" (func (param i32 i32)))
+  (import "caeles_v2" "host_notify" (func (param i32 i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main"))
+)"#,
+        )
+        .expect("WAT should compile");
+        let summary = WasmSummary::read(&bytes).expect("wasm should be valid");
+        assert_eq!(summary.size, bytes.len() as u64);
+        assert!(!summary.component);
+        assert_eq!(
+            summary.exports,
+            [
+                ("memory".to_string(), "memory".to_string()),
+                ("caeles_main".to_string(), "func".to_string())
+            ]
+        );
+        assert_eq!(summary.import_modules, ["caeles_v2"]);
+        assert_eq!(summary.imports, 2);
+        assert!(WasmSummary::read(b"\0asm\x01\0\0\0\x7f").is_err());
+    }
+
+    #[test]
+    fn set_manifest_entry_replaces_only_the_top_level_entry() {
+        let text = r#"{
+  "id": "com.x",
+  "name": "entry: \"old.wasm\"",
+  "config": { "entry": { "type": "string" } },
+  "entry": "old.wasm"
+}"#;
+        let updated: serde_json::Value = serde_json::from_str(
+            &set_manifest_entry(text, "new_capsule.wasm").expect("entry should be replaced"),
+        )
+        .expect("manifest should stay valid json");
+        assert_eq!(updated["entry"], "new_capsule.wasm");
+        assert_eq!(updated["name"], "entry: \"old.wasm\"");
+        assert_eq!(updated["config"]["entry"]["type"], "string");
+        assert!(set_manifest_entry("[]", "a.wasm").is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Content-addressed storage shared by packaged and pulled images.
+//!
+//! Each file is stored once as `<root>/sha256/<hex digest>`. Image directories
+//! reference blobs by digest and link to them, so identical wasm and assets are
+//! kept on disk a single time.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Location inside the store directory of the settings.
+pub const BLOBS_DIR: &str = "blobs";
+
+const ALGORITHM: &str = "sha256";
+
+/// A stored file: `sha256:<hex>` and its length in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobRef {
+    pub digest: String,
+    pub size: u64,
+}
+
+impl BlobRef {
+    pub fn of(bytes: &[u8]) -> Self {
+        Self {
+            digest: digest(bytes),
+            size: bytes.len() as u64,
+        }
+    }
+
+    pub fn short_digest(&self) -> &str {
+        short_digest(&self.digest)
+    }
+}
+
+/// `sha256:` and the first 12 hex digits, as shown by `caeles images`.
+pub fn short_digest(digest: &str) -> &str {
+    let end = (ALGORITHM.len() + 1 + 12).min(digest.len());
+    &digest[..end]
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// `sha256:<hex>` of `bytes`.
+pub fn digest(bytes: &[u8]) -> String {
+    format!("{ALGORITHM}:{}", sha256_hex(bytes))
+}
+
+/// Fails unless the file at `path` has the digest and size of `blob`.
+pub fn verify_file(path: &Path, blob: &BlobRef) -> Result<()> {
+    let bytes = fs::read(path).with_context(|| format!("Falha ao ler '{}'", path.display()))?;
+    let found = BlobRef::of(&bytes);
+    if found != *blob {
+        bail!(
+            "'{}' foi alterado: conteúdo tem {} ({} bytes), esperado {} ({} bytes)",
+            path.display(),
+            found.digest,
+            found.size,
+            blob.digest,
+            blob.size
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// The store in the directory of the settings, next to the images.
+    pub fn local() -> Result<Self> {
+        Ok(Self::new(
+            &crate::settings::Settings::load()?
+                .store_dir()
+                .join(BLOBS_DIR),
+        ))
+    }
+
+    /// Where the blob with `digest` is stored.
+    pub fn path(&self, digest: &str) -> Result<PathBuf> {
+        let hex = digest
+            .strip_prefix(ALGORITHM)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .with_context(|| format!("Digest inválido '{digest}' (esperado sha256:<hex>)"))?;
+        Ok(self.root.join(ALGORITHM).join(hex))
+    }
+
+    /// Stores `bytes` unless an intact blob with the same digest exists.
+    ///
+    /// Returns whether a new blob was written.
+    pub fn put(&self, bytes: &[u8]) -> Result<(BlobRef, bool)> {
+        let blob = BlobRef::of(bytes);
+        let path = self.path(&blob.digest)?;
+        if path.is_file() && verify_file(&path, &blob).is_ok() {
+            return Ok((blob, false));
+        }
+        let dir = path.parent().expect("blob paths have a parent");
+        fs::create_dir_all(dir)?;
+        // Written under a temporary name and renamed, so a blob is never seen
+        // half-written and a damaged one is replaced rather than rewritten in place.
+        let temp = dir.join(format!(".{}.tmp", std::process::id()));
+        fs::write(&temp, bytes)?;
+        fs::rename(&temp, &path)?;
+        Ok((blob, true))
+    }
+
+    /// Every blob in the store, with its size on disk.
+    pub fn list(&self) -> Result<Vec<BlobRef>> {
+        let dir = self.root.join(ALGORITHM);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut blobs = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || !entry.file_type()?.is_file() {
+                continue;
+            }
+            blobs.push(BlobRef {
+                digest: format!("{ALGORITHM}:{name}"),
+                size: entry.metadata()?.len(),
+            });
+        }
+        blobs.sort_by(|a, b| a.digest.cmp(&b.digest));
+        Ok(blobs)
+    }
+
+    pub fn remove(&self, blob: &BlobRef) -> Result<()> {
+        fs::remove_file(self.path(&blob.digest)?)
+            .with_context(|| format!("Falha ao remover blob {}", blob.digest))
+    }
+
+    /// Makes `dest` a hard link to the blob, or a copy where links are not supported.
+    pub fn link(&self, blob: &BlobRef, dest: &Path) -> Result<()> {
+        let path = self.path(&blob.digest)?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::hard_link(&path, dest).is_err() {
+            fs::copy(&path, dest).with_context(|| {
+                format!(
+                    "Falha ao copiar blob {} para '{}'",
+                    blob.digest,
+                    dest.display()
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_file, BlobRef, BlobStore};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn stores_blobs_once_and_detects_changes() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let store = BlobStore::new(&root.join("blobs"));
+
+        let (blob, written) = store.put(b"capsule").expect("blob should be stored");
+        assert!(written);
+        assert_eq!(blob.size, 7);
+        assert!(blob.digest.starts_with("sha256:"));
+        assert_eq!(blob.short_digest().len(), "sha256:".len() + 12);
+        let (again, written) = store.put(b"capsule").expect("blob should be stored");
+        assert_eq!(again, blob);
+        assert!(!written);
+
+        let linked = root.join("image/capsule.wasm");
+        store.link(&blob, &linked).expect("blob should be linked");
+        assert!(verify_file(&linked, &blob).is_ok());
+
+        let path = store.path(&blob.digest).expect("digest should be valid");
+        fs::write(&path, b"tampered").expect("blob should be overwritten");
+        assert!(verify_file(&path, &blob).is_err());
+        let (_, written) = store.put(b"capsule").expect("blob should be repaired");
+        assert!(written);
+        assert!(verify_file(&path, &blob).is_ok());
+
+        assert_eq!(store.list().expect("blobs should list"), vec![blob.clone()]);
+        store.remove(&blob).expect("blob should be removed");
+        assert!(store.list().expect("blobs should list").is_empty());
+
+        assert!(store.path("md5:abc").is_err());
+        assert_ne!(BlobRef::of(b"a"), BlobRef::of(b"b"));
+    }
+}
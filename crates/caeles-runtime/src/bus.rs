@@ -0,0 +1,103 @@
+//! Host-managed message bus between capsules.
+//!
+//! Capsules publish with `host_publish` to topics listed in
+//! `permissions.publish`. Every `event` capsule whose `permissions.subscribe`
+//! matches the topic then runs once with the message as its input.
+
+use serde::{Deserialize, Serialize};
+
+/// Largest payload accepted by `host_publish`.
+pub const MAX_PAYLOAD_BYTES: usize = 64 * 1024;
+
+/// Event runs allowed between the first publish and a message; deeper
+/// messages are dropped so capsules cannot trigger each other forever.
+pub const MAX_CHAIN_DEPTH: u32 = 8;
+
+/// Publisher of messages sent with `caeles publish`.
+pub const CLI_PUBLISHER: &str = "cli";
+
+/// Checks a topic (`orders.created`) or, with `pattern`, a subscription that
+/// may end with `*` to match any remaining segments (`orders.*`, `*`).
+pub fn validate_topic(topic: &str, pattern: bool) -> Result<(), String> {
+    let segments: Vec<&str> = topic.split('.').collect();
+    for (index, segment) in segments.iter().enumerate() {
+        if pattern && *segment == "*" && index == segments.len() - 1 {
+            continue;
+        }
+        let valid = !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-'));
+        if !valid {
+            return Err(if pattern {
+                format!(
+                    "'{topic}' deve ter segmentos com a-z, 0-9, '_' ou '-' separados por '.' ('*' so no fim)"
+                )
+            } else {
+                format!("'{topic}' deve ter segmentos com a-z, 0-9, '_' ou '-' separados por '.'")
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Whether `topic` is covered by a `permissions.publish`/`subscribe` entry.
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some("") => true,
+        Some(prefix) => topic.starts_with(prefix) && topic.len() > prefix.len(),
+        None => pattern == topic,
+    }
+}
+
+/// A message published by a capsule during a run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Published {
+    pub topic: String,
+    pub payload: String,
+}
+
+/// A message on its way to subscribers; delivered as the JSON input of each `event` run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub id: String,
+    pub topic: String,
+    pub payload: String,
+    /// Capsule id of the publisher, or [`CLI_PUBLISHER`].
+    pub publisher: String,
+    /// Run that published the message; absent for `caeles publish`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// Event runs between the first publish and this message.
+    pub depth: u32,
+}
+
+impl Message {
+    pub fn input(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("message should serialize")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{topic_matches, validate_topic};
+
+    #[test]
+    fn topics_and_subscriptions_are_validated_and_matched() {
+        assert!(validate_topic("orders.created", false).is_ok());
+        assert!(validate_topic("orders.*", false).is_err());
+        assert!(validate_topic("orders.*", true).is_ok());
+        assert!(validate_topic("*", true).is_ok());
+        assert!(validate_topic("orders.*.created", true).is_err());
+        assert!(validate_topic("orders..created", false).is_err());
+        assert!(validate_topic("Orders", false).is_err());
+
+        assert!(topic_matches("orders.created", "orders.created"));
+        assert!(!topic_matches("orders.created", "orders.created.eu"));
+        assert!(topic_matches("orders.*", "orders.created"));
+        assert!(topic_matches("orders.*", "orders.eu.created"));
+        assert!(!topic_matches("orders.*", "orders"));
+        assert!(!topic_matches("orders.*", "ordersx.created"));
+        assert!(topic_matches("*", "anything.at.all"));
+    }
+}
@@ -0,0 +1,226 @@
+use crate::manifest::CapsuleManifest;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Capability kinds a capsule can request with `host_cap_acquire`; the codes are part of the ABI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CapabilityKind {
+    Network,
+    Notifications,
+}
+
+impl CapabilityKind {
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            1 => Some(CapabilityKind::Network),
+            2 => Some(CapabilityKind::Notifications),
+            _ => None,
+        }
+    }
+
+    /// Manifest permission that grants this capability.
+    pub fn permission(self) -> &'static str {
+        match self {
+            CapabilityKind::Network => "network",
+            CapabilityKind::Notifications => "notifications",
+        }
+    }
+}
+
+impl fmt::Display for CapabilityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.permission())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Capability {
+    kind: CapabilityKind,
+    /// Hosts the capability is limited to; empty means unrestricted.
+    scope: Vec<String>,
+    revoked: bool,
+}
+
+/// Why a handle was refused by [`CapabilityTable::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleError {
+    Unknown,
+    Revoked,
+    WrongKind,
+}
+
+impl HandleError {
+    pub fn reason(self) -> &'static str {
+        match self {
+            HandleError::Unknown => "unknown_handle",
+            HandleError::Revoked => "revoked_handle",
+            HandleError::WrongKind => "wrong_kind",
+        }
+    }
+}
+
+/// Salt for [`CapabilityTable::issue`], drawn from the OS on every run.
+pub fn random_salt() -> u64 {
+    rand::rngs::OsRng.next_u64()
+}
+
+/// Handle of grant slot `index`: a hash of the salt and the slot, so one handle
+/// says nothing about the salt or the other handles.
+fn slot_handle(salt: u64, index: u32) -> i32 {
+    let digest = Sha256::new()
+        .chain_update(salt.to_le_bytes())
+        .chain_update(index.to_le_bytes())
+        .finalize();
+    let handle = i32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]]) & 0x7fff_ffff;
+    handle.max(1)
+}
+
+/// Capability handles issued to one capsule run.
+///
+/// Handles are created once at startup from the manifest permissions. They are
+/// salted per run so a capsule cannot guess them (replays reuse the recorded salt),
+/// and revoked handles stay in the table so later use can be told apart from
+/// forged values in the audit log.
+#[derive(Debug)]
+pub struct CapabilityTable {
+    entries: HashMap<i32, Capability>,
+}
+
+impl CapabilityTable {
+    pub fn issue(manifest: &CapsuleManifest, salt: u64) -> Self {
+        let mut table = Self {
+            entries: HashMap::new(),
+        };
+
+        let grants = [
+            (
+                CapabilityKind::Network,
+                manifest.permissions.network,
+                manifest.permissions.network_hosts.clone(),
+            ),
+            (
+                CapabilityKind::Notifications,
+                manifest.permissions.notifications,
+                Vec::new(),
+            ),
+        ];
+        let grants_len = grants.len() as u32;
+        for (index, (kind, granted, scope)) in grants.into_iter().enumerate() {
+            if !granted {
+                continue;
+            }
+            let mut slot = index as u32;
+            let mut handle = slot_handle(salt, slot);
+            while table.entries.contains_key(&handle) {
+                slot += grants_len;
+                handle = slot_handle(salt, slot);
+            }
+            table.entries.insert(
+                handle,
+                Capability {
+                    kind,
+                    scope,
+                    revoked: false,
+                },
+            );
+        }
+
+        table
+    }
+
+    /// Handle issued for `kind`, if the manifest granted it and it was not revoked.
+    pub fn handle_for(&self, kind: CapabilityKind) -> Result<i32, HandleError> {
+        match self.entries.iter().find(|(_, cap)| cap.kind == kind) {
+            Some((_, cap)) if cap.revoked => Err(HandleError::Revoked),
+            Some((handle, _)) => Ok(*handle),
+            None => Err(HandleError::Unknown),
+        }
+    }
+
+    /// Validates `handle` for a call that needs `kind`, returning the capability scope.
+    pub fn check(&self, handle: i32, kind: CapabilityKind) -> Result<&[String], HandleError> {
+        let cap = self.entries.get(&handle).ok_or(HandleError::Unknown)?;
+        if cap.revoked {
+            return Err(HandleError::Revoked);
+        }
+        if cap.kind != kind {
+            return Err(HandleError::WrongKind);
+        }
+        Ok(&cap.scope)
+    }
+
+    pub fn revoke(&mut self, handle: i32) -> Result<CapabilityKind, HandleError> {
+        let cap = self.entries.get_mut(&handle).ok_or(HandleError::Unknown)?;
+        if cap.revoked {
+            return Err(HandleError::Revoked);
+        }
+        cap.revoked = true;
+        Ok(cap.kind)
+    }
+}
+
+/// Checks whether `host` is allowed by a network scope; an empty scope allows any host.
+///
+/// Entries match exactly or, when written as `*.example.com`, any subdomain.
+pub fn host_in_scope(scope: &[String], host: &str) -> bool {
+    if scope.is_empty() {
+        return true;
+    }
+    let host = host.to_ascii_lowercase();
+    scope.iter().any(|allowed| {
+        let allowed = allowed.to_ascii_lowercase();
+        match allowed.strip_prefix("*.") {
+            Some(suffix) => host.ends_with(&format!(".{suffix}")),
+            None => host == allowed,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{host_in_scope, CapabilityKind, CapabilityTable};
+    use crate::manifest::CapsuleManifest;
+    use serde_json::json;
+
+    #[test]
+    fn handles_repeat_for_a_salt_and_do_not_reveal_each_other() {
+        let manifest: CapsuleManifest = serde_json::from_value(json!({
+            "id": "com.caeles.tests.caps",
+            "name": "Caps",
+            "version": "0.1.0",
+            "entry": "capsule.wasm",
+            "permissions": { "notifications": true, "network": true },
+            "lifecycle": { "kind": "on_demand" }
+        }))
+        .expect("manifest should parse");
+        let handles = |salt| {
+            let table = CapabilityTable::issue(&manifest, salt);
+            (
+                table
+                    .handle_for(CapabilityKind::Network)
+                    .expect("network is granted"),
+                table
+                    .handle_for(CapabilityKind::Notifications)
+                    .expect("notifications are granted"),
+            )
+        };
+
+        let (network, notifications) = handles(7);
+        assert_eq!(handles(7), (network, notifications));
+        assert!(network > 0 && notifications > 0 && network != notifications);
+        let (other_network, other_notifications) = handles(8);
+        assert_ne!(network ^ notifications, other_network ^ other_notifications);
+    }
+
+    #[test]
+    fn host_in_scope_matches_exact_and_wildcard_hosts() {
+        let scope = vec!["example.com".to_string(), "*.caeles.dev".to_string()];
+        assert!(host_in_scope(&scope, "example.com"));
+        assert!(host_in_scope(&scope, "api.caeles.dev"));
+        assert!(!host_in_scope(&scope, "caeles.dev"));
+        assert!(!host_in_scope(&scope, "evil-example.com"));
+        assert!(host_in_scope(&[], "anything.test"));
+    }
+}
@@ -0,0 +1,375 @@
+//! Per-capsule configuration values and secrets.
+//!
+//! Config values live in `<state>/config/<id>.json` in plain text. Secrets live
+//! in `<state>/secrets/<id>.json`, each sealed with ChaCha20-Poly1305 under the
+//! key in `<state>/secrets.key`, which is created on first use.
+
+use crate::manifest::{validate_config_name, CapsuleManifest};
+use anyhow::{bail, Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const SECRET_KEY_LEN: usize = 32;
+
+pub fn config_store_path(base: &Path, capsule_id: &str) -> PathBuf {
+    base.join("config").join(format!("{capsule_id}.json"))
+}
+
+pub fn secret_store_path(base: &Path, capsule_id: &str) -> PathBuf {
+    base.join("secrets").join(format!("{capsule_id}.json"))
+}
+
+fn secret_key_path(base: &Path) -> PathBuf {
+    base.join("secrets.key")
+}
+
+/// Values saved with `caeles capsule-config set`, as text.
+pub fn load_config_store(base: &Path, capsule_id: &str) -> Result<BTreeMap<String, String>> {
+    let path = config_store_path(base, capsule_id);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let text = fs::read_to_string(&path)?;
+    serde_json::from_str(&text).with_context(|| format!("Config inválida em '{}'", path.display()))
+}
+
+pub fn save_config_store(
+    base: &Path,
+    capsule_id: &str,
+    values: &BTreeMap<String, String>,
+) -> Result<()> {
+    let path = config_store_path(base, capsule_id);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(values)?)?;
+    Ok(())
+}
+
+/// Splits a `key=value` argument.
+pub fn parse_assignment(text: &str) -> Result<(String, String)> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => bail!("Esperado chave=valor, recebido '{text}'"),
+    }
+}
+
+/// Reads a `--config-file`: a JSON object of key -> value.
+pub fn load_config_file(path: &Path) -> Result<BTreeMap<String, Value>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Falha ao ler arquivo de config '{}'", path.display()))?;
+    serde_json::from_str(&text).with_context(|| {
+        format!(
+            "Arquivo de config '{}' deve ser um objeto JSON",
+            path.display()
+        )
+    })
+}
+
+/// Checks a value against the manifest schema and returns its text form.
+pub fn check_config_value(manifest: &CapsuleManifest, key: &str, value: &str) -> Result<String> {
+    let Some(spec) = manifest.config.get(key) else {
+        bail!(
+            "Chave de config '{key}' não declarada no manifest de '{}'",
+            manifest.id
+        );
+    };
+    spec.kind
+        .parse_text(value)
+        .map_err(|err| anyhow::anyhow!("config '{key}': {err}"))
+}
+
+/// Resolves the config of a run. Later sources win: manifest defaults, the
+/// config store, `--config-file`, then `--config key=value`.
+pub fn resolve_config(
+    manifest: &CapsuleManifest,
+    store: &BTreeMap<String, String>,
+    file: &BTreeMap<String, Value>,
+    overrides: &[(String, String)],
+) -> Result<BTreeMap<String, String>> {
+    let mut values = BTreeMap::new();
+    for (key, spec) in &manifest.config {
+        if let Some(default) = &spec.default {
+            let value = spec
+                .kind
+                .parse_json(default)
+                .map_err(|err| anyhow::anyhow!("config '{key}': {err}"))?;
+            values.insert(key.clone(), value);
+        }
+    }
+    for (key, value) in store {
+        values.insert(key.clone(), check_config_value(manifest, key, value)?);
+    }
+    for (key, value) in file {
+        let Some(spec) = manifest.config.get(key) else {
+            bail!(
+                "Chave de config '{key}' não declarada no manifest de '{}'",
+                manifest.id
+            );
+        };
+        let value = spec
+            .kind
+            .parse_json(value)
+            .map_err(|err| anyhow::anyhow!("config '{key}': {err}"))?;
+        values.insert(key.clone(), value);
+    }
+    for (key, value) in overrides {
+        values.insert(key.clone(), check_config_value(manifest, key, value)?);
+    }
+    Ok(values)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SealedSecret {
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Encrypted secrets of one capsule.
+pub struct SecretStore {
+    key: LessSafeKey,
+    path: PathBuf,
+    capsule_id: String,
+    entries: BTreeMap<String, SealedSecret>,
+}
+
+impl SecretStore {
+    pub fn open(base: &Path, capsule_id: &str) -> Result<Self> {
+        let key_bytes = load_or_create_key(base)?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key_bytes)
+            .map_err(|_| anyhow::anyhow!("Chave de segredos inválida"))?;
+        let path = secret_store_path(base, capsule_id);
+        let entries = if path.exists() {
+            let text = fs::read_to_string(&path)?;
+            serde_json::from_str(&text)
+                .with_context(|| format!("Segredos inválidos em '{}'", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            path,
+            capsule_id: capsule_id.to_string(),
+            entries,
+        })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.entries.keys()
+    }
+
+    /// Binds each ciphertext to its capsule and name, so entries cannot be swapped.
+    fn aad(&self, name: &str) -> Vec<u8> {
+        format!("{}/{name}", self.capsule_id).into_bytes()
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<String>> {
+        let Some(sealed) = self.entries.get(name) else {
+            return Ok(None);
+        };
+        let nonce: [u8; NONCE_LEN] = hex_decode(&sealed.nonce)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| anyhow::anyhow!("Segredo '{name}' corrompido"))?;
+        let mut data = hex_decode(&sealed.ciphertext)
+            .ok_or_else(|| anyhow::anyhow!("Segredo '{name}' corrompido"))?;
+        let plain = self
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(self.aad(name)),
+                &mut data,
+            )
+            .map_err(|_| anyhow::anyhow!("Segredo '{name}' não pôde ser decifrado"))?;
+        Ok(Some(String::from_utf8(plain.to_vec())?))
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        validate_config_name(name).map_err(anyhow::Error::msg)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("Falha ao gerar nonce"))?;
+        let mut data = value.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(self.aad(name)),
+                &mut data,
+            )
+            .map_err(|_| anyhow::anyhow!("Falha ao cifrar segredo '{name}'"))?;
+        self.entries.insert(
+            name.to_string(),
+            SealedSecret {
+                nonce: hex_encode(&nonce),
+                ciphertext: hex_encode(&data),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_private(
+            &self.path,
+            serde_json::to_string_pretty(&self.entries)?.as_bytes(),
+        )
+    }
+}
+
+/// Decrypts the secrets the manifest declares; undeclared entries are ignored.
+pub fn load_secrets(base: &Path, manifest: &CapsuleManifest) -> Result<BTreeMap<String, String>> {
+    let mut secrets = BTreeMap::new();
+    if manifest.secrets.is_empty() {
+        return Ok(secrets);
+    }
+    let store = SecretStore::open(base, &manifest.id)?;
+    for name in &manifest.secrets {
+        if let Some(value) = store.get(name)? {
+            secrets.insert(name.clone(), value);
+        }
+    }
+    Ok(secrets)
+}
+
+fn load_or_create_key(base: &Path) -> Result<Vec<u8>> {
+    let path = secret_key_path(base);
+    if path.exists() {
+        let key = fs::read(&path)?;
+        if key.len() != SECRET_KEY_LEN {
+            bail!("Chave de segredos inválida em '{}'", path.display());
+        }
+        return Ok(key);
+    }
+    let mut key = vec![0u8; SECRET_KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| anyhow::anyhow!("Falha ao gerar chave de segredos"))?;
+    write_private(&path, &key)?;
+    Ok(key)
+}
+
+/// Writes a file readable only by the current user (on Unix).
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    // `mode` only applies to new files; tighten an existing one before writing.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents)?;
+    Ok(())
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_config, secret_store_path, SecretStore};
+    use crate::manifest::CapsuleManifest;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn resolve_config_applies_sources_in_order_and_checks_types() {
+        let manifest: CapsuleManifest = serde_json::from_value(json!({
+            "id": "com.caeles.tests.config",
+            "name": "Config",
+            "version": "0.1.0",
+            "entry": "capsule.wasm",
+            "permissions": { "notifications": false, "network": false },
+            "lifecycle": { "kind": "on_demand" },
+            "config": {
+                "greeting": { "type": "string", "default": "hi" },
+                "retries": { "type": "integer", "default": 1 },
+                "ratio": { "type": "number" },
+                "verbose": { "type": "boolean" }
+            }
+        }))
+        .expect("manifest should parse");
+
+        let store = BTreeMap::from([("retries".to_string(), "2".to_string())]);
+        let file = BTreeMap::from([
+            ("retries".to_string(), json!(3)),
+            ("ratio".to_string(), json!(0.5)),
+        ]);
+        let overrides = [("verbose".to_string(), "true".to_string())];
+        let config =
+            resolve_config(&manifest, &store, &file, &overrides).expect("config should resolve");
+        assert_eq!(config["greeting"], "hi");
+        assert_eq!(config["retries"], "3");
+        assert_eq!(config["ratio"], "0.5");
+        assert_eq!(config["verbose"], "true");
+
+        let wrong_type = [("retries".to_string(), "many".to_string())];
+        assert!(resolve_config(&manifest, &store, &BTreeMap::new(), &wrong_type).is_err());
+        let unknown = [("colour".to_string(), "red".to_string())];
+        assert!(resolve_config(&manifest, &store, &BTreeMap::new(), &unknown).is_err());
+    }
+
+    #[test]
+    fn secrets_are_encrypted_at_rest_and_bound_to_their_name() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let base = temp.path();
+
+        let mut store =
+            SecretStore::open(base, "com.caeles.tests.secrets").expect("store should open");
+        store
+            .set("api_token", "s3cr3t-value")
+            .expect("secret should be sealed");
+        store.save().expect("store should be saved");
+
+        let on_disk = fs::read_to_string(secret_store_path(base, "com.caeles.tests.secrets"))
+            .expect("store file should exist");
+        assert!(!on_disk.contains("s3cr3t-value"), "{on_disk}");
+
+        let reopened =
+            SecretStore::open(base, "com.caeles.tests.secrets").expect("store should reopen");
+        assert_eq!(
+            reopened.get("api_token").expect("secret should decrypt"),
+            Some("s3cr3t-value".to_string())
+        );
+
+        let swapped = on_disk.replace("api_token", "other");
+        fs::write(secret_store_path(base, "com.caeles.tests.secrets"), swapped)
+            .expect("store should be rewritten");
+        let tampered =
+            SecretStore::open(base, "com.caeles.tests.secrets").expect("store should reopen");
+        assert!(tampered.get("other").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::capabilities::CapabilityKind;
+use crate::manifest::CapsuleManifest;
+use crate::preflight::PreflightIssue;
+use crate::runtime::{arm_timeout, http_request, timeout_error, HostState, HttpFailure, LogLevel};
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Engine, Store};
+
+wasmtime::component::bindgen!({
+    path: "../../wit",
+    world: "capsule",
+});
+
+use caeles::capsule::http::{self, HttpError, Response};
+use caeles::capsule::{log, notify};
+
+/// Interface prefix every component import must use.
+pub const WIT_PACKAGE: &str = "caeles:capsule/";
+
+/// Tells core modules and components apart from the binary header.
+pub fn is_component(bytes: &[u8]) -> bool {
+    wasmparser::Parser::is_component(bytes)
+}
+
+impl HostState {
+    /// Component counterpart of the core host call tracing. Component host
+    /// functions cannot trap, so a divergence is kept and reported after the run.
+    fn traced_call<T>(
+        &mut self,
+        function: &str,
+        args: Value,
+        encode: impl Fn(&T) -> Value,
+        decode: impl FnOnce(Value) -> Option<T>,
+        live: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let redactor = self.log.redactor().clone();
+        let args = redactor.redact_json(&args);
+        match self.calls.enter(function, &args) {
+            Ok(Some(recorded)) => {
+                println!("[caeles-replay] {function} served from trace -> {recorded}");
+                if let Some(result) = decode(recorded) {
+                    return result;
+                }
+            }
+            Ok(None) => {}
+            Err(divergence) => {
+                self.calls.fail(divergence);
+                return live(self);
+            }
+        }
+        let result = live(self);
+        let recorded = redactor.redact_json(&encode(&result));
+        if let Err(divergence) = self.calls.exit(function, args, recorded) {
+            self.calls.fail(divergence);
+        }
+        result
+    }
+}
+
+impl log::Host for HostState {
+    fn log(&mut self, message: String) {
+        self.traced_call(
+            "log.log",
+            json!([message]),
+            |_| Value::Null,
+            |_| Some(()),
+            |state| {
+                if state.log_level < LogLevel::Info {
+                    return;
+                }
+                let message = state.redact(&message);
+                println!("[capsule-log] {message}");
+                state.trace.logs.push(message);
+            },
+        )
+    }
+}
+
+impl notify::Host for HostState {
+    fn notify(&mut self, message: String) -> Result<(), notify::NotifyError> {
+        self.traced_call(
+            "notify.notify",
+            json!([message]),
+            |result: &Result<(), notify::NotifyError>| json!(result.is_ok()),
+            |value| {
+                value.as_bool().map(|ok| {
+                    if ok {
+                        Ok(())
+                    } else {
+                        Err(notify::NotifyError::BlockedByPermission)
+                    }
+                })
+            },
+            |state| state.notify_live(message.clone()),
+        )
+    }
+}
+
+impl http::Host for HostState {
+    fn get(&mut self, url: String) -> Result<Response, HttpError> {
+        self.traced_call(
+            "http.get",
+            json!([url]),
+            |result: &Result<Response, HttpError>| match result {
+                Ok(response) => json!({ "status": response.status, "body": response.body }),
+                Err(err) => json!({ "error": http_error_name(err) }),
+            },
+            |value| {
+                if let Some(error) = value.get("error").and_then(Value::as_str) {
+                    return Some(Err(match error {
+                        "blocked_by_permission" => HttpError::BlockedByPermission,
+                        "invalid_request" => HttpError::InvalidRequest,
+                        _ => HttpError::HostFailure,
+                    }));
+                }
+                Some(Ok(Response {
+                    status: u16::try_from(value.get("status")?.as_u64()?).ok()?,
+                    body: value.get("body")?.as_str()?.to_string(),
+                }))
+            },
+            |state| state.http_get_live(url.clone()),
+        )
+    }
+}
+
+fn http_error_name(err: &HttpError) -> &'static str {
+    match err {
+        HttpError::BlockedByPermission => "blocked_by_permission",
+        HttpError::InvalidRequest => "invalid_request",
+        HttpError::HostFailure => "host_failure",
+    }
+}
+
+impl HostState {
+    fn notify_live(&mut self, message: String) -> Result<(), notify::NotifyError> {
+        let message = self.redact(&message);
+        if self
+            .capabilities
+            .handle_for(CapabilityKind::Notifications)
+            .is_err()
+        {
+            println!(
+                "[capsule-notify BLOCKED] permission 'notifications' = false. Message: {message}"
+            );
+            return Err(notify::NotifyError::BlockedByPermission);
+        }
+        println!("[capsule-notify] {message}");
+        self.trace.notifications.push(message);
+        Ok(())
+    }
+
+    fn http_get_live(&mut self, url: String) -> Result<Response, HttpError> {
+        let capabilities = &self.capabilities;
+        let scope = match capabilities
+            .handle_for(CapabilityKind::Network)
+            .and_then(|handle| capabilities.check(handle, CapabilityKind::Network))
+        {
+            Ok(scope) => scope,
+            Err(_) => {
+                println!(
+                    "[capsule-network BLOCKED] permission 'network' = false. Requested URL: {}",
+                    self.redact(&url)
+                );
+                self.log.audit(
+                    "permission_denied",
+                    json!({ "function": "caeles:capsule/http.get", "kind": "network" }),
+                );
+                return Err(HttpError::BlockedByPermission);
+            }
+        };
+
+        let response = http_request(self.http_stubs.as_ref(), scope, &url, self.log.redactor())
+            .map_err(|failure| match failure {
+                HttpFailure::Blocked => HttpError::BlockedByPermission,
+                HttpFailure::InvalidRequest => HttpError::InvalidRequest,
+                HttpFailure::HostFailure => HttpError::HostFailure,
+            })?;
+        Ok(Response {
+            status: response.status,
+            body: response.body,
+        })
+    }
+}
+
+/// Checks that a component only imports interfaces of the `caeles:capsule` package.
+pub fn analyze(engine: &Engine, component: &Component) -> Vec<PreflightIssue> {
+    let mut issues = Vec::new();
+    for (name, _) in component.component_type().imports(engine) {
+        if !name.starts_with(WIT_PACKAGE) {
+            issues.push(PreflightIssue::error(
+                name,
+                format!("import não declarado no mundo WIT '{WIT_PACKAGE}'; o runtime só fornece as interfaces de wit/caeles.wit"),
+            ));
+        }
+    }
+    let exports_run = component
+        .component_type()
+        .exports(engine)
+        .any(|(name, _)| name == "run");
+    if !exports_run {
+        issues.push(PreflightIssue::error(
+            "run",
+            "componente deve exportar `run: func() -> result<_, string>`",
+        ));
+    }
+    issues
+}
+
+/// Instantiates a component capsule against the `capsule` world and calls `run`.
+pub fn run_component(
+    engine: &Engine,
+    bytes: &[u8],
+    manifest: &CapsuleManifest,
+    state: HostState,
+    finished: &mut Option<HostState>,
+) -> Result<()> {
+    let component = Component::new(engine, bytes)?;
+    let issues = analyze(engine, &component);
+    crate::preflight::ensure_no_errors(&issues)?;
+    println!("> Capsule format: component (world caeles:capsule/capsule)");
+
+    let mut linker = Linker::new(engine);
+    Capsule::add_to_linker(&mut linker, |state: &mut HostState| state)?;
+
+    let mut store = Store::new(engine, state);
+    let timeout = store.data().timeout;
+    // Instantiation runs start functions, so it is bound by the timeout as well.
+    let timer = arm_timeout(&mut store);
+    let capsule = Capsule::instantiate(&mut store, &component, &linker)
+        .map_err(|err| timeout_error(err, timeout))?;
+
+    println!("> Calling component run for '{}'...", manifest.id);
+    let result = capsule.call_run(&mut store);
+    drop(timer);
+    *finished = Some(store.into_data());
+    if let Err(message) = result.map_err(|err| timeout_error(err, timeout))? {
+        bail!("component run returned error: {message}");
+    }
+    println!("> run finished.");
+    Ok(())
+}
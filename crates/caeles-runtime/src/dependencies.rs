@@ -0,0 +1,310 @@
+//! Library capsules linked into a capsule through the manifest `dependencies`.
+//!
+//! A capsule imports the functions of a dependency from the `dep:<id>` module.
+//! Before instantiating it, the runtime instantiates each library (deepest
+//! first) in the same store and links its exported functions under that name.
+
+use crate::manifest::{CapsuleManifest, LifecycleKind};
+use anyhow::{bail, Context, Result};
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+/// Import module prefix of dependency functions (`dep:com.example.math`).
+pub const MODULE_PREFIX: &str = "dep:";
+
+/// Import module the functions of `id` are linked under.
+pub fn import_module(id: &str) -> String {
+    format!("{MODULE_PREFIX}{id}")
+}
+
+/// Capsule id behind a `dep:<id>` import module.
+pub fn module_dependency(module: &str) -> Option<&str> {
+    module.strip_prefix(MODULE_PREFIX)
+}
+
+/// A manifest that may satisfy a dependency: a registry entry or a local image.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub id: String,
+    /// `registry`, or the image source (`package`, `pull`).
+    pub source: String,
+    pub manifest_path: PathBuf,
+    /// Wasm file next to the manifest when it is not the manifest `entry`,
+    /// as in images, which store it as `capsule.wasm`.
+    pub wasm_entry: Option<String>,
+}
+
+/// A dependency picked for a `dependencies` entry, with its own dependencies.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedDependency {
+    pub id: String,
+    pub requirement: String,
+    pub version: String,
+    pub source: String,
+    pub manifest: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<ResolvedDependency>,
+    #[serde(skip)]
+    pub capsule: CapsuleManifest,
+}
+
+/// Picks the highest version matching each `dependencies` range, recursively.
+///
+/// A capsule id gets one version for the whole tree, since its functions are
+/// linked under a single `dep:<id>` module.
+pub fn resolve(
+    manifest: &CapsuleManifest,
+    candidates: &[Candidate],
+) -> Result<Vec<ResolvedDependency>> {
+    let mut chosen = BTreeMap::new();
+    resolve_level(
+        manifest,
+        candidates,
+        &mut vec![manifest.id.clone()],
+        &mut chosen,
+    )
+}
+
+fn resolve_level(
+    manifest: &CapsuleManifest,
+    candidates: &[Candidate],
+    chain: &mut Vec<String>,
+    chosen: &mut BTreeMap<String, Version>,
+) -> Result<Vec<ResolvedDependency>> {
+    let mut resolved = Vec::new();
+    for (id, requirement) in &manifest.dependencies {
+        if chain.contains(id) {
+            bail!("Dependência circular: {} -> {id}", chain.join(" -> "));
+        }
+        let range = VersionReq::parse(requirement).with_context(|| {
+            format!(
+                "Intervalo de versão inválido para '{id}' em '{}'",
+                manifest.id
+            )
+        })?;
+        let (candidate, capsule, version) = best_match(id, &range, candidates, chosen.get(id))?;
+        if capsule.lifecycle.kind != LifecycleKind::Library {
+            bail!(
+                "'{id}' ({}) não é uma cápsula library; declare lifecycle.kind = 'library'",
+                candidate.manifest_path.display()
+            );
+        }
+
+        chosen.insert(id.clone(), version.clone());
+        chain.push(id.clone());
+        let dependencies = resolve_level(&capsule, candidates, chain, chosen)?;
+        chain.pop();
+
+        resolved.push(ResolvedDependency {
+            id: id.clone(),
+            requirement: requirement.clone(),
+            version: version.to_string(),
+            source: candidate.source.clone(),
+            manifest: candidate.manifest_path.display().to_string(),
+            dependencies,
+            capsule,
+        });
+    }
+    Ok(resolved)
+}
+
+fn best_match<'a>(
+    id: &str,
+    range: &VersionReq,
+    candidates: &'a [Candidate],
+    pinned: Option<&Version>,
+) -> Result<(&'a Candidate, CapsuleManifest, Version)> {
+    let mut found = Vec::new();
+    let mut best: Option<(&Candidate, CapsuleManifest, Version)> = None;
+    for candidate in candidates.iter().filter(|c| c.id == id) {
+        let Ok(mut capsule) = CapsuleManifest::load(&candidate.manifest_path) else {
+            continue;
+        };
+        if let Some(entry) = &candidate.wasm_entry {
+            capsule.entry = entry.clone();
+        }
+        let Ok(version) = Version::parse(&capsule.version) else {
+            continue;
+        };
+        found.push(version.to_string());
+        if !range.matches(&version) || pinned.is_some_and(|pinned| *pinned != version) {
+            continue;
+        }
+        if best.as_ref().is_none_or(|(_, _, best)| version > *best) {
+            best = Some((candidate, capsule, version));
+        }
+    }
+
+    if let Some(best) = best {
+        return Ok(best);
+    }
+    if let Some(pinned) = pinned {
+        bail!("Dependência '{id}' exigida como '{range}', mas a árvore já usa a versão {pinned}");
+    }
+    if found.is_empty() {
+        bail!("Dependência '{id}' não encontrada no registry nem nas imagens locais");
+    }
+    found.sort();
+    found.dedup();
+    bail!(
+        "Nenhuma versão de '{id}' satisfaz '{range}' (disponíveis: {})",
+        found.join(", ")
+    )
+}
+
+/// Dependencies in instantiation order: each library after the ones it imports.
+pub fn load_order(dependencies: &[ResolvedDependency]) -> Vec<&ResolvedDependency> {
+    fn visit<'a>(
+        dependency: &'a ResolvedDependency,
+        seen: &mut HashSet<&'a str>,
+        order: &mut Vec<&'a ResolvedDependency>,
+    ) {
+        for child in &dependency.dependencies {
+            visit(child, seen, order);
+        }
+        if seen.insert(&dependency.id) {
+            order.push(dependency);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    for dependency in dependencies {
+        visit(dependency, &mut seen, &mut order);
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_order, resolve, Candidate};
+    use crate::manifest::CapsuleManifest;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn write_manifest(
+        root: &Path,
+        id: &str,
+        version: &str,
+        kind: &str,
+        dependencies: serde_json::Value,
+    ) -> Candidate {
+        let path = root.join(format!("{id}-{version}.json"));
+        let manifest = serde_json::json!({
+            "id": id,
+            "name": id,
+            "version": version,
+            "entry": "capsule.wasm",
+            "permissions": { "notifications": false, "network": false },
+            "lifecycle": { "kind": kind },
+            "dependencies": dependencies
+        });
+        fs::write(&path, manifest.to_string()).expect("manifest should be written");
+        Candidate {
+            id: id.to_string(),
+            source: "registry".to_string(),
+            manifest_path: path,
+            wasm_entry: None,
+        }
+    }
+
+    #[test]
+    fn resolves_highest_matching_versions_in_load_order() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let candidates = vec![
+            write_manifest(
+                root,
+                "com.tests.math",
+                "1.2.0",
+                "library",
+                serde_json::json!({}),
+            ),
+            write_manifest(
+                root,
+                "com.tests.math",
+                "1.10.0",
+                "library",
+                serde_json::json!({}),
+            ),
+            write_manifest(
+                root,
+                "com.tests.math",
+                "2.0.0",
+                "library",
+                serde_json::json!({}),
+            ),
+            write_manifest(
+                root,
+                "com.tests.stats",
+                "0.3.1",
+                "library",
+                serde_json::json!({ "com.tests.math": "^1.2" }),
+            ),
+        ];
+        let app = write_manifest(
+            root,
+            "com.tests.app",
+            "0.1.0",
+            "on_demand",
+            serde_json::json!({ "com.tests.stats": "0.3", "com.tests.math": ">=1.0, <2" }),
+        );
+        let app = CapsuleManifest::load(&app.manifest_path).expect("manifest should load");
+
+        let tree = resolve(&app, &candidates).expect("dependencies should resolve");
+        let versions: Vec<(&str, &str)> = load_order(&tree)
+            .iter()
+            .map(|d| (d.id.as_str(), d.version.as_str()))
+            .collect();
+        assert_eq!(
+            versions,
+            vec![("com.tests.math", "1.10.0"), ("com.tests.stats", "0.3.1")]
+        );
+    }
+
+    #[test]
+    fn rejects_cycles_missing_ranges_and_non_libraries() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let candidates = vec![
+            write_manifest(
+                root,
+                "com.tests.a",
+                "1.0.0",
+                "library",
+                serde_json::json!({ "com.tests.b": "1" }),
+            ),
+            write_manifest(
+                root,
+                "com.tests.b",
+                "1.0.0",
+                "library",
+                serde_json::json!({ "com.tests.a": "1" }),
+            ),
+            write_manifest(
+                root,
+                "com.tests.tool",
+                "1.0.0",
+                "on_demand",
+                serde_json::json!({}),
+            ),
+        ];
+        let resolve_app = |dependencies: serde_json::Value| {
+            let app = write_manifest(root, "com.tests.app", "0.1.0", "on_demand", dependencies);
+            let app = CapsuleManifest::load(&app.manifest_path).expect("manifest should load");
+            resolve(&app, &candidates)
+                .expect_err("resolution should fail")
+                .to_string()
+        };
+
+        assert!(resolve_app(serde_json::json!({ "com.tests.a": "1" })).contains("circular"));
+        assert!(resolve_app(serde_json::json!({ "com.tests.a": "^2" })).contains("1.0.0"));
+        assert!(resolve_app(serde_json::json!({ "com.tests.tool": "1" })).contains("library"));
+        assert!(
+            resolve_app(serde_json::json!({ "com.tests.none": "1" })).contains("não encontrada")
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use wasmtime::Config;
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock, WasiCtxBuilder};
+
+/// Wall-clock time seen by deterministic runs at startup (2024-01-01T00:00:00Z).
+pub const DETERMINISTIC_EPOCH: Duration = Duration::from_secs(1_704_067_200);
+
+/// Virtual time that passes on every clock read of a deterministic run.
+pub const CLOCK_TICK: Duration = Duration::from_millis(1);
+
+/// Seed for `--deterministic` runs without `--seed`.
+pub fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// Engine settings of a run. Deterministic runs canonicalize NaNs, disable
+/// threads and make relaxed SIMD behave the same on every host.
+pub fn engine_config(deterministic: bool) -> Config {
+    let mut config = Config::new();
+    if deterministic {
+        config
+            .cranelift_nan_canonicalization(true)
+            .wasm_threads(false)
+            .relaxed_simd_deterministic(true);
+    }
+    config
+}
+
+/// Random source of a deterministic run.
+pub fn seeded_rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+/// Time of a deterministic run: starts at zero and only advances by
+/// [`CLOCK_TICK`] when read. Clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock {
+    elapsed_ns: Arc<AtomicU64>,
+}
+
+impl VirtualClock {
+    /// Advances the clock by one tick and returns the elapsed time.
+    pub fn tick(&self) -> Duration {
+        self.advance(CLOCK_TICK)
+    }
+
+    /// Advances the clock by `duration` (a sleep) and returns the elapsed time.
+    pub fn advance(&self, duration: Duration) -> Duration {
+        let step = duration.as_nanos() as u64;
+        Duration::from_nanos(self.elapsed_ns.fetch_add(step, Ordering::Relaxed) + step)
+    }
+
+    /// Elapsed time, without advancing the clock.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_ns.load(Ordering::Relaxed))
+    }
+}
+
+struct VirtualWallClock(VirtualClock);
+
+impl HostWallClock for VirtualWallClock {
+    fn resolution(&self) -> Duration {
+        CLOCK_TICK
+    }
+
+    fn now(&self) -> Duration {
+        DETERMINISTIC_EPOCH + self.0.tick()
+    }
+}
+
+struct VirtualMonotonicClock(VirtualClock);
+
+impl HostMonotonicClock for VirtualMonotonicClock {
+    fn resolution(&self) -> u64 {
+        CLOCK_TICK.as_nanos() as u64
+    }
+
+    fn now(&self) -> u64 {
+        self.0.tick().as_nanos() as u64
+    }
+}
+
+/// Serves WASI clocks from `clock` and WASI randomness from `seed`.
+pub fn configure_wasi(builder: &mut WasiCtxBuilder, seed: u64, clock: &VirtualClock) {
+    builder
+        .secure_random(seeded_rng(seed))
+        .insecure_random(seeded_rng(seed.wrapping_add(1)))
+        .insecure_random_seed(u128::from(seed))
+        .wall_clock(VirtualWallClock(clock.clone()))
+        .monotonic_clock(VirtualMonotonicClock(clock.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{seeded_rng, VirtualClock, CLOCK_TICK};
+    use rand::RngCore;
+
+    #[test]
+    fn seeded_sources_repeat_across_runs() {
+        let mut first = [0u8; 16];
+        let mut second = [0u8; 16];
+        seeded_rng(7).fill_bytes(&mut first);
+        seeded_rng(7).fill_bytes(&mut second);
+        assert_eq!(first, second);
+
+        let clock = VirtualClock::default();
+        let shared = clock.clone();
+        assert_eq!(clock.tick(), CLOCK_TICK);
+        assert_eq!(shared.tick(), CLOCK_TICK * 2);
+        assert_eq!(clock.advance(CLOCK_TICK * 10), CLOCK_TICK * 12);
+        assert_eq!(shared.elapsed(), CLOCK_TICK * 12);
+    }
+}
@@ -0,0 +1,442 @@
+//! File area of a capsule, reached through the `host_fs_*` calls.
+//!
+//! Capsule paths are relative and `/`-separated. `assets/<dir>/...` reads the
+//! asset directories declared in the manifest; every other path lives in the
+//! private directory of the capsule, which also backs WASI preopens. Writes
+//! count against the quota of the private directory.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Path prefix under which declared asset directories are visible.
+pub const ASSETS_PREFIX: &str = "assets";
+
+/// Files a capsule can keep open at the same time.
+pub const MAX_OPEN_FILES: usize = 16;
+
+/// Largest read served by a single `host_fs_read`.
+pub const MAX_READ_CHUNK: usize = 1024 * 1024;
+
+/// Why a file call failed; the negative codes are part of the ABI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FsError {
+    Denied,
+    InvalidPath,
+    NotFound,
+    QuotaExceeded,
+    ReadOnly,
+    BadHandle,
+    TooManyOpen,
+    Io,
+}
+
+impl FsError {
+    pub fn code(self) -> i32 {
+        match self {
+            FsError::Denied => -1,
+            FsError::InvalidPath => -2,
+            FsError::NotFound => -3,
+            FsError::QuotaExceeded => -4,
+            FsError::ReadOnly => -5,
+            FsError::BadHandle => -6,
+            FsError::TooManyOpen => -7,
+            FsError::Io => -8,
+        }
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            FsError::Denied => "permission_denied",
+            FsError::InvalidPath => "invalid_path",
+            FsError::NotFound => "not_found",
+            FsError::QuotaExceeded => "quota_exceeded",
+            FsError::ReadOnly => "read_only",
+            FsError::BadHandle => "bad_handle",
+            FsError::TooManyOpen => "too_many_open_files",
+            FsError::Io => "io_error",
+        }
+    }
+
+    fn from_io(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => FsError::NotFound,
+            _ => FsError::Io,
+        }
+    }
+}
+
+/// Mode argument of `host_fs_open`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    Read,
+    /// Creates the file (and missing parent directories) or truncates it.
+    Write,
+    Append,
+}
+
+impl OpenMode {
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(OpenMode::Read),
+            1 => Some(OpenMode::Write),
+            2 => Some(OpenMode::Append),
+            _ => None,
+        }
+    }
+}
+
+/// A capsule path mapped to the host.
+struct Resolved {
+    path: PathBuf,
+    /// Directory the path must stay inside, even through symlinks.
+    root: PathBuf,
+    read_only: bool,
+}
+
+struct OpenFile {
+    file: fs::File,
+    writable: bool,
+}
+
+/// Private directory, assets and open files of one run.
+pub struct CapsuleFiles {
+    root: PathBuf,
+    assets: Vec<(String, PathBuf)>,
+    quota: u64,
+    used: u64,
+    open: HashMap<i32, OpenFile>,
+    next_fd: i32,
+}
+
+impl CapsuleFiles {
+    pub fn new(root: &Path, assets: Vec<(String, PathBuf)>, quota: u64) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            assets,
+            quota,
+            used: dir_size(root),
+            open: HashMap::new(),
+            next_fd: 1,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn quota(&self) -> u64 {
+        self.quota
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Maps a capsule path to the host, rejecting `..`, absolute paths and escapes through symlinks.
+    fn resolve(&self, path: &str) -> Result<Resolved, FsError> {
+        if path.contains(['\\', '\0']) {
+            return Err(FsError::InvalidPath);
+        }
+        let parts: Vec<&str> = path
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect();
+        if parts.contains(&"..") {
+            return Err(FsError::InvalidPath);
+        }
+
+        let resolved = if parts.first() == Some(&ASSETS_PREFIX) {
+            let rest = parts[1..].join("/");
+            let (name, dir) = self
+                .assets
+                .iter()
+                .find(|(name, _)| rest == *name || rest.starts_with(&format!("{name}/")))
+                .ok_or(FsError::NotFound)?;
+            Resolved {
+                path: dir.join(rest[name.len()..].trim_start_matches('/')),
+                root: dir.clone(),
+                read_only: true,
+            }
+        } else {
+            Resolved {
+                path: parts
+                    .iter()
+                    .fold(self.root.clone(), |dir, part| dir.join(part)),
+                root: self.root.clone(),
+                read_only: false,
+            }
+        };
+        ensure_inside(&resolved.root, &resolved.path)?;
+        Ok(resolved)
+    }
+
+    pub fn open(&mut self, path: &str, mode: OpenMode) -> Result<i32, FsError> {
+        if self.open.len() >= MAX_OPEN_FILES {
+            return Err(FsError::TooManyOpen);
+        }
+        let resolved = self.resolve(path)?;
+        if resolved.path == resolved.root {
+            return Err(FsError::InvalidPath);
+        }
+        if mode != OpenMode::Read && resolved.read_only {
+            return Err(FsError::ReadOnly);
+        }
+
+        let mut truncated = 0;
+        let file = match mode {
+            OpenMode::Read => fs::File::open(&resolved.path),
+            OpenMode::Write | OpenMode::Append => {
+                if let Some(parent) = resolved.path.parent() {
+                    fs::create_dir_all(parent).map_err(|err| FsError::from_io(&err))?;
+                    ensure_inside(&resolved.root, parent)?;
+                }
+                if mode == OpenMode::Write {
+                    truncated = fs::metadata(&resolved.path)
+                        .ok()
+                        .filter(|meta| meta.is_file())
+                        .map_or(0, |meta| meta.len());
+                }
+                fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(mode == OpenMode::Write)
+                    .append(mode == OpenMode::Append)
+                    .open(&resolved.path)
+            }
+        }
+        .map_err(|err| FsError::from_io(&err))?;
+        if file.metadata().is_ok_and(|meta| meta.is_dir()) {
+            return Err(FsError::InvalidPath);
+        }
+        // Only a successful truncating open frees the old file's bytes.
+        self.used = self.used.saturating_sub(truncated);
+
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        self.open.insert(
+            fd,
+            OpenFile {
+                file,
+                writable: mode != OpenMode::Read,
+            },
+        );
+        Ok(fd)
+    }
+
+    pub fn read(&mut self, fd: i32, len: usize) -> Result<Vec<u8>, FsError> {
+        let open = self.open.get_mut(&fd).ok_or(FsError::BadHandle)?;
+        let mut buf = vec![0u8; len.min(MAX_READ_CHUNK)];
+        let count = open
+            .file
+            .read(&mut buf)
+            .map_err(|err| FsError::from_io(&err))?;
+        buf.truncate(count);
+        Ok(buf)
+    }
+
+    pub fn write(&mut self, fd: i32, bytes: &[u8]) -> Result<usize, FsError> {
+        let open = self.open.get_mut(&fd).ok_or(FsError::BadHandle)?;
+        if !open.writable {
+            return Err(FsError::ReadOnly);
+        }
+        if self.used + bytes.len() as u64 > self.quota {
+            return Err(FsError::QuotaExceeded);
+        }
+        open.file
+            .write_all(bytes)
+            .map_err(|err| FsError::from_io(&err))?;
+        self.used += bytes.len() as u64;
+        Ok(bytes.len())
+    }
+
+    pub fn close(&mut self, fd: i32) -> Result<(), FsError> {
+        self.open.remove(&fd).map(|_| ()).ok_or(FsError::BadHandle)
+    }
+
+    /// Entries of a directory, sorted; directories end with `/`.
+    pub fn list(&self, path: &str) -> Result<Vec<String>, FsError> {
+        let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        if parts == [ASSETS_PREFIX] {
+            let mut names: Vec<String> = self
+                .assets
+                .iter()
+                .map(|(name, _)| format!("{name}/"))
+                .collect();
+            names.sort();
+            return Ok(names);
+        }
+
+        let resolved = self.resolve(path)?;
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&resolved.path).map_err(|err| FsError::from_io(&err))? {
+            let entry = entry.map_err(|err| FsError::from_io(&err))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                names.push(format!("{name}/"));
+            } else {
+                names.push(name);
+            }
+        }
+        if resolved.path == self.root && !self.assets.is_empty() {
+            names.retain(|name| name != &format!("{ASSETS_PREFIX}/"));
+            names.push(format!("{ASSETS_PREFIX}/"));
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Removes a file or an empty directory of the private directory.
+    pub fn delete(&mut self, path: &str) -> Result<(), FsError> {
+        let resolved = self.resolve(path)?;
+        if resolved.read_only {
+            return Err(FsError::ReadOnly);
+        }
+        if resolved.path == resolved.root {
+            return Err(FsError::InvalidPath);
+        }
+        let meta = fs::symlink_metadata(&resolved.path).map_err(|err| FsError::from_io(&err))?;
+        if meta.is_dir() {
+            fs::remove_dir(&resolved.path).map_err(|err| FsError::from_io(&err))?;
+        } else {
+            fs::remove_file(&resolved.path).map_err(|err| FsError::from_io(&err))?;
+            self.used = self.used.saturating_sub(meta.len());
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `path`, or its closest existing ancestor, is inside `root` once symlinks are followed.
+///
+/// A symlink counts as existing even when its target does not, and a dangling one is
+/// rejected: creating a file through it would land wherever it points.
+fn ensure_inside(root: &Path, path: &Path) -> Result<(), FsError> {
+    let root = root.canonicalize().map_err(|err| FsError::from_io(&err))?;
+    let mut existing = path;
+    while fs::symlink_metadata(existing).is_err() {
+        existing = existing.parent().ok_or(FsError::InvalidPath)?;
+    }
+    let existing = existing.canonicalize().map_err(|_| FsError::InvalidPath)?;
+    if existing.starts_with(&root) {
+        Ok(())
+    } else {
+        Err(FsError::InvalidPath)
+    }
+}
+
+/// Bytes of every file under `path`; symlinks are not followed.
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(kind) if kind.is_file() => entry.metadata().map_or(0, |meta| meta.len()),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CapsuleFiles, FsError, OpenMode};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn paths_stay_inside_the_private_directory_and_assets() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let private = root.join("private");
+        let assets = root.join("static");
+        fs::create_dir_all(&assets).expect("assets directory should be created");
+        fs::write(assets.join("readme.txt"), "asset").expect("asset should be written");
+        fs::write(root.join("outside.txt"), "secret").expect("outside file should be written");
+
+        let mut files = CapsuleFiles::new(&private, vec![("static".to_string(), assets)], 1024)
+            .expect("file area should be created");
+        for path in ["../outside.txt", "a/../../outside.txt", "a\\b"] {
+            assert_eq!(files.open(path, OpenMode::Read), Err(FsError::InvalidPath));
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("outside.txt"), private.join("link"))
+                .expect("symlink should be created");
+            assert_eq!(
+                files.open("link", OpenMode::Read),
+                Err(FsError::InvalidPath)
+            );
+            fs::remove_file(private.join("link")).expect("symlink should be removed");
+
+            std::os::unix::fs::symlink(root.join("created.txt"), private.join("dangling"))
+                .expect("symlink should be created");
+            assert_eq!(
+                files.open("dangling", OpenMode::Write),
+                Err(FsError::InvalidPath)
+            );
+            assert!(!root.join("created.txt").exists());
+            fs::remove_file(private.join("dangling")).expect("symlink should be removed");
+        }
+
+        let fd = files
+            .open("assets/static/readme.txt", OpenMode::Read)
+            .expect("asset should open");
+        assert_eq!(files.read(fd, 64), Ok(b"asset".to_vec()));
+        assert_eq!(
+            files.open("assets/static/new.txt", OpenMode::Write),
+            Err(FsError::ReadOnly)
+        );
+        assert_eq!(
+            files.delete("assets/static/readme.txt"),
+            Err(FsError::ReadOnly)
+        );
+
+        let fd = files
+            .open("notes/today.txt", OpenMode::Write)
+            .expect("file should be created");
+        assert_eq!(files.write(fd, b"hello"), Ok(5));
+        assert_eq!(
+            files.list(""),
+            Ok(vec!["assets/".to_string(), "notes/".to_string()])
+        );
+        assert_eq!(files.list("notes"), Ok(vec!["today.txt".to_string()]));
+    }
+
+    #[test]
+    fn writes_are_limited_by_the_quota() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let mut files = CapsuleFiles::new(root, Vec::new(), 8).expect("file area should exist");
+
+        let fd = files
+            .open("a.txt", OpenMode::Write)
+            .expect("file should open");
+        assert_eq!(files.write(fd, b"123456"), Ok(6));
+        assert_eq!(files.write(fd, b"789"), Err(FsError::QuotaExceeded));
+        files.close(fd).expect("file should close");
+        assert_eq!(files.used(), 6);
+
+        // A failed open leaves the quota alone.
+        fs::create_dir_all(root.join("dir")).expect("directory should be created");
+        assert!(files.open("dir", OpenMode::Write).is_err());
+        assert_eq!(files.used(), 6);
+
+        // Truncating gives the space back.
+        let fd = files
+            .open("a.txt", OpenMode::Write)
+            .expect("file should reopen");
+        assert_eq!(files.write(fd, b"12345678"), Ok(8));
+        files.close(fd).expect("file should close");
+        assert_eq!(files.close(fd), Err(FsError::BadHandle));
+
+        files.delete("a.txt").expect("file should be deleted");
+        assert_eq!(files.used(), 0);
+        assert_eq!(files.delete("a.txt"), Err(FsError::NotFound));
+    }
+}
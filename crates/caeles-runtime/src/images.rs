@@ -0,0 +1,639 @@
+//! Local image store: capsules copied by `caeles package` and `caeles pull`,
+//! laid out as `<root>/<id>/<version>/` with the wasm stored as `capsule.wasm`.
+//!
+//! Image files are links to blobs of the [`BlobStore`]; `image.json` records
+//! their digests, which are checked whenever an image is loaded.
+//!
+//! Images are referenced as `<id>[@<selector>]`, where the selector is an exact
+//! version (`1.2.0`), a tag (`stable`) or a SemVer range (`^1.2`).
+
+use crate::blobs::{self, BlobRef, BlobStore};
+use crate::manifest::{validate_capsule_id, CapsuleManifest};
+use anyhow::{bail, Context, Result};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Locations inside the store directory of the settings.
+pub const PACKAGES_DIR: &str = "packages";
+pub const PULLED_DIR: &str = "pulled";
+pub const TAGS_FILE: &str = "tags.json";
+
+/// Tag that points at the highest version unless it was set with `caeles tag`.
+pub const LATEST_TAG: &str = "latest";
+
+/// File name of the wasm inside an image directory.
+pub const IMAGE_WASM: &str = "capsule.wasm";
+
+/// Descriptor of an image, next to its files.
+pub const IMAGE_DESCRIPTOR: &str = "image.json";
+
+const DESCRIPTOR_VERSION: u32 = 1;
+
+/// Command that created an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSource {
+    Package,
+    Pull,
+}
+
+impl ImageSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageSource::Package => "package",
+            ImageSource::Pull => "pull",
+        }
+    }
+}
+
+/// `image.json`: what an image was built from and the blobs behind its files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageDescriptor {
+    pub descriptor_version: u32,
+    pub capsule_id: String,
+    pub name: String,
+    pub version: String,
+    pub source_manifest: String,
+    pub created_at_unix_ms: u128,
+    pub manifest: BlobRef,
+    pub wasm: BlobRef,
+    /// Asset files by path inside the image (`static/logo.png`).
+    #[serde(default)]
+    pub assets: BTreeMap<String, BlobRef>,
+}
+
+impl ImageDescriptor {
+    /// Every blob of the image with the file it backs, relative to the image directory.
+    pub fn files(&self) -> Vec<(String, &BlobRef)> {
+        let mut files = vec![
+            ("manifest.json".to_string(), &self.manifest),
+            (IMAGE_WASM.to_string(), &self.wasm),
+        ];
+        files.extend(self.assets.iter().map(|(path, blob)| (path.clone(), blob)));
+        files
+    }
+
+    /// Bytes of all blobs of the image, shared ones included.
+    pub fn size(&self) -> u64 {
+        self.files().iter().map(|(_, blob)| blob.size).sum()
+    }
+}
+
+/// One `<id>/<version>` directory of the store.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub source: ImageSource,
+    pub capsule_id: String,
+    pub version: Version,
+    pub path: PathBuf,
+    /// `None` for images created before blobs were introduced.
+    pub descriptor: Option<ImageDescriptor>,
+}
+
+impl Image {
+    /// `<id>@<version>`.
+    pub fn reference(&self) -> String {
+        format!("{}@{}", self.capsule_id, self.version)
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.path.join("manifest.json")
+    }
+
+    /// `sha256:<hex>` of the wasm, from `image.json` or, for older images, the file itself.
+    pub fn wasm_digest(&self) -> Result<String> {
+        if let Some(descriptor) = &self.descriptor {
+            return Ok(descriptor.wasm.digest.clone());
+        }
+        let path = self.path.join(IMAGE_WASM);
+        let bytes =
+            fs::read(&path).with_context(|| format!("Falha ao ler '{}'", path.display()))?;
+        Ok(blobs::digest(&bytes))
+    }
+
+    /// Whether `path` (a manifest or wasm) is a file of this image, however
+    /// either path was written.
+    pub fn contains(&self, path: &Path) -> bool {
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        canonical(path).starts_with(canonical(&self.path))
+    }
+
+    /// Manifest of the image, with `entry` pointing at its `capsule.wasm`, after
+    /// checking every file of the image against the digests in `image.json`.
+    pub fn manifest(&self) -> Result<CapsuleManifest> {
+        if let Some(descriptor) = &self.descriptor {
+            for (file, blob) in descriptor.files() {
+                blobs::verify_file(&self.path.join(&file), blob)
+                    .with_context(|| format!("Imagem '{}' corrompida", self.reference()))?;
+            }
+        }
+        let mut manifest = CapsuleManifest::load(&self.manifest_path())?;
+        manifest.entry = IMAGE_WASM.to_string();
+        Ok(manifest)
+    }
+}
+
+/// Blobs of the store that no image in `images` links to.
+pub fn unreferenced_blobs(images: &[Image], blobs: &BlobStore) -> Result<Vec<BlobRef>> {
+    let referenced: std::collections::HashSet<&str> = images
+        .iter()
+        .filter_map(|image| image.descriptor.as_ref())
+        .flat_map(|descriptor| descriptor.files())
+        .map(|(_, blob)| blob.digest.as_str())
+        .collect();
+    Ok(blobs
+        .list()?
+        .into_iter()
+        .filter(|blob| !referenced.contains(blob.digest.as_str()))
+        .collect())
+}
+
+fn read_descriptor(image_dir: &Path) -> Result<Option<ImageDescriptor>> {
+    let path = image_dir.join(IMAGE_DESCRIPTOR);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(&path)?;
+    let descriptor = serde_json::from_str(&text)
+        .with_context(|| format!("Descritor de imagem inválido '{}'", path.display()))?;
+    Ok(Some(descriptor))
+}
+
+/// Files under `dir`, as `/`-separated paths relative to it.
+fn walk_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            walk_files(&entry.path(), &format!("{name}/"), files)?;
+        } else if kind.is_file() {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+/// Stores the manifest, wasm and assets of a capsule as blobs and writes the
+/// image directory `<root>/<id>/<version>/` linking to them, replacing any
+/// previous image of that version.
+///
+/// The image manifest's `entry` is rewritten to `capsule.wasm`.
+pub fn create_image(
+    root: &Path,
+    blobs: &BlobStore,
+    manifest: &CapsuleManifest,
+    manifest_path: &Path,
+) -> Result<(PathBuf, ImageDescriptor)> {
+    let wasm_path = manifest.wasm_path();
+    let wasm = fs::read(&wasm_path)
+        .with_context(|| format!("Arquivo wasm não encontrado em '{}'", wasm_path.display()))?;
+    let mut manifest_json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(manifest_path)?)?;
+    manifest_json["entry"] = serde_json::Value::String(IMAGE_WASM.to_string());
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest_json)?;
+
+    let mut assets = Vec::new();
+    for (name, dir) in manifest.asset_dirs() {
+        if !dir.is_dir() {
+            bail!("Diretório de assets '{}' não encontrado", dir.display());
+        }
+        walk_files(&dir, &format!("{name}/"), &mut assets)?;
+    }
+
+    let (manifest_blob, _) = blobs.put(&manifest_bytes)?;
+    let (wasm_blob, wasm_written) = blobs.put(&wasm)?;
+    if !wasm_written {
+        println!("> wasm {} já estava no store", wasm_blob.short_digest());
+    }
+    let mut asset_blobs = BTreeMap::new();
+    for (path, file) in assets {
+        let (blob, _) = blobs.put(&fs::read(&file)?)?;
+        asset_blobs.insert(path, blob);
+    }
+
+    let descriptor = ImageDescriptor {
+        descriptor_version: DESCRIPTOR_VERSION,
+        capsule_id: manifest.id.clone(),
+        name: manifest.name.clone(),
+        version: manifest.version.clone(),
+        source_manifest: manifest_path.display().to_string(),
+        created_at_unix_ms: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system clock should be after unix epoch")
+            .as_millis(),
+        manifest: manifest_blob,
+        wasm: wasm_blob,
+        assets: asset_blobs,
+    };
+
+    // Files of the old image may be links to blobs, so they are removed
+    // rather than overwritten.
+    let image_dir = root.join(&manifest.id).join(&manifest.version);
+    if image_dir.exists() {
+        fs::remove_dir_all(&image_dir)?;
+    }
+    fs::create_dir_all(&image_dir)?;
+    for (file, blob) in descriptor.files() {
+        blobs.link(blob, &image_dir.join(file))?;
+    }
+    fs::write(
+        image_dir.join(IMAGE_DESCRIPTOR),
+        serde_json::to_string_pretty(&descriptor)?,
+    )?;
+    Ok((image_dir, descriptor))
+}
+
+/// `<id>[@<version|tag|range>]` as given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageRef {
+    pub capsule_id: String,
+    pub selector: Option<String>,
+}
+
+impl ImageRef {
+    pub fn parse(text: &str) -> Result<Self> {
+        let (capsule_id, selector) = match text.split_once('@') {
+            Some((id, selector)) => (id, Some(selector.trim())),
+            None => (text, None),
+        };
+        validate_capsule_id(capsule_id)
+            .map_err(|message| anyhow::anyhow!("Referência de imagem '{text}': {message}"))?;
+        if selector == Some("") {
+            bail!("Referência de imagem '{text}': versão, intervalo ou tag vazio após '@'");
+        }
+        Ok(Self {
+            capsule_id: capsule_id.to_string(),
+            selector: selector.map(str::to_string),
+        })
+    }
+}
+
+/// Tags must start with a letter so they never read as a version or range.
+pub fn validate_tag(tag: &str) -> Result<(), String> {
+    let valid = tag.len() <= 64
+        && tag.starts_with(|c: char| c.is_ascii_lowercase())
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "tag '{tag}' deve começar com a-z e usar apenas a-z, 0-9, '.', '_' ou '-'"
+        ))
+    }
+}
+
+/// Tags per capsule id: tag name to version.
+pub type Tags = BTreeMap<String, BTreeMap<String, String>>;
+
+/// The package and pull trees plus the tags file.
+#[derive(Debug, Clone)]
+pub struct ImageStore {
+    roots: Vec<(ImageSource, PathBuf)>,
+    tags_path: PathBuf,
+}
+
+impl ImageStore {
+    pub fn new(packages_dir: &Path, pulled_dir: &Path, tags_path: &Path) -> Self {
+        Self {
+            roots: vec![
+                (ImageSource::Package, packages_dir.to_path_buf()),
+                (ImageSource::Pull, pulled_dir.to_path_buf()),
+            ],
+            tags_path: tags_path.to_path_buf(),
+        }
+    }
+
+    /// The store laid out under `dir`.
+    pub fn in_dir(dir: &Path) -> Self {
+        Self::new(
+            &dir.join(PACKAGES_DIR),
+            &dir.join(PULLED_DIR),
+            &dir.join(TAGS_FILE),
+        )
+    }
+
+    /// The store in the directory of the settings ([`Settings::store_dir`]).
+    ///
+    /// [`Settings::store_dir`]: crate::settings::Settings::store_dir
+    pub fn local() -> Result<Self> {
+        Ok(Self::in_dir(
+            &crate::settings::Settings::load()?.store_dir(),
+        ))
+    }
+
+    /// Every image, by id, then version (SemVer order), then source.
+    ///
+    /// Version directories whose name is not a SemVer version are skipped.
+    pub fn list(&self) -> Result<Vec<Image>> {
+        let mut images = Vec::new();
+        for (source, root) in &self.roots {
+            if !root.exists() {
+                continue;
+            }
+            for id_entry in fs::read_dir(root)? {
+                let id_entry = id_entry?;
+                if !id_entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let capsule_id = id_entry.file_name().to_string_lossy().to_string();
+                for version_entry in fs::read_dir(id_entry.path())? {
+                    let version_entry = version_entry?;
+                    if !version_entry.file_type()?.is_dir() {
+                        continue;
+                    }
+                    let name = version_entry.file_name().to_string_lossy().to_string();
+                    let Ok(version) = Version::parse(&name) else {
+                        continue;
+                    };
+                    images.push(Image {
+                        source: *source,
+                        capsule_id: capsule_id.clone(),
+                        version,
+                        descriptor: read_descriptor(&version_entry.path())?,
+                        path: version_entry.path(),
+                    });
+                }
+            }
+        }
+        images.sort_by(|a, b| {
+            a.capsule_id
+                .cmp(&b.capsule_id)
+                .then_with(|| a.version.cmp(&b.version))
+                .then_with(|| a.source.cmp(&b.source))
+        });
+        Ok(images)
+    }
+
+    pub fn tags(&self) -> Result<Tags> {
+        if !self.tags_path.exists() {
+            return Ok(Tags::new());
+        }
+        let text = fs::read_to_string(&self.tags_path)
+            .with_context(|| format!("Falha ao ler tags '{}'", self.tags_path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("Arquivo de tags inválido '{}'", self.tags_path.display()))
+    }
+
+    fn save_tags(&self, tags: &Tags) -> Result<()> {
+        if let Some(parent) = self.tags_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.tags_path, serde_json::to_string_pretty(tags)?)?;
+        Ok(())
+    }
+
+    /// Tags pointing at `image`, including the implicit `latest`.
+    pub fn tags_of(image: &Image, images: &[Image], tags: &Tags) -> Vec<String> {
+        let explicit = tags.get(&image.capsule_id);
+        let version = image.version.to_string();
+        let mut names: Vec<String> = explicit
+            .into_iter()
+            .flatten()
+            .filter(|(_, tagged)| **tagged == version)
+            .map(|(tag, _)| tag.clone())
+            .collect();
+        let latest_is_explicit = explicit.is_some_and(|t| t.contains_key(LATEST_TAG));
+        let highest = images
+            .iter()
+            .filter(|i| i.capsule_id == image.capsule_id)
+            .map(|i| &i.version)
+            .max();
+        if !latest_is_explicit && highest == Some(&image.version) {
+            names.insert(0, LATEST_TAG.to_string());
+        }
+        names
+    }
+
+    /// Finds the image a reference points to; without a selector, `latest`.
+    ///
+    /// When both a package and a pull hold the version, the package wins.
+    pub fn resolve(&self, reference: &ImageRef) -> Result<Image> {
+        let id = &reference.capsule_id;
+        let images: Vec<Image> = self
+            .list()?
+            .into_iter()
+            .filter(|image| image.capsule_id == *id)
+            .collect();
+        if images.is_empty() {
+            bail!("Nenhuma imagem local de '{id}'; crie uma com `caeles package` ou `caeles pull`");
+        }
+        let available = || {
+            let mut versions: Vec<String> = images.iter().map(|i| i.version.to_string()).collect();
+            versions.dedup();
+            versions.join(", ")
+        };
+        let highest = |range: Option<&VersionReq>| {
+            images
+                .iter()
+                .filter(|image| range.is_none_or(|range| range.matches(&image.version)))
+                .max_by(|a, b| a.version.cmp(&b.version).then(b.source.cmp(&a.source)))
+                .cloned()
+        };
+        let exact = |version: &Version| images.iter().find(|i| i.version == *version).cloned();
+
+        let selector = reference.selector.as_deref().unwrap_or(LATEST_TAG);
+        if let Ok(version) = Version::parse(selector) {
+            return exact(&version).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Imagem '{id}@{version}' não encontrada (disponíveis: {})",
+                    available()
+                )
+            });
+        }
+        if let Some(tagged) = self.tags()?.get(id).and_then(|t| t.get(selector)) {
+            let version = Version::parse(tagged)
+                .with_context(|| format!("Tag '{selector}' de '{id}' aponta para '{tagged}'"))?;
+            return exact(&version).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Tag '{selector}' de '{id}' aponta para {version}, que não existe mais"
+                )
+            });
+        }
+        if selector == LATEST_TAG {
+            return Ok(highest(None).expect("images is not empty"));
+        }
+        if validate_tag(selector).is_ok() {
+            bail!("Tag '{selector}' não existe para '{id}'");
+        }
+        let range = VersionReq::parse(selector).with_context(|| {
+            format!("'{selector}' não é versão, tag nem intervalo SemVer válido")
+        })?;
+        highest(Some(&range)).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Nenhuma versão de '{id}' satisfaz '{range}' (disponíveis: {})",
+                available()
+            )
+        })
+    }
+
+    /// Points `tag` of the image's capsule at the image's version.
+    pub fn set_tag(&self, image: &Image, tag: &str) -> Result<Option<String>> {
+        validate_tag(tag).map_err(|message| anyhow::anyhow!("Tag inválida: {message}"))?;
+        let mut tags = self.tags()?;
+        let previous = tags
+            .entry(image.capsule_id.clone())
+            .or_default()
+            .insert(tag.to_string(), image.version.to_string());
+        self.save_tags(&tags)?;
+        Ok(previous)
+    }
+
+    /// Deletes the image directory and, once no source holds its version, the
+    /// tags pointing at it. Returns the removed tags.
+    ///
+    /// Blobs are left in the store; see [`unreferenced_blobs`].
+    pub fn remove(&self, image: &Image) -> Result<Vec<String>> {
+        fs::remove_dir_all(&image.path)
+            .with_context(|| format!("Falha ao remover '{}'", image.path.display()))?;
+        if let Some(id_dir) = image.path.parent() {
+            if fs::read_dir(id_dir)?.next().is_none() {
+                fs::remove_dir(id_dir)?;
+            }
+        }
+
+        let version = image.version.to_string();
+        let still_present = self
+            .list()?
+            .iter()
+            .any(|other| other.capsule_id == image.capsule_id && other.version == image.version);
+        let mut tags = self.tags()?;
+        let Some(capsule_tags) = tags.get_mut(&image.capsule_id).filter(|_| !still_present) else {
+            return Ok(Vec::new());
+        };
+        let removed: Vec<String> = capsule_tags
+            .iter()
+            .filter(|(_, tagged)| **tagged == version)
+            .map(|(tag, _)| tag.clone())
+            .collect();
+        capsule_tags.retain(|_, tagged| *tagged != version);
+        if capsule_tags.is_empty() {
+            tags.remove(&image.capsule_id);
+        }
+        if !removed.is_empty() {
+            self.save_tags(&tags)?;
+        }
+        Ok(removed)
+    }
+
+    /// Removes `tag` of `capsule_id`; returns the version it pointed to.
+    pub fn remove_tag(&self, capsule_id: &str, tag: &str) -> Result<Option<String>> {
+        let mut tags = self.tags()?;
+        let Some(capsule_tags) = tags.get_mut(capsule_id) else {
+            return Ok(None);
+        };
+        let removed = capsule_tags.remove(tag);
+        if capsule_tags.is_empty() {
+            tags.remove(capsule_id);
+        }
+        if removed.is_some() {
+            self.save_tags(&tags)?;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImageRef, ImageSource, ImageStore};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn temp_store(versions: &[(&str, &str)]) -> (TempDir, ImageStore) {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        for (tree, version) in versions {
+            fs::create_dir_all(root.join(tree).join("com.tests.app").join(version))
+                .expect("image directory should be created");
+        }
+        let store = ImageStore::new(
+            &root.join("packages"),
+            &root.join("pulled"),
+            &root.join("tags.json"),
+        );
+        (temp, store)
+    }
+
+    fn resolve(store: &ImageStore, reference: &str) -> String {
+        store
+            .resolve(&ImageRef::parse(reference).expect("reference should parse"))
+            .map(|image| format!("{}/{}", image.source.as_str(), image.version))
+            .unwrap_or_else(|err| format!("error: {err}"))
+    }
+
+    #[test]
+    fn resolves_versions_ranges_and_tags_in_semver_order() {
+        let (_temp, store) = temp_store(&[
+            ("packages", "0.9.0"),
+            ("packages", "0.10.0"),
+            ("pulled", "0.10.0"),
+            ("pulled", "1.2.3"),
+            ("packages", "not-a-version"),
+        ]);
+
+        let versions: Vec<String> = store
+            .list()
+            .expect("images should list")
+            .iter()
+            .map(|image| image.version.to_string())
+            .collect();
+        assert_eq!(versions, vec!["0.9.0", "0.10.0", "0.10.0", "1.2.3"]);
+
+        assert_eq!(resolve(&store, "com.tests.app"), "pull/1.2.3");
+        assert_eq!(resolve(&store, "com.tests.app@^0.9"), "package/0.9.0");
+        assert_eq!(resolve(&store, "com.tests.app@<1"), "package/0.10.0");
+        assert_eq!(resolve(&store, "com.tests.app@0.10.0"), "package/0.10.0");
+        assert!(resolve(&store, "com.tests.app@^2").contains("0.9.0, 0.10.0, 1.2.3"));
+        assert!(resolve(&store, "com.tests.app@stable").contains("não existe"));
+
+        let image = store
+            .resolve(&ImageRef::parse("com.tests.app@0.9.0").expect("reference should parse"))
+            .expect("image should resolve");
+        assert_eq!(image.source, ImageSource::Package);
+        store.set_tag(&image, "stable").expect("tag should be set");
+        store.set_tag(&image, "latest").expect("tag should be set");
+        assert_eq!(resolve(&store, "com.tests.app@stable"), "package/0.9.0");
+        assert_eq!(resolve(&store, "com.tests.app"), "package/0.9.0");
+
+        let images = store.list().expect("images should list");
+        let tags = store.tags().expect("tags should load");
+        assert_eq!(
+            ImageStore::tags_of(&images[0], &images, &tags),
+            vec!["latest", "stable"]
+        );
+        assert!(ImageStore::tags_of(&images[3], &images, &tags).is_empty());
+
+        assert_eq!(
+            store
+                .remove_tag("com.tests.app", "latest")
+                .expect("tag should be removed"),
+            Some("0.9.0".to_string())
+        );
+        assert_eq!(resolve(&store, "com.tests.app"), "pull/1.2.3");
+        assert!(store.set_tag(&image, "1.0").is_err());
+
+        assert_eq!(
+            store.remove(&image).expect("image should be removed"),
+            vec!["stable"]
+        );
+        assert!(resolve(&store, "com.tests.app@stable").contains("não existe para"));
+
+        // A version still held by another source keeps its tags.
+        let images = store.list().expect("images should list");
+        let packaged = images
+            .iter()
+            .find(|image| image.source == ImageSource::Package)
+            .expect("packaged 0.10.0 should exist");
+        store.set_tag(packaged, "edge").expect("tag should be set");
+        assert!(store
+            .remove(packaged)
+            .expect("image should be removed")
+            .is_empty());
+        assert_eq!(resolve(&store, "com.tests.app@edge"), "pull/0.10.0");
+    }
+}
@@ -1,29 +1,53 @@
+mod abi;
+mod artifact;
+mod blobs;
+mod bus;
+mod capabilities;
+mod capsule_config;
+mod component;
+mod dependencies;
+mod determinism;
+mod files;
+mod images;
 mod manifest;
+mod preflight;
+mod registry;
+mod replay;
 mod runtime;
+mod scaffold;
+mod settings;
 mod state;
-
-use crate::manifest::CapsuleManifest;
+mod testing;
+
+use crate::abi::AbiVersion;
+use crate::artifact::WasmSummary;
+use crate::blobs::BlobStore;
+use crate::bus::{Message, Published};
+use crate::dependencies::{Candidate, ResolvedDependency};
+use crate::images::{Image, ImageRef, ImageSource, ImageStore};
+use crate::manifest::{CapsuleManifest, ManifestIssue};
+use crate::preflight::{CapsuleFormat, PreflightIssue};
+use crate::registry::{Registries, RegistryEntry, RegistryIssue};
+use crate::replay::{HostCallMode, ReplayDivergence, TraceFile};
+use crate::runtime::{LogLevel, RunOptions};
+use crate::scaffold::{NewCapsule, Template};
+use crate::settings::{Settings, SettingsFile};
 use crate::state::{
-    append_run_record, ensure_state_dirs, load_run_records, log_file_path, persist_run_records,
-    runs_file_path, write_log_line, RunRecord,
+    append_run_record, ensure_state_dirs, load_active_runs, load_run_records, log_file_path,
+    persist_run_records, register_active_run, runs_file_path, sandbox_dir, write_log_line,
+    ActiveRun, Redactor, RunImage, RunLog, RunRecord, RunTrigger,
 };
-use clap::{Args, Parser, Subcommand};
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const WASM_TARGET_V0: &str = "wasm32-unknown-unknown";
 
-#[derive(Debug, Deserialize)]
-struct RegistryEntry {
-    pub id: String,
-    pub name: String,
-    pub manifest: String,
-}
-
 #[derive(Debug, Parser)]
 #[command(name = "caeles", about = "CAELES CLI")]
 struct Cli {
@@ -35,6 +59,7 @@ struct Cli {
 enum Commands {
     Run(RunArgs),
     List(ListArgs),
+    New(NewArgs),
     Build(BuildArgs),
     Package(PackageArgs),
     Pull(PullArgs),
@@ -44,33 +69,261 @@ enum Commands {
     InspectRun(InspectRunArgs),
     Logs(LogsArgs),
     Rm(RmArgs),
+    Validate(ValidateArgs),
+    Test(TestArgs),
+    Replay(ReplayArgs),
+    CapsuleConfig(CapsuleConfigArgs),
+    Secret(SecretArgs),
+    Publish(PublishArgs),
+    Tag(TagArgs),
+    Rmi(RmiArgs),
+    Image(ImageArgs),
+    Registry(RegistryArgs),
+    Config(ConfigArgs),
 }
 
 #[derive(Debug, Args)]
 struct RunArgs {
-    #[arg(long, conflicts_with = "capsule_id")]
+    /// Capsule id from the registry, or `<id>@<version|range|tag>` to run a local image.
+    ///
+    /// Ids missing from the registry are looked up in the local images.
+    #[arg(conflicts_with_all = ["manifest", "capsule_id", "image"])]
+    capsule: Option<String>,
+    #[arg(long, conflicts_with_all = ["capsule_id", "image"])]
     manifest: Option<PathBuf>,
-    #[arg(long, conflicts_with = "manifest")]
+    #[arg(long, conflicts_with_all = ["manifest", "image"])]
     capsule_id: Option<String>,
-    #[arg(long, default_value = "capsules/registry.json")]
-    registry: PathBuf,
+    /// Local image `<id>[@<version|range|tag>]`, without looking at the registry.
+    #[arg(long)]
+    image: Option<String>,
+    #[arg(long)]
+    registry: Option<PathBuf>,
+    /// JSON value passed to the capsule (typed input of `#[caeles_sdk::main]`).
+    #[arg(long, conflicts_with = "input_file")]
+    input: Option<String>,
+    /// Reads the JSON input from a file.
+    #[arg(long, conflicts_with = "input")]
+    input_file: Option<PathBuf>,
+    /// Minimum level of capsule log records kept for this run; `run.log_level` of the
+    /// settings, or `info`, when omitted.
+    #[arg(long, value_enum)]
+    log_level: Option<LogLevel>,
+    /// Records every host call to a trace file replayable with `caeles replay`.
+    #[arg(long, default_value_t = false)]
+    record: bool,
+    /// Serves clocks and randomness from a seed and makes float results reproducible.
+    #[arg(long, default_value_t = false)]
+    deterministic: bool,
+    /// Seed of the deterministic run; random (and recorded) when omitted.
+    #[arg(long, requires = "deterministic")]
+    seed: Option<u64>,
+    /// Stops the capsule after this many milliseconds; also bounds `host_sleep_ms`.
+    #[arg(long)]
+    timeout_ms: Option<u64>,
+    /// Config value of this run (`key=value`), checked against the manifest `config` schema.
+    #[arg(long = "config", value_name = "KEY=VALUE")]
+    config: Vec<String>,
+    /// JSON object with config values; `--config` wins over it.
+    #[arg(long)]
+    config_file: Option<PathBuf>,
+}
+
+/// Sends a message to the bus; every subscribed `event` capsule runs once.
+#[derive(Debug, Args)]
+struct PublishArgs {
+    /// Topic of the message, e.g. `orders.created`.
+    topic: String,
+    /// Message body, as text.
+    #[arg(long, default_value = "")]
+    payload: String,
+    #[arg(long)]
+    registry: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct ReplayArgs {
+    /// Trace file, or the id of a run made with `--record`.
+    trace: String,
+    /// Registry used to resolve the capsule's dependencies.
+    #[arg(long)]
+    registry: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct CapsuleConfigArgs {
+    #[arg(long, global = true)]
+    registry: Option<PathBuf>,
+    #[command(subcommand)]
+    command: CapsuleConfigCommand,
+}
+
+/// Config store of a capsule, used by every run unless overridden.
+#[derive(Debug, Subcommand)]
+enum CapsuleConfigCommand {
+    Set {
+        capsule: String,
+        #[arg(required = true, value_name = "KEY=VALUE")]
+        values: Vec<String>,
+    },
+    Get {
+        capsule: String,
+        key: String,
+    },
+    List {
+        capsule: String,
+    },
+    Unset {
+        capsule: String,
+        key: String,
+    },
+}
+
+/// Manages the secrets of a capsule.
+///
+/// Values are encrypted with a key kept in `secrets.key`, in the same state directory as
+/// the ciphertext: this keeps them out of manifests and logs, not safe from anyone who can
+/// read that directory.
+#[derive(Debug, Args)]
+struct SecretArgs {
+    #[arg(long, global = true)]
+    registry: Option<PathBuf>,
+    #[command(subcommand)]
+    command: SecretCommand,
+}
+
+/// Encrypted secrets of a capsule; values are never printed.
+#[derive(Debug, Subcommand)]
+enum SecretCommand {
+    Set {
+        capsule: String,
+        name: String,
+        /// Secret value; read from stdin when omitted, so it stays out of the shell history.
+        #[arg(long)]
+        value: Option<String>,
+    },
+    List {
+        capsule: String,
+    },
+    Rm {
+        capsule: String,
+        name: String,
+    },
+}
+
+#[derive(Debug, Args)]
+struct RegistryArgs {
+    #[arg(long, global = true)]
+    registry: Option<PathBuf>,
+    #[command(subcommand)]
+    command: RegistryCommand,
+}
+
+/// Edits and checks the registry file.
+#[derive(Debug, Subcommand)]
+enum RegistryCommand {
+    /// Adds the capsule of a manifest, with the manifest's id and name.
+    Add {
+        manifest: PathBuf,
+    },
+    Remove {
+        id: String,
+    },
+    /// Reports missing or invalid manifests and ids or names that differ from them.
+    Verify {
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Capsules whose id or name contains the text.
+    Search {
+        text: String,
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+/// Reads and edits `caeles.toml` and `~/.config/caeles/config.toml`.
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Effective value of a key.
+    Get { key: String },
+    /// Writes a key to the project file; lists are comma-separated.
+    Set {
+        key: String,
+        value: String,
+        /// Writes to the user file instead.
+        #[arg(long, default_value_t = false)]
+        user: bool,
+    },
+    /// Every key with its effective value and the file that set it.
+    List {
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
 }
 
 #[derive(Debug, Args)]
 struct ListArgs {
-    #[arg(long, default_value = "capsules/registry.json")]
-    registry: PathBuf,
+    #[arg(long)]
+    registry: Option<PathBuf>,
     #[arg(long, default_value_t = false)]
     json: bool,
 }
 
 #[derive(Debug, Args)]
+struct NewArgs {
+    /// Crate name, also the name of its directory.
+    name: String,
+    /// Capsule id, in reverse-DNS form (`com.example.my-capsule`).
+    #[arg(long)]
+    id: String,
+    /// Name shown in the manifest; derived from the crate name by default.
+    #[arg(long)]
+    display_name: Option<String>,
+    /// Built-in template: minimal, http-client, kv-storage or scheduled.
+    #[arg(long, default_value = "minimal", conflicts_with = "template_dir")]
+    template: String,
+    /// Local template directory, with `{{crate}}`, `{{lib}}`, `{{id}}`, `{{name}}` and `{{sdk}}`
+    /// placeholders.
+    #[arg(long)]
+    template_dir: Option<PathBuf>,
+    /// Directory the crate is created in.
+    #[arg(long, default_value = "capsules")]
+    dir: PathBuf,
+    #[arg(long)]
+    registry: Option<PathBuf>,
+    /// Leaves the registry and the Cargo workspace untouched.
+    #[arg(long, default_value_t = false)]
+    no_register: bool,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("wire").args(["copy", "update_entry"])))]
 struct BuildArgs {
     path: PathBuf,
     #[arg(long, default_value_t = false)]
     release: bool,
-    #[arg(long, default_value = WASM_TARGET_V0)]
-    target: String,
+    /// Defaults to the manifest's `target`, or wasm32-unknown-unknown.
+    #[arg(long)]
+    target: Option<String>,
+    /// Capsule manifest checked against the artifact; `manifest.json` next to
+    /// `Cargo.toml` by default.
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// Copies the artifact next to the manifest, under the artifact's file name.
+    #[arg(long, default_value_t = false)]
+    copy: bool,
+    /// Copies like `--copy` and points `entry` at the copy.
+    #[arg(long, default_value_t = false)]
+    update_entry: bool,
+    /// Runs `wasm-opt -Oz` on the copy.
+    #[arg(long, default_value_t = false, requires = "wire")]
+    optimize: bool,
 }
 
 #[derive(Debug, Args)]
@@ -79,31 +332,94 @@ struct PackageArgs {
     manifest: Option<PathBuf>,
     #[arg(long, conflicts_with = "manifest")]
     capsule_id: Option<String>,
-    #[arg(long, default_value = "capsules/registry.json")]
-    registry: PathBuf,
-    #[arg(long, default_value = ".caeles/packages")]
-    output_dir: PathBuf,
+    #[arg(long)]
+    registry: Option<PathBuf>,
+    /// Defaults to `packages/` in the store directory (`.caeles/` or `state_dir`).
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+    /// Defaults to `blobs/` in the store directory.
+    #[arg(long)]
+    blobs_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Args)]
 struct PullArgs {
     capsule_id: String,
-    #[arg(long, default_value = "capsules/registry.json")]
-    registry: PathBuf,
-    #[arg(long, default_value = ".caeles/pulled")]
-    output_dir: PathBuf,
+    #[arg(long)]
+    registry: Option<PathBuf>,
+    /// Defaults to `pulled/` in the store directory (`.caeles/` or `state_dir`).
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+    /// Defaults to `blobs/` in the store directory.
+    #[arg(long)]
+    blobs_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Args)]
 struct ImagesArgs {
-    #[arg(long, default_value = ".caeles/packages")]
-    packages_dir: PathBuf,
-    #[arg(long, default_value = ".caeles/pulled")]
-    pulled_dir: PathBuf,
+    /// The `--*-dir`/`--tags-file` defaults sit in the store directory (`.caeles/` or `state_dir`).
+    #[arg(long)]
+    packages_dir: Option<PathBuf>,
+    #[arg(long)]
+    pulled_dir: Option<PathBuf>,
+    #[arg(long)]
+    tags_file: Option<PathBuf>,
+    /// Only images of this capsule id.
+    #[arg(long)]
+    id: Option<String>,
     #[arg(long, default_value_t = false)]
     json: bool,
 }
 
+/// Points a tag of a capsule at one of its local images.
+#[derive(Debug, Args)]
+struct TagArgs {
+    /// Image as `<id>@<version|range|tag>`; with `--remove`, just the capsule id.
+    image: String,
+    tag: String,
+    #[arg(long, default_value_t = false)]
+    remove: bool,
+}
+
+/// Removes local images of a capsule.
+#[derive(Debug, Args)]
+struct RmiArgs {
+    /// `<id>` for every version, or `<id>@<version|tag>`.
+    image: String,
+    /// Only images created by this command.
+    #[arg(long, value_enum)]
+    source: Option<ImageSource>,
+    /// Shows what would be removed without removing it.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+    /// Blob store the images link to; defaults to `blobs/` in the store directory.
+    #[arg(long)]
+    blobs_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct ImageArgs {
+    #[command(subcommand)]
+    command: ImageCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum ImageCommand {
+    /// Removes images not used by the registry, by recent runs or by running capsules.
+    Prune {
+        #[arg(long)]
+        registry: Option<PathBuf>,
+        /// Runs finished in the last N days keep the images they loaded.
+        #[arg(long, default_value_t = 7)]
+        recent_days: u64,
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Blob store the images link to; defaults to `blobs/` in the store directory.
+        #[arg(long)]
+        blobs_dir: Option<PathBuf>,
+    },
+}
+
 #[derive(Debug, Args)]
 struct PsArgs {
     #[arg(long, default_value_t = 10)]
@@ -121,8 +437,8 @@ struct PsArgs {
 #[derive(Debug, Args)]
 struct InspectArgs {
     capsule_id: String,
-    #[arg(long, default_value = "capsules/registry.json")]
-    registry: PathBuf,
+    #[arg(long)]
+    registry: Option<PathBuf>,
     #[arg(long, default_value_t = false)]
     json: bool,
 }
@@ -156,6 +472,28 @@ struct RmArgs {
     capsule_id: Option<String>,
 }
 
+#[derive(Debug, Args)]
+struct TestArgs {
+    /// Id da cápsula no registry ou caminho do manifest.json.
+    capsule: String,
+    /// Arquivo de testes (padrão: caeles-test.json ao lado do manifest).
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// Grava um relatório JUnit XML neste caminho.
+    #[arg(long)]
+    junit: Option<PathBuf>,
+    #[arg(long)]
+    registry: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct ValidateArgs {
+    /// Caminho do manifest.json da cápsula.
+    manifest: PathBuf,
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
 fn now_unix_ms() -> u128 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -163,51 +501,34 @@ fn now_unix_ms() -> u128 {
         .as_millis()
 }
 
-fn load_registry_entries(registry_path: &Path) -> anyhow::Result<Vec<RegistryEntry>> {
-    let text = fs::read_to_string(registry_path)?;
-    let entries: Vec<RegistryEntry> = serde_json::from_str(&text)?;
-
-    let mut seen_ids = HashSet::new();
-    for entry in &entries {
-        if !seen_ids.insert(&entry.id) {
-            anyhow::bail!("ID duplicado no registry: '{}'", entry.id);
-        }
-    }
-
-    Ok(entries)
-}
-
-fn resolve_manifest_path(registry_path: &Path, manifest: &str) -> PathBuf {
-    let manifest_path = Path::new(manifest);
-
-    if manifest_path.is_absolute() {
-        return manifest_path.to_path_buf();
-    }
-
-    let registry_dir = registry_path.parent().unwrap_or_else(|| Path::new("."));
-    if manifest_path.exists() {
-        return manifest_path.to_path_buf();
-    }
-
-    registry_dir.join(manifest_path)
+/// Registries of a command: `--registry` alone, or the `registries` of the settings.
+fn registries(registry: Option<&PathBuf>) -> anyhow::Result<Registries> {
+    let settings = Settings::load()?;
+    let locations = match registry {
+        Some(path) => vec![path.display().to_string()],
+        None => settings.registries.value,
+    };
+    Ok(Registries::new(
+        locations,
+        &settings.state_dir.value.join("registries"),
+    ))
 }
 
 fn resolve_manifest_with_registry(
     manifest: Option<&PathBuf>,
     capsule_id: Option<&String>,
-    registry: &Path,
+    registries: &Registries,
 ) -> anyhow::Result<(CapsuleManifest, PathBuf)> {
     if let Some(path) = manifest {
         return Ok((CapsuleManifest::load(path)?, path.clone()));
     }
 
     if let Some(id) = capsule_id {
-        let entries = load_registry_entries(registry)?;
-        let entry = entries.iter().find(|e| e.id == *id).ok_or_else(|| {
+        let listed = registries.find(id)?.ok_or_else(|| {
             anyhow::anyhow!(format!("Capsule id '{id}' não encontrado no registry"))
         })?;
 
-        let manifest_path = resolve_manifest_path(registry, &entry.manifest);
+        let manifest_path = listed.manifest_path.clone();
         if !manifest_path.exists() {
             anyhow::bail!(
                 "Manifest da cápsula '{}' não encontrado em '{}'",
@@ -222,23 +543,263 @@ fn resolve_manifest_with_registry(
     anyhow::bail!("Use --manifest <arquivo> ou --capsule-id <id-da-capsula>")
 }
 
-fn resolve_manifest_by_args(args: &RunArgs) -> anyhow::Result<(CapsuleManifest, PathBuf)> {
-    resolve_manifest_with_registry(
-        args.manifest.as_ref(),
-        args.capsule_id.as_ref(),
-        &args.registry,
-    )
+/// Resolves a positional `<capsule>`: an existing manifest file or a registry id.
+fn resolve_capsule_arg(
+    capsule: &str,
+    registries: &Registries,
+) -> anyhow::Result<(CapsuleManifest, PathBuf)> {
+    let path = PathBuf::from(capsule);
+    if path.is_file() {
+        return resolve_manifest_with_registry(Some(&path), None, registries);
+    }
+    resolve_manifest_with_registry(None, Some(&capsule.to_string()), registries)
+}
+
+fn resolve_manifest_by_args(
+    args: &RunArgs,
+    registries: &Registries,
+) -> anyhow::Result<(CapsuleManifest, PathBuf, Option<RunImage>)> {
+    if let Some(reference) = &args.image {
+        return load_image(reference);
+    }
+    let capsule = args.capsule.as_ref().or(args.capsule_id.as_ref());
+    if let Some(reference) = capsule.filter(|c| c.contains('@')) {
+        return load_image(reference);
+    }
+    // A registry that cannot be read is an error, not a reason to fall back to images.
+    let unregistered = match capsule {
+        Some(id) => registries.find(id)?.is_none(),
+        None => false,
+    };
+    if let Some(id) = capsule.filter(|_| unregistered) {
+        let has_image = ImageStore::local()?
+            .list()?
+            .iter()
+            .any(|image| image.capsule_id == *id);
+        if has_image {
+            println!("> '{id}' não está no registry; usando imagem local");
+            return load_image(id);
+        }
+    }
+    let (manifest, manifest_path) =
+        resolve_manifest_with_registry(args.manifest.as_ref(), capsule, registries)?;
+    Ok((manifest, manifest_path, None))
+}
+
+/// Manifest of a local image, checked against its digests.
+fn load_image(reference: &str) -> anyhow::Result<(CapsuleManifest, PathBuf, Option<RunImage>)> {
+    let image = ImageStore::local()?.resolve(&ImageRef::parse(reference)?)?;
+    println!("> Imagem: {} ({})", image.reference(), image.path.display());
+    let run_image = RunImage {
+        reference: image.reference(),
+        source: image.source.as_str().to_string(),
+        digest: image.wasm_digest()?,
+    };
+    Ok((image.manifest()?, image.manifest_path(), Some(run_image)))
+}
+
+/// Reads `--input`/`--input-file` and checks that it is valid JSON.
+fn read_run_input(args: &RunArgs) -> anyhow::Result<Vec<u8>> {
+    let input = match (&args.input, &args.input_file) {
+        (Some(value), _) => value.clone(),
+        (None, Some(path)) => fs::read_to_string(path).map_err(|err| {
+            anyhow::anyhow!("Falha ao ler arquivo de input '{}': {err}", path.display())
+        })?,
+        (None, None) => return Ok(Vec::new()),
+    };
+    serde_json::from_str::<serde_json::Value>(&input)
+        .map_err(|err| anyhow::anyhow!("Input JSON inválido: {err}"))?;
+    Ok(input.into_bytes())
+}
+
+fn wasm_sha256(manifest: &CapsuleManifest) -> anyhow::Result<String> {
+    let path = manifest.wasm_path();
+    let bytes = fs::read(&path)
+        .map_err(|err| anyhow::anyhow!("Falha ao ler wasm '{}': {err}", path.display()))?;
+    Ok(blobs::sha256_hex(&bytes))
+}
+
+/// Manifests that can satisfy `dependencies`: registry entries, then local images.
+fn dependency_candidates(registries: &Registries) -> anyhow::Result<Vec<Candidate>> {
+    let mut candidates = Vec::new();
+    for listed in registries.entries()? {
+        candidates.push(Candidate {
+            manifest_path: listed.manifest_path.clone(),
+            id: listed.entry.id.clone(),
+            source: "registry".to_string(),
+            wasm_entry: None,
+        });
+    }
+    for image in ImageStore::local()?.list()? {
+        candidates.push(Candidate {
+            manifest_path: image.manifest_path(),
+            id: image.capsule_id,
+            source: image.source.as_str().to_string(),
+            wasm_entry: Some(images::IMAGE_WASM.to_string()),
+        });
+    }
+    Ok(candidates)
+}
+
+/// `path` made absolute, so image files loaded from any directory compare equal.
+fn canonical_display(path: &Path) -> String {
+    path.canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string()
+}
+
+fn collect_dependency_manifests(dependencies: &[ResolvedDependency], manifests: &mut Vec<String>) {
+    for dependency in dependencies {
+        manifests.push(dependency.manifest.clone());
+        collect_dependency_manifests(&dependency.dependencies, manifests);
+    }
+}
+
+/// Resolves the dependency tree of `manifest` against the registries and the local images.
+fn resolve_dependencies(
+    manifest: &CapsuleManifest,
+    registries: &Registries,
+) -> anyhow::Result<Vec<ResolvedDependency>> {
+    if manifest.dependencies.is_empty() {
+        return Ok(Vec::new());
+    }
+    dependencies::resolve(manifest, &dependency_candidates(registries)?)
+}
+
+/// Config of a run: manifest defaults, the config store, `--config-file`, then `--config`.
+fn resolve_run_config(
+    args: &RunArgs,
+    manifest: &CapsuleManifest,
+    state_dir: &Path,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let store = capsule_config::load_config_store(state_dir, &manifest.id)?;
+    let file = match &args.config_file {
+        Some(path) => capsule_config::load_config_file(path)?,
+        None => BTreeMap::new(),
+    };
+    let overrides = args
+        .config
+        .iter()
+        .map(|value| capsule_config::parse_assignment(value))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    capsule_config::resolve_config(manifest, &store, &file, &overrides)
 }
 
 fn run_command(args: RunArgs) -> anyhow::Result<()> {
     let state_dir = ensure_state_dirs()?;
-    let (manifest, manifest_path) = resolve_manifest_by_args(&args)?;
+    let registries = registries(args.registry.as_ref())?;
+    let (manifest, manifest_path, image) = resolve_manifest_by_args(&args, &registries)?;
+    let input = read_run_input(&args)?;
+    let seed = args
+        .deterministic
+        .then(|| args.seed.unwrap_or_else(determinism::random_seed));
+    let config = resolve_run_config(&args, &manifest, &state_dir)?;
+    let dependencies = resolve_dependencies(&manifest, &registries)?;
+
+    let run = execute_run(
+        &state_dir,
+        &manifest,
+        &manifest_path,
+        RunRequest {
+            input,
+            log_level: args.log_level,
+            record: args.record,
+            seed,
+            timeout_ms: args.timeout_ms,
+            config,
+            dependencies,
+            trigger: None,
+            image,
+        },
+    )?;
+    let messages = outgoing_messages(&manifest.id, &run, 0);
+    dispatch_messages(&state_dir, &registries, messages);
+    run.result
+}
+
+/// Settings of a run started by `caeles run` or by a bus message.
+///
+/// `log_level` and `timeout_ms` fall back to the `run` defaults of the settings.
+struct RunRequest {
+    input: Vec<u8>,
+    log_level: Option<LogLevel>,
+    record: bool,
+    seed: Option<u64>,
+    timeout_ms: Option<u64>,
+    config: BTreeMap<String, String>,
+    dependencies: Vec<ResolvedDependency>,
+    trigger: Option<RunTrigger>,
+    image: Option<RunImage>,
+}
+
+/// A run that was recorded in `runs.jsonl`, with the messages it published.
+struct FinishedRun {
+    run_id: String,
+    result: anyhow::Result<()>,
+    published: Vec<Published>,
+}
+
+/// `run-<ms>`, with a suffix when another run started in the same millisecond.
+fn new_run_id(state_dir: &Path, started: u128) -> String {
+    let base = format!("run-{started}");
+    let mut run_id = base.clone();
+    let mut suffix = 1;
+    while log_file_path(state_dir, &run_id).exists() {
+        suffix += 1;
+        run_id = format!("{base}-{suffix}");
+    }
+    run_id
+}
+
+/// Runs the capsule, writes its log, trace and run record.
+fn execute_run(
+    state_dir: &Path,
+    manifest: &CapsuleManifest,
+    manifest_path: &Path,
+    request: RunRequest,
+) -> anyhow::Result<FinishedRun> {
+    let RunRequest {
+        input,
+        log_level,
+        record,
+        seed,
+        timeout_ms,
+        config,
+        dependencies,
+        trigger,
+        image,
+    } = request;
+    let settings = Settings::load()?;
+    settings.check_permissions(manifest)?;
+    for dependency in dependencies::load_order(&dependencies) {
+        settings.check_permissions(&dependency.capsule)?;
+    }
+    let log_level = log_level.unwrap_or(settings.log_level.value);
+    let timeout_ms = timeout_ms.or(settings.timeout_ms.value);
+    let input_text = (!input.is_empty()).then(|| String::from_utf8_lossy(&input).into_owned());
+    let secrets = capsule_config::load_secrets(state_dir, manifest)?;
 
     let started = now_unix_ms();
-    let run_id = format!("run-{started}");
+    let run_id = new_run_id(state_dir, started);
+    let mut manifests = vec![manifest_path.display().to_string()];
+    collect_dependency_manifests(&dependencies, &mut manifests);
+    let manifests = manifests
+        .iter()
+        .map(|path| canonical_display(Path::new(path)))
+        .collect();
+    let _active = register_active_run(
+        state_dir,
+        &ActiveRun {
+            run_id: run_id.clone(),
+            pid: std::process::id(),
+            capsule_id: manifest.id.clone(),
+            manifests,
+        },
+    )?;
 
     write_log_line(
-        &state_dir,
+        state_dir,
         &run_id,
         &format!(
             "starting capsule id={} name={} manifest={}",
@@ -248,32 +809,468 @@ fn run_command(args: RunArgs) -> anyhow::Result<()> {
         ),
     )?;
 
-    let result = runtime::run_capsule(&manifest);
+    let options = RunOptions {
+        log: RunLog::new(state_dir, &run_id)
+            .with_redactor(Redactor::new(secrets.values().cloned())),
+        sandbox_dir: sandbox_dir(state_dir, &manifest.id),
+        input,
+        log_level,
+        http_stubs: None,
+        host_calls: if record {
+            HostCallMode::Record
+        } else {
+            HostCallMode::Off
+        },
+        capability_salt: None,
+        seed,
+        timeout: timeout_ms.map(Duration::from_millis),
+        config: config.clone(),
+        secrets,
+        dependencies,
+    };
+    let outcome = runtime::run_capsule(manifest, &options);
+    let result = outcome.result;
+
+    let trace_path = if record {
+        let path = replay::trace_file_path(state_dir, &run_id);
+        TraceFile {
+            version: replay::TRACE_VERSION,
+            run_id: run_id.clone(),
+            capsule_id: manifest.id.clone(),
+            manifest_path: manifest_path.display().to_string(),
+            wasm_sha256: wasm_sha256(manifest)?,
+            capability_salt: outcome.capability_salt,
+            input: input_text,
+            log_level,
+            seed,
+            timeout_ms,
+            config,
+            exit_code: runtime::exit_code(&result),
+            calls: outcome.host_calls,
+        }
+        .save(&path)?;
+        println!("> trace: {}", path.display());
+        Some(path.display().to_string())
+    } else {
+        None
+    };
 
     let finished = now_unix_ms();
     let status = if result.is_ok() { "exited" } else { "failed" };
 
     if let Err(err) = &result {
-        write_log_line(&state_dir, &run_id, &format!("runtime_error: {err}"))?;
+        write_log_line(state_dir, &run_id, &format!("runtime_error: {err}"))?;
     } else {
-        write_log_line(&state_dir, &run_id, "runtime_exit: success")?;
+        write_log_line(state_dir, &run_id, "runtime_exit: success")?;
     }
 
     append_run_record(
-        &state_dir,
+        state_dir,
         &RunRecord {
             run_id: run_id.clone(),
             capsule_id: manifest.id.clone(),
             capsule_name: manifest.name.clone(),
-            manifest_path: manifest_path.display().to_string(),
+            manifest_path: canonical_display(manifest_path),
             status: status.to_string(),
             started_at_unix_ms: started,
             finished_at_unix_ms: finished,
+            trace_path,
+            seed,
+            trigger,
+            image,
         },
     )?;
 
+    if let Some(seed) = seed {
+        println!("> seed: {seed}");
+    }
     println!("> run id: {run_id}");
-    result
+    Ok(FinishedRun {
+        run_id,
+        result,
+        published: outcome.trace.published,
+    })
+}
+
+/// Messages published by a finished run, one link further down the chain.
+fn outgoing_messages(publisher: &str, run: &FinishedRun, depth: u32) -> Vec<Message> {
+    let sent_at = now_unix_ms();
+    run.published
+        .iter()
+        .enumerate()
+        .map(|(index, published)| Message {
+            id: format!("msg-{sent_at}-{index}"),
+            topic: published.topic.clone(),
+            payload: published.payload.clone(),
+            publisher: publisher.to_string(),
+            run_id: Some(run.run_id.clone()),
+            depth,
+        })
+        .collect()
+}
+
+/// `event` capsules of the registries; a missing registry has none.
+fn load_subscribers(registries: &Registries) -> Vec<(CapsuleManifest, PathBuf)> {
+    let entries = match registries.entries() {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!(
+                "> aviso: registry '{}' ignorado: {err}",
+                registries.describe()
+            );
+            return Vec::new();
+        }
+    };
+    entries
+        .iter()
+        .filter_map(|listed| {
+            let path = listed.manifest_path.clone();
+            match CapsuleManifest::load(&path) {
+                Ok(manifest) => Some((manifest, path)),
+                Err(err) => {
+                    eprintln!("> aviso: cápsula '{}' ignorada: {err}", listed.entry.id);
+                    None
+                }
+            }
+        })
+        .filter(|(manifest, _)| manifest.lifecycle.kind == manifest::LifecycleKind::Event)
+        .collect()
+}
+
+/// Runs started by [`dispatch_messages`].
+#[derive(Debug, Default)]
+struct DispatchSummary {
+    runs: usize,
+    failed: usize,
+}
+
+/// Runs each subscribed `event` capsule once per message, with the message as
+/// input. Messages published by those runs are delivered in turn, breadth
+/// first, up to [`bus::MAX_CHAIN_DEPTH`].
+fn dispatch_messages(
+    state_dir: &Path,
+    registries: &Registries,
+    messages: Vec<Message>,
+) -> DispatchSummary {
+    let mut summary = DispatchSummary::default();
+    if messages.is_empty() {
+        return summary;
+    }
+    let subscribers = load_subscribers(registries);
+    let mut queue = VecDeque::from(messages);
+    while let Some(message) = queue.pop_front() {
+        if message.depth >= bus::MAX_CHAIN_DEPTH {
+            println!(
+                "> evento {} ({}) descartado: cadeia passou de {} execuções",
+                message.topic,
+                message.id,
+                bus::MAX_CHAIN_DEPTH
+            );
+            continue;
+        }
+        for (manifest, manifest_path) in subscribers
+            .iter()
+            .filter(|(manifest, _)| manifest.subscribes_to(&message.topic))
+        {
+            println!(
+                "> evento {} ({}) -> {}",
+                message.topic, message.id, manifest.id
+            );
+            summary.runs += 1;
+            let run = capsule_config::load_config_store(state_dir, &manifest.id)
+                .and_then(|store| {
+                    capsule_config::resolve_config(manifest, &store, &BTreeMap::new(), &[])
+                })
+                .and_then(|config| Ok((config, resolve_dependencies(manifest, registries)?)))
+                .and_then(|(config, dependencies)| {
+                    execute_run(
+                        state_dir,
+                        manifest,
+                        manifest_path,
+                        RunRequest {
+                            input: message.input(),
+                            log_level: None,
+                            record: false,
+                            seed: None,
+                            timeout_ms: None,
+                            config,
+                            dependencies,
+                            trigger: Some(RunTrigger {
+                                message_id: message.id.clone(),
+                                topic: message.topic.clone(),
+                                publisher: message.publisher.clone(),
+                                parent_run_id: message.run_id.clone(),
+                            }),
+                            image: None,
+                        },
+                    )
+                });
+            match run {
+                Ok(run) => {
+                    if let Err(err) = &run.result {
+                        println!("> execução de evento {} falhou: {err:#}", run.run_id);
+                        summary.failed += 1;
+                    }
+                    queue.extend(outgoing_messages(&manifest.id, &run, message.depth + 1));
+                }
+                Err(err) => {
+                    println!("> execução de evento de '{}' falhou: {err:#}", manifest.id);
+                    summary.failed += 1;
+                }
+            }
+        }
+    }
+    summary
+}
+
+fn publish_command(args: PublishArgs) -> anyhow::Result<()> {
+    bus::validate_topic(&args.topic, false)
+        .map_err(|message| anyhow::anyhow!("Tópico inválido: {message}"))?;
+    if args.payload.len() > bus::MAX_PAYLOAD_BYTES {
+        anyhow::bail!(
+            "Payload passa do limite de {} bytes",
+            bus::MAX_PAYLOAD_BYTES
+        );
+    }
+    let state_dir = ensure_state_dirs()?;
+    let message = Message {
+        id: format!("msg-{}-0", now_unix_ms()),
+        topic: args.topic,
+        payload: args.payload,
+        publisher: bus::CLI_PUBLISHER.to_string(),
+        run_id: None,
+        depth: 0,
+    };
+    println!("> message id: {}", message.id);
+    let topic = message.topic.clone();
+    let summary = dispatch_messages(
+        &state_dir,
+        &registries(args.registry.as_ref())?,
+        vec![message],
+    );
+    if summary.runs == 0 {
+        println!("Nenhuma cápsula assina '{topic}'.");
+    }
+    if summary.failed > 0 {
+        anyhow::bail!(
+            "{} de {} execuções de evento falharam",
+            summary.failed,
+            summary.runs
+        );
+    }
+    Ok(())
+}
+
+/// Accepts a trace file path or the id of a recorded run.
+fn resolve_trace_arg(trace: &str, state_dir: &Path) -> anyhow::Result<PathBuf> {
+    let path = PathBuf::from(trace);
+    if path.is_file() {
+        return Ok(path);
+    }
+    let recorded = replay::trace_file_path(state_dir, trace);
+    if recorded.is_file() {
+        return Ok(recorded);
+    }
+    anyhow::bail!("Trace '{trace}' não encontrado (nem arquivo nem run gravado com --record)")
+}
+
+fn replay_command(args: ReplayArgs) -> anyhow::Result<()> {
+    let state_dir = ensure_state_dirs()?;
+    let trace_path = resolve_trace_arg(&args.trace, &state_dir)?;
+    let trace = TraceFile::load(&trace_path)?;
+    let manifest = CapsuleManifest::load(Path::new(&trace.manifest_path))?;
+    if manifest.id != trace.capsule_id {
+        anyhow::bail!(
+            "Manifest '{}' agora descreve '{}', mas o trace é de '{}'",
+            trace.manifest_path,
+            manifest.id,
+            trace.capsule_id
+        );
+    }
+    let digest = wasm_sha256(&manifest)?;
+    if digest != trace.wasm_sha256 {
+        anyhow::bail!(
+            "O wasm de '{}' mudou desde a gravação (sha256 {digest}, trace {})",
+            manifest.id,
+            trace.wasm_sha256
+        );
+    }
+
+    let dependencies = resolve_dependencies(&manifest, &registries(args.registry.as_ref())?)?;
+    let secrets = capsule_config::load_secrets(&state_dir, &manifest)?;
+    let run_id = format!("replay-{}", now_unix_ms());
+    println!(
+        "> Reproduzindo {} ({} chamadas de host gravadas)",
+        trace.run_id,
+        trace.calls.len()
+    );
+    let options = RunOptions {
+        log: RunLog::new(&state_dir, &run_id)
+            .with_redactor(Redactor::new(secrets.values().cloned())),
+        sandbox_dir: sandbox_dir(&state_dir, &manifest.id),
+        input: trace.input.clone().unwrap_or_default().into_bytes(),
+        log_level: trace.log_level,
+        http_stubs: None,
+        host_calls: HostCallMode::Replay(trace.calls.clone()),
+        capability_salt: Some(trace.capability_salt),
+        seed: trace.seed,
+        timeout: trace.timeout_ms.map(Duration::from_millis),
+        config: trace.config.clone(),
+        secrets,
+        dependencies,
+    };
+    let result = runtime::run_capsule(&manifest, &options).result;
+
+    if let Err(err) = &result {
+        if let Some(divergence) = err.downcast_ref::<ReplayDivergence>() {
+            anyhow::bail!("Reprodução divergiu do trace: {}", divergence.message);
+        }
+    }
+    let exit_code = runtime::exit_code(&result);
+    if exit_code != trace.exit_code {
+        anyhow::bail!(
+            "Reprodução divergiu do trace: exit code {exit_code}, gravado {}",
+            trace.exit_code
+        );
+    }
+    println!(
+        "Reprodução de {} conferiu com o trace (exit code {exit_code}).",
+        trace.run_id
+    );
+    Ok(())
+}
+
+fn capsule_config_command(args: CapsuleConfigArgs) -> anyhow::Result<()> {
+    let state_dir = ensure_state_dirs()?;
+    match args.command {
+        CapsuleConfigCommand::Set { capsule, values } => {
+            let (manifest, _) =
+                resolve_capsule_arg(&capsule, &registries(args.registry.as_ref())?)?;
+            let mut store = capsule_config::load_config_store(&state_dir, &manifest.id)?;
+            for value in &values {
+                let (key, value) = capsule_config::parse_assignment(value)?;
+                let value = capsule_config::check_config_value(&manifest, &key, &value)?;
+                println!("{key} = {value}");
+                store.insert(key, value);
+            }
+            capsule_config::save_config_store(&state_dir, &manifest.id, &store)?;
+        }
+        CapsuleConfigCommand::Get { capsule, key } => {
+            let (manifest, _) =
+                resolve_capsule_arg(&capsule, &registries(args.registry.as_ref())?)?;
+            let store = capsule_config::load_config_store(&state_dir, &manifest.id)?;
+            let config = capsule_config::resolve_config(&manifest, &store, &BTreeMap::new(), &[])?;
+            if !manifest.config.contains_key(&key) {
+                anyhow::bail!(
+                    "Chave de config '{key}' não declarada no manifest de '{}'",
+                    manifest.id
+                );
+            }
+            match config.get(&key) {
+                Some(value) => println!("{value}"),
+                None => anyhow::bail!("Config '{key}' sem valor para '{}'", manifest.id),
+            }
+        }
+        CapsuleConfigCommand::List { capsule } => {
+            let (manifest, _) =
+                resolve_capsule_arg(&capsule, &registries(args.registry.as_ref())?)?;
+            let store = capsule_config::load_config_store(&state_dir, &manifest.id)?;
+            let config = capsule_config::resolve_config(&manifest, &store, &BTreeMap::new(), &[])?;
+            if manifest.config.is_empty() {
+                println!("Cápsula '{}' não declara config.", manifest.id);
+            }
+            for (key, spec) in &manifest.config {
+                let source = if store.contains_key(key) {
+                    "store"
+                } else {
+                    "padrão"
+                };
+                match config.get(key) {
+                    Some(value) => {
+                        println!("{key} = {value} ({}, {source})", spec.kind.as_str())
+                    }
+                    None => println!("{key} ({}, sem valor)", spec.kind.as_str()),
+                }
+                if let Some(description) = &spec.description {
+                    println!("  {description}");
+                }
+            }
+        }
+        CapsuleConfigCommand::Unset { capsule, key } => {
+            let (manifest, _) =
+                resolve_capsule_arg(&capsule, &registries(args.registry.as_ref())?)?;
+            let mut store = capsule_config::load_config_store(&state_dir, &manifest.id)?;
+            if store.remove(&key).is_none() {
+                anyhow::bail!("Config '{key}' não está no store de '{}'", manifest.id);
+            }
+            capsule_config::save_config_store(&state_dir, &manifest.id, &store)?;
+            println!("Config '{key}' removida de '{}'.", manifest.id);
+        }
+    }
+    Ok(())
+}
+
+fn secret_command(args: SecretArgs) -> anyhow::Result<()> {
+    let state_dir = ensure_state_dirs()?;
+    match args.command {
+        SecretCommand::Set {
+            capsule,
+            name,
+            value,
+        } => {
+            let (manifest, _) =
+                resolve_capsule_arg(&capsule, &registries(args.registry.as_ref())?)?;
+            if !manifest.secrets.contains(&name) {
+                anyhow::bail!(
+                    "Segredo '{name}' não declarado no manifest de '{}'",
+                    manifest.id
+                );
+            }
+            let value = match value {
+                Some(value) => value,
+                None => {
+                    let mut value = String::new();
+                    std::io::stdin().read_to_string(&mut value)?;
+                    value.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            if value.is_empty() {
+                anyhow::bail!("Valor do segredo '{name}' está vazio");
+            }
+            let mut store = capsule_config::SecretStore::open(&state_dir, &manifest.id)?;
+            store.set(&name, &value)?;
+            store.save()?;
+            println!("Segredo '{name}' salvo para '{}'.", manifest.id);
+        }
+        SecretCommand::List { capsule } => {
+            let (manifest, _) =
+                resolve_capsule_arg(&capsule, &registries(args.registry.as_ref())?)?;
+            let store = capsule_config::SecretStore::open(&state_dir, &manifest.id)?;
+            let stored: HashSet<&String> = store.names().collect();
+            if manifest.secrets.is_empty() {
+                println!("Cápsula '{}' não declara segredos.", manifest.id);
+            }
+            for name in &manifest.secrets {
+                let status = if stored.contains(name) {
+                    "definido"
+                } else {
+                    "não definido"
+                };
+                println!("{name} ({status})");
+            }
+        }
+        SecretCommand::Rm { capsule, name } => {
+            let (manifest, _) =
+                resolve_capsule_arg(&capsule, &registries(args.registry.as_ref())?)?;
+            let mut store = capsule_config::SecretStore::open(&state_dir, &manifest.id)?;
+            if !store.remove(&name) {
+                anyhow::bail!("Segredo '{name}' não está salvo para '{}'", manifest.id);
+            }
+            store.save()?;
+            println!("Segredo '{name}' removido de '{}'.", manifest.id);
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Serialize)]
@@ -284,40 +1281,40 @@ struct ListViewItem {
     manifest_exists: bool,
 }
 
+fn list_items<'a>(listed: impl IntoIterator<Item = &'a registry::Listed>) -> Vec<ListViewItem> {
+    listed
+        .into_iter()
+        .map(|listed| ListViewItem {
+            id: listed.entry.id.clone(),
+            name: listed.entry.name.clone(),
+            manifest: listed.manifest_path.display().to_string(),
+            manifest_exists: listed.manifest_path.exists(),
+        })
+        .collect()
+}
+
 fn list_command(args: ListArgs) -> anyhow::Result<()> {
-    let entries = load_registry_entries(&args.registry)?;
+    let registries = registries(args.registry.as_ref())?;
+    let items = list_items(registries.entries()?);
 
-    if entries.is_empty() {
+    if items.is_empty() {
         if args.json {
             println!("[]");
         } else {
             println!(
                 "Nenhuma cápsula encontrada no registry: {}",
-                args.registry.display()
+                registries.describe()
             );
         }
         return Ok(());
     }
 
-    let items: Vec<ListViewItem> = entries
-        .into_iter()
-        .map(|entry| {
-            let manifest_path = resolve_manifest_path(&args.registry, &entry.manifest);
-            ListViewItem {
-                id: entry.id,
-                name: entry.name,
-                manifest: manifest_path.display().to_string(),
-                manifest_exists: manifest_path.exists(),
-            }
-        })
-        .collect();
-
     if args.json {
         println!("{}", serde_json::to_string_pretty(&items)?);
         return Ok(());
     }
 
-    println!("Cápsulas em {}:", args.registry.display());
+    println!("Cápsulas em {}:", registries.describe());
     for item in items {
         println!("- {} ({})", item.id, item.name);
         if item.manifest_exists {
@@ -330,97 +1327,434 @@ fn list_command(args: ListArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
+#[derive(Debug, Serialize)]
+struct RegistryVerifyView {
+    registry: String,
+    entries: usize,
+    valid: bool,
+    issues: Vec<RegistryIssue>,
+}
+
+/// Registry file edited by `registry add/remove`: `--registry` or the first
+/// local registry of the configuration.
+fn editable_registry(registry: Option<PathBuf>) -> anyhow::Result<PathBuf> {
+    if let Some(path) = registry {
+        return Ok(path);
+    }
+    Settings::load()?
+        .registries
+        .value
+        .into_iter()
+        .find(|location| !registry::is_url(location))
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow::anyhow!("Nenhum registry local configurado; use --registry"))
+}
+
+fn registry_command(args: RegistryArgs) -> anyhow::Result<()> {
+    match args.command {
+        RegistryCommand::Add { manifest } => {
+            let registry = editable_registry(args.registry)?;
+            let entry = registry::add(&registry, &manifest)?;
+            println!(
+                "Cápsula '{}' ({}) adicionada a {}.",
+                entry.id,
+                entry.name,
+                registry.display()
+            );
+        }
+        RegistryCommand::Remove { id } => {
+            let registry = editable_registry(args.registry)?;
+            registry::remove(&registry, &id)?;
+            println!("Cápsula '{id}' removida de {}.", registry.display());
+        }
+        RegistryCommand::Verify { json } => {
+            let registries = registries(args.registry.as_ref())?;
+            let mut view = RegistryVerifyView {
+                registry: registries.describe(),
+                entries: 0,
+                valid: true,
+                issues: Vec::new(),
+            };
+            for location in registries.locations() {
+                let (entries, mut issues) = registry::verify(&registries.local_file(location)?)?;
+                for issue in &mut issues {
+                    issue.registry = location.clone();
+                }
+                view.entries += entries;
+                view.issues.extend(issues);
+            }
+            view.valid = view.issues.is_empty();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&view)?);
+            } else if view.valid {
+                println!(
+                    "Registry válido: {} ({} cápsula(s))",
+                    view.registry, view.entries
+                );
+            } else {
+                println!(
+                    "Registry inválido: {} ({} problema(s))",
+                    view.registry,
+                    view.issues.len()
+                );
+                for issue in &view.issues {
+                    if registries.locations().len() > 1 {
+                        println!("- {} [{}]: {}", issue.id, issue.registry, issue.message);
+                    } else {
+                        println!("- {}: {}", issue.id, issue.message);
+                    }
+                }
+            }
+            if !view.valid {
+                anyhow::bail!("Verificação falhou para '{}'", view.registry);
+            }
+        }
+        RegistryCommand::Search { text, json } => {
+            let registries = registries(args.registry.as_ref())?;
+            let listed = registries.entries()?;
+            let entries: Vec<RegistryEntry> = listed.iter().map(|l| l.entry.clone()).collect();
+            let found: Vec<&registry::Listed> = registry::search(&entries, &text)
+                .into_iter()
+                .filter_map(|entry| listed.iter().find(|l| l.entry.id == entry.id))
+                .collect();
+            let items = list_items(found);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&items)?);
+            } else if items.is_empty() {
+                println!("Nenhuma cápsula corresponde a '{text}'.");
+            } else {
+                for item in items {
+                    println!("- {} ({})", item.id, item.name);
+                    println!("  manifest: {}", item.manifest);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct ConfigViewItem {
+    key: &'static str,
+    value: String,
+    origin: String,
+}
+
+fn config_command(args: ConfigArgs) -> anyhow::Result<()> {
+    match args.command {
+        ConfigCommand::Get { key } => {
+            let settings = Settings::load()?;
+            println!("{}", settings.get(&key)?.0);
+        }
+        ConfigCommand::Set { key, value, user } => {
+            let path = if user {
+                settings::user_file().ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Não foi possível localizar o diretório de configuração do usuário"
+                    )
+                })?
+            } else {
+                settings::project_file().unwrap_or_else(|| PathBuf::from(settings::PROJECT_FILE))
+            };
+            let mut file = SettingsFile::load(&path)?;
+            file.set(&key, &value)?;
+            file.save(&path)?;
+            println!("{key} = {value} ({})", path.display());
+        }
+        ConfigCommand::List { json } => {
+            let settings = Settings::load()?;
+            let items = settings::KEYS
+                .into_iter()
+                .map(|key| {
+                    let (value, origin) = settings.get(key)?;
+                    Ok(ConfigViewItem {
+                        key,
+                        value,
+                        origin: origin.describe(),
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&items)?);
+                return Ok(());
+            }
+            for item in items {
+                println!("{} = {} ({})", item.key, item.value, item.origin);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn new_command(args: NewArgs) -> anyhow::Result<()> {
+    let template = match args.template_dir {
+        Some(dir) => Template::Dir(dir),
+        None => Template::builtin(&args.template)?,
+    };
+    let capsule = NewCapsule {
+        display_name: args
+            .display_name
+            .unwrap_or_else(|| scaffold::display_name(&args.name)),
+        dir: args.dir.join(&args.name),
+        name: args.name,
+        id: args.id,
+    };
+    let manifest_path = capsule.generate(&template)?;
+    println!(
+        "> Cápsula '{}' ({}) criada em {} (template {})",
+        capsule.id,
+        capsule.display_name,
+        capsule.dir.display(),
+        template.describe()
+    );
+
+    if !args.no_register {
+        if let Some(workspace) = scaffold::add_to_workspace(&capsule.dir)? {
+            println!(
+                "> Adicionada aos membros do workspace {}",
+                workspace.display()
+            );
+        }
+        let registry = editable_registry(args.registry)?;
+        registry::add(&registry, &manifest_path)?;
+        println!("> Registrada em {}", registry.display());
+    }
+
+    println!("Próximos passos:");
+    println!("  caeles build {} --copy", capsule.dir.display());
+    println!("  caeles run --manifest {}", manifest_path.display());
+    Ok(())
+}
+
 fn build_command(args: BuildArgs) -> anyhow::Result<()> {
-    let manifest_path = if args.path.is_dir() {
-        args.path.join("Cargo.toml")
+    let (crate_dir, manifest_path) = if args.path.is_dir() {
+        (args.path.clone(), args.path.join("Cargo.toml"))
     } else {
-        args.path.clone()
+        let dir = args.path.parent().unwrap_or_else(|| Path::new("."));
+        (dir.to_path_buf(), args.path.clone())
     };
 
     if !manifest_path.exists() {
         anyhow::bail!("Cargo.toml não encontrado em '{}'", manifest_path.display());
     }
 
-    let mut cmd = Command::new("cargo");
+    let capsule_manifest_path = args.manifest.clone().or_else(|| {
+        let path = crate_dir.join("manifest.json");
+        path.is_file().then_some(path)
+    });
+    let wire = args.copy || args.update_entry;
+    let capsule_manifest = match &capsule_manifest_path {
+        Some(path) => Some(CapsuleManifest::load(path)?),
+        None if wire => anyhow::bail!(
+            "Nenhum manifest.json em '{}'; informe --manifest",
+            crate_dir.display()
+        ),
+        None => None,
+    };
+    let target = args.target.clone().unwrap_or_else(|| {
+        capsule_manifest
+            .as_ref()
+            .map_or(WASM_TARGET_V0, |m| m.target.as_str())
+            .to_string()
+    });
+
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut cmd = Command::new(cargo);
     cmd.arg("build")
         .arg("--manifest-path")
         .arg(&manifest_path)
         .arg("--target")
-        .arg(&args.target);
+        .arg(&target)
+        .arg("--message-format=json-render-diagnostics")
+        .stdout(Stdio::piped());
 
     if args.release {
         cmd.arg("--release");
     }
 
     println!("> Executando: {:?}", cmd);
-    let status = cmd.status().map_err(|e| {
+    let mut child = cmd.spawn().map_err(|e| {
         anyhow::anyhow!(
             "Não foi possível executar o comando cargo. Instale Rust/Cargo para usar `caeles build`: {e}"
         )
     })?;
-    if !status.success() {
+    // Read every message before waiting, so cargo never writes into a closed pipe.
+    let mut messages = String::new();
+    child
+        .stdout
+        .take()
+        .expect("cargo stdout should be piped")
+        .read_to_string(&mut messages)?;
+    if !child.wait()?.success() {
         anyhow::bail!("Falha ao compilar cápsula com cargo build");
     }
 
     println!("> Build concluído: {}", manifest_path.display());
-    Ok(())
-}
-
-fn package_command(args: PackageArgs) -> anyhow::Result<()> {
-    let (manifest, manifest_path) = resolve_manifest_with_registry(
-        args.manifest.as_ref(),
-        args.capsule_id.as_ref(),
-        &args.registry,
-    )?;
+    let artifact = artifact::find_wasm(messages.as_bytes(), &manifest_path)?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "O pacote '{}' não produziu um .wasm para {target}; verifique o target e `crate-type = [\"cdylib\"]` em {}",
+            artifact::package_name(&manifest_path).unwrap_or_else(|| "?".to_string()),
+            manifest_path.display()
+        )
+    })?;
+    let bytes = fs::read(&artifact)
+        .map_err(|e| anyhow::anyhow!("Falha ao ler artefato '{}': {e}", artifact.display()))?;
+    let summary = WasmSummary::read(&bytes)
+        .map_err(|e| anyhow::anyhow!("Artefato inválido '{}': {e:#}", artifact.display()))?;
+    println!(
+        "> Artefato: {} ({} bytes{})",
+        artifact.display(),
+        summary.size,
+        if summary.component {
+            ", componente"
+        } else {
+            ""
+        }
+    );
+    let exports: Vec<String> = summary
+        .exports
+        .iter()
+        .map(|(name, kind)| format!("{name} ({kind})"))
+        .collect();
+    println!("> Exports: {}", exports.join(", "));
+    if summary.imports > 0 {
+        println!(
+            "> Imports: {} de {}",
+            summary.imports,
+            summary.import_modules.join(", ")
+        );
+    }
 
-    let wasm_path = manifest.wasm_path();
-    if !wasm_path.exists() {
-        anyhow::bail!("Arquivo wasm não encontrado em '{}'", wasm_path.display());
+    let (Some(mut manifest), Some(capsule_manifest_path)) =
+        (capsule_manifest, capsule_manifest_path)
+    else {
+        return Ok(());
+    };
+    let artifact_name = artifact
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if args.update_entry && manifest.entry != artifact_name {
+        let text = fs::read_to_string(&capsule_manifest_path)?;
+        fs::write(
+            &capsule_manifest_path,
+            artifact::set_manifest_entry(&text, &artifact_name)?,
+        )?;
+        println!(
+            "> entry de {} atualizado: {} -> {artifact_name}",
+            capsule_manifest_path.display(),
+            manifest.entry
+        );
+        manifest = CapsuleManifest::load(&capsule_manifest_path)?;
     }
 
-    let pkg_dir = args.output_dir.join(&manifest.id).join(&manifest.version);
-    fs::create_dir_all(&pkg_dir)?;
+    let entry_path = manifest.wasm_path();
+    let checked = if wire {
+        // Never the entry itself: it may point into another profile's target directory.
+        let copy_path = capsule_manifest_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(&artifact_name);
+        fs::copy(&artifact, &copy_path)?;
+        println!("> Copiado para {}", copy_path.display());
+        if args.optimize {
+            let (before, after) = artifact::optimize(&copy_path)?;
+            println!("> wasm-opt: {before} -> {after} bytes");
+        }
+        if manifest.entry != artifact_name {
+            println!(
+                "> aviso: entry '{}' não é a cópia '{artifact_name}'; use --update-entry",
+                manifest.entry
+            );
+        }
+        copy_path
+    } else {
+        if Path::new(&manifest.entry).file_name() != artifact.file_name() {
+            println!(
+                "> aviso: entry '{}' não é o artefato '{artifact_name}'; use --update-entry",
+                manifest.entry
+            );
+        } else {
+            match fs::read(&entry_path) {
+                Ok(entry) if entry == bytes => {
+                    println!("> entry '{}' está atualizado", manifest.entry)
+                }
+                Ok(_) => println!(
+                    "> aviso: entry '{}' difere do artefato; use --copy para atualizá-lo",
+                    manifest.entry
+                ),
+                Err(_) => println!(
+                    "> aviso: entry '{}' não existe; use --copy para criá-lo",
+                    manifest.entry
+                ),
+            }
+        }
+        artifact
+    };
+
+    let preflight = preflight::analyze_file(&checked, &manifest)?;
+    for issue in preflight.issues.iter().filter(|i| !i.is_error()) {
+        println!("> aviso: {issue}");
+    }
+    preflight::ensure_no_errors(&preflight.issues)?;
+    Ok(())
+}
 
-    let manifest_out = pkg_dir.join("manifest.json");
-    let wasm_out = pkg_dir.join("capsule.wasm");
-    fs::copy(&manifest_path, &manifest_out)?;
-    fs::copy(&wasm_path, &wasm_out)?;
+fn package_command(args: PackageArgs) -> anyhow::Result<()> {
+    let (manifest, manifest_path) = resolve_manifest_with_registry(
+        args.manifest.as_ref(),
+        args.capsule_id.as_ref(),
+        &registries(args.registry.as_ref())?,
+    )?;
 
-    let metadata = serde_json::json!({
-        "id": manifest.id,
-        "name": manifest.name,
-        "version": manifest.version,
-        "source_manifest": manifest_path.display().to_string(),
-        "packaged_at_unix_ms": now_unix_ms()
-    });
-    fs::write(
-        pkg_dir.join("package.json"),
-        serde_json::to_string_pretty(&metadata)?,
+    let store_dir = Settings::load()?.store_dir();
+    let (pkg_dir, descriptor) = images::create_image(
+        &args
+            .output_dir
+            .unwrap_or_else(|| store_dir.join(images::PACKAGES_DIR)),
+        &BlobStore::new(
+            &args
+                .blobs_dir
+                .unwrap_or_else(|| store_dir.join(blobs::BLOBS_DIR)),
+        ),
+        &manifest,
+        &manifest_path,
     )?;
 
-    println!("> Package criado em {}", pkg_dir.display());
+    println!(
+        "> Package criado em {} ({})",
+        pkg_dir.display(),
+        descriptor.wasm.digest
+    );
     Ok(())
 }
 
 fn pull_command(args: PullArgs) -> anyhow::Result<()> {
-    let (manifest, manifest_path) =
-        resolve_manifest_with_registry(None, Some(&args.capsule_id), &args.registry)?;
-
-    let wasm_path = manifest.wasm_path();
-    if !wasm_path.exists() {
-        anyhow::bail!("Arquivo wasm não encontrado em '{}'", wasm_path.display());
-    }
-
-    let pull_dir = args.output_dir.join(&manifest.id).join(&manifest.version);
-    fs::create_dir_all(&pull_dir)?;
+    let (manifest, manifest_path) = resolve_manifest_with_registry(
+        None,
+        Some(&args.capsule_id),
+        &registries(args.registry.as_ref())?,
+    )?;
 
-    fs::copy(&manifest_path, pull_dir.join("manifest.json"))?;
-    fs::copy(&wasm_path, pull_dir.join("capsule.wasm"))?;
+    let store_dir = Settings::load()?.store_dir();
+    let (pull_dir, descriptor) = images::create_image(
+        &args
+            .output_dir
+            .unwrap_or_else(|| store_dir.join(images::PULLED_DIR)),
+        &BlobStore::new(
+            &args
+                .blobs_dir
+                .unwrap_or_else(|| store_dir.join(blobs::BLOBS_DIR)),
+        ),
+        &manifest,
+        &manifest_path,
+    )?;
 
     println!(
-        "> Capsule '{}' disponível em {}",
+        "> Capsule '{}' disponível em {} ({})",
         manifest.id,
-        pull_dir.display()
+        pull_dir.display(),
+        descriptor.wasm.digest
     );
     Ok(())
 }
@@ -430,69 +1764,259 @@ struct ImageView {
     source: String,
     capsule_id: String,
     version: String,
+    tags: Vec<String>,
+    /// Digest of the wasm; `None` for images without `image.json`.
+    digest: Option<String>,
+    /// Bytes of the image blobs.
+    size: Option<u64>,
     path: String,
 }
 
-fn collect_images(root: &Path, source: &str) -> anyhow::Result<Vec<ImageView>> {
-    let mut images = Vec::new();
-    if !root.exists() {
-        return Ok(images);
+fn images_command(args: ImagesArgs) -> anyhow::Result<()> {
+    let store_dir = Settings::load()?.store_dir();
+    let store = ImageStore::new(
+        &args
+            .packages_dir
+            .unwrap_or_else(|| store_dir.join(images::PACKAGES_DIR)),
+        &args
+            .pulled_dir
+            .unwrap_or_else(|| store_dir.join(images::PULLED_DIR)),
+        &args
+            .tags_file
+            .unwrap_or_else(|| store_dir.join(images::TAGS_FILE)),
+    );
+    let all = store.list()?;
+    let tags = store.tags()?;
+    let images: Vec<ImageView> = all
+        .iter()
+        .filter(|image| args.id.as_ref().is_none_or(|id| image.capsule_id == *id))
+        .map(|image| ImageView {
+            source: image.source.as_str().to_string(),
+            capsule_id: image.capsule_id.clone(),
+            version: image.version.to_string(),
+            tags: ImageStore::tags_of(image, &all, &tags),
+            digest: image.descriptor.as_ref().map(|d| d.wasm.digest.clone()),
+            size: image.descriptor.as_ref().map(|d| d.size()),
+            path: image.path.display().to_string(),
+        })
+        .collect();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&images)?);
+        return Ok(());
+    }
+
+    if images.is_empty() {
+        println!("Nenhuma imagem local encontrada.");
+        return Ok(());
+    }
+
+    println!("SOURCE | CAPSULE | VERSION | TAGS | DIGEST | SIZE | PATH");
+    for image in images {
+        let digest = image.digest.as_deref().map_or("-", blobs::short_digest);
+        let size = image
+            .size
+            .map_or_else(|| "-".to_string(), |size| size.to_string());
+        println!(
+            "{} | {} | {} | {} | {} | {} | {}",
+            image.source,
+            image.capsule_id,
+            image.version,
+            image.tags.join(","),
+            digest,
+            size,
+            image.path
+        );
     }
 
-    for id_entry in fs::read_dir(root)? {
-        let id_entry = id_entry?;
-        if !id_entry.file_type()?.is_dir() {
+    Ok(())
+}
+
+/// Running capsules that loaded a file of `image`.
+fn image_users(image: &Image, active: &[ActiveRun]) -> Vec<String> {
+    active
+        .iter()
+        .filter(|run| {
+            run.manifests
+                .iter()
+                .any(|manifest| image.contains(Path::new(manifest)))
+        })
+        .map(|run| format!("{} ({})", run.run_id, run.capsule_id))
+        .collect()
+}
+
+/// Removes `images`, then the blobs of `blobs` no remaining image links to.
+fn remove_images(
+    store: &ImageStore,
+    blobs: &BlobStore,
+    images: &[Image],
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let verb = if dry_run { "Removeria" } else { "Removida" };
+    for image in images {
+        println!("{verb}: {} {}", image.source.as_str(), image.reference());
+        if dry_run {
             continue;
         }
-        let capsule_id = id_entry.file_name().to_string_lossy().to_string();
+        for tag in store.remove(image)? {
+            println!("  tag '{tag}' removida");
+        }
+    }
 
-        for version_entry in fs::read_dir(id_entry.path())? {
-            let version_entry = version_entry?;
-            if !version_entry.file_type()?.is_dir() {
-                continue;
-            }
-            let version = version_entry.file_name().to_string_lossy().to_string();
-            images.push(ImageView {
-                source: source.to_string(),
-                capsule_id: capsule_id.clone(),
-                version,
-                path: version_entry.path().display().to_string(),
-            });
+    let remaining: Vec<Image> = store
+        .list()?
+        .into_iter()
+        .filter(|image| !dry_run || !images.iter().any(|removed| removed.path == image.path))
+        .collect();
+    let unused = images::unreferenced_blobs(&remaining, blobs)?;
+    let freed: u64 = unused.iter().map(|blob| blob.size).sum();
+    if !dry_run {
+        for blob in &unused {
+            blobs.remove(blob)?;
         }
     }
+    println!(
+        "{} imagem(ns), {} blob(s), {freed} bytes{}.",
+        images.len(),
+        unused.len(),
+        if dry_run {
+            " seriam liberados"
+        } else {
+            " liberados"
+        }
+    );
+    Ok(())
+}
 
-    Ok(images)
+/// `--blobs-dir`, or the blob store of the settings.
+fn blobs_store(blobs_dir: Option<PathBuf>) -> anyhow::Result<BlobStore> {
+    match blobs_dir {
+        Some(dir) => Ok(BlobStore::new(&dir)),
+        None => BlobStore::local(),
+    }
 }
 
-fn images_command(args: ImagesArgs) -> anyhow::Result<()> {
-    let mut images = collect_images(&args.packages_dir, "package")?;
-    images.extend(collect_images(&args.pulled_dir, "pull")?);
-
-    images.sort_by(|a, b| {
-        a.capsule_id
-            .cmp(&b.capsule_id)
-            .then_with(|| a.version.cmp(&b.version))
-            .then_with(|| a.source.cmp(&b.source))
-    });
+fn rmi_command(args: RmiArgs) -> anyhow::Result<()> {
+    let store = ImageStore::local()?;
+    let reference = ImageRef::parse(&args.image)?;
+    let version = match &reference.selector {
+        Some(_) => Some(store.resolve(&reference)?.version),
+        None => None,
+    };
+    let images: Vec<Image> = store
+        .list()?
+        .into_iter()
+        .filter(|image| image.capsule_id == reference.capsule_id)
+        .filter(|image| version.as_ref().is_none_or(|v| image.version == *v))
+        .filter(|image| args.source.is_none_or(|source| image.source == source))
+        .collect();
+    if images.is_empty() {
+        anyhow::bail!("Nenhuma imagem local corresponde a '{}'", args.image);
+    }
 
-    if args.json {
-        println!("{}", serde_json::to_string_pretty(&images)?);
-        return Ok(());
+    let active = load_active_runs(&ensure_state_dirs()?)?;
+    for image in &images {
+        let users = image_users(image, &active);
+        if !users.is_empty() {
+            anyhow::bail!(
+                "Imagem {} em uso por execução em andamento: {}",
+                image.reference(),
+                users.join(", ")
+            );
+        }
     }
 
-    if images.is_empty() {
-        println!("Nenhuma imagem local encontrada.");
+    remove_images(&store, &blobs_store(args.blobs_dir)?, &images, args.dry_run)
+}
+
+fn image_command(args: ImageArgs) -> anyhow::Result<()> {
+    match args.command {
+        ImageCommand::Prune {
+            registry,
+            recent_days,
+            dry_run,
+            blobs_dir,
+        } => prune_images(
+            registry.as_ref(),
+            &blobs_store(blobs_dir)?,
+            recent_days,
+            dry_run,
+        ),
+    }
+}
+
+/// Images kept by `caeles image prune`: the registry version of each capsule,
+/// images its dependencies resolve to, and images loaded by recent or running runs.
+fn prune_images(
+    registry: Option<&PathBuf>,
+    blobs: &BlobStore,
+    recent_days: u64,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let store = ImageStore::local()?;
+    let state_dir = ensure_state_dirs()?;
+    let registries = registries(registry)?;
+
+    let mut used: Vec<String> = Vec::new();
+    let mut registry_versions = HashSet::new();
+    for listed in registries.entries()? {
+        used.push(listed.manifest_path.display().to_string());
+        let Ok(manifest) = CapsuleManifest::load(&listed.manifest_path) else {
+            continue;
+        };
+        registry_versions.insert((manifest.id.clone(), manifest.version.clone()));
+        if let Ok(dependencies) = resolve_dependencies(&manifest, &registries) {
+            collect_dependency_manifests(&dependencies, &mut used);
+        }
+    }
+
+    let since = now_unix_ms().saturating_sub(u128::from(recent_days) * 24 * 60 * 60 * 1000);
+    used.extend(
+        load_run_records(&state_dir)?
+            .into_iter()
+            .filter(|run| run.finished_at_unix_ms >= since)
+            .map(|run| run.manifest_path),
+    );
+    let active = load_active_runs(&state_dir)?;
+    used.extend(active.iter().flat_map(|run| run.manifests.iter().cloned()));
+
+    let unused: Vec<Image> = store
+        .list()?
+        .into_iter()
+        .filter(|image| {
+            !registry_versions.contains(&(image.capsule_id.clone(), image.version.to_string()))
+                && !used.iter().any(|path| image.contains(Path::new(path)))
+        })
+        .collect();
+    if unused.is_empty() {
+        println!("Nenhuma imagem sem uso.");
         return Ok(());
     }
+    remove_images(&store, blobs, &unused, dry_run)
+}
 
-    println!("SOURCE | CAPSULE | VERSION | PATH");
-    for image in images {
-        println!(
-            "{} | {} | {} | {}",
-            image.source, image.capsule_id, image.version, image.path
-        );
+fn tag_command(args: TagArgs) -> anyhow::Result<()> {
+    let store = ImageStore::local()?;
+    if args.remove {
+        let capsule_id = ImageRef::parse(&args.image)?.capsule_id;
+        return match store.remove_tag(&capsule_id, &args.tag)? {
+            Some(version) => {
+                println!("Tag '{}' removida de {capsule_id}@{version}.", args.tag);
+                Ok(())
+            }
+            None => anyhow::bail!("Tag '{}' não existe para '{capsule_id}'", args.tag),
+        };
     }
 
+    let image = store.resolve(&ImageRef::parse(&args.image)?)?;
+    match store.set_tag(&image, &args.tag)? {
+        Some(previous) if previous != image.version.to_string() => println!(
+            "Tag '{}' movida de {previous} para {}.",
+            args.tag,
+            image.reference()
+        ),
+        _ => println!("Tag '{}' -> {}.", args.tag, image.reference()),
+    }
     Ok(())
 }
 
@@ -558,6 +2082,23 @@ struct InspectRunViewItem {
     manifest: String,
 }
 
+#[derive(Debug, Serialize)]
+struct AbiMatrixRow {
+    version: AbiVersion,
+    module: &'static str,
+    functions: Vec<&'static str>,
+    used_by_capsule: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AbiCompatView {
+    format: Option<CapsuleFormat>,
+    capsule_abi: Option<AbiVersion>,
+    compatible: bool,
+    problems: Vec<String>,
+    runtime_supported: Vec<AbiMatrixRow>,
+}
+
 #[derive(Debug, Serialize)]
 struct InspectView {
     id: String,
@@ -565,17 +2106,104 @@ struct InspectView {
     registry: String,
     manifest: String,
     manifest_exists: bool,
+    abi: AbiCompatView,
+    /// Resolved `dependencies` of the manifest, as a tree.
+    dependencies: Vec<ResolvedDependency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dependency_error: Option<String>,
+    disk: DiskUsageView,
     last_runs: Vec<InspectRunViewItem>,
 }
 
-fn inspect_command(args: InspectArgs) -> anyhow::Result<()> {
-    let entries = load_registry_entries(&args.registry)?;
-    let entry = entries
+/// Private directory of a capsule and how much of its quota is used.
+#[derive(Debug, Serialize)]
+struct DiskUsageView {
+    path: String,
+    used_bytes: u64,
+    /// `None` when the manifest does not grant `permissions.filesystem`.
+    quota_bytes: Option<u64>,
+}
+
+fn disk_usage_view(state_dir: &Path, capsule_id: &str, manifest_path: &Path) -> DiskUsageView {
+    let dir = sandbox_dir(state_dir, capsule_id);
+    let quota_bytes = CapsuleManifest::load(manifest_path)
+        .ok()
+        .filter(|manifest| manifest.permissions.filesystem)
+        .map(|manifest| manifest.filesystem().quota_bytes);
+    DiskUsageView {
+        path: dir.display().to_string(),
+        used_bytes: files::dir_size(&dir),
+        quota_bytes,
+    }
+}
+
+/// Builds the host ABI compatibility matrix for the capsule behind `manifest_path`.
+fn abi_compat_view(manifest_path: &Path) -> AbiCompatView {
+    let mut format = None;
+    let mut capsule_abi = None;
+    let mut problems = Vec::new();
+
+    match CapsuleManifest::load(manifest_path) {
+        Ok(manifest) => match preflight::analyze_file(&manifest.wasm_path(), &manifest) {
+            Ok(preflight) => {
+                problems.extend(
+                    preflight
+                        .issues
+                        .iter()
+                        .filter(|i| i.is_error())
+                        .map(ToString::to_string),
+                );
+                format = Some(preflight.format);
+                if preflight.format == CapsuleFormat::CoreModule {
+                    capsule_abi = Some(preflight.abi);
+                }
+            }
+            Err(err) => problems.push(format!("{err:#}")),
+        },
+        Err(err) => problems.push(format!("{err:#}")),
+    }
+
+    let runtime_supported = AbiVersion::SUPPORTED
         .iter()
-        .find(|e| e.id == args.capsule_id)
-        .ok_or_else(|| anyhow::anyhow!("Capsule id '{}' não encontrado", args.capsule_id))?;
+        .map(|&version| AbiMatrixRow {
+            version,
+            module: version.module(),
+            functions: version.functions().map(|f| f.name).collect(),
+            used_by_capsule: capsule_abi == Some(version),
+        })
+        .collect();
+
+    AbiCompatView {
+        format,
+        capsule_abi,
+        compatible: format.is_some() && problems.is_empty(),
+        problems,
+        runtime_supported,
+    }
+}
+
+fn print_dependency_tree(dependencies: &[ResolvedDependency], depth: usize) {
+    for dependency in dependencies {
+        println!(
+            "{}- {} {} -> {} ({}: {})",
+            "  ".repeat(depth),
+            dependency.id,
+            dependency.requirement,
+            dependency.version,
+            dependency.source,
+            dependency.manifest
+        );
+        print_dependency_tree(&dependency.dependencies, depth + 1);
+    }
+}
 
-    let manifest_path = resolve_manifest_path(&args.registry, &entry.manifest);
+fn inspect_command(args: InspectArgs) -> anyhow::Result<()> {
+    let registries = registries(args.registry.as_ref())?;
+    let listed = registries
+        .find(&args.capsule_id)?
+        .ok_or_else(|| anyhow::anyhow!("Capsule id '{}' não encontrado", args.capsule_id))?;
+    let entry = &listed.entry;
+    let manifest_path = &listed.manifest_path;
 
     let state_dir = ensure_state_dirs()?;
     let mut runs: Vec<RunRecord> = load_run_records(&state_dir)?
@@ -597,12 +2225,23 @@ fn inspect_command(args: InspectArgs) -> anyhow::Result<()> {
         })
         .collect();
 
+    let (dependencies, dependency_error) = match CapsuleManifest::load(manifest_path)
+        .and_then(|manifest| resolve_dependencies(&manifest, &registries))
+    {
+        Ok(dependencies) => (dependencies, None),
+        Err(err) => (Vec::new(), Some(format!("{err:#}"))),
+    };
+
     let view = InspectView {
         id: entry.id.clone(),
         name: entry.name.clone(),
-        registry: args.registry.display().to_string(),
+        registry: listed.registry.clone(),
         manifest: manifest_path.display().to_string(),
         manifest_exists: manifest_path.exists(),
+        abi: abi_compat_view(manifest_path),
+        dependencies,
+        dependency_error,
+        disk: disk_usage_view(&state_dir, &entry.id, manifest_path),
         last_runs,
     };
 
@@ -616,6 +2255,49 @@ fn inspect_command(args: InspectArgs) -> anyhow::Result<()> {
     println!("registry: {}", view.registry);
     println!("manifest: {}", view.manifest);
     println!("manifest_exists: {}", view.manifest_exists);
+    match (view.abi.format, view.abi.capsule_abi) {
+        (Some(CapsuleFormat::Component), _) => println!(
+            "abi: component caeles:capsule (compatible: {})",
+            view.abi.compatible
+        ),
+        (_, Some(abi)) => println!("abi: {abi} (compatible: {})", view.abi.compatible),
+        _ => println!("abi: desconhecido (compatible: false)"),
+    }
+    for problem in &view.abi.problems {
+        println!("  problema: {problem}");
+    }
+    println!("abi_matrix:");
+    for row in &view.abi.runtime_supported {
+        let marker = if row.used_by_capsule {
+            " <- cápsula"
+        } else {
+            ""
+        };
+        println!(
+            "- {} module={} functions={}{}",
+            row.version,
+            row.module,
+            row.functions.join(","),
+            marker
+        );
+    }
+
+    if let Some(err) = &view.dependency_error {
+        println!("dependencies: erro: {err}");
+    } else if view.dependencies.is_empty() {
+        println!("dependencies: []");
+    } else {
+        println!("dependencies:");
+        print_dependency_tree(&view.dependencies, 0);
+    }
+
+    match view.disk.quota_bytes {
+        Some(quota) => println!(
+            "disk: {} bytes de {quota} ({})",
+            view.disk.used_bytes, view.disk.path
+        ),
+        None => println!("disk: {} bytes ({})", view.disk.used_bytes, view.disk.path),
+    }
 
     if view.last_runs.is_empty() {
         println!("last_runs: []");
@@ -644,11 +2326,31 @@ struct InspectRunView {
     duration_ms: u128,
     log_path: String,
     log_exists: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trigger: Option<RunTrigger>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<RunImage>,
+    /// Event runs started by messages this run published.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    triggered_runs: Vec<String>,
 }
 
 fn inspect_run_command(args: InspectRunArgs) -> anyhow::Result<()> {
     let state_dir = ensure_state_dirs()?;
     let runs = load_run_records(&state_dir)?;
+    let triggered_runs = runs
+        .iter()
+        .filter(|r| {
+            r.trigger
+                .as_ref()
+                .is_some_and(|t| t.parent_run_id.as_deref() == Some(args.run_id.as_str()))
+        })
+        .map(|r| r.run_id.clone())
+        .collect();
     let run = runs
         .into_iter()
         .find(|r| r.run_id == args.run_id)
@@ -668,6 +2370,11 @@ fn inspect_run_command(args: InspectRunArgs) -> anyhow::Result<()> {
             .saturating_sub(run.started_at_unix_ms),
         log_path: log_path.display().to_string(),
         log_exists: log_path.exists(),
+        trace_path: run.trace_path,
+        seed: run.seed,
+        trigger: run.trigger,
+        image: run.image,
+        triggered_runs,
     };
 
     if args.json {
@@ -685,6 +2392,32 @@ fn inspect_run_command(args: InspectRunArgs) -> anyhow::Result<()> {
     println!("duration_ms: {}", view.duration_ms);
     println!("log_path: {}", view.log_path);
     println!("log_exists: {}", view.log_exists);
+    if let Some(trace_path) = &view.trace_path {
+        println!("trace_path: {trace_path}");
+    }
+    if let Some(seed) = view.seed {
+        println!("seed: {seed}");
+    }
+    if let Some(trigger) = &view.trigger {
+        let parent = trigger
+            .parent_run_id
+            .as_ref()
+            .map(|run_id| format!(", run {run_id}"))
+            .unwrap_or_default();
+        println!(
+            "trigger: {} ({}) de {}{parent}",
+            trigger.topic, trigger.message_id, trigger.publisher
+        );
+    }
+    if let Some(image) = &view.image {
+        println!(
+            "image: {} ({}, {})",
+            image.reference, image.source, image.digest
+        );
+    }
+    if !view.triggered_runs.is_empty() {
+        println!("triggered_runs: {}", view.triggered_runs.join(", "));
+    }
 
     Ok(())
 }
@@ -715,6 +2448,19 @@ fn logs_command(args: LogsArgs) -> anyhow::Result<()> {
 
     Ok(())
 }
+/// Removes the log and the recorded trace (if any) of a run.
+fn remove_run_files(state_dir: &Path, run_id: &str) -> anyhow::Result<()> {
+    for path in [
+        log_file_path(state_dir, run_id),
+        replay::trace_file_path(state_dir, run_id),
+    ] {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
 fn rm_command(args: RmArgs) -> anyhow::Result<()> {
     let state_dir = ensure_state_dirs()?;
 
@@ -728,6 +2474,10 @@ fn rm_command(args: RmArgs) -> anyhow::Result<()> {
             fs::remove_dir_all(&logs_dir)?;
         }
         fs::create_dir_all(state_dir.join("logs"))?;
+        let traces_dir = state_dir.join("traces");
+        if traces_dir.exists() {
+            fs::remove_dir_all(&traces_dir)?;
+        }
         println!("Histórico e logs removidos.");
         return Ok(());
     }
@@ -742,10 +2492,7 @@ fn rm_command(args: RmArgs) -> anyhow::Result<()> {
         }
         persist_run_records(&state_dir, &runs)?;
 
-        let log_path = log_file_path(&state_dir, &run_id);
-        if log_path.exists() {
-            fs::remove_file(log_path)?;
-        }
+        remove_run_files(&state_dir, &run_id)?;
 
         println!("Run '{}' removido.", run_id);
         return Ok(());
@@ -783,22 +2530,159 @@ fn rm_command(args: RmArgs) -> anyhow::Result<()> {
 
     persist_run_records(&state_dir, &runs)?;
     for run_id in &removed_ids {
-        let log_path = log_file_path(&state_dir, run_id);
-        if log_path.exists() {
-            fs::remove_file(log_path)?;
-        }
+        remove_run_files(&state_dir, run_id)?;
     }
 
     println!("{} execução(ões) removida(s).", removed_ids.len());
     Ok(())
 }
 
+#[derive(Debug, Serialize)]
+struct ValidateView {
+    manifest: String,
+    valid: bool,
+    issues: Vec<ManifestIssue>,
+    preflight: Vec<PreflightIssue>,
+}
+
+fn preflight_wasm(manifest: &CapsuleManifest) -> Vec<PreflightIssue> {
+    let wasm_path = manifest.wasm_path();
+    if !wasm_path.exists() {
+        return vec![PreflightIssue::warning(
+            "entry",
+            format!(
+                "wasm não encontrado em '{}'; análise de imports/exports ignorada",
+                wasm_path.display()
+            ),
+        )];
+    }
+
+    match preflight::analyze_file(&wasm_path, manifest) {
+        Ok(preflight) => preflight.issues,
+        Err(err) => vec![PreflightIssue::error("entry", format!("{err:#}"))],
+    }
+}
+
+fn validate_command(args: ValidateArgs) -> anyhow::Result<()> {
+    let (manifest, issues) = CapsuleManifest::check(&args.manifest)?;
+    let preflight = match &manifest {
+        Some(manifest) if issues.is_empty() => preflight_wasm(manifest),
+        _ => Vec::new(),
+    };
+
+    let view = ValidateView {
+        manifest: args.manifest.display().to_string(),
+        valid: issues.is_empty() && !preflight.iter().any(PreflightIssue::is_error),
+        issues,
+        preflight,
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&view)?);
+    } else {
+        if view.valid {
+            println!("Manifest válido: {}", view.manifest);
+        } else {
+            println!(
+                "Manifest inválido: {} ({} problema(s))",
+                view.manifest,
+                view.issues.len() + view.preflight.iter().filter(|i| i.is_error()).count()
+            );
+        }
+        for issue in &view.issues {
+            println!("- {issue}");
+        }
+        for issue in &view.preflight {
+            let label = if issue.is_error() { "erro" } else { "aviso" };
+            println!("- [{label}] {issue}");
+        }
+    }
+
+    if !view.valid {
+        anyhow::bail!("Validação falhou para '{}'", view.manifest);
+    }
+    Ok(())
+}
+
+fn test_command(args: TestArgs) -> anyhow::Result<()> {
+    let state_dir = ensure_state_dirs()?;
+    let registries = registries(args.registry.as_ref())?;
+    let (manifest, manifest_path) = resolve_capsule_arg(&args.capsule, &registries)?;
+    let dependencies = resolve_dependencies(&manifest, &registries)?;
+    let settings = Settings::load()?;
+    settings.check_permissions(&manifest)?;
+    for dependency in dependencies::load_order(&dependencies) {
+        settings.check_permissions(&dependency.capsule)?;
+    }
+    let test_path = args.file.clone().unwrap_or_else(|| {
+        manifest_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(testing::DEFAULT_TEST_FILE)
+    });
+    let test_file = testing::TestFile::load(&test_path)?;
+    if test_file.cases.is_empty() {
+        anyhow::bail!("Nenhum caso de teste em '{}'", test_path.display());
+    }
+
+    let started = now_unix_ms();
+    let mut results = Vec::new();
+    for (index, case) in test_file.cases.iter().enumerate() {
+        println!("=== caso '{}'", case.name);
+        let run_id = format!("test-{started}-{index}");
+        results.push(testing::run_case(
+            &manifest,
+            &dependencies,
+            case,
+            &state_dir,
+            &run_id,
+        ));
+    }
+
+    println!();
+    for result in &results {
+        if result.passed() {
+            println!("caso '{}' ... ok", result.name);
+            continue;
+        }
+        println!("caso '{}' ... FALHOU", result.name);
+        for failure in &result.failures {
+            for line in failure.lines() {
+                println!("    {line}");
+            }
+        }
+    }
+
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    println!(
+        "\nresultado: {} passaram, {} falharam",
+        results.len() - failed,
+        failed
+    );
+
+    if let Some(junit) = &args.junit {
+        fs::write(junit, testing::junit_xml(&manifest.id, &results)).map_err(|err| {
+            anyhow::anyhow!(
+                "Falha ao gravar relatório JUnit '{}': {err}",
+                junit.display()
+            )
+        })?;
+        println!("relatório JUnit: {}", junit.display());
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{failed} caso(s) de teste falharam");
+    }
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
         Commands::Run(args) => run_command(args),
         Commands::List(args) => list_command(args),
+        Commands::New(args) => new_command(args),
         Commands::Build(args) => build_command(args),
         Commands::Package(args) => package_command(args),
         Commands::Pull(args) => pull_command(args),
@@ -808,26 +2692,28 @@ fn main() -> anyhow::Result<()> {
         Commands::InspectRun(args) => inspect_run_command(args),
         Commands::Logs(args) => logs_command(args),
         Commands::Rm(args) => rm_command(args),
+        Commands::Validate(args) => validate_command(args),
+        Commands::Test(args) => test_command(args),
+        Commands::Replay(args) => replay_command(args),
+        Commands::CapsuleConfig(args) => capsule_config_command(args),
+        Commands::Secret(args) => secret_command(args),
+        Commands::Publish(args) => publish_command(args),
+        Commands::Tag(args) => tag_command(args),
+        Commands::Rmi(args) => rmi_command(args),
+        Commands::Image(args) => image_command(args),
+        Commands::Registry(args) => registry_command(args),
+        Commands::Config(args) => config_command(args),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{resolve_manifest_path, Cli, Commands};
+    use super::{
+        Cli, Commands, ConfigArgs, ConfigCommand, ImageArgs, ImageCommand, ImageSource, RmiArgs,
+    };
+    use crate::registry::resolve_manifest_path;
     use clap::Parser;
-    use std::fs;
-    use std::path::{Path, PathBuf};
-    use std::time::{SystemTime, UNIX_EPOCH};
-
-    fn temp_dir(prefix: &str) -> PathBuf {
-        let suffix = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("clock should be after unix epoch")
-            .as_nanos();
-        let dir = std::env::temp_dir().join(format!("caeles-{prefix}-{suffix}"));
-        fs::create_dir_all(&dir).expect("temp directory should be created");
-        dir
-    }
+    use std::path::Path;
 
     #[test]
     fn parse_run_subcommand() {
@@ -837,11 +2723,46 @@ mod tests {
         assert!(matches!(cli.command, Commands::Run(_)));
     }
 
+    #[test]
+    fn parse_new_subcommand() {
+        let cli = Cli::try_parse_from([
+            "caeles",
+            "new",
+            "my-capsule",
+            "--id",
+            "com.example.my",
+            "--template",
+            "kv-storage",
+        ])
+        .expect("new command should parse");
+        assert!(matches!(cli.command, Commands::New(_)));
+        assert!(Cli::try_parse_from([
+            "caeles",
+            "new",
+            "my-capsule",
+            "--id",
+            "com.example.my",
+            "--template",
+            "minimal",
+            "--template-dir",
+            "templates/mine",
+        ])
+        .is_err());
+    }
+
     #[test]
     fn parse_build_subcommand() {
         let cli = Cli::try_parse_from(["caeles", "build", "capsules/hello-capsule"])
             .expect("build command should parse");
         assert!(matches!(cli.command, Commands::Build(_)));
+        assert!(Cli::try_parse_from(["caeles", "build", "capsules/x", "--optimize"]).is_err());
+        assert!(
+            Cli::try_parse_from(["caeles", "build", "capsules/x", "--copy", "--update-entry"])
+                .is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["caeles", "build", "capsules/x", "--copy", "--optimize"]).is_ok()
+        );
     }
 
     #[test]
@@ -863,6 +2784,21 @@ mod tests {
         assert!(matches!(cli.command, Commands::Pull(_)));
     }
 
+    #[test]
+    fn parse_config_subcommands() {
+        let cli =
+            Cli::try_parse_from(["caeles", "config", "set", "--user", "run.timeout_ms", "500"])
+                .expect("config set should parse");
+        assert!(matches!(
+            cli.command,
+            Commands::Config(ConfigArgs {
+                command: ConfigCommand::Set { user: true, .. }
+            })
+        ));
+        assert!(Cli::try_parse_from(["caeles", "config", "get", "state_dir"]).is_ok());
+        assert!(Cli::try_parse_from(["caeles", "config", "list", "--json"]).is_ok());
+    }
+
     #[test]
     fn parse_images_json_subcommand() {
         let cli = Cli::try_parse_from(["caeles", "images", "--json"])
@@ -870,6 +2806,29 @@ mod tests {
         assert!(matches!(cli.command, Commands::Images(_)));
     }
 
+    #[test]
+    fn parse_rmi_and_image_prune_subcommands() {
+        let cli = Cli::try_parse_from(["caeles", "rmi", "com.x@1.0.0", "--source", "pull"])
+            .expect("rmi command should parse");
+        assert!(matches!(
+            cli.command,
+            Commands::Rmi(RmiArgs {
+                source: Some(ImageSource::Pull),
+                ..
+            })
+        ));
+        assert!(Cli::try_parse_from(["caeles", "rmi", "com.x", "--source", "local"]).is_err());
+
+        let cli = Cli::try_parse_from(["caeles", "image", "prune", "--dry-run"])
+            .expect("image prune should parse");
+        assert!(matches!(
+            cli.command,
+            Commands::Image(ImageArgs {
+                command: ImageCommand::Prune { dry_run: true, .. }
+            })
+        ));
+    }
+
     #[test]
     fn parse_list_json_subcommand() {
         let cli = Cli::try_parse_from(["caeles", "list", "--json"]).expect("list should parse");
@@ -944,32 +2903,41 @@ mod tests {
     }
 
     #[test]
-    fn resolve_manifest_path_keeps_existing_relative_manifest() {
-        let root = temp_dir("existing-relative");
-        let existing_manifest = root.join("capsules/hello-capsule/manifest.json");
-
-        fs::create_dir_all(
-            existing_manifest
-                .parent()
-                .expect("manifest should have parent"),
-        )
-        .expect("manifest parent should be created");
-        fs::write(&existing_manifest, "{}")
-            .expect("manifest file should be created for test setup");
-
-        let previous_dir = std::env::current_dir().expect("current dir should be readable");
-        std::env::set_current_dir(&root).expect("current dir should be changed for test");
-
-        let resolved = resolve_manifest_path(
-            Path::new("capsules/registry.json"),
+    fn parse_validate_json_subcommand() {
+        let cli = Cli::try_parse_from([
+            "caeles",
+            "validate",
             "capsules/hello-capsule/manifest.json",
-        );
-
-        std::env::set_current_dir(previous_dir).expect("current dir should be restored");
+            "--json",
+        ])
+        .expect("validate should parse");
+        assert!(matches!(cli.command, Commands::Validate(_)));
+    }
 
-        assert_eq!(resolved, Path::new("capsules/hello-capsule/manifest.json"));
+    #[test]
+    fn parse_run_deterministic_with_seed() {
+        let cli = Cli::try_parse_from([
+            "caeles",
+            "run",
+            "--capsule-id",
+            "com.caeles.example.hello",
+            "--deterministic",
+            "--seed",
+            "42",
+        ])
+        .expect("deterministic run should parse");
+        let Commands::Run(args) = cli.command else {
+            panic!("expected run command");
+        };
+        assert!(args.deterministic);
+        assert_eq!(args.seed, Some(42));
+    }
 
-        fs::remove_dir_all(root).expect("temp directory should be removed");
+    #[test]
+    fn parse_replay_subcommand() {
+        let cli = Cli::try_parse_from(["caeles", "replay", "run-1"])
+            .expect("replay command should parse");
+        assert!(matches!(cli.command, Commands::Replay(_)));
     }
 
     #[test]
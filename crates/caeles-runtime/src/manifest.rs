@@ -1,33 +1,315 @@
+use crate::bus;
 use anyhow::{bail, Context};
-use serde::Deserialize;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 fn default_path_buf() -> PathBuf {
     PathBuf::new()
 }
 
-#[derive(Debug, Deserialize)]
+/// Checks that `id` is a reverse-DNS identifier such as `com.example.capsule`.
+///
+/// The id is used as a directory name by `package` and `pull`, so only lowercase
+/// ASCII letters, digits, `-` and `_` are accepted in each dot-separated segment.
+pub fn validate_capsule_id(id: &str) -> Result<(), String> {
+    if id.trim().is_empty() {
+        return Err("campo nao pode ser vazio".to_string());
+    }
+    if id.len() > 255 {
+        return Err("deve ter no maximo 255 caracteres".to_string());
+    }
+
+    let segments: Vec<&str> = id.split('.').collect();
+    if segments.len() < 2 {
+        return Err(format!(
+            "'{id}' deve usar notacao reverse-DNS (ex.: com.exemplo.capsula)"
+        ));
+    }
+
+    for segment in segments {
+        let mut chars = segment.chars();
+        let Some(first) = chars.next() else {
+            return Err(format!("'{id}' contem segmento vazio"));
+        };
+        if !first.is_ascii_lowercase() {
+            return Err(format!(
+                "segmento '{segment}' deve comecar com letra minuscula"
+            ));
+        }
+        if let Some(bad) = chars
+            .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '-' || *c == '_'))
+        {
+            return Err(format!(
+                "segmento '{segment}' contem caractere invalido '{bad}' (use a-z, 0-9, '-' ou '_')"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that `path` is relative and stays inside its base directory.
+fn validate_relative_path(path: &str) -> Result<(), String> {
+    let as_path = Path::new(path);
+    if as_path.is_absolute() || path.starts_with('/') || path.starts_with('\\') {
+        return Err("deve ser caminho relativo ao manifesto".to_string());
+    }
+
+    for component in as_path.components() {
+        match component {
+            Component::ParentDir => {
+                return Err("nao pode conter '..' (path traversal)".to_string());
+            }
+            Component::Prefix(_) | Component::RootDir => {
+                return Err("deve ser caminho relativo ao manifesto".to_string());
+            }
+            Component::CurDir | Component::Normal(_) => {}
+        }
+    }
+    if path.split(['/', '\\']).any(|part| part == "..") {
+        return Err("nao pode conter '..' (path traversal)".to_string());
+    }
+
+    Ok(())
+}
+
+fn validate_entry(entry: &str) -> Result<(), String> {
+    if entry.trim().is_empty() {
+        return Err("campo nao pode ser vazio".to_string());
+    }
+    validate_relative_path(entry)?;
+
+    let entry_path = Path::new(entry);
+    let is_wasm = entry_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("wasm"))
+        .unwrap_or(false);
+    if !is_wasm {
+        return Err("deve apontar para um arquivo .wasm".to_string());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Permissions {
     pub notifications: bool,
     pub network: bool,
+    /// Hosts the network capability is scoped to (`example.com`, `*.example.com`); empty allows any.
+    #[serde(default)]
+    pub network_hosts: Vec<String>,
+    /// Real time through `host_now_ms`; monotonic time and sleeping are always allowed.
+    #[serde(default)]
+    pub wall_clock: bool,
+    /// Private directory and declared assets through the `host_fs_*` calls.
+    #[serde(default)]
+    pub filesystem: bool,
+    /// Topics the capsule may send with `host_publish` (`orders.created`, `orders.*`).
+    #[serde(default)]
+    pub publish: Vec<String>,
+    /// Topics that start an `event` capsule.
+    #[serde(default)]
+    pub subscribe: Vec<String>,
+}
+
+impl Permissions {
+    /// Looks up a permission by its manifest field name; unknown names are never granted.
+    pub fn is_granted(&self, name: &str) -> bool {
+        match name {
+            "notifications" => self.notifications,
+            "network" => self.network,
+            "wall_clock" => self.wall_clock,
+            "filesystem" => self.filesystem,
+            "publish" => !self.publish.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// Compilation target of the capsule wasm.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Pure `caeles` host ABI, no WASI imports.
+    #[default]
+    #[serde(rename = "wasm32-unknown-unknown")]
+    Unknown,
+    /// WASI preview1, run with the restricted context described by [`Wasi`].
+    #[serde(rename = "wasm32-wasip1")]
+    WasiP1,
+}
+
+impl Target {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Target::Unknown => "wasm32-unknown-unknown",
+            Target::WasiP1 => "wasm32-wasip1",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreopenMode {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Directory exposed to a WASI capsule, backed by `path` inside the capsule sandbox.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Preopen {
+    /// Directory relative to the per-capsule sandbox on the host.
+    pub path: String,
+    /// Path the capsule sees, e.g. `/data`.
+    pub guest: String,
+    #[serde(default = "default_preopen_mode")]
+    pub mode: PreopenMode,
+}
+
+fn default_preopen_mode() -> PreopenMode {
+    PreopenMode::ReadOnly
+}
+
+/// WASI context granted to `wasm32-wasip1` capsules; everything not listed is denied.
+#[derive(Debug, Default, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Wasi {
+    #[serde(default)]
+    pub preopens: Vec<Preopen>,
+    /// Names of host environment variables passed through to the capsule.
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Default size limit of the private directory of a capsule.
+pub const DEFAULT_FS_QUOTA: u64 = 16 * 1024 * 1024;
+
+fn default_fs_quota() -> u64 {
+    DEFAULT_FS_QUOTA
+}
+
+/// Settings of the `host_fs_*` file area; requires `permissions.filesystem`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Filesystem {
+    /// Maximum bytes stored in the private directory.
+    #[serde(default = "default_fs_quota")]
+    pub quota_bytes: u64,
+    /// Read-only directories relative to the manifest, bundled by `caeles package`
+    /// and visible to the capsule as `assets/<dir>`.
+    #[serde(default)]
+    pub assets: Vec<String>,
+}
+
+impl Default for Filesystem {
+    fn default() -> Self {
+        Self {
+            quota_bytes: DEFAULT_FS_QUOTA,
+            assets: Vec::new(),
+        }
+    }
+}
+
+/// Type of a configuration key; values reach the capsule as text.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+}
+
+impl ConfigType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConfigType::String => "string",
+            ConfigType::Integer => "integer",
+            ConfigType::Number => "number",
+            ConfigType::Boolean => "boolean",
+        }
+    }
+
+    /// Checks a value given as text (`--config key=value`) and returns its canonical form.
+    pub fn parse_text(self, text: &str) -> Result<String, String> {
+        let valid = match self {
+            ConfigType::String => true,
+            ConfigType::Integer => text.parse::<i64>().is_ok(),
+            ConfigType::Number => text.parse::<f64>().is_ok_and(f64::is_finite),
+            ConfigType::Boolean => matches!(text, "true" | "false"),
+        };
+        if valid {
+            Ok(text.to_string())
+        } else {
+            Err(format!("'{text}' nao e um valor {}", self.as_str()))
+        }
+    }
+
+    /// Checks a JSON value (manifest default, `--config-file`) and returns its text form.
+    pub fn parse_json(self, value: &serde_json::Value) -> Result<String, String> {
+        use serde_json::Value;
+        match (self, value) {
+            (ConfigType::String, Value::String(text)) => Ok(text.clone()),
+            (ConfigType::Integer, Value::Number(n)) if n.is_i64() => Ok(n.to_string()),
+            (ConfigType::Number, Value::Number(n)) => Ok(n.to_string()),
+            (ConfigType::Boolean, Value::Bool(b)) => Ok(b.to_string()),
+            _ => Err(format!("{value} nao e um valor {}", self.as_str())),
+        }
+    }
+}
+
+/// Declared configuration key; values come from `caeles run --config`, a config file or the store.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigKey {
+    #[serde(rename = "type")]
+    pub kind: ConfigType,
+    #[serde(default)]
+    pub default: Option<serde_json::Value>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Config keys and secret names share this format: `a-z`, `0-9`, `_`, `-` and `.`.
+pub fn validate_config_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "'{name}' deve usar apenas letras minusculas, digitos, '_', '-' e '.'"
+        ))
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum LifecycleKind {
     OnDemand,
+    /// Runs once for each bus message matching `permissions.subscribe`.
+    Event,
+    /// Shared module loaded through another capsule's `dependencies`; never run on its own.
+    Library,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Lifecycle {
-    #[allow(dead_code)]
     pub kind: LifecycleKind,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct CapsuleManifest {
     pub id: String,
@@ -35,69 +317,320 @@ pub struct CapsuleManifest {
     pub version: String,
     pub entry: String,
     pub permissions: Permissions,
-    #[allow(dead_code)]
     pub lifecycle: Lifecycle,
+    #[serde(default)]
+    pub target: Target,
+    #[serde(default)]
+    pub wasi: Option<Wasi>,
+    #[serde(default)]
+    pub filesystem: Option<Filesystem>,
+    /// Configuration schema, read with `host_config_get`.
+    #[serde(default)]
+    pub config: BTreeMap<String, ConfigKey>,
+    /// Names of secrets the capsule reads with `host_secret_get` (`caeles secret set`).
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// Library capsules by id and SemVer range; their exports are imported from `dep:<id>`.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
 
     #[serde(skip, default = "default_path_buf")]
     base_dir: PathBuf,
 }
 
+/// A single problem found while validating a manifest, tied to the field it refers to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManifestIssue {
+    pub field: String,
+    pub message: String,
+}
+
+impl ManifestIssue {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ManifestIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+fn read_manifest_text(path: &Path) -> anyhow::Result<String> {
+    fs::read_to_string(path)
+        .with_context(|| format!("Nao foi possivel ler manifest '{}'", path.display()))
+}
+
 impl CapsuleManifest {
     pub fn load(path: &Path) -> anyhow::Result<Self> {
-        let text = fs::read_to_string(path)
-            .with_context(|| format!("Nao foi possivel ler manifest '{}'", path.display()))?;
+        let manifest = Self::load_unchecked(path)?;
+        let issues = manifest.issues();
+        if !issues.is_empty() {
+            let details: Vec<String> = issues.iter().map(|i| format!("  - {i}")).collect();
+            bail!(
+                "Manifest invalido em '{}':\n{}",
+                path.display(),
+                details.join("\n")
+            );
+        }
+        Ok(manifest)
+    }
 
-        let mut manifest: CapsuleManifest = serde_json::from_str(&text).with_context(|| {
-            format!(
-                "Manifest invalido em '{}': verifique campos obrigatorios e tipos (lifecycle.kind aceita: on_demand)",
-                path.display()
+    /// Parses the manifest without running the semantic checks from [`Self::issues`].
+    pub fn load_unchecked(path: &Path) -> anyhow::Result<Self> {
+        let text = read_manifest_text(path)?;
+        Self::parse(path, &text).map_err(|issue| {
+            anyhow::anyhow!(
+                "Manifest invalido em '{}' (campo '{}'): verifique campos obrigatorios e tipos (lifecycle.kind aceita: on_demand, event, library): {}",
+                path.display(),
+                issue.field,
+                issue.message
             )
-        })?;
+        })
+    }
+
+    /// Collects every problem in the manifest instead of stopping at the first one.
+    ///
+    /// Parse errors (unknown fields, wrong types) still stop the semantic checks,
+    /// since there is no manifest to inspect, but are reported with their field path.
+    pub fn check(path: &Path) -> anyhow::Result<(Option<Self>, Vec<ManifestIssue>)> {
+        let text = read_manifest_text(path)?;
+        match Self::parse(path, &text) {
+            Ok(manifest) => {
+                let issues = manifest.issues();
+                Ok((Some(manifest), issues))
+            }
+            Err(issue) => Ok((None, vec![issue])),
+        }
+    }
+
+    fn parse(path: &Path, text: &str) -> Result<Self, ManifestIssue> {
+        let deserializer = &mut serde_json::Deserializer::from_str(text);
+        let mut manifest: CapsuleManifest = serde_path_to_error::deserialize(deserializer)
+            .map_err(|err| {
+                let field = match err.path().to_string().as_str() {
+                    "." => "$".to_string(),
+                    other => other.to_string(),
+                };
+                ManifestIssue::new(&field, err.into_inner().to_string())
+            })?;
         let base = path.parent().unwrap_or_else(|| Path::new("."));
         manifest.base_dir = base.to_path_buf();
-        manifest.validate(path)?;
         Ok(manifest)
     }
 
-    fn validate_non_empty(path: &Path, field: &str, value: &str) -> anyhow::Result<()> {
-        if value.trim().is_empty() {
-            bail!(
-                "Manifest invalido em '{}': campo '{}' nao pode ser vazio",
-                path.display(),
-                field
-            );
+    /// Runs every semantic check and returns all problems found, in field order.
+    pub fn issues(&self) -> Vec<ManifestIssue> {
+        let mut issues = Vec::new();
+
+        if let Err(message) = validate_capsule_id(&self.id) {
+            issues.push(ManifestIssue::new("id", message));
         }
 
-        Ok(())
-    }
+        if self.name.trim().is_empty() {
+            issues.push(ManifestIssue::new("name", "campo nao pode ser vazio"));
+        }
 
-    fn validate(&self, path: &Path) -> anyhow::Result<()> {
-        Self::validate_non_empty(path, "id", &self.id)?;
-        Self::validate_non_empty(path, "name", &self.name)?;
-        Self::validate_non_empty(path, "version", &self.version)?;
-        Self::validate_non_empty(path, "entry", &self.entry)?;
+        if self.version.trim().is_empty() {
+            issues.push(ManifestIssue::new("version", "campo nao pode ser vazio"));
+        } else if let Err(err) = Version::parse(&self.version) {
+            issues.push(ManifestIssue::new(
+                "version",
+                format!("'{}' nao e uma versao SemVer valida: {err}", self.version),
+            ));
+        }
 
-        let entry_path = Path::new(&self.entry);
-        if entry_path.is_absolute() {
-            bail!(
-                "Manifest invalido em '{}': 'entry' deve ser caminho relativo ao manifesto",
-                path.display()
-            );
+        if let Err(message) = validate_entry(&self.entry) {
+            issues.push(ManifestIssue::new("entry", message));
         }
 
-        let is_wasm = entry_path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| ext.eq_ignore_ascii_case("wasm"))
-            .unwrap_or(false);
-        if !is_wasm {
-            bail!(
-                "Manifest invalido em '{}': 'entry' deve apontar para um arquivo .wasm",
-                path.display()
-            );
+        for (list, topics) in [
+            ("publish", &self.permissions.publish),
+            ("subscribe", &self.permissions.subscribe),
+        ] {
+            let mut seen = HashSet::new();
+            for (index, topic) in topics.iter().enumerate() {
+                let field = format!("permissions.{list}[{index}]");
+                if let Err(message) = bus::validate_topic(topic, true) {
+                    issues.push(ManifestIssue::new(&field, message));
+                } else if !seen.insert(topic.as_str()) {
+                    issues.push(ManifestIssue::new(
+                        &field,
+                        format!("'{topic}' declarado mais de uma vez"),
+                    ));
+                }
+            }
+        }
+        match self.lifecycle.kind {
+            LifecycleKind::Event if self.permissions.subscribe.is_empty() => {
+                issues.push(ManifestIssue::new(
+                    "permissions.subscribe",
+                    "lifecycle.kind 'event' exige ao menos um topico",
+                ));
+            }
+            LifecycleKind::OnDemand | LifecycleKind::Library
+                if !self.permissions.subscribe.is_empty() =>
+            {
+                issues.push(ManifestIssue::new(
+                    "permissions.subscribe",
+                    "topicos assinados exigem lifecycle.kind = 'event'",
+                ));
+            }
+            _ => {}
         }
 
-        Ok(())
+        if let Some(wasi) = &self.wasi {
+            if self.target != Target::WasiP1 {
+                issues.push(ManifestIssue::new(
+                    "wasi",
+                    "secao 'wasi' exige \"target\": \"wasm32-wasip1\"",
+                ));
+            }
+            Self::wasi_issues(wasi, &mut issues);
+        }
+
+        if let Some(filesystem) = &self.filesystem {
+            if !self.permissions.filesystem {
+                issues.push(ManifestIssue::new(
+                    "filesystem",
+                    "secao 'filesystem' exige permissions.filesystem = true",
+                ));
+            }
+            let mut assets = HashSet::new();
+            for (index, asset) in filesystem.assets.iter().enumerate() {
+                let field = format!("filesystem.assets[{index}]");
+                if asset.trim().is_empty() {
+                    issues.push(ManifestIssue::new(&field, "campo nao pode ser vazio"));
+                } else if let Err(message) = validate_relative_path(asset) {
+                    issues.push(ManifestIssue::new(&field, message));
+                } else if !assets.insert(asset.trim_end_matches('/')) {
+                    issues.push(ManifestIssue::new(
+                        &field,
+                        format!("'{asset}' declarado mais de uma vez"),
+                    ));
+                }
+            }
+        }
+
+        for (key, spec) in &self.config {
+            let field = format!("config.{key}");
+            if let Err(message) = validate_config_name(key) {
+                issues.push(ManifestIssue::new(&field, message));
+            }
+            if let Some(default) = &spec.default {
+                if let Err(message) = spec.kind.parse_json(default) {
+                    issues.push(ManifestIssue::new(&format!("{field}.default"), message));
+                }
+            }
+        }
+
+        for (id, requirement) in &self.dependencies {
+            let field = format!("dependencies.{id}");
+            if let Err(message) = validate_capsule_id(id) {
+                issues.push(ManifestIssue::new(&field, message));
+            } else if *id == self.id {
+                issues.push(ManifestIssue::new(
+                    &field,
+                    "a capsula nao pode depender de si mesma",
+                ));
+            }
+            if let Err(err) = VersionReq::parse(requirement) {
+                issues.push(ManifestIssue::new(
+                    &field,
+                    format!("'{requirement}' nao e um intervalo SemVer valido: {err}"),
+                ));
+            }
+        }
+
+        let mut secrets = HashSet::new();
+        for (index, name) in self.secrets.iter().enumerate() {
+            let field = format!("secrets[{index}]");
+            if let Err(message) = validate_config_name(name) {
+                issues.push(ManifestIssue::new(&field, message));
+            } else if !secrets.insert(name.as_str()) {
+                issues.push(ManifestIssue::new(
+                    &field,
+                    format!("'{name}' declarado mais de uma vez"),
+                ));
+            } else if self.config.contains_key(name) {
+                issues.push(ManifestIssue::new(
+                    &field,
+                    format!("'{name}' ja e uma chave de config"),
+                ));
+            }
+        }
+
+        issues
+    }
+
+    fn wasi_issues(wasi: &Wasi, issues: &mut Vec<ManifestIssue>) {
+        let mut guests = HashSet::new();
+        for (index, preopen) in wasi.preopens.iter().enumerate() {
+            let field = format!("wasi.preopens[{index}]");
+            if preopen.path.trim().is_empty() {
+                issues.push(ManifestIssue::new(
+                    &format!("{field}.path"),
+                    "campo nao pode ser vazio",
+                ));
+            } else if let Err(message) = validate_relative_path(&preopen.path) {
+                issues.push(ManifestIssue::new(&format!("{field}.path"), message));
+            }
+            if preopen.guest.trim().is_empty() {
+                issues.push(ManifestIssue::new(
+                    &format!("{field}.guest"),
+                    "campo nao pode ser vazio",
+                ));
+            } else if !guests.insert(preopen.guest.as_str()) {
+                issues.push(ManifestIssue::new(
+                    &format!("{field}.guest"),
+                    format!("'{}' declarado mais de uma vez", preopen.guest),
+                ));
+            }
+        }
+
+        for (index, name) in wasi.env.iter().enumerate() {
+            let valid = !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                issues.push(ManifestIssue::new(
+                    &format!("wasi.env[{index}]"),
+                    format!("'{name}' nao e um nome de variavel de ambiente valido"),
+                ));
+            }
+        }
+    }
+
+    /// Whether a message on `topic` starts this capsule.
+    pub fn subscribes_to(&self, topic: &str) -> bool {
+        self.lifecycle.kind == LifecycleKind::Event
+            && self
+                .permissions
+                .subscribe
+                .iter()
+                .any(|pattern| bus::topic_matches(pattern, topic))
+    }
+
+    /// File area settings, or the defaults when the section is omitted.
+    pub fn filesystem(&self) -> Filesystem {
+        self.filesystem.clone().unwrap_or_default()
+    }
+
+    /// Asset directories on the host, by the name the capsule uses (`assets/<name>`).
+    pub fn asset_dirs(&self) -> Vec<(String, PathBuf)> {
+        self.filesystem()
+            .assets
+            .iter()
+            .map(|asset| {
+                let name = asset.trim_end_matches('/').to_string();
+                let dir = self.base_dir.join(&name);
+                (name, dir)
+            })
+            .collect()
     }
 
     /// Full path for the wasm file.
@@ -108,24 +641,14 @@ impl CapsuleManifest {
 
 #[cfg(test)]
 mod tests {
-    use super::CapsuleManifest;
+    use super::{validate_capsule_id, CapsuleManifest};
     use std::fs;
-    use std::path::PathBuf;
-    use std::time::{SystemTime, UNIX_EPOCH};
-
-    fn temp_dir(prefix: &str) -> PathBuf {
-        let suffix = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("clock should be after unix epoch")
-            .as_nanos();
-        let dir = std::env::temp_dir().join(format!("caeles-manifest-{prefix}-{suffix}"));
-        fs::create_dir_all(&dir).expect("temp directory should be created");
-        dir
-    }
+    use tempfile::TempDir;
 
     #[test]
     fn load_accepts_valid_v0_manifest() {
-        let root = temp_dir("valid");
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
         let manifest_path = root.join("manifest.json");
 
         fs::write(
@@ -144,13 +667,12 @@ mod tests {
         let manifest = CapsuleManifest::load(&manifest_path).expect("manifest should load");
         assert_eq!(manifest.id, "com.caeles.tests.valid");
         assert_eq!(manifest.wasm_path(), root.join("capsule.wasm"));
-
-        fs::remove_dir_all(root).expect("temp directory should be removed");
     }
 
     #[test]
     fn load_rejects_invalid_lifecycle_kind() {
-        let root = temp_dir("invalid-lifecycle");
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
         let manifest_path = root.join("manifest.json");
 
         fs::write(
@@ -177,7 +699,214 @@ mod tests {
             err_text.contains("on_demand"),
             "error should include accepted lifecycle value: {err_text}"
         );
+    }
+
+    #[test]
+    fn validate_capsule_id_requires_reverse_dns() {
+        assert!(validate_capsule_id("com.caeles.example.hello").is_ok());
+        assert!(validate_capsule_id("io.acme.my-capsule_2").is_ok());
+        assert!(validate_capsule_id("hello").is_err());
+        assert!(validate_capsule_id("../../etc").is_err());
+        assert!(validate_capsule_id("com..caeles").is_err());
+        assert!(validate_capsule_id("com.Caeles.hello").is_err());
+        assert!(validate_capsule_id("com.caeles/hello").is_err());
+    }
+
+    #[test]
+    fn load_reports_every_invalid_field() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let manifest_path = root.join("manifest.json");
+
+        fs::write(
+            &manifest_path,
+            r#"{
+  "id": "../../etc",
+  "name": "",
+  "version": "banana",
+  "entry": "../outside/capsule.wasm",
+  "permissions": { "notifications": true, "network": false },
+  "lifecycle": { "kind": "on_demand" }
+}"#,
+        )
+        .expect("manifest should be written");
+
+        let manifest =
+            CapsuleManifest::load_unchecked(&manifest_path).expect("manifest should parse");
+        let fields: Vec<String> = manifest.issues().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["id", "name", "version", "entry"]);
+
+        let err_text = CapsuleManifest::load(&manifest_path)
+            .expect_err("manifest should be rejected")
+            .to_string();
+        assert!(err_text.contains("version: 'banana'"), "{err_text}");
+        assert!(err_text.contains("path traversal"), "{err_text}");
+    }
+
+    #[test]
+    fn load_reports_field_path_for_type_errors() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let manifest_path = root.join("manifest.json");
+
+        fs::write(
+            &manifest_path,
+            r#"{
+  "id": "com.caeles.tests.types",
+  "name": "Types",
+  "version": "0.1.0",
+  "entry": "capsule.wasm",
+  "permissions": { "notifications": true, "network": "yes" },
+  "lifecycle": { "kind": "on_demand" }
+}"#,
+        )
+        .expect("manifest should be written");
+
+        let err_text = CapsuleManifest::load(&manifest_path)
+            .expect_err("manifest should fail for wrong type")
+            .to_string();
+        assert!(err_text.contains("permissions.network"), "{err_text}");
+    }
+
+    #[test]
+    fn load_rejects_wasi_section_without_wasip1_target() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let manifest_path = root.join("manifest.json");
+
+        fs::write(
+            &manifest_path,
+            r#"{
+  "id": "com.caeles.tests.wasi",
+  "name": "Wasi",
+  "version": "0.1.0",
+  "entry": "capsule.wasm",
+  "wasi": {
+    "preopens": [{ "path": "../host", "guest": "/data" }],
+    "env": ["HOME", "1BAD"]
+  },
+  "permissions": { "notifications": true, "network": false },
+  "lifecycle": { "kind": "on_demand" }
+}"#,
+        )
+        .expect("manifest should be written");
+
+        let manifest =
+            CapsuleManifest::load_unchecked(&manifest_path).expect("manifest should parse");
+        let fields: Vec<String> = manifest.issues().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["wasi", "wasi.preopens[0].path", "wasi.env[1]"]);
+    }
+
+    #[test]
+    fn load_checks_config_schema_and_secret_names() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let manifest_path = root.join("manifest.json");
+
+        fs::write(
+            &manifest_path,
+            r#"{
+  "id": "com.caeles.tests.config",
+  "name": "Config",
+  "version": "0.1.0",
+  "entry": "capsule.wasm",
+  "permissions": { "notifications": true, "network": false },
+  "lifecycle": { "kind": "on_demand" },
+  "config": {
+    "greeting": { "type": "string", "default": "hi" },
+    "retries": { "type": "integer", "default": "three" },
+    "Bad Key": { "type": "boolean" }
+  },
+  "secrets": ["api_token", "api_token", "greeting"]
+}"#,
+        )
+        .expect("manifest should be written");
+
+        let manifest =
+            CapsuleManifest::load_unchecked(&manifest_path).expect("manifest should parse");
+        let fields: Vec<String> = manifest.issues().into_iter().map(|i| i.field).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "config.Bad Key",
+                "config.retries.default",
+                "secrets[1]",
+                "secrets[2]"
+            ]
+        );
+    }
+
+    #[test]
+    fn event_lifecycle_requires_valid_subscriptions() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let manifest_path = root.join("manifest.json");
 
-        fs::remove_dir_all(root).expect("temp directory should be removed");
+        fs::write(
+            &manifest_path,
+            r#"{
+  "id": "com.caeles.tests.event",
+  "name": "Event",
+  "version": "0.1.0",
+  "entry": "capsule.wasm",
+  "permissions": {
+    "notifications": false,
+    "network": false,
+    "publish": ["orders.*.created", "audit"],
+    "subscribe": ["orders.*", "orders.*"]
+  },
+  "lifecycle": { "kind": "event" }
+}"#,
+        )
+        .expect("manifest should be written");
+
+        let manifest =
+            CapsuleManifest::load_unchecked(&manifest_path).expect("manifest should parse");
+        let fields: Vec<String> = manifest.issues().into_iter().map(|i| i.field).collect();
+        assert_eq!(
+            fields,
+            vec!["permissions.publish[0]", "permissions.subscribe[1]"]
+        );
+        assert!(manifest.subscribes_to("orders.created"));
+        assert!(!manifest.subscribes_to("audit"));
+        assert!(manifest.permissions.is_granted("publish"));
+    }
+
+    #[test]
+    fn dependencies_need_valid_ids_and_ranges() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let manifest_path = root.join("manifest.json");
+
+        fs::write(
+            &manifest_path,
+            r#"{
+  "id": "com.caeles.tests.app",
+  "name": "App",
+  "version": "0.1.0",
+  "entry": "capsule.wasm",
+  "permissions": { "notifications": false, "network": false },
+  "lifecycle": { "kind": "on_demand" },
+  "dependencies": {
+    "com.caeles.tests.app": "^0.1",
+    "com.caeles.tests.math": "^1.2",
+    "Math": "1.x",
+    "com.caeles.tests.text": "not-a-range"
+  }
+}"#,
+        )
+        .expect("manifest should be written");
+
+        let manifest =
+            CapsuleManifest::load_unchecked(&manifest_path).expect("manifest should parse");
+        let fields: Vec<String> = manifest.issues().into_iter().map(|i| i.field).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "dependencies.Math",
+                "dependencies.com.caeles.tests.app",
+                "dependencies.com.caeles.tests.text"
+            ]
+        );
     }
 }
@@ -0,0 +1,534 @@
+use crate::abi::{
+    module_version_number, read_marker, AbiVersion, HostFunction, ENTRY_EXPORT, HOST_FUNCTIONS,
+    WASI_P1_MODULE, WASI_START_EXPORT,
+};
+use crate::component;
+use crate::dependencies;
+use crate::manifest::{CapsuleManifest, LifecycleKind, Target};
+use anyhow::Context;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use wasmtime::component::Component;
+use wasmtime::{Engine, ExternType, FuncType, Module};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found by inspecting the module's imports and exports before instantiation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PreflightIssue {
+    pub severity: Severity,
+    /// Import (`module::name`) or export the issue refers to.
+    pub item: String,
+    pub message: String,
+}
+
+impl PreflightIssue {
+    pub fn error(item: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            item: item.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(item: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            item: item.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for PreflightIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.item, self.message)
+    }
+}
+
+fn signature(params: &[String], results: &[String]) -> String {
+    let results = match results.len() {
+        0 => "()".to_string(),
+        1 => results[0].clone(),
+        _ => format!("({})", results.join(", ")),
+    };
+    format!("({}) -> {}", params.join(", "), results)
+}
+
+fn func_signature(ty: &FuncType) -> String {
+    let params: Vec<String> = ty.params().map(|p| p.to_string()).collect();
+    let results: Vec<String> = ty.results().map(|r| r.to_string()).collect();
+    signature(&params, &results)
+}
+
+fn host_signature(host: &HostFunction) -> String {
+    let params: Vec<String> = host.params.iter().map(|p| p.to_string()).collect();
+    let results: Vec<String> = host.results.iter().map(|r| r.to_string()).collect();
+    signature(&params, &results)
+}
+
+fn extern_kind(ty: &ExternType) -> &'static str {
+    match ty {
+        ExternType::Func(_) => "func",
+        ExternType::Global(_) => "global",
+        ExternType::Table(_) => "table",
+        ExternType::Memory(_) => "memory",
+    }
+}
+
+fn unknown_module_hint(module: &str) -> &'static str {
+    if module.starts_with("wasi") {
+        "a cápsula foi compilada para WASI; declare \"target\": \"wasm32-wasip1\" no manifest ou compile para wasm32-unknown-unknown"
+    } else if module.starts_with("__wbindgen") || module == "wbg" {
+        "a cápsula depende de wasm-bindgen, que não é suportado pelo runtime"
+    } else {
+        "apenas o módulo de import 'caeles' é fornecido pelo runtime"
+    }
+}
+
+/// Result of the pre-flight analysis: the ABI the capsule was built against and every issue found.
+#[derive(Debug)]
+pub struct Preflight {
+    pub format: CapsuleFormat,
+    /// Core-module ABI version; not meaningful for components.
+    pub abi: AbiVersion,
+    pub issues: Vec<PreflightIssue>,
+}
+
+/// Binary format of a capsule, detected from the wasm header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapsuleFormat {
+    /// Core module using the `caeles_v*` import ABI.
+    CoreModule,
+    /// Component implementing the `caeles:capsule` WIT world.
+    Component,
+}
+
+fn supported_versions() -> String {
+    AbiVersion::SUPPORTED
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Works out which ABI version the capsule targets from its imports and SDK marker.
+///
+/// Returns `None` when the version cannot be used, with the reason recorded in `issues`.
+fn resolve_abi(
+    module: &Module,
+    marker: Option<u32>,
+    issues: &mut Vec<PreflightIssue>,
+) -> Option<AbiVersion> {
+    let mut imported: Vec<u32> = module
+        .imports()
+        .filter_map(|i| module_version_number(i.module()))
+        .collect();
+    imported.sort_unstable();
+    imported.dedup();
+
+    if imported.len() > 1 {
+        let modules: Vec<String> = imported.iter().map(|n| format!("v{n}")).collect();
+        issues.push(PreflightIssue::error(
+            "abi",
+            format!(
+                "a cápsula mistura versões do ABI ({}); recompile com uma única versão do caeles-sdk",
+                modules.join(", ")
+            ),
+        ));
+        return None;
+    }
+
+    let imported = imported.first().copied();
+    if let (Some(declared), Some(used)) = (marker, imported) {
+        if declared != used {
+            issues.push(PreflightIssue::error(
+                "abi",
+                format!(
+                    "marcador do caeles-sdk declara ABI v{declared}, mas os imports usam v{used}"
+                ),
+            ));
+            return None;
+        }
+    }
+
+    let Some(number) = imported.or(marker) else {
+        return Some(AbiVersion::LATEST);
+    };
+    match AbiVersion::from_number(number) {
+        Some(version) => Some(version),
+        None => {
+            issues.push(PreflightIssue::error(
+                "abi",
+                format!(
+                    "a cápsula requer ABI v{number}, mas este runtime suporta {}; atualize o runtime ou recompile com um caeles-sdk compatível",
+                    supported_versions()
+                ),
+            ));
+            None
+        }
+    }
+}
+
+fn check_imports(
+    module: &Module,
+    abi: Option<AbiVersion>,
+    manifest: &CapsuleManifest,
+    issues: &mut Vec<PreflightIssue>,
+) {
+    for import in module.imports() {
+        let item = format!("{}::{}", import.module(), import.name());
+        let ty = import.ty();
+
+        if import.module() == WASI_P1_MODULE && manifest.target == Target::WasiP1 {
+            continue;
+        }
+
+        if let Some(id) = dependencies::module_dependency(import.module()) {
+            if !manifest.dependencies.contains_key(id) {
+                issues.push(PreflightIssue::error(
+                    item,
+                    format!("dependência '{id}' não declarada em 'dependencies' no manifest"),
+                ));
+            } else if !matches!(ty, ExternType::Func(_)) {
+                issues.push(PreflightIssue::error(
+                    item,
+                    format!(
+                        "dependências só exportam funções, encontrado {}",
+                        extern_kind(&ty)
+                    ),
+                ));
+            }
+            continue;
+        }
+
+        if module_version_number(import.module()).is_none() {
+            issues.push(PreflightIssue::error(
+                item,
+                format!(
+                    "import não declarado no ABI: {}",
+                    unknown_module_hint(import.module())
+                ),
+            ));
+            continue;
+        }
+
+        // Version conflicts were already reported by `resolve_abi`.
+        let Some(abi) = abi else {
+            continue;
+        };
+
+        let Some(host) = abi.function(import.name()) else {
+            let message = match HOST_FUNCTIONS.iter().find(|h| h.name == import.name()) {
+                Some(host) if host.since > abi.number() => format!(
+                    "função disponível apenas a partir do ABI v{}; a cápsula usa {abi}",
+                    host.since
+                ),
+                Some(host) => format!(
+                    "função removida após o ABI v{}; a cápsula usa {abi} (use capability handles)",
+                    host.until.unwrap_or_default()
+                ),
+                None => "função de host desconhecida; atualize o runtime ou recompile com uma versão compatível do caeles-sdk".to_string(),
+            };
+            issues.push(PreflightIssue::error(item, message));
+            continue;
+        };
+
+        let ExternType::Func(func_ty) = ty else {
+            issues.push(PreflightIssue::error(
+                item,
+                format!(
+                    "esperado import do tipo func, encontrado {}",
+                    extern_kind(&ty)
+                ),
+            ));
+            continue;
+        };
+
+        let expected = host_signature(host);
+        let found = func_signature(&func_ty);
+        if expected != found {
+            issues.push(PreflightIssue::error(
+                item,
+                format!(
+                    "incompatibilidade de ABI: runtime fornece {expected}, cápsula espera {found}; recompile com uma versão compatível do caeles-sdk"
+                ),
+            ));
+            continue;
+        }
+
+        if let Some(permission) = host.permission {
+            if !manifest.permissions.is_granted(permission) {
+                issues.push(PreflightIssue::warning(
+                    item,
+                    format!(
+                        "permissão '{permission}' está desabilitada no manifest; chamadas serão bloqueadas"
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn check_exports(module: &Module, manifest: &CapsuleManifest, issues: &mut Vec<PreflightIssue>) {
+    let uses_host_abi = module
+        .imports()
+        .any(|i| module_version_number(i.module()).is_some() || i.module() == WASI_P1_MODULE);
+
+    match module.get_export("memory") {
+        Some(ExternType::Memory(_)) => {}
+        Some(other) => issues.push(PreflightIssue::error(
+            "memory",
+            format!(
+                "export 'memory' deve ser memory, encontrado {}",
+                extern_kind(&other)
+            ),
+        )),
+        None if uses_host_abi => issues.push(PreflightIssue::error(
+            "memory",
+            "export 'memory' ausente; o host lê strings da memória da cápsula",
+        )),
+        None => {}
+    }
+
+    // Libraries are only called through their exports.
+    if manifest.lifecycle.kind == LifecycleKind::Library {
+        return;
+    }
+
+    match module.get_export(ENTRY_EXPORT) {
+        Some(ExternType::Func(ty)) => {
+            let found = func_signature(&ty);
+            if found != "() -> ()" {
+                issues.push(PreflightIssue::error(
+                    ENTRY_EXPORT,
+                    format!("assinatura deve ser () -> (), encontrada {found}"),
+                ));
+            }
+        }
+        Some(other) => issues.push(PreflightIssue::error(
+            ENTRY_EXPORT,
+            format!("esperado export func, encontrado {}", extern_kind(&other)),
+        )),
+        None if manifest.target == Target::WasiP1
+            && matches!(
+                module.get_export(WASI_START_EXPORT),
+                Some(ExternType::Func(_))
+            ) => {}
+        None => issues.push(PreflightIssue::error(
+            ENTRY_EXPORT,
+            "export de entrada ausente; declare `#[no_mangle] pub extern \"C\" fn caeles_main()`",
+        )),
+    }
+}
+
+/// Checks the module's imports and exports against the host ABI and the manifest.
+///
+/// `marker` is the version read from the `caeles_abi` custom section, if any.
+pub fn analyze(module: &Module, marker: Option<u32>, manifest: &CapsuleManifest) -> Preflight {
+    let mut issues = Vec::new();
+    let abi = resolve_abi(module, marker, &mut issues);
+    check_imports(module, abi, manifest, &mut issues);
+    check_exports(module, manifest, &mut issues);
+    Preflight {
+        format: CapsuleFormat::CoreModule,
+        abi: abi.unwrap_or(AbiVersion::LATEST),
+        issues,
+    }
+}
+
+/// Reads and compiles the wasm at `path`, then runs [`analyze`] on it.
+pub fn analyze_file(path: &Path, manifest: &CapsuleManifest) -> anyhow::Result<Preflight> {
+    let bytes = fs::read(path)
+        .with_context(|| format!("Não foi possível ler wasm '{}'", path.display()))?;
+    let engine = Engine::default();
+
+    if component::is_component(&bytes) {
+        let parsed = Component::new(&engine, &bytes)
+            .with_context(|| format!("componente wasm inválido '{}'", path.display()))?;
+        return Ok(Preflight {
+            format: CapsuleFormat::Component,
+            abi: AbiVersion::LATEST,
+            issues: component::analyze(&engine, &parsed),
+        });
+    }
+
+    let module = Module::new(&engine, &bytes)
+        .with_context(|| format!("módulo wasm inválido '{}'", path.display()))?;
+    Ok(analyze(&module, read_marker(&bytes), manifest))
+}
+
+/// Fails with every error-level issue listed, so users can fix them in one pass.
+pub fn ensure_no_errors(issues: &[PreflightIssue]) -> anyhow::Result<()> {
+    let errors: Vec<String> = issues
+        .iter()
+        .filter(|i| i.is_error())
+        .map(|i| format!("  - {i}"))
+        .collect();
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "Verificação preflight falhou ({} erro(s)):\n{}",
+            errors.len(),
+            errors.join("\n")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, Severity};
+    use crate::abi::AbiVersion;
+    use crate::manifest::CapsuleManifest;
+    use std::fs;
+    use tempfile::TempDir;
+    use wasmtime::{Engine, Module};
+
+    fn manifest_with_network(network: bool) -> CapsuleManifest {
+        let dir = TempDir::new().expect("temp directory should be created");
+        let path = dir.path().join("manifest.json");
+        fs::write(
+            &path,
+            format!(
+                r#"{{
+  "id": "com.caeles.tests.preflight",
+  "name": "Preflight",
+  "version": "0.1.0",
+  "entry": "capsule.wasm",
+  "permissions": {{ "notifications": true, "network": {network} }},
+  "lifecycle": {{ "kind": "on_demand" }}
+}}"#
+            ),
+        )
+        .expect("manifest should be written");
+        CapsuleManifest::load(&path).expect("manifest should load")
+    }
+
+    fn module(wat: &str) -> Module {
+        Module::new(&Engine::default(), wat).expect("WAT should compile")
+    }
+
+    #[test]
+    fn analyze_accepts_well_formed_capsule() {
+        let module = module(
+            r#"(module
+  (import "caeles" "host_log" (func (param i32 i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main")))"#,
+        );
+        let preflight = analyze(&module, None, &manifest_with_network(false));
+        assert!(preflight.issues.is_empty());
+        assert_eq!(preflight.abi, AbiVersion::V0);
+    }
+
+    #[test]
+    fn analyze_rejects_undeclared_and_mismatched_imports() {
+        let module = module(
+            r#"(module
+  (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+  (import "caeles" "host_open_socket" (func (param i32)))
+  (import "caeles" "host_log" (func (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main")))"#,
+        );
+        let issues = analyze(&module, None, &manifest_with_network(false)).issues;
+        let items: Vec<&str> = issues.iter().map(|i| i.item.as_str()).collect();
+        assert_eq!(
+            items,
+            vec![
+                "wasi_snapshot_preview1::fd_write",
+                "caeles::host_open_socket",
+                "caeles::host_log"
+            ]
+        );
+        assert!(issues.iter().all(|i| i.severity == Severity::Error));
+        assert!(issues[0].message.contains("WASI"));
+        assert!(issues[2].message.contains("incompatibilidade de ABI"));
+    }
+
+    #[test]
+    fn analyze_flags_disabled_permission_and_missing_exports() {
+        let module = module(
+            r#"(module
+  (import "caeles" "host_http_get" (func (param i32 i32) (result i32))))"#,
+        );
+        let issues = analyze(&module, None, &manifest_with_network(false)).issues;
+        let summary: Vec<(Severity, &str)> = issues
+            .iter()
+            .map(|i| (i.severity, i.item.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Severity::Warning, "caeles::host_http_get"),
+                (Severity::Error, "memory"),
+                (Severity::Error, "caeles_main"),
+            ]
+        );
+    }
+
+    #[test]
+    fn analyze_requires_declared_dependencies() {
+        let module = module(
+            r#"(module
+  (import "dep:com.caeles.tests.math" "add" (func (param i32 i32) (result i32)))
+  (func (export "caeles_main")))"#,
+        );
+        let issues = analyze(&module, None, &manifest_with_network(false)).issues;
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].item, "dep:com.caeles.tests.math::add");
+        assert!(issues[0].message.contains("não declarada"));
+    }
+
+    #[test]
+    fn analyze_resolves_abi_version_from_imports_and_marker() {
+        let module = module(
+            r#"(module
+  (import "caeles_v1" "host_log" (func (param i32 i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main")))"#,
+        );
+        let preflight = analyze(&module, Some(1), &manifest_with_network(false));
+        assert!(preflight.issues.is_empty());
+        assert_eq!(preflight.abi, AbiVersion::V1);
+
+        let mismatch = analyze(&module, Some(0), &manifest_with_network(false));
+        assert!(mismatch.issues[0].message.contains("declara ABI v0"));
+    }
+
+    #[test]
+    fn analyze_rejects_unsupported_and_mixed_abi_versions() {
+        let future = module(
+            r#"(module
+  (import "caeles_v9" "host_log" (func (param i32 i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main")))"#,
+        );
+        let issues = analyze(&future, None, &manifest_with_network(false)).issues;
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("requer ABI v9"));
+
+        let mixed = module(
+            r#"(module
+  (import "caeles" "host_log" (func (param i32 i32)))
+  (import "caeles_v1" "host_notify" (func (param i32 i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main")))"#,
+        );
+        let issues = analyze(&mixed, None, &manifest_with_network(false)).issues;
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("mistura"));
+    }
+}
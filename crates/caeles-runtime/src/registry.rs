@@ -0,0 +1,410 @@
+//! The registry file (`capsules/registry.json`): capsule ids with the name shown
+//! by `caeles list` and the manifest each one points to.
+//!
+//! `caeles registry` rewrites the file with serde_json's two-space layout and a
+//! trailing newline, keeping the order of the entries and any extra fields.
+//!
+//! Several registries can be configured (see `settings`); an id is taken from the
+//! first one that lists it. Registries at `http(s)://` URLs are mirrored, with the
+//! manifests and wasm of their capsules, under the state directory.
+
+use crate::blobs;
+use crate::manifest::{validate_capsule_id, CapsuleManifest};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub id: String,
+    pub name: String,
+    pub manifest: String,
+    /// Fields `caeles` does not use, written back as they were.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+fn read(path: &Path) -> Result<Vec<RegistryEntry>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Falha ao ler registry '{}'", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("Registry inválido '{}'", path.display()))
+}
+
+/// Entries of the registry; fails on duplicate ids.
+pub fn load_registry_entries(path: &Path) -> Result<Vec<RegistryEntry>> {
+    let entries = read(path)?;
+    let mut seen_ids = HashSet::new();
+    for entry in &entries {
+        if !seen_ids.insert(&entry.id) {
+            bail!("ID duplicado no registry: '{}'", entry.id);
+        }
+    }
+    Ok(entries)
+}
+
+pub fn save(path: &Path, entries: &[RegistryEntry]) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut text = serde_json::to_string_pretty(entries)?;
+    text.push('\n');
+    fs::write(path, text).with_context(|| format!("Falha ao gravar registry '{}'", path.display()))
+}
+
+/// Manifest path of an entry, relative to the directory of the registry.
+pub fn resolve_manifest_path(registry_path: &Path, manifest: &str) -> PathBuf {
+    let manifest_path = Path::new(manifest);
+    if manifest_path.is_absolute() {
+        return manifest_path.to_path_buf();
+    }
+    registry_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(manifest_path)
+}
+
+/// Adds the capsule of a valid manifest, named as in the manifest.
+///
+/// The manifest is stored relative to the registry, and a missing registry file is created.
+pub fn add(path: &Path, manifest_path: &Path) -> Result<RegistryEntry> {
+    let manifest = CapsuleManifest::load(manifest_path)?;
+    let mut entries = if path.exists() {
+        load_registry_entries(path)?
+    } else {
+        Vec::new()
+    };
+    if let Some(existing) = entries.iter().find(|e| e.id == manifest.id) {
+        bail!(
+            "'{}' já está no registry (manifest: {}); remova com `caeles registry remove {}`",
+            manifest.id,
+            existing.manifest,
+            manifest.id
+        );
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let entry = RegistryEntry {
+        id: manifest.id,
+        name: manifest.name,
+        manifest: entry_manifest(path, manifest_path)
+            .to_string_lossy()
+            .replace('\\', "/"),
+        extra: BTreeMap::new(),
+    };
+    entries.push(entry.clone());
+    save(path, &entries)?;
+    Ok(entry)
+}
+
+/// `manifest_path` as an entry of the registry at `registry_path` refers to it.
+fn entry_manifest(registry_path: &Path, manifest_path: &Path) -> PathBuf {
+    let registry_dir = match registry_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    crate::scaffold::relative_path(registry_dir, manifest_path)
+        .or_else(|| manifest_path.canonicalize().ok())
+        .unwrap_or_else(|| manifest_path.to_path_buf())
+}
+
+pub fn remove(path: &Path, id: &str) -> Result<RegistryEntry> {
+    let mut entries = load_registry_entries(path)?;
+    let index = entries
+        .iter()
+        .position(|e| e.id == id)
+        .ok_or_else(|| anyhow::anyhow!("Capsule id '{id}' não encontrado no registry"))?;
+    let entry = entries.remove(index);
+    save(path, &entries)?;
+    Ok(entry)
+}
+
+/// A problem with one entry of the registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegistryIssue {
+    pub registry: String,
+    pub id: String,
+    pub message: String,
+}
+
+/// Every problem of the registry: duplicate ids, missing or invalid manifests,
+/// and ids or names that differ from the manifest.
+pub fn verify(path: &Path) -> Result<(usize, Vec<RegistryIssue>)> {
+    let entries = read(path)?;
+    let mut issues = Vec::new();
+    let mut issue = |id: &str, message: String| {
+        issues.push(RegistryIssue {
+            registry: path.display().to_string(),
+            id: id.to_string(),
+            message,
+        })
+    };
+
+    let mut seen_ids = HashSet::new();
+    for entry in &entries {
+        if !seen_ids.insert(&entry.id) {
+            issue(&entry.id, "id duplicado".to_string());
+        }
+        let manifest_path = resolve_manifest_path(path, &entry.manifest);
+        if !manifest_path.is_file() {
+            issue(
+                &entry.id,
+                format!("manifest não encontrado: {}", manifest_path.display()),
+            );
+            continue;
+        }
+        let (manifest, manifest_issues) = CapsuleManifest::check(&manifest_path)?;
+        for manifest_issue in manifest_issues {
+            issue(
+                &entry.id,
+                format!(
+                    "manifest inválido ({}): {manifest_issue}",
+                    manifest_path.display()
+                ),
+            );
+        }
+        let Some(manifest) = manifest else {
+            continue;
+        };
+        if manifest.id != entry.id {
+            issue(&entry.id, format!("manifest declara id '{}'", manifest.id));
+        }
+        if manifest.name != entry.name {
+            issue(
+                &entry.id,
+                format!(
+                    "nome '{}' difere do manifest ('{}')",
+                    entry.name, manifest.name
+                ),
+            );
+        }
+    }
+    Ok((entries.len(), issues))
+}
+
+/// Entries whose id or name contains `text`, ignoring case.
+pub fn search<'a>(entries: &'a [RegistryEntry], text: &str) -> Vec<&'a RegistryEntry> {
+    let text = text.to_lowercase();
+    entries
+        .iter()
+        .filter(|e| e.id.to_lowercase().contains(&text) || e.name.to_lowercase().contains(&text))
+        .collect()
+}
+
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// An entry with the manifest it points to and the registry that lists it.
+#[derive(Debug, Clone)]
+pub struct Listed {
+    pub entry: RegistryEntry,
+    pub manifest_path: PathBuf,
+    pub registry: String,
+}
+
+/// Registries in precedence order.
+#[derive(Debug)]
+pub struct Registries {
+    locations: Vec<String>,
+    cache_dir: PathBuf,
+    entries: OnceCell<Vec<Listed>>,
+}
+
+impl Registries {
+    /// `locations` are files or URLs; URLs are mirrored under `cache_dir`.
+    pub fn new(locations: Vec<String>, cache_dir: &Path) -> Self {
+        Self {
+            locations,
+            cache_dir: cache_dir.to_path_buf(),
+            entries: OnceCell::new(),
+        }
+    }
+
+    pub fn locations(&self) -> &[String] {
+        &self.locations
+    }
+
+    pub fn describe(&self) -> String {
+        self.locations.join(", ")
+    }
+
+    /// File holding the entries of `location`: the file itself or the mirror of a URL.
+    pub fn local_file(&self, location: &str) -> Result<PathBuf> {
+        if !is_url(location) {
+            return Ok(PathBuf::from(location));
+        }
+        let dir = self
+            .cache_dir
+            .join(&blobs::sha256_hex(location.as_bytes())[..16]);
+        mirror(location, &dir)
+    }
+
+    /// Entries of every registry; an id listed twice comes from the first registry.
+    ///
+    /// Registry files that do not exist are skipped.
+    pub fn entries(&self) -> Result<&[Listed]> {
+        if let Some(entries) = self.entries.get() {
+            return Ok(entries);
+        }
+        let mut listed: Vec<Listed> = Vec::new();
+        for location in &self.locations {
+            let file = self.local_file(location)?;
+            if !file.is_file() {
+                continue;
+            }
+            for entry in load_registry_entries(&file)? {
+                if listed.iter().any(|l| l.entry.id == entry.id) {
+                    continue;
+                }
+                listed.push(Listed {
+                    manifest_path: resolve_manifest_path(&file, &entry.manifest),
+                    entry,
+                    registry: location.clone(),
+                });
+            }
+        }
+        Ok(self.entries.get_or_init(|| listed))
+    }
+
+    pub fn find(&self, id: &str) -> Result<Option<&Listed>> {
+        Ok(self.entries()?.iter().find(|l| l.entry.id == id))
+    }
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>> {
+    let response = ureq::get(url)
+        .call()
+        .map_err(|err| anyhow::anyhow!("Falha ao baixar '{url}': {err}"))?;
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Falha ao baixar '{url}'"))?;
+    Ok(bytes)
+}
+
+/// Downloads a remote registry into `dir`; keeps the previous mirror when the
+/// registry cannot be reached.
+fn mirror(url: &str, dir: &Path) -> Result<PathBuf> {
+    let registry_file = dir.join("registry.json");
+    match download(url, dir) {
+        Ok(()) => Ok(registry_file),
+        Err(err) if registry_file.is_file() => {
+            eprintln!("> aviso: {err:#}; usando a cópia local do registry '{url}'");
+            Ok(registry_file)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn download(url: &str, dir: &Path) -> Result<()> {
+    let base = url::Url::parse(url).with_context(|| format!("URL de registry inválida '{url}'"))?;
+    let entries: Vec<RegistryEntry> = serde_json::from_slice(&fetch(url)?)
+        .with_context(|| format!("Registry inválido '{url}'"))?;
+
+    let mut mirrored = Vec::new();
+    for entry in entries {
+        validate_capsule_id(&entry.id).map_err(|message| {
+            anyhow::anyhow!("Id inválido '{}' em '{url}': {message}", entry.id)
+        })?;
+        let manifest_url = base.join(&entry.manifest)?;
+        let manifest_bytes = fetch(manifest_url.as_str())?;
+        let manifest: serde_json::Value = serde_json::from_slice(&manifest_bytes)
+            .with_context(|| format!("Manifest inválido '{manifest_url}'"))?;
+        let wasm_entry = manifest["entry"]
+            .as_str()
+            .filter(|e| !e.is_empty() && !e.starts_with('/') && !e.contains(".."))
+            .with_context(|| format!("Manifest '{manifest_url}' sem 'entry' relativo"))?;
+
+        let capsule_dir = dir.join(&entry.id);
+        let manifest_path = capsule_dir.join("manifest.json");
+        let wasm_path = capsule_dir.join(wasm_entry);
+        let unchanged = fs::read(&manifest_path).is_ok_and(|old| old == manifest_bytes);
+        if !unchanged || !wasm_path.is_file() {
+            let wasm = fetch(manifest_url.join(wasm_entry)?.as_str())?;
+            if let Some(parent) = wasm_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&wasm_path, wasm)?;
+        }
+        fs::write(&manifest_path, &manifest_bytes)?;
+
+        mirrored.push(RegistryEntry {
+            manifest: format!("{}/manifest.json", entry.id),
+            ..entry
+        });
+    }
+    save(&dir.join("registry.json"), &mirrored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add, load_registry_entries, remove, search, verify};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn write_manifest(path: &Path, id: &str, name: &str) {
+        fs::create_dir_all(path.parent().expect("manifest should have a parent"))
+            .expect("manifest directory should be created");
+        let manifest = serde_json::json!({
+            "id": id,
+            "name": name,
+            "version": "0.1.0",
+            "entry": "capsule.wasm",
+            "permissions": { "notifications": false, "network": false },
+            "lifecycle": { "kind": "on_demand" }
+        });
+        fs::write(path, manifest.to_string()).expect("manifest should be written");
+    }
+
+    #[test]
+    fn adds_removes_and_verifies_entries_keeping_the_layout() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        let registry = root.join("registry.json");
+        fs::write(
+            &registry,
+            "[\n  {\n    \"id\": \"com.tests.old\",\n    \"name\": \"Old\",\n    \"manifest\": \"missing/manifest.json\",\n    \"owner\": \"team\"\n  }\n]\n",
+        )
+        .expect("registry should be written");
+
+        let hello = root.join("hello/manifest.json");
+        write_manifest(&hello, "com.tests.hello", "Hello");
+        let entry = add(&registry, &hello).expect("capsule should be added");
+        assert_eq!(entry.name, "Hello");
+        assert_eq!(entry.manifest, "hello/manifest.json");
+        assert!(add(&registry, &hello)
+            .expect_err("duplicate should fail")
+            .to_string()
+            .contains("já está no registry"));
+
+        let entries = load_registry_entries(&registry).expect("registry should load");
+        assert_eq!(entries[0].extra["owner"], "team");
+        assert_eq!(search(&entries, "HELL").len(), 1);
+        assert_eq!(search(&entries, "com.tests").len(), 2);
+
+        write_manifest(&hello, "com.tests.hello", "Hello v2");
+        let (count, issues) = verify(&registry).expect("registry should be checked");
+        assert_eq!(count, 2);
+        let messages: Vec<String> = issues
+            .iter()
+            .map(|i| format!("{}: {}", i.id, i.message))
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("com.tests.old: manifest não encontrado"));
+        assert!(messages[1].contains("'Hello' difere do manifest ('Hello v2')"));
+
+        remove(&registry, "com.tests.hello").expect("capsule should be removed");
+        assert!(remove(&registry, "com.tests.hello").is_err());
+        assert_eq!(
+            fs::read_to_string(&registry).expect("registry should be readable"),
+            "[\n  {\n    \"id\": \"com.tests.old\",\n    \"name\": \"Old\",\n    \"manifest\": \"missing/manifest.json\",\n    \"owner\": \"team\"\n  }\n]\n"
+        );
+    }
+}
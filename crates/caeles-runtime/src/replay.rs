@@ -0,0 +1,287 @@
+use crate::runtime::LogLevel;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Format version written to trace files.
+pub const TRACE_VERSION: u32 = 1;
+
+/// Host calls whose results come from outside the sandbox (network, clocks,
+/// entropy, files kept between runs) or that only wait; on replay they are
+/// served from the trace instead of running again, so replays never touch files.
+const SERVED_FROM_TRACE: &[&str] = &[
+    "host_http_get",
+    "host_http_get_cap",
+    "http.get",
+    "host_now_ms",
+    "host_monotonic_ns",
+    "host_sleep_ms",
+    "host_random_bytes",
+    "host_fs_open",
+    "host_fs_read",
+    "host_fs_write",
+    "host_fs_close",
+    "host_fs_list",
+    "host_fs_delete",
+];
+
+/// One host call made by the capsule, in call order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostCall {
+    pub seq: usize,
+    pub function: String,
+    pub args: Value,
+    pub result: Value,
+}
+
+/// Trace of a recorded run (`caeles run --record`), replayable with `caeles replay`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceFile {
+    pub version: u32,
+    pub run_id: String,
+    pub capsule_id: String,
+    pub manifest_path: String,
+    pub wasm_sha256: String,
+    /// Salt of the capability handles, so replayed handles match the recorded ones.
+    pub capability_salt: u64,
+    /// Input given with `--input`, as text.
+    #[serde(default)]
+    pub input: Option<String>,
+    pub log_level: LogLevel,
+    /// Seed of a `--deterministic` recording, reused by the replay.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Resolved config of the run; secrets are never written to traces.
+    #[serde(default)]
+    pub config: BTreeMap<String, String>,
+    pub exit_code: i32,
+    pub calls: Vec<HostCall>,
+}
+
+impl TraceFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Falha ao ler trace '{}'", path.display()))?;
+        let trace: Self = serde_json::from_str(&text)
+            .with_context(|| format!("Trace invalido '{}'", path.display()))?;
+        if trace.version != TRACE_VERSION {
+            anyhow::bail!(
+                "Versao de trace nao suportada: {} (esperado {TRACE_VERSION})",
+                trace.version
+            );
+        }
+        Ok(trace)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Where the trace of `run_id` is stored.
+pub fn trace_file_path(base: &Path, run_id: &str) -> PathBuf {
+    base.join("traces").join(format!("{run_id}.json"))
+}
+
+/// Replayed run stopped matching its trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayDivergence {
+    pub message: String,
+}
+
+impl fmt::Display for ReplayDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "replay diverged: {}", self.message)
+    }
+}
+
+impl std::error::Error for ReplayDivergence {}
+
+/// How host calls of a run are traced.
+#[derive(Debug, Clone, Default)]
+pub enum HostCallMode {
+    #[default]
+    Off,
+    Record,
+    Replay(Vec<HostCall>),
+}
+
+fn describe(function: &str, args: &Value) -> String {
+    format!("{function}({args})")
+}
+
+/// Records host calls, or checks them against a trace on replay.
+///
+/// Host functions call [`CallRecorder::enter`] with their arguments before
+/// doing any work and [`CallRecorder::exit`] with the result.
+#[derive(Debug, Default)]
+pub struct CallRecorder {
+    mode: HostCallMode,
+    calls: Vec<HostCall>,
+    cursor: usize,
+    /// First divergence seen by a host function that cannot trap.
+    divergence: Option<ReplayDivergence>,
+}
+
+impl CallRecorder {
+    pub fn new(mode: HostCallMode) -> Self {
+        Self {
+            mode,
+            calls: Vec::new(),
+            cursor: 0,
+            divergence: None,
+        }
+    }
+
+    /// Matches the call against the trace on replay. Returns the recorded
+    /// result when the call must not run again.
+    pub fn enter(
+        &mut self,
+        function: &str,
+        args: &Value,
+    ) -> Result<Option<Value>, ReplayDivergence> {
+        let HostCallMode::Replay(recorded) = &self.mode else {
+            return Ok(None);
+        };
+        let Some(expected) = recorded.get(self.cursor) else {
+            return Err(ReplayDivergence {
+                message: format!(
+                    "call #{} {} was not recorded (trace has {} calls)",
+                    self.cursor,
+                    describe(function, args),
+                    recorded.len()
+                ),
+            });
+        };
+        if expected.function != function || &expected.args != args {
+            return Err(ReplayDivergence {
+                message: format!(
+                    "call #{}: expected {}, got {}",
+                    self.cursor,
+                    describe(&expected.function, &expected.args),
+                    describe(function, args)
+                ),
+            });
+        }
+        if SERVED_FROM_TRACE.contains(&function) {
+            self.cursor += 1;
+            return Ok(Some(expected.result.clone()));
+        }
+        Ok(None)
+    }
+
+    /// Stores the result of a call that ran; on replay it must equal the recorded one.
+    pub fn exit(
+        &mut self,
+        function: &str,
+        args: Value,
+        result: Value,
+    ) -> Result<(), ReplayDivergence> {
+        match &self.mode {
+            HostCallMode::Off => Ok(()),
+            HostCallMode::Record => {
+                self.calls.push(HostCall {
+                    seq: self.calls.len(),
+                    function: function.to_string(),
+                    args,
+                    result,
+                });
+                Ok(())
+            }
+            HostCallMode::Replay(recorded) => {
+                let expected = &recorded[self.cursor];
+                if expected.result != result {
+                    return Err(ReplayDivergence {
+                        message: format!(
+                            "call #{} {}: recorded result {}, got {}",
+                            self.cursor,
+                            describe(function, &args),
+                            expected.result,
+                            result
+                        ),
+                    });
+                }
+                self.cursor += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Keeps a divergence to be reported by [`CallRecorder::finish`].
+    pub fn fail(&mut self, divergence: ReplayDivergence) {
+        self.divergence.get_or_insert(divergence);
+    }
+
+    /// Calls recorded so far (empty unless recording).
+    pub fn take_calls(&mut self) -> Vec<HostCall> {
+        std::mem::take(&mut self.calls)
+    }
+
+    /// On replay, fails on a kept divergence or if the capsule made fewer
+    /// calls than recorded.
+    pub fn finish(&self) -> Result<(), ReplayDivergence> {
+        if let Some(divergence) = &self.divergence {
+            return Err(divergence.clone());
+        }
+        match &self.mode {
+            HostCallMode::Replay(recorded) if self.cursor < recorded.len() => {
+                let next = &recorded[self.cursor];
+                Err(ReplayDivergence {
+                    message: format!(
+                        "capsule stopped after {} of {} recorded calls; next was {}",
+                        self.cursor,
+                        recorded.len(),
+                        describe(&next.function, &next.args)
+                    ),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CallRecorder, HostCallMode};
+    use serde_json::json;
+
+    #[test]
+    fn replay_serves_network_results_and_flags_divergence() {
+        let mut recorder = CallRecorder::new(HostCallMode::Record);
+        recorder
+            .exit("host_log", json!(["start"]), json!(null))
+            .expect("recording should succeed");
+        recorder
+            .exit("host_http_get_cap", json!([7, "https://a.test"]), json!(0))
+            .expect("recording should succeed");
+        let calls = recorder.take_calls();
+
+        let mut replay = CallRecorder::new(HostCallMode::Replay(calls.clone()));
+        assert_eq!(replay.enter("host_log", &json!(["start"])), Ok(None));
+        replay
+            .exit("host_log", json!(["start"]), json!(null))
+            .expect("result should match");
+        assert_eq!(
+            replay.enter("host_http_get_cap", &json!([7, "https://a.test"])),
+            Ok(Some(json!(0)))
+        );
+        assert!(replay.finish().is_ok());
+
+        let mut diverged = CallRecorder::new(HostCallMode::Replay(calls));
+        let err = diverged
+            .enter("host_log", &json!(["other"]))
+            .expect_err("different args should diverge");
+        assert!(err.message.contains("call #0"), "{}", err.message);
+        assert!(diverged.finish().is_err());
+    }
+}
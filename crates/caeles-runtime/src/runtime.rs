@@ -1,8 +1,375 @@
-use crate::manifest::CapsuleManifest;
-use anyhow::{Context, Result};
-use wasmtime::{Caller, Engine, Extern, Linker, Module, Store};
+use crate::abi::{self, AbiVersion, ENTRY_EXPORT, WASI_START_EXPORT};
+use crate::bus::{self, Published};
+use crate::capabilities::{self, CapabilityKind, CapabilityTable, HandleError};
+use crate::component;
+use crate::dependencies::{self, ResolvedDependency};
+use crate::determinism::{self, VirtualClock};
+use crate::files::{CapsuleFiles, FsError, OpenMode};
+use crate::manifest::{CapsuleManifest, LifecycleKind, PreopenMode, Target};
+use crate::preflight;
+use crate::replay::{CallRecorder, HostCall, HostCallMode};
+use crate::state::{Redactor, RunLog};
+use anyhow::{bail, Context, Result};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wasmtime::{Caller, Engine, Extern, Instance, Linker, Module, Store, Trap};
+use wasmtime_wasi::pipe::MemoryOutputPipe;
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::{DirPerms, FilePerms, I32Exit, WasiCtxBuilder};
 
-fn read_string_from_memory(mut caller: Caller<'_, ()>, ptr: i32, len: i32) -> Option<String> {
+/// Maximum bytes of stdout/stderr kept per WASI run.
+const WASI_OUTPUT_CAPACITY: usize = 1024 * 1024;
+
+/// Maximum bytes a single `host_random_bytes` call can fill.
+const MAX_RANDOM_BYTES: usize = 64 * 1024;
+
+/// Per-run data host functions reach through the wasmtime store.
+pub struct HostState {
+    pub capabilities: CapabilityTable,
+    pub log: RunLog,
+    /// Only present for `wasm32-wasip1` capsules.
+    pub wasi: Option<WasiP1Ctx>,
+    /// Bytes passed with `caeles run --input`.
+    pub input: Vec<u8>,
+    /// Panic message reported through `host_panic` before the capsule trapped.
+    pub panic: Option<String>,
+    /// Non-zero exit reported through `host_exit`.
+    pub exit: Option<CapsuleExit>,
+    /// Capsule log records below this level are dropped.
+    pub log_level: LogLevel,
+    /// Canned HTTP results used instead of the network (`caeles test`).
+    pub http_stubs: Option<HttpStubs>,
+    pub trace: RunTrace,
+    pub calls: CallRecorder,
+    /// Clock behind `host_now_ms`, `host_monotonic_ns` and `host_sleep_ms`.
+    pub clock: RunClock,
+    /// Source of `host_random_bytes`; seeded in deterministic mode.
+    pub rng: Box<dyn RngCore + Send>,
+    /// Limit of the whole run (`--timeout-ms`).
+    pub timeout: Option<Duration>,
+    /// `permissions.wall_clock` of the manifest.
+    pub wall_clock: bool,
+    /// Resolved config values served by `host_config_get`.
+    pub config: BTreeMap<String, String>,
+    /// Secrets declared by the manifest, served by `host_secret_get`.
+    pub secrets: BTreeMap<String, String>,
+    /// File area behind `host_fs_*`; only present with `permissions.filesystem`.
+    pub files: Option<CapsuleFiles>,
+    /// `permissions.publish` of the manifest.
+    pub publish_topics: Vec<String>,
+}
+
+/// Clock of a run: real time, or the virtual clock of a deterministic run.
+#[derive(Debug, Clone)]
+pub enum RunClock {
+    /// Real time; the instant is the start of the run.
+    Real(Instant),
+    Virtual(VirtualClock),
+}
+
+impl RunClock {
+    /// Time since the Unix epoch.
+    fn wall(&self) -> Duration {
+        match self {
+            RunClock::Real(_) => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            RunClock::Virtual(clock) => determinism::DETERMINISTIC_EPOCH + clock.tick(),
+        }
+    }
+
+    /// Time since the run started.
+    fn monotonic(&self) -> Duration {
+        match self {
+            RunClock::Real(started) => started.elapsed(),
+            RunClock::Virtual(clock) => clock.tick(),
+        }
+    }
+
+    /// Time since the run started, without advancing a virtual clock.
+    fn elapsed(&self) -> Duration {
+        match self {
+            RunClock::Real(started) => started.elapsed(),
+            RunClock::Virtual(clock) => clock.elapsed(),
+        }
+    }
+
+    /// Blocks for `duration`; a virtual clock just moves forward.
+    fn sleep(&self, duration: Duration) {
+        match self {
+            RunClock::Real(_) => thread::sleep(duration),
+            RunClock::Virtual(clock) => {
+                clock.advance(duration);
+            }
+        }
+    }
+}
+
+/// The run went past its `--timeout-ms` limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunTimeout {
+    pub timeout: Duration,
+}
+
+impl fmt::Display for RunTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "capsule exceeded the run timeout of {} ms",
+            self.timeout.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for RunTimeout {}
+
+/// Exit code of runs stopped by [`RunTimeout`], as with `timeout(1)`.
+pub const EXIT_TIMEOUT: i32 = 124;
+
+/// Interrupts the capsule when the run timeout elapses; dropping it stops the timer.
+pub struct TimeoutGuard {
+    _cancel: mpsc::Sender<()>,
+}
+
+/// Arms the run timeout of `store`. The engine must have epoch interruption enabled.
+pub fn arm_timeout(store: &mut Store<HostState>) -> Option<TimeoutGuard> {
+    let timeout = store.data().timeout?;
+    store.set_epoch_deadline(1);
+    let engine = store.engine().clone();
+    let (cancel, cancelled) = mpsc::channel::<()>();
+    thread::spawn(move || {
+        if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(timeout) {
+            engine.increment_epoch();
+        }
+    });
+    Some(TimeoutGuard { _cancel: cancel })
+}
+
+/// Turns the trap raised by an elapsed epoch deadline into a [`RunTimeout`].
+pub fn timeout_error(err: anyhow::Error, timeout: Option<Duration>) -> anyhow::Error {
+    match (err.downcast_ref::<Trap>(), timeout) {
+        (Some(Trap::Interrupt), Some(timeout)) => RunTimeout { timeout }.into(),
+        _ => err,
+    }
+}
+
+/// Severity of capsule log records; the numeric codes are part of the ABI.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl LogLevel {
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            1 => Some(LogLevel::Error),
+            2 => Some(LogLevel::Warn),
+            3 => Some(LogLevel::Info),
+            4 => Some(LogLevel::Debug),
+            5 => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+/// Prints a structured capsule log record and stores it as a `capsule_log {json}` run log line.
+fn emit_capsule_log(
+    state: &mut HostState,
+    level: LogLevel,
+    target: &str,
+    message: &str,
+    fields: serde_json::Map<String, serde_json::Value>,
+) {
+    if level > state.log_level {
+        return;
+    }
+
+    let mut line = format!(
+        "[capsule-log {} {target}] {message}",
+        level.as_str().to_uppercase()
+    );
+    for (key, value) in &fields {
+        match value {
+            serde_json::Value::String(text) => line.push_str(&format!(" {key}={text}")),
+            other => line.push_str(&format!(" {key}={other}")),
+        }
+    }
+    println!("{}", state.redact(&line));
+    state.trace.logs.push(state.redact(message));
+
+    let record = json!({
+        "level": level.as_str(),
+        "target": target,
+        "message": message,
+        "fields": fields,
+    });
+    if let Err(err) = state.log.line(&format!("capsule_log {record}")) {
+        eprintln!("[caeles-runtime] failed to write run log: {err}");
+    }
+}
+
+/// Non-zero exit reported by the capsule itself instead of a trap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapsuleExit {
+    pub code: i32,
+    pub message: String,
+}
+
+impl fmt::Display for CapsuleExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "capsule exited with code {}", self.code)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CapsuleExit {}
+
+/// Exit code of a finished run: 0 on success, the capsule's own code for
+/// `host_exit`/`proc_exit`, [`EXIT_TIMEOUT`] for timeouts and 101 for panics,
+/// traps and host errors.
+pub fn exit_code(result: &Result<()>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(err) if err.is::<RunTimeout>() => EXIT_TIMEOUT,
+        Err(err) => err
+            .downcast_ref::<CapsuleExit>()
+            .map_or(101, |exit| exit.code),
+    }
+}
+
+/// What a capsule did during a run, as observed by the host.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RunTrace {
+    /// Messages of the log records that passed the level filter.
+    pub logs: Vec<String>,
+    /// Notifications delivered to the host.
+    pub notifications: Vec<String>,
+    /// Value set with `host_set_output`, or the stdout of a WASI capsule.
+    pub output: Option<String>,
+    /// Messages sent with `host_publish`, delivered to subscribers after the run.
+    pub published: Vec<Published>,
+}
+
+/// Result of [`run_capsule`]; the trace is kept even when the run fails.
+#[derive(Debug)]
+pub struct RunOutcome {
+    pub trace: RunTrace,
+    /// Host calls in order; only filled when recording.
+    pub host_calls: Vec<HostCall>,
+    pub capability_salt: u64,
+    pub result: Result<()>,
+}
+
+impl HostState {
+    /// Hides secret values in capsule-provided text before the host shows it.
+    pub(crate) fn redact(&self, text: &str) -> String {
+        self.log.redactor().redact(text)
+    }
+
+    /// File area of the run; the call is reported and audited when `permissions.filesystem` is off.
+    fn files(&mut self, function: &str) -> Result<&mut CapsuleFiles, FsError> {
+        if self.files.is_none() {
+            println!("[capsule-fs BLOCKED] permission 'filesystem' = false");
+            self.log.audit(
+                "permission_denied",
+                json!({ "function": function, "kind": "filesystem" }),
+            );
+        }
+        self.files.as_mut().ok_or(FsError::Denied)
+    }
+
+    /// Reports failed file calls worth a look: escapes from the file area (audited) and full quotas.
+    fn fs_result<T>(
+        &self,
+        function: &str,
+        subject: &str,
+        result: Result<T, FsError>,
+    ) -> Result<T, FsError> {
+        match &result {
+            Err(FsError::InvalidPath) => {
+                let subject = self.redact(subject);
+                println!("[capsule-fs REJECTED] {function}: '{subject}' is outside the capsule file area");
+                self.log.audit(
+                    "fs_rejected",
+                    json!({ "function": function, "path": subject, "reason": FsError::InvalidPath.reason() }),
+                );
+            }
+            Err(FsError::QuotaExceeded) => {
+                if let Some(files) = &self.files {
+                    println!(
+                        "[capsule-fs QUOTA] {function}: {subject} would exceed the quota of {} bytes ({} used)",
+                        files.quota(),
+                        files.used()
+                    );
+                }
+            }
+            _ => {}
+        }
+        result
+    }
+
+    fn reject_handle(&self, function: &str, handle: i32, err: HandleError) {
+        println!(
+            "[capsule-capability REJECTED] {function}: handle {handle} ({})",
+            err.reason()
+        );
+        self.log.audit(
+            "capability_rejected",
+            json!({ "function": function, "handle": handle, "reason": err.reason() }),
+        );
+    }
+}
+
+fn read_string_from_memory(
+    caller: &mut Caller<'_, HostState>,
+    ptr: i32,
+    len: i32,
+) -> Option<String> {
+    match String::from_utf8(read_bytes_from_memory(caller, ptr, len)?) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            eprintln!("[caeles-runtime] bytes are not valid UTF-8");
+            None
+        }
+    }
+}
+
+fn read_bytes_from_memory(
+    caller: &mut Caller<'_, HostState>,
+    ptr: i32,
+    len: i32,
+) -> Option<Vec<u8>> {
     if ptr < 0 || len < 0 {
         eprintln!("[caeles-runtime] invalid pointer or length (ptr={ptr}, len={len})");
         return None;
@@ -17,22 +384,1214 @@ fn read_string_from_memory(mut caller: Caller<'_, ()>, ptr: i32, len: i32) -> Op
     };
 
     let mut buf = vec![0u8; len as usize];
-    if let Err(err) = memory.read(&mut caller, ptr as usize, &mut buf) {
+    if let Err(err) = memory.read(&mut *caller, ptr as usize, &mut buf) {
         eprintln!("[caeles-runtime] error reading capsule memory: {err}");
         return None;
     }
+    Some(buf)
+}
 
-    match String::from_utf8(buf) {
-        Ok(value) => Some(value),
-        Err(_) => {
-            eprintln!("[caeles-runtime] bytes are not valid UTF-8");
-            None
+fn write_to_memory(caller: &mut Caller<'_, HostState>, ptr: i32, bytes: &[u8]) -> bool {
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        eprintln!("[caeles-runtime] capsule does not export memory \"memory\"");
+        return false;
+    };
+    if ptr < 0 {
+        eprintln!("[caeles-runtime] invalid pointer (ptr={ptr})");
+        return false;
+    }
+    if let Err(err) = memory.write(&mut *caller, ptr as usize, bytes) {
+        eprintln!("[caeles-runtime] error writing capsule memory: {err}");
+        return false;
+    }
+    true
+}
+
+/// Runs a host call through the run's [`CallRecorder`]; a replay divergence traps the capsule.
+fn traced<T: Serialize + DeserializeOwned>(
+    caller: &mut Caller<'_, HostState>,
+    function: &str,
+    args: serde_json::Value,
+    live: impl FnOnce(&mut Caller<'_, HostState>) -> T,
+) -> Result<T> {
+    let redactor = caller.data().log.redactor().clone();
+    let args = redactor.redact_json(&args);
+    if let Some(recorded) = caller.data_mut().calls.enter(function, &args)? {
+        println!("[caeles-replay] {function} served from trace -> {recorded}");
+        return Ok(serde_json::from_value(recorded)?);
+    }
+    let result = live(caller);
+    let recorded = redactor.redact_json(&serde_json::to_value(&result)?);
+    caller.data_mut().calls.exit(function, args, recorded)?;
+    Ok(result)
+}
+
+/// Why a host-mediated HTTP request did not produce a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpFailure {
+    Blocked,
+    InvalidRequest,
+    HostFailure,
+}
+
+impl HttpFailure {
+    /// Status code returned by the core ABI `host_http_get*` functions.
+    fn code(self) -> i32 {
+        match self {
+            HttpFailure::Blocked => 1,
+            HttpFailure::InvalidRequest => 2,
+            HttpFailure::HostFailure => 3,
+        }
+    }
+}
+
+/// Maximum response body kept from a host-mediated HTTP request.
+const MAX_HTTP_BODY: u64 = 1024 * 1024;
+
+/// Redirects followed by a host-mediated HTTP request, each checked against the scope.
+const MAX_HTTP_REDIRECTS: u32 = 5;
+
+/// Response of a host-mediated HTTP request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Canned results by URL; a URL without a stub fails with `HostFailure`.
+pub type HttpStubs = HashMap<String, Result<HttpResponse, HttpFailure>>;
+
+/// Performs a GET within the network capability `scope`, answered from `stubs` when given.
+pub fn http_request(
+    stubs: Option<&HttpStubs>,
+    scope: &[String],
+    url: &str,
+    redactor: &Redactor,
+) -> Result<HttpResponse, HttpFailure> {
+    let shown = redactor.redact(url);
+    let host = match url::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {
+            parsed.host_str().unwrap_or_default().to_string()
+        }
+        _ => {
+            println!("[capsule-network ERROR] invalid URL (use http:// or https://): {shown}");
+            return Err(HttpFailure::InvalidRequest);
+        }
+    };
+
+    if !capabilities::host_in_scope(scope, &host) {
+        println!("[capsule-network BLOCKED] host '{host}' is outside permissions.network_hosts. Requested URL: {shown}");
+        return Err(HttpFailure::Blocked);
+    }
+
+    if let Some(stubs) = stubs {
+        let Some(stub) = stubs.get(url) else {
+            println!("[capsule-network STUB] no stub for {shown}");
+            return Err(HttpFailure::HostFailure);
+        };
+        if let Ok(response) = stub {
+            println!("[capsule-network STUB] GET {shown} -> {}", response.status);
+        }
+        return stub.clone();
+    }
+
+    // Redirects are followed here rather than by ureq, so every hop is checked
+    // against the scope of the capability.
+    let agent = ureq::AgentBuilder::new().redirects(0).build();
+    let mut url = url::Url::parse(url).map_err(|_| HttpFailure::InvalidRequest)?;
+    let mut redirects = 0;
+    let result = loop {
+        let result = agent.request_url("GET", &url).call();
+        let location = match &result {
+            Ok(response) if (300..400).contains(&response.status()) => {
+                response.header("location").map(str::to_string)
+            }
+            _ => None,
+        };
+        let Some(location) = location else {
+            break result;
+        };
+        redirects += 1;
+        let next = match url.join(&location) {
+            Ok(next) if matches!(next.scheme(), "http" | "https") => next,
+            _ => {
+                println!("[capsule-network ERROR] GET {shown} redirected to an invalid URL");
+                return Err(HttpFailure::HostFailure);
+            }
+        };
+        let next_host = next.host_str().unwrap_or_default();
+        if !capabilities::host_in_scope(scope, next_host) {
+            println!("[capsule-network BLOCKED] GET {shown} redirected to host '{next_host}', outside permissions.network_hosts");
+            return Err(HttpFailure::Blocked);
+        }
+        if redirects > MAX_HTTP_REDIRECTS {
+            println!(
+                "[capsule-network ERROR] GET {shown}: more than {MAX_HTTP_REDIRECTS} redirects"
+            );
+            return Err(HttpFailure::HostFailure);
         }
+        url = next;
+    };
+
+    match result {
+        Ok(response) => {
+            println!("[capsule-network] GET {} -> {}", shown, response.status());
+            let status = response.status();
+            let mut body = String::new();
+            response
+                .into_reader()
+                .take(MAX_HTTP_BODY)
+                .read_to_string(&mut body)
+                .map_err(|_| HttpFailure::HostFailure)?;
+            Ok(HttpResponse { status, body })
+        }
+        Err(err) => {
+            println!("[capsule-network ERROR] GET {} failed: {}", shown, err);
+            Err(HttpFailure::HostFailure)
+        }
+    }
+}
+
+fn http_get(state: &HostState, scope: &[String], url: &str) -> i32 {
+    match http_request(state.http_stubs.as_ref(), scope, url, state.log.redactor()) {
+        Ok(_) => 0,
+        Err(failure) => failure.code(),
     }
 }
 
-pub fn run_capsule(manifest: &CapsuleManifest) -> Result<()> {
-    let engine = Engine::default();
+/// Links the host function set of `abi` under its import module name.
+fn link_host_functions(linker: &mut Linker<HostState>, abi: AbiVersion) -> Result<()> {
+    let module = abi.module();
+    let provides = |name: &str| abi.function(name).is_some();
+
+    linker.func_wrap(
+        module,
+        "host_log",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<()> {
+            let msg = read_string_from_memory(&mut caller, ptr, len);
+            traced(&mut caller, "host_log", json!([msg]), |caller| {
+                if caller.data().log_level < LogLevel::Info {
+                    return;
+                }
+                if let Some(msg) = msg {
+                    let msg = caller.data().redact(&msg);
+                    println!("[capsule-log] {msg}");
+                    caller.data_mut().trace.logs.push(msg);
+                }
+            })
+        },
+    )?;
+
+    if provides("host_log_level") {
+        linker.func_wrap(
+            module,
+            "host_log_level",
+            |mut caller: Caller<'_, HostState>| -> Result<i32> {
+                traced(&mut caller, "host_log_level", json!([]), |caller| {
+                    caller.data().log_level as i32
+                })
+            },
+        )?;
+    }
+
+    if provides("host_log_v2") {
+        linker.func_wrap(
+            module,
+            "host_log_v2",
+            |mut caller: Caller<'_, HostState>,
+             level: i32,
+             target_ptr: i32,
+             target_len: i32,
+             msg_ptr: i32,
+             msg_len: i32,
+             fields_ptr: i32,
+             fields_len: i32|
+             -> Result<()> {
+                let target = read_string_from_memory(&mut caller, target_ptr, target_len);
+                let message = read_string_from_memory(&mut caller, msg_ptr, msg_len);
+                let raw_fields = if fields_len == 0 {
+                    Some(String::new())
+                } else {
+                    read_string_from_memory(&mut caller, fields_ptr, fields_len)
+                };
+                let args = json!([level, target, message, raw_fields]);
+                traced(&mut caller, "host_log_v2", args, |caller| {
+                    let Some(level) = LogLevel::from_code(level) else {
+                        eprintln!("[caeles-runtime] host_log_v2: unknown level {level}");
+                        return;
+                    };
+                    if level > caller.data().log_level {
+                        return;
+                    }
+                    let (Some(target), Some(message), Some(raw)) = (target, message, raw_fields)
+                    else {
+                        return;
+                    };
+                    let fields = if raw.is_empty() {
+                        serde_json::Map::new()
+                    } else {
+                        match serde_json::from_str(&raw) {
+                            Ok(serde_json::Value::Object(fields)) => fields,
+                            _ => {
+                                eprintln!(
+                                    "[caeles-runtime] host_log_v2: fields must be a JSON object, got: {raw}"
+                                );
+                                serde_json::Map::new()
+                            }
+                        }
+                    };
+                    emit_capsule_log(caller.data_mut(), level, &target, &message, fields);
+                })
+            },
+        )?;
+    }
+
+    if provides("host_notify") {
+        linker.func_wrap(
+            module,
+            "host_notify",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<()> {
+                let msg = read_string_from_memory(&mut caller, ptr, len);
+                traced(&mut caller, "host_notify", json!([msg]), |caller| {
+                    let Some(msg) = msg.map(|msg| caller.data().redact(&msg)) else {
+                        return;
+                    };
+                    let granted = caller
+                        .data()
+                        .capabilities
+                        .handle_for(CapabilityKind::Notifications)
+                        .is_ok();
+                    if granted {
+                        println!("[capsule-notify] {msg}");
+                        caller.data_mut().trace.notifications.push(msg);
+                    } else {
+                        println!(
+                            "[capsule-notify BLOCKED] permission 'notifications' = false. Message: {msg}"
+                        );
+                    }
+                })
+            },
+        )?;
+    }
+
+    if provides("host_http_get") {
+        linker.func_wrap(
+            module,
+            "host_http_get",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<i32> {
+                let url = read_string_from_memory(&mut caller, ptr, len);
+                traced(&mut caller, "host_http_get", json!([url]), |caller| {
+                    let Some(url) = url else {
+                        return 2;
+                    };
+
+                    let state = caller.data();
+                    let capabilities = &state.capabilities;
+                    let scope = match capabilities
+                        .handle_for(CapabilityKind::Network)
+                        .and_then(|handle| capabilities.check(handle, CapabilityKind::Network))
+                    {
+                        Ok(scope) => scope,
+                        Err(_) => {
+                            println!(
+                                    "[capsule-network BLOCKED] permission 'network' = false. Requested URL: {}",
+                                state.redact(&url)
+                            );
+                            return 1;
+                        }
+                    };
+
+                    http_get(state, scope, &url)
+                })
+            },
+        )?;
+    }
+
+    if provides("host_cap_acquire") {
+        linker.func_wrap(
+            module,
+            "host_cap_acquire",
+            |mut caller: Caller<'_, HostState>, kind: i32| -> Result<i32> {
+                traced(&mut caller, "host_cap_acquire", json!([kind]), |caller| {
+                    let state = caller.data();
+                    let Some(kind) = CapabilityKind::from_code(kind) else {
+                        return -2;
+                    };
+                    match state.capabilities.handle_for(kind) {
+                        Ok(handle) => handle,
+                        Err(HandleError::Revoked) => {
+                            state.log.audit(
+                                "capability_rejected",
+                                json!({ "function": "host_cap_acquire", "kind": kind.to_string(), "reason": HandleError::Revoked.reason() }),
+                            );
+                            -1
+                        }
+                        Err(_) => {
+                            println!(
+                                "[capsule-capability DENIED] permission '{}' = false",
+                                kind.permission()
+                            );
+                            state.log.audit(
+                                "permission_denied",
+                                json!({ "function": "host_cap_acquire", "kind": kind.to_string() }),
+                            );
+                            -1
+                        }
+                    }
+                })
+            },
+        )?;
+    }
+
+    if provides("host_cap_release") {
+        linker.func_wrap(
+            module,
+            "host_cap_release",
+            |mut caller: Caller<'_, HostState>, handle: i32| -> Result<i32> {
+                traced(&mut caller, "host_cap_release", json!([handle]), |caller| {
+                    let state = caller.data_mut();
+                    match state.capabilities.revoke(handle) {
+                        Ok(kind) => {
+                            state.log.audit(
+                                "capability_revoked",
+                                json!({ "kind": kind.to_string(), "handle": handle }),
+                            );
+                            0
+                        }
+                        Err(err) => {
+                            state.reject_handle("host_cap_release", handle, err);
+                            -1
+                        }
+                    }
+                })
+            },
+        )?;
+    }
+
+    if provides("host_notify_cap") {
+        linker.func_wrap(
+            module,
+            "host_notify_cap",
+            |mut caller: Caller<'_, HostState>, handle: i32, ptr: i32, len: i32| -> Result<i32> {
+                let msg = read_string_from_memory(&mut caller, ptr, len);
+                traced(
+                    &mut caller,
+                    "host_notify_cap",
+                    json!([handle, msg]),
+                    |caller| {
+                        let Some(msg) = msg else {
+                            return 2;
+                        };
+                        let state = caller.data_mut();
+                        let msg = state.redact(&msg);
+                        if let Err(err) = state
+                            .capabilities
+                            .check(handle, CapabilityKind::Notifications)
+                        {
+                            state.reject_handle("host_notify_cap", handle, err);
+                            return 4;
+                        }
+                        println!("[capsule-notify] {msg}");
+                        state.trace.notifications.push(msg);
+                        0
+                    },
+                )
+            },
+        )?;
+    }
+
+    if provides("host_http_get_cap") {
+        linker.func_wrap(
+            module,
+            "host_http_get_cap",
+            |mut caller: Caller<'_, HostState>, handle: i32, ptr: i32, len: i32| -> Result<i32> {
+                let url = read_string_from_memory(&mut caller, ptr, len);
+                traced(
+                    &mut caller,
+                    "host_http_get_cap",
+                    json!([handle, url]),
+                    |caller| {
+                        let Some(url) = url else {
+                            return 2;
+                        };
+                        let state = caller.data();
+                        match state.capabilities.check(handle, CapabilityKind::Network) {
+                            Ok(scope) => http_get(state, scope, &url),
+                            Err(err) => {
+                                state.reject_handle("host_http_get_cap", handle, err);
+                                4
+                            }
+                        }
+                    },
+                )
+            },
+        )?;
+    }
+
+    if provides("host_panic") {
+        linker.func_wrap(
+            module,
+            "host_panic",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<()> {
+                let msg = read_string_from_memory(&mut caller, ptr, len);
+                traced(&mut caller, "host_panic", json!([msg]), |caller| {
+                    if let Some(msg) = msg {
+                        let msg = caller.data().redact(&msg);
+                        eprintln!("[capsule-panic] {msg}");
+                        caller.data_mut().panic = Some(msg);
+                    }
+                })
+            },
+        )?;
+    }
+
+    if provides("host_exit") {
+        linker.func_wrap(
+            module,
+            "host_exit",
+            |mut caller: Caller<'_, HostState>, code: i32, ptr: i32, len: i32| -> Result<()> {
+                let message = read_string_from_memory(&mut caller, ptr, len).unwrap_or_default();
+                traced(&mut caller, "host_exit", json!([code, message]), |caller| {
+                    if code != 0 {
+                        let message = caller.data().redact(&message);
+                        caller.data_mut().exit = Some(CapsuleExit { code, message });
+                    }
+                })
+            },
+        )?;
+    }
+
+    if provides("host_set_output") {
+        linker.func_wrap(
+            module,
+            "host_set_output",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<i32> {
+                let output = read_string_from_memory(&mut caller, ptr, len);
+                traced(
+                    &mut caller,
+                    "host_set_output",
+                    json!([output]),
+                    |caller| match output {
+                        Some(output) => {
+                            caller.data_mut().trace.output = Some(caller.data().redact(&output));
+                            0
+                        }
+                        None => 2,
+                    },
+                )
+            },
+        )?;
+    }
+
+    if provides("host_input_len") {
+        linker.func_wrap(
+            module,
+            "host_input_len",
+            |mut caller: Caller<'_, HostState>| -> Result<i32> {
+                traced(&mut caller, "host_input_len", json!([]), |caller| {
+                    caller.data().input.len() as i32
+                })
+            },
+        )?;
+    }
+
+    if provides("host_input_read") {
+        linker.func_wrap(
+            module,
+            "host_input_read",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<i32> {
+                traced(
+                    &mut caller,
+                    "host_input_read",
+                    json!([ptr, len]),
+                    |caller| {
+                        if len < 0 {
+                            return -1;
+                        }
+                        let input = std::mem::take(&mut caller.data_mut().input);
+                        let count = input.len().min(len as usize);
+                        let written = write_to_memory(caller, ptr, &input[..count]);
+                        caller.data_mut().input = input;
+                        if written {
+                            count as i32
+                        } else {
+                            -1
+                        }
+                    },
+                )
+            },
+        )?;
+    }
+
+    if provides("host_now_ms") {
+        linker.func_wrap(
+            module,
+            "host_now_ms",
+            |mut caller: Caller<'_, HostState>| -> Result<i64> {
+                traced(&mut caller, "host_now_ms", json!([]), |caller| {
+                    let state = caller.data();
+                    if !state.wall_clock {
+                        println!("[capsule-clock BLOCKED] permission 'wall_clock' = false");
+                        state.log.audit(
+                            "permission_denied",
+                            json!({ "function": "host_now_ms", "kind": "wall_clock" }),
+                        );
+                        return -1;
+                    }
+                    state.clock.wall().as_millis() as i64
+                })
+            },
+        )?;
+    }
+
+    if provides("host_monotonic_ns") {
+        linker.func_wrap(
+            module,
+            "host_monotonic_ns",
+            |mut caller: Caller<'_, HostState>| -> Result<i64> {
+                traced(&mut caller, "host_monotonic_ns", json!([]), |caller| {
+                    caller.data().clock.monotonic().as_nanos() as i64
+                })
+            },
+        )?;
+    }
+
+    if provides("host_sleep_ms") {
+        linker.func_wrap(
+            module,
+            "host_sleep_ms",
+            |mut caller: Caller<'_, HostState>, ms: i32| -> Result<i32> {
+                let state = caller.data();
+                if let (Some(timeout), Ok(ms)) = (state.timeout, u64::try_from(ms)) {
+                    if state.clock.elapsed() + Duration::from_millis(ms) > timeout {
+                        return Err(RunTimeout { timeout }.into());
+                    }
+                }
+                traced(&mut caller, "host_sleep_ms", json!([ms]), |caller| {
+                    let Ok(ms) = u64::try_from(ms) else {
+                        return 2;
+                    };
+                    caller.data().clock.sleep(Duration::from_millis(ms));
+                    0
+                })
+            },
+        )?;
+    }
+
+    if provides("host_random_bytes") {
+        linker.func_wrap(
+            module,
+            "host_random_bytes",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<i32> {
+                let bytes = traced(
+                    &mut caller,
+                    "host_random_bytes",
+                    json!([ptr, len]),
+                    |caller| {
+                        let len = usize::try_from(len).ok()?;
+                        if len > MAX_RANDOM_BYTES {
+                            return None;
+                        }
+                        let mut bytes = vec![0u8; len];
+                        caller.data_mut().rng.fill_bytes(&mut bytes);
+                        Some(bytes)
+                    },
+                )?;
+                match bytes {
+                    Some(bytes) if write_to_memory(&mut caller, ptr, &bytes) => Ok(0),
+                    _ => Ok(2),
+                }
+            },
+        )?;
+    }
+
+    if provides("host_config_get") {
+        linker.func_wrap(
+            module,
+            "host_config_get",
+            |mut caller: Caller<'_, HostState>,
+             key_ptr: i32,
+             key_len: i32,
+             buf_ptr: i32,
+             buf_len: i32|
+             -> Result<i32> {
+                let key = read_string_from_memory(&mut caller, key_ptr, key_len);
+                let value = traced(&mut caller, "host_config_get", json!([key]), |caller| {
+                    caller.data().config.get(key.as_ref()?).cloned()
+                })?;
+                if key.is_none() {
+                    return Ok(-2);
+                }
+                Ok(write_lookup(
+                    &mut caller,
+                    value.as_deref(),
+                    buf_ptr,
+                    buf_len,
+                ))
+            },
+        )?;
+    }
+
+    if provides("host_secret_get") {
+        linker.func_wrap(
+            module,
+            "host_secret_get",
+            |mut caller: Caller<'_, HostState>,
+             name_ptr: i32,
+             name_len: i32,
+             buf_ptr: i32,
+             buf_len: i32|
+             -> Result<i32> {
+                let name = read_string_from_memory(&mut caller, name_ptr, name_len);
+                // Only the length goes to the trace; replays read the value from the secret store again.
+                let found = traced(&mut caller, "host_secret_get", json!([name]), |caller| {
+                    let state = caller.data();
+                    let name = name.as_ref()?;
+                    let len = state.secrets.get(name)?.len();
+                    state.log.audit("secret_read", json!({ "name": name }));
+                    Some(len)
+                })?;
+                let Some(name) = name else {
+                    return Ok(-2);
+                };
+                let value = found.and_then(|_| caller.data().secrets.get(&name).cloned());
+                Ok(write_lookup(
+                    &mut caller,
+                    value.as_deref(),
+                    buf_ptr,
+                    buf_len,
+                ))
+            },
+        )?;
+    }
+
+    if provides("host_fs_open") {
+        linker.func_wrap(
+            module,
+            "host_fs_open",
+            |mut caller: Caller<'_, HostState>,
+             path_ptr: i32,
+             path_len: i32,
+             mode: i32|
+             -> Result<i32> {
+                let path = read_string_from_memory(&mut caller, path_ptr, path_len);
+                traced(&mut caller, "host_fs_open", json!([path, mode]), |caller| {
+                    let (Some(path), Some(mode)) = (path, OpenMode::from_code(mode)) else {
+                        return FsError::InvalidPath.code();
+                    };
+                    let state = caller.data_mut();
+                    let result = state
+                        .files("host_fs_open")
+                        .and_then(|files| files.open(&path, mode));
+                    state
+                        .fs_result("host_fs_open", &path, result)
+                        .unwrap_or_else(FsError::code)
+                })
+            },
+        )?;
+    }
+
+    if provides("host_fs_read") {
+        linker.func_wrap(
+            module,
+            "host_fs_read",
+            |mut caller: Caller<'_, HostState>, fd: i32, ptr: i32, len: i32| -> Result<i32> {
+                let bytes = traced(
+                    &mut caller,
+                    "host_fs_read",
+                    json!([fd, ptr, len]),
+                    |caller| {
+                        let len = usize::try_from(len).map_err(|_| FsError::InvalidPath.code())?;
+                        caller
+                            .data_mut()
+                            .files("host_fs_read")
+                            .and_then(|files| files.read(fd, len))
+                            .map_err(FsError::code)
+                    },
+                )?;
+                Ok(match bytes {
+                    Ok(bytes) if write_to_memory(&mut caller, ptr, &bytes) => bytes.len() as i32,
+                    Ok(_) => FsError::InvalidPath.code(),
+                    Err(code) => code,
+                })
+            },
+        )?;
+    }
+
+    if provides("host_fs_write") {
+        linker.func_wrap(
+            module,
+            "host_fs_write",
+            |mut caller: Caller<'_, HostState>, fd: i32, ptr: i32, len: i32| -> Result<i32> {
+                let data = read_bytes_from_memory(&mut caller, ptr, len);
+                let shown = data
+                    .as_deref()
+                    .map(|data| String::from_utf8_lossy(data).into_owned());
+                traced(&mut caller, "host_fs_write", json!([fd, shown]), |caller| {
+                    let Some(data) = data else {
+                        return FsError::InvalidPath.code();
+                    };
+                    let state = caller.data_mut();
+                    let result = state
+                        .files("host_fs_write")
+                        .and_then(|files| files.write(fd, &data));
+                    let subject = format!("write of {} bytes", data.len());
+                    state
+                        .fs_result("host_fs_write", &subject, result)
+                        .map_or_else(FsError::code, |count| count as i32)
+                })
+            },
+        )?;
+    }
+
+    if provides("host_fs_close") {
+        linker.func_wrap(
+            module,
+            "host_fs_close",
+            |mut caller: Caller<'_, HostState>, fd: i32| -> Result<i32> {
+                traced(&mut caller, "host_fs_close", json!([fd]), |caller| {
+                    caller
+                        .data_mut()
+                        .files("host_fs_close")
+                        .and_then(|files| files.close(fd))
+                        .map_or_else(FsError::code, |()| 0)
+                })
+            },
+        )?;
+    }
+
+    if provides("host_fs_list") {
+        linker.func_wrap(
+            module,
+            "host_fs_list",
+            |mut caller: Caller<'_, HostState>,
+             path_ptr: i32,
+             path_len: i32,
+             buf_ptr: i32,
+             buf_len: i32|
+             -> Result<i32> {
+                let path = read_string_from_memory(&mut caller, path_ptr, path_len);
+                let listing = traced(&mut caller, "host_fs_list", json!([path]), |caller| {
+                    let path = path.ok_or(FsError::InvalidPath.code())?;
+                    let state = caller.data_mut();
+                    let result = state
+                        .files("host_fs_list")
+                        .and_then(|files| files.list(&path));
+                    state
+                        .fs_result("host_fs_list", &path, result)
+                        .map(|names| names.join("\n"))
+                        .map_err(FsError::code)
+                })?;
+                Ok(match listing {
+                    Ok(text) => write_lookup(&mut caller, Some(&text), buf_ptr, buf_len),
+                    Err(code) => code,
+                })
+            },
+        )?;
+    }
+
+    if provides("host_fs_delete") {
+        linker.func_wrap(
+            module,
+            "host_fs_delete",
+            |mut caller: Caller<'_, HostState>, path_ptr: i32, path_len: i32| -> Result<i32> {
+                let path = read_string_from_memory(&mut caller, path_ptr, path_len);
+                traced(&mut caller, "host_fs_delete", json!([path]), |caller| {
+                    let Some(path) = path else {
+                        return FsError::InvalidPath.code();
+                    };
+                    let state = caller.data_mut();
+                    let result = state
+                        .files("host_fs_delete")
+                        .and_then(|files| files.delete(&path));
+                    state
+                        .fs_result("host_fs_delete", &path, result)
+                        .map_or_else(FsError::code, |()| 0)
+                })
+            },
+        )?;
+    }
+
+    if provides("host_publish") {
+        linker.func_wrap(
+            module,
+            "host_publish",
+            |mut caller: Caller<'_, HostState>,
+             topic_ptr: i32,
+             topic_len: i32,
+             payload_ptr: i32,
+             payload_len: i32|
+             -> Result<i32> {
+                let topic = read_string_from_memory(&mut caller, topic_ptr, topic_len);
+                let payload = read_string_from_memory(&mut caller, payload_ptr, payload_len);
+                traced(
+                    &mut caller,
+                    "host_publish",
+                    json!([topic, payload]),
+                    |caller| {
+                        let (Some(topic), Some(payload)) = (topic, payload) else {
+                            return -2;
+                        };
+                        let state = caller.data_mut();
+                        if bus::validate_topic(&topic, false).is_err() {
+                            return -2;
+                        }
+                        if payload.len() > bus::MAX_PAYLOAD_BYTES {
+                            return -3;
+                        }
+                        if !state
+                            .publish_topics
+                            .iter()
+                            .any(|pattern| bus::topic_matches(pattern, &topic))
+                        {
+                            println!(
+                                "[capsule-publish BLOCKED] topic '{topic}' is outside permissions.publish"
+                            );
+                            state.log.audit(
+                                "permission_denied",
+                                json!({ "function": "host_publish", "kind": "publish", "topic": topic }),
+                            );
+                            return -1;
+                        }
+                        let payload = state.redact(&payload);
+                        println!("[capsule-publish] {topic}: {payload}");
+                        state.log.audit(
+                            "message_published",
+                            json!({ "topic": topic, "bytes": payload.len() }),
+                        );
+                        state.trace.published.push(Published { topic, payload });
+                        0
+                    },
+                )
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Result of `host_config_get`/`host_secret_get`/`host_fs_list`: the value
+/// length, or `-1` when unset. The value is only written when it fits in the buffer.
+fn write_lookup(
+    caller: &mut Caller<'_, HostState>,
+    value: Option<&str>,
+    ptr: i32,
+    len: i32,
+) -> i32 {
+    let Some(value) = value else {
+        return -1;
+    };
+    if len < 0 {
+        return -2;
+    }
+    if value.len() <= len as usize && !write_to_memory(caller, ptr, value.as_bytes()) {
+        return -2;
+    }
+    value.len() as i32
+}
+
+/// Host-side settings of a single run.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub log: RunLog,
+    /// Private directory of the capsule; WASI preopens are resolved inside it.
+    pub sandbox_dir: PathBuf,
+    /// Input handed to the capsule through `host_input_read`.
+    pub input: Vec<u8>,
+    pub log_level: LogLevel,
+    pub http_stubs: Option<HttpStubs>,
+    /// Records host calls for a trace file, or replays them from one.
+    pub host_calls: HostCallMode,
+    /// Salt of the capability handles; random unless replaying a trace.
+    pub capability_salt: Option<u64>,
+    /// Seed of a deterministic run (`--deterministic`); `None` uses real clocks and entropy.
+    pub seed: Option<u64>,
+    /// Limit of the whole run, including `host_sleep_ms`.
+    pub timeout: Option<Duration>,
+    /// Resolved config of the capsule (see [`crate::capsule_config::resolve_config`]).
+    pub config: BTreeMap<String, String>,
+    /// Decrypted secrets; the run log redactor should know their values.
+    pub secrets: BTreeMap<String, String>,
+    /// Libraries linked under `dep:<id>` (see [`crate::dependencies::resolve`]).
+    pub dependencies: Vec<ResolvedDependency>,
+}
+
+/// Captured stdout/stderr of a WASI capsule.
+struct WasiOutput {
+    stdout: MemoryOutputPipe,
+    stderr: MemoryOutputPipe,
+}
+
+impl WasiOutput {
+    /// Echoes captured output to the console, appends it to the run log and returns stdout.
+    fn flush(&self, log: &RunLog) -> String {
+        for (stream, pipe) in [("stdout", &self.stdout), ("stderr", &self.stderr)] {
+            let contents = pipe.contents();
+            for line in String::from_utf8_lossy(&contents).lines() {
+                println!("[capsule-{stream}] {}", log.redactor().redact(line));
+                if let Err(err) = log.line(&format!("wasi_{stream}: {line}")) {
+                    eprintln!("[caeles-runtime] failed to write run log: {err}");
+                }
+            }
+        }
+        log.redactor()
+            .redact(String::from_utf8_lossy(&self.stdout.contents()).trim_end())
+    }
+}
+
+/// Builds the restricted WASI context declared in the manifest: only listed
+/// preopens (inside the sandbox), env vars and args are visible to the capsule.
+fn build_wasi(
+    manifest: &CapsuleManifest,
+    options: &RunOptions,
+    clock: &RunClock,
+) -> Result<(WasiP1Ctx, WasiOutput)> {
+    let wasi = manifest.wasi.clone().unwrap_or_default();
+    let mut builder = WasiCtxBuilder::new();
+
+    let mut args = vec![manifest.id.clone()];
+    args.extend(wasi.args.iter().cloned());
+    builder.args(&args);
+
+    for name in &wasi.env {
+        if let Ok(value) = std::env::var(name) {
+            builder.env(name, value);
+        }
+    }
+
+    for preopen in &wasi.preopens {
+        let host_dir = options.sandbox_dir.join(&preopen.path);
+        fs::create_dir_all(&host_dir).with_context(|| {
+            format!(
+                "Failed to create sandbox directory '{}'",
+                host_dir.display()
+            )
+        })?;
+        let (dir_perms, file_perms) = match preopen.mode {
+            PreopenMode::ReadOnly => (DirPerms::READ, FilePerms::READ),
+            PreopenMode::ReadWrite => (DirPerms::all(), FilePerms::all()),
+        };
+        builder.preopened_dir(&host_dir, &preopen.guest, dir_perms, file_perms)?;
+        println!(
+            "> WASI preopen: {} -> {} ({:?})",
+            preopen.guest,
+            host_dir.display(),
+            preopen.mode
+        );
+    }
+
+    let output = WasiOutput {
+        stdout: MemoryOutputPipe::new(WASI_OUTPUT_CAPACITY),
+        stderr: MemoryOutputPipe::new(WASI_OUTPUT_CAPACITY),
+    };
+    builder.stdout(output.stdout.clone());
+    builder.stderr(output.stderr.clone());
+    if let (Some(seed), RunClock::Virtual(clock)) = (options.seed, clock) {
+        determinism::configure_wasi(&mut builder, seed, clock);
+    }
+
+    Ok((builder.build_p1(), output))
+}
+
+/// Calls the capsule entrypoint; WASI command modules fall back to `_start`,
+/// where `proc_exit(0)` counts as success.
+fn call_entry(store: &mut Store<HostState>, instance: &Instance) -> Result<()> {
+    let entry = if instance.get_func(&mut *store, ENTRY_EXPORT).is_some() {
+        ENTRY_EXPORT
+    } else {
+        WASI_START_EXPORT
+    };
+    let func = instance.get_typed_func::<(), ()>(&mut *store, entry)?;
+
+    println!("> Calling capsule {entry}...");
+    match func.call(&mut *store, ()) {
+        Ok(()) => {}
+        Err(err) => match err.downcast_ref::<I32Exit>() {
+            Some(I32Exit(0)) => {}
+            Some(I32Exit(code)) => {
+                return Err(CapsuleExit {
+                    code: *code,
+                    message: String::new(),
+                }
+                .into())
+            }
+            None => match store.data_mut().panic.take() {
+                Some(msg) => bail!("capsule panicked: {msg}"),
+                None => return Err(timeout_error(err, store.data().timeout)),
+            },
+        },
+    }
+    if let Some(exit) = store.data_mut().exit.take() {
+        return Err(exit.into());
+    }
+    println!("> {entry} finished.");
+    Ok(())
+}
+
+/// Instantiates the dependency libraries, deepest first, in the capsule's
+/// store and links the functions each one exports under `dep:<id>`.
+///
+/// Libraries share the host state of the run, so their host calls are bound
+/// by the permissions of the capsule being run.
+fn link_dependencies(
+    engine: &Engine,
+    linker: &mut Linker<HostState>,
+    store: &mut Store<HostState>,
+    dependencies: &[ResolvedDependency],
+    abi: AbiVersion,
+) -> Result<()> {
+    let mut linked_abis = vec![abi];
+    for dependency in dependencies::load_order(dependencies) {
+        let capsule = &dependency.capsule;
+        if capsule.target != Target::Unknown {
+            bail!(
+                "Dependency '{}' targets {}; only {} libraries can be linked",
+                dependency.id,
+                capsule.target.as_str(),
+                Target::Unknown.as_str()
+            );
+        }
+        let path = capsule.wasm_path();
+        let bytes = fs::read(&path).with_context(|| {
+            format!(
+                "Failed to load dependency '{}' from '{}'",
+                dependency.id,
+                path.display()
+            )
+        })?;
+        if component::is_component(&bytes) {
+            bail!(
+                "Dependency '{}' is a component; only core modules can be linked",
+                dependency.id
+            );
+        }
+        let module = Module::new(engine, &bytes)
+            .with_context(|| format!("Invalid WASM module '{}'", path.display()))?;
+
+        let preflight = preflight::analyze(&module, abi::read_marker(&bytes), capsule);
+        for issue in preflight.issues.iter().filter(|i| !i.is_error()) {
+            println!(
+                "[caeles-runtime] preflight warning ({}): {issue}",
+                dependency.id
+            );
+        }
+        preflight::ensure_no_errors(&preflight.issues)
+            .with_context(|| format!("Dependency '{}'", dependency.id))?;
+        if !linked_abis.contains(&preflight.abi) {
+            link_host_functions(linker, preflight.abi)?;
+            linked_abis.push(preflight.abi);
+        }
+
+        let instance = linker
+            .instantiate(&mut *store, &module)
+            .with_context(|| format!("Failed to instantiate dependency '{}'", dependency.id))?;
+        let functions: Vec<_> = instance
+            .exports(&mut *store)
+            .filter_map(|export| {
+                let name = export.name().to_string();
+                export.into_func().map(|func| (name, func))
+            })
+            .collect();
+        let module_name = dependencies::import_module(&dependency.id);
+        for (name, func) in &functions {
+            linker.define(&*store, &module_name, name, *func)?;
+        }
+        println!(
+            "> Dependency: {} {} ({} functions under '{module_name}')",
+            dependency.id,
+            dependency.version,
+            functions.len()
+        );
+    }
+    Ok(())
+}
+
+/// Opens the file area of the capsule when `permissions.filesystem` is granted.
+fn open_files(manifest: &CapsuleManifest, options: &RunOptions) -> Result<Option<CapsuleFiles>> {
+    if !manifest.permissions.filesystem {
+        return Ok(None);
+    }
+    let settings = manifest.filesystem();
+    let files = CapsuleFiles::new(
+        &options.sandbox_dir,
+        manifest.asset_dirs(),
+        settings.quota_bytes,
+    )
+    .with_context(|| {
+        format!(
+            "Failed to create capsule directory '{}'",
+            options.sandbox_dir.display()
+        )
+    })?;
+    println!(
+        "> Files: {} ({} of {} bytes used)",
+        files.root().display(),
+        files.used(),
+        files.quota()
+    );
+    Ok(Some(files))
+}
+
+/// Issues the capability handles granted by the manifest and audits each one.
+fn issue_capabilities(manifest: &CapsuleManifest, log: &RunLog, salt: u64) -> CapabilityTable {
+    let capabilities = CapabilityTable::issue(manifest, salt);
+    for kind in [CapabilityKind::Network, CapabilityKind::Notifications] {
+        if capabilities.handle_for(kind).is_ok() {
+            log.audit("capability_issued", json!({ "kind": kind.to_string() }));
+        }
+    }
+    capabilities
+}
+
+/// Runs a capsule to completion, keeping what it did even when it fails.
+pub fn run_capsule(manifest: &CapsuleManifest, options: &RunOptions) -> RunOutcome {
+    let capability_salt = options
+        .capability_salt
+        .unwrap_or_else(capabilities::random_salt);
+    let mut finished = None;
+    let mut result = execute(manifest, options, capability_salt, &mut finished);
+
+    let (trace, host_calls) = match finished {
+        Some(mut state) => {
+            if result.is_ok() {
+                result = state.calls.finish().map_err(Into::into);
+            }
+            (state.trace, state.calls.take_calls())
+        }
+        None => Default::default(),
+    };
+    if let Some(output) = &trace.output {
+        println!("> output: {output}");
+        if let Err(err) = options.log.line(&format!("output: {output}")) {
+            eprintln!("[caeles-runtime] failed to write run log: {err}");
+        }
+    }
+    RunOutcome {
+        trace,
+        host_calls,
+        capability_salt,
+        result,
+    }
+}
+
+/// Runs the capsule and leaves the final host state in `finished` once it was created.
+fn execute(
+    manifest: &CapsuleManifest,
+    options: &RunOptions,
+    capability_salt: u64,
+    finished: &mut Option<HostState>,
+) -> Result<()> {
+    let log = &options.log;
+    let mut config = determinism::engine_config(options.seed.is_some());
+    config.epoch_interruption(options.timeout.is_some());
+    let engine = Engine::new(&config)?;
+
+    if manifest.lifecycle.kind == LifecycleKind::Library {
+        bail!(
+            "Capsule '{}' is a library; it only runs as a dependency of another capsule",
+            manifest.id
+        );
+    }
 
     let module_path = manifest.wasm_path();
     println!(
@@ -43,85 +1602,125 @@ pub fn run_capsule(manifest: &CapsuleManifest) -> Result<()> {
         "> Permissions: notifications={}, network={}",
         manifest.permissions.notifications, manifest.permissions.network
     );
+    println!("> Target: {}", manifest.target.as_str());
+    if let Some(seed) = options.seed {
+        println!("> Deterministic mode: seed {seed}");
+    }
+    if let Some(timeout) = options.timeout {
+        println!("> Timeout: {} ms", timeout.as_millis());
+    }
     println!("> Loading capsule: {}", module_path.display());
 
-    let module = Module::from_file(&engine, &module_path).with_context(|| {
+    let bytes = fs::read(&module_path).with_context(|| {
         format!(
             "Failed to load WASM module '{}'. Build the capsule before running.",
             module_path.display()
         )
     })?;
+    let mut state = HostState {
+        capabilities: issue_capabilities(manifest, log, capability_salt),
+        log: log.clone(),
+        wasi: None,
+        input: options.input.clone(),
+        panic: None,
+        exit: None,
+        log_level: options.log_level,
+        http_stubs: options.http_stubs.clone(),
+        trace: RunTrace::default(),
+        calls: CallRecorder::new(options.host_calls.clone()),
+        clock: match options.seed {
+            Some(_) => RunClock::Virtual(VirtualClock::default()),
+            None => RunClock::Real(Instant::now()),
+        },
+        rng: match options.seed {
+            Some(seed) => Box::new(determinism::seeded_rng(seed)),
+            None => Box::new(StdRng::from_entropy()),
+        },
+        timeout: options.timeout,
+        wall_clock: manifest.permissions.wall_clock,
+        config: options.config.clone(),
+        secrets: options.secrets.clone(),
+        files: open_files(manifest, options)?,
+        publish_topics: manifest.permissions.publish.clone(),
+    };
 
-    let mut store = Store::new(&engine, ());
-    let mut linker = Linker::new(&engine);
+    if component::is_component(&bytes) {
+        if !options.dependencies.is_empty() {
+            bail!("Component capsules cannot declare dependencies");
+        }
+        return component::run_component(&engine, &bytes, manifest, state, finished);
+    }
 
-    linker.func_wrap(
-        "caeles",
-        "host_log",
-        |caller: Caller<'_, ()>, ptr: i32, len: i32| {
-            if let Some(msg) = read_string_from_memory(caller, ptr, len) {
-                println!("[capsule-log] {msg}");
-            }
-        },
-    )?;
+    let module = Module::new(&engine, &bytes)
+        .with_context(|| format!("Invalid WASM module '{}'", module_path.display()))?;
 
-    let notifications_allowed = manifest.permissions.notifications;
-    linker.func_wrap(
-        "caeles",
-        "host_notify",
-        move |caller: Caller<'_, ()>, ptr: i32, len: i32| {
-            if let Some(msg) = read_string_from_memory(caller, ptr, len) {
-                if notifications_allowed {
-                    println!("[capsule-notify] {msg}");
-                } else {
-                    println!(
-                        "[capsule-notify BLOCKED] permission 'notifications' = false. Message: {msg}"
-                    );
-                }
-            }
-        },
-    )?;
+    let preflight = preflight::analyze(&module, abi::read_marker(&bytes), manifest);
+    for issue in preflight.issues.iter().filter(|i| !i.is_error()) {
+        println!("[caeles-runtime] preflight warning: {issue}");
+    }
+    preflight::ensure_no_errors(&preflight.issues)?;
+    // Only `caeles*` imports are traced; WASI clocks and entropy are only
+    // reproducible when the deterministic mode pins them.
+    if !matches!(options.host_calls, HostCallMode::Off)
+        && options.seed.is_none()
+        && module
+            .imports()
+            .any(|import| import.module() == abi::WASI_P1_MODULE)
+    {
+        bail!(
+            "Capsule '{}' imports {}, whose clocks and random values are not traced; record it with --deterministic to replay it",
+            manifest.id,
+            abi::WASI_P1_MODULE
+        );
+    }
+    println!(
+        "> Host ABI: {} (module '{}')",
+        preflight.abi,
+        preflight.abi.module()
+    );
 
-    let network_allowed = manifest.permissions.network;
-    linker.func_wrap(
-        "caeles",
-        "host_http_get",
-        move |caller: Caller<'_, ()>, ptr: i32, len: i32| -> i32 {
-            let Some(url) = read_string_from_memory(caller, ptr, len) else {
-                return 2;
-            };
-
-            if !network_allowed {
-                println!(
-                    "[capsule-network BLOCKED] permission 'network' = false. Requested URL: {url}"
-                );
-                return 1;
-            }
+    let mut linker = Linker::new(&engine);
+    link_host_functions(&mut linker, preflight.abi)?;
 
-            if !(url.starts_with("http://") || url.starts_with("https://")) {
-                println!("[capsule-network ERROR] invalid URL (use http:// or https://): {url}");
-                return 2;
-            }
+    let wasi_output = match manifest.target {
+        Target::WasiP1 => {
+            let (ctx, output) = build_wasi(manifest, options, &state.clock)?;
+            preview1::add_to_linker_sync(&mut linker, |state: &mut HostState| {
+                state
+                    .wasi
+                    .as_mut()
+                    .expect("WASI context is set for wasm32-wasip1 capsules")
+            })?;
+            state.wasi = Some(ctx);
+            Some(output)
+        }
+        Target::Unknown => None,
+    };
 
-            match ureq::get(&url).call() {
-                Ok(response) => {
-                    println!("[capsule-network] GET {} -> {}", url, response.status());
-                    0
-                }
-                Err(err) => {
-                    println!("[capsule-network ERROR] GET {} failed: {}", url, err);
-                    3
-                }
-            }
-        },
-    )?;
+    let mut store = Store::new(&engine, state);
 
-    let instance = linker.instantiate(&mut store, &module)?;
-    let func = instance.get_typed_func::<(), ()>(&mut store, "caeles_main")?;
+    // Start functions of the dependencies and the capsule count against the timeout too.
+    let timer = arm_timeout(&mut store);
+    let result = link_dependencies(
+        &engine,
+        &mut linker,
+        &mut store,
+        &options.dependencies,
+        preflight.abi,
+    )
+    .and_then(|()| linker.instantiate(&mut store, &module))
+    .map_err(|err| timeout_error(err, options.timeout))
+    .and_then(|instance| call_entry(&mut store, &instance));
+    drop(timer);
 
-    println!("> Calling capsule caeles_main...");
-    func.call(&mut store, ())?;
-    println!("> caeles_main finished.");
+    let mut state = store.into_data();
+    if let Some(output) = &wasi_output {
+        let stdout = output.flush(log);
+        if state.trace.output.is_none() && !stdout.is_empty() {
+            state.trace.output = Some(stdout);
+        }
+    }
+    *finished = Some(state);
 
-    Ok(())
+    result
 }
@@ -0,0 +1,399 @@
+//! `caeles new`: generates a capsule crate from a template.
+//!
+//! Templates are directories of files where `{{crate}}`, `{{lib}}`, `{{id}}`,
+//! `{{name}}` and `{{sdk}}` are replaced, in contents and file names. The
+//! built-in ones are embedded in the binary; a local directory with the same
+//! layout can be used instead.
+
+use crate::manifest::{validate_capsule_id, CapsuleManifest};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// `caeles-sdk` for crates outside a checkout of this repository.
+const SDK_GIT: &str = "https://github.com/jardelva96/caeles";
+
+type TemplateFiles = &'static [(&'static str, &'static str)];
+
+macro_rules! template_files {
+    ($dir:literal: $($file:literal),+ $(,)?) => {
+        &[$(($file, include_str!(concat!("../templates/", $dir, "/", $file)))),+]
+    };
+}
+
+/// Built-in templates, by name.
+pub const TEMPLATES: [(&str, TemplateFiles); 4] = [
+    (
+        "minimal",
+        template_files!("minimal": "Cargo.toml", "manifest.json", "caeles-test.json", "src/lib.rs"),
+    ),
+    (
+        "http-client",
+        template_files!("http-client": "Cargo.toml", "manifest.json", "src/lib.rs"),
+    ),
+    (
+        "kv-storage",
+        template_files!("kv-storage": "Cargo.toml", "manifest.json", "src/lib.rs"),
+    ),
+    (
+        "scheduled",
+        template_files!("scheduled": "Cargo.toml", "manifest.json", "src/lib.rs"),
+    ),
+];
+
+/// Where the files of a new capsule come from.
+#[derive(Debug, Clone)]
+pub enum Template {
+    Builtin(&'static str),
+    Dir(PathBuf),
+}
+
+impl Template {
+    pub fn builtin(name: &str) -> Result<Self> {
+        TEMPLATES
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(builtin, _)| Template::Builtin(builtin))
+            .ok_or_else(|| {
+                let names: Vec<&str> = TEMPLATES.iter().map(|(name, _)| *name).collect();
+                anyhow::anyhow!(
+                    "Template '{name}' desconhecido (use: {}, ou --template-dir)",
+                    names.join(", ")
+                )
+            })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Template::Builtin(name) => name.to_string(),
+            Template::Dir(dir) => dir.display().to_string(),
+        }
+    }
+
+    /// Relative paths and contents of every file.
+    fn files(&self) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+        match self {
+            Template::Builtin(name) => {
+                let (_, files) = TEMPLATES
+                    .iter()
+                    .find(|(builtin, _)| builtin == name)
+                    .expect("built-in template should exist");
+                Ok(files
+                    .iter()
+                    .map(|(path, text)| (PathBuf::from(path), text.as_bytes().to_vec()))
+                    .collect())
+            }
+            Template::Dir(dir) => {
+                if !dir.join("manifest.json").is_file() {
+                    bail!("Template '{}' não tem manifest.json", dir.display());
+                }
+                let mut files = Vec::new();
+                collect_files(dir, Path::new(""), &mut files)?;
+                Ok(files)
+            }
+        }
+    }
+}
+
+fn collect_files(root: &Path, relative: &Path, files: &mut Vec<(PathBuf, Vec<u8>)>) -> Result<()> {
+    let dir = root.join(relative);
+    let mut entries = fs::read_dir(&dir)
+        .with_context(|| format!("Falha ao ler template '{}'", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        if name == "target" || name == ".git" || name == "Cargo.lock" {
+            continue;
+        }
+        let path = relative.join(&name);
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            files.push((path, fs::read(entry.path())?));
+        }
+    }
+    Ok(())
+}
+
+/// What to generate.
+#[derive(Debug, Clone)]
+pub struct NewCapsule {
+    /// Crate name, also the directory name.
+    pub name: String,
+    pub id: String,
+    /// Name shown in the manifest and registry.
+    pub display_name: String,
+    /// Directory of the crate.
+    pub dir: PathBuf,
+}
+
+/// `hello-capsule` -> `Hello Capsule`.
+pub fn display_name(name: &str) -> String {
+    name.split(['-', '_'])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn validate_crate_name(name: &str) -> Result<()> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!("Nome '{name}' inválido: use letras, dígitos, '-' e '_', começando por uma letra");
+    }
+    Ok(())
+}
+
+impl NewCapsule {
+    /// Writes the crate and returns the path of its manifest.
+    pub fn generate(&self, template: &Template) -> Result<PathBuf> {
+        validate_crate_name(&self.name)?;
+        validate_capsule_id(&self.id)
+            .map_err(|message| anyhow::anyhow!("Id inválido '{}': {message}", self.id))?;
+        if self.dir.exists() {
+            bail!("'{}' já existe", self.dir.display());
+        }
+
+        let files = template.files()?;
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Falha ao criar '{}'", self.dir.display()))?;
+        let replacements = [
+            ("{{crate}}", self.name.clone()),
+            ("{{lib}}", self.name.replace('-', "_")),
+            ("{{id}}", self.id.clone()),
+            ("{{name}}", self.display_name.clone()),
+            ("{{sdk}}", sdk_dependency(&self.dir)),
+        ];
+        let fill = |text: &str| {
+            replacements
+                .iter()
+                .fold(text.to_string(), |text, (placeholder, value)| {
+                    text.replace(placeholder, value)
+                })
+        };
+        for (relative, contents) in files {
+            let path = self.dir.join(fill(&relative.to_string_lossy()));
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let contents = match String::from_utf8(contents) {
+                Ok(text) => fill(&text).into_bytes(),
+                Err(err) => err.into_bytes(),
+            };
+            fs::write(&path, contents)
+                .with_context(|| format!("Falha ao gravar '{}'", path.display()))?;
+        }
+
+        let manifest_path = self.dir.join("manifest.json");
+        if let Err(err) = CapsuleManifest::load(&manifest_path) {
+            fs::remove_dir_all(&self.dir)?;
+            return Err(err.context(format!(
+                "Template '{}' gerou um manifest inválido",
+                template.describe()
+            )));
+        }
+        Ok(manifest_path)
+    }
+}
+
+/// `caeles-sdk` from the checkout that holds `dir`, or from git.
+fn sdk_dependency(dir: &Path) -> String {
+    let Some(crate_dir) = absolute(dir) else {
+        return format!("git = \"{SDK_GIT}\"");
+    };
+    let sdk = crate_dir
+        .ancestors()
+        .skip(1)
+        .map(|ancestor| ancestor.join("crates/caeles-sdk"))
+        .find(|sdk| sdk.join("Cargo.toml").is_file());
+    match sdk.and_then(|sdk| relative_path(&crate_dir, &sdk)) {
+        Some(path) => format!("path = \"{}\"", path.to_string_lossy().replace('\\', "/")),
+        None => format!("git = \"{SDK_GIT}\""),
+    }
+}
+
+/// `path` made absolute and normalized, without requiring it to exist.
+fn absolute(path: &Path) -> Option<PathBuf> {
+    let path = std::env::current_dir().ok()?.join(path);
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    Some(normalized)
+}
+
+/// `to` relative to `from`; both absolute.
+pub fn relative_path(from: &Path, to: &Path) -> Option<PathBuf> {
+    let to = to.canonicalize().ok()?;
+    let from = match from.canonicalize() {
+        Ok(from) => from,
+        Err(_) => {
+            let parent = from.parent()?.canonicalize().ok()?;
+            parent.join(from.file_name()?)
+        }
+    };
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut path = PathBuf::new();
+    for _ in common..from.components().count() {
+        path.push("..");
+    }
+    for component in to.components().skip(common) {
+        path.push(component);
+    }
+    Some(path)
+}
+
+/// Adds `dir` to the members of the Cargo workspace that holds it, if any.
+///
+/// Returns the workspace manifest when it was changed.
+pub fn add_to_workspace(dir: &Path) -> Result<Option<PathBuf>> {
+    let Some(crate_dir) = absolute(dir) else {
+        return Ok(None);
+    };
+    for root in crate_dir.ancestors().skip(1) {
+        let manifest = root.join("Cargo.toml");
+        let Ok(text) = fs::read_to_string(&manifest) else {
+            continue;
+        };
+        let mut document: toml_edit::DocumentMut = text
+            .parse()
+            .with_context(|| format!("Cargo.toml inválido em '{}'", manifest.display()))?;
+        let Some(workspace) = document
+            .get_mut("workspace")
+            .and_then(|w| w.as_table_like_mut())
+        else {
+            continue;
+        };
+        let Some(member) = relative_path(root, &crate_dir) else {
+            return Ok(None);
+        };
+        let member = member.to_string_lossy().replace('\\', "/");
+        let mut members: Vec<String> = workspace
+            .get("members")
+            .and_then(|m| m.as_array())
+            .map(|m| {
+                m.iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if members.contains(&member) {
+            return Ok(None);
+        }
+        members.push(member);
+
+        // One member per line, the way `cargo new` leaves workspace lists.
+        let mut list = toml_edit::Array::new();
+        for member in members {
+            let mut value = toml_edit::Value::from(member);
+            value.decor_mut().set_prefix("\n    ");
+            list.push_formatted(value);
+        }
+        list.set_trailing_comma(true);
+        list.set_trailing("\n");
+        workspace.insert("members", toml_edit::value(list));
+        let updated = document.to_string();
+        fs::write(&manifest, updated)
+            .with_context(|| format!("Falha ao gravar '{}'", manifest.display()))?;
+        return Ok(Some(manifest));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_to_workspace, display_name, NewCapsule, Template, TEMPLATES};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn every_builtin_template_generates_a_valid_capsule() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        for (name, _) in TEMPLATES {
+            let capsule = NewCapsule {
+                name: format!("{name}-capsule"),
+                id: format!("com.tests.{}", name.replace('-', "")),
+                display_name: display_name(name),
+                dir: root.join(name),
+            };
+            let manifest = capsule
+                .generate(&Template::builtin(name).expect("template should exist"))
+                .expect("capsule should be generated");
+            let text = fs::read_to_string(&manifest).expect("manifest should be readable");
+            let entry = format!("\"entry\": \"{}_capsule.wasm\"", name.replace('-', "_"));
+            assert!(text.contains(&entry), "{text}");
+            let cargo = fs::read_to_string(root.join(name).join("Cargo.toml"))
+                .expect("Cargo.toml should be readable");
+            assert!(cargo.contains(&format!("name = \"{name}-capsule\"")));
+            assert!(!cargo.contains("{{"), "{cargo}");
+        }
+        assert!(Template::builtin("cron").is_err());
+    }
+
+    #[test]
+    fn add_to_workspace_appends_member_once() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/a\"]\nresolver = \"2\"\n",
+        )
+        .expect("workspace should be written");
+        let dir = root.join("capsules/new-one");
+        fs::create_dir_all(&dir).expect("capsule directory should be created");
+
+        assert_eq!(
+            add_to_workspace(&dir).expect("workspace should be updated"),
+            Some(root.join("Cargo.toml"))
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("Cargo.toml")).expect("workspace should be readable"),
+            "[workspace]\nmembers = [\n    \"crates/a\",\n    \"capsules/new-one\",\n]\nresolver = \"2\"\n"
+        );
+        assert_eq!(
+            add_to_workspace(&dir).expect("workspace should be read"),
+            None
+        );
+        assert_eq!(display_name("hello_world-capsule"), "Hello World Capsule");
+    }
+
+    #[test]
+    fn add_to_workspace_edits_only_the_workspace_members_list() {
+        let temp = TempDir::new().expect("temp directory should be created");
+        let root = temp.path();
+        fs::write(
+            root.join("Cargo.toml"),
+            "# members = [\"old\"]\n[workspace]\ndefault-members = [\"crates/a\"]\nmembers = [\"crates/a\", \"crates/b\"]\n",
+        )
+        .expect("workspace should be written");
+        let dir = root.join("capsules/new-one");
+        fs::create_dir_all(&dir).expect("capsule directory should be created");
+
+        add_to_workspace(&dir).expect("workspace should be updated");
+        assert_eq!(
+            fs::read_to_string(root.join("Cargo.toml")).expect("workspace should be readable"),
+            "# members = [\"old\"]\n[workspace]\ndefault-members = [\"crates/a\"]\nmembers = [\n    \"crates/a\",\n    \"crates/b\",\n    \"capsules/new-one\",\n]\n"
+        );
+    }
+}
@@ -0,0 +1,568 @@
+//! `caeles` settings: the user file (`~/.config/caeles/config.toml`) and the
+//! project file (`caeles.toml` in the working directory or its nearest parent),
+//! which wins over it. Command-line flags win over both.
+//!
+//! Relative paths in a file are relative to the directory holding it, so a
+//! project behaves the same from any of its subdirectories.
+
+use crate::manifest::CapsuleManifest;
+use crate::registry::is_url;
+use crate::runtime::LogLevel;
+use crate::state::STATE_DIR;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+pub const PROJECT_FILE: &str = "caeles.toml";
+
+/// Registry used when no file sets `registries`.
+pub const DEFAULT_REGISTRY: &str = "capsules/registry.json";
+
+/// Images, tags and blobs when no file sets `state_dir`.
+pub const DEFAULT_STORE_DIR: &str = ".caeles";
+
+/// Permissions `permissions.deny` may name.
+pub const POLICY_PERMISSIONS: [&str; 5] = [
+    "notifications",
+    "network",
+    "wall_clock",
+    "filesystem",
+    "publish",
+];
+
+/// Keys shown by `caeles config list`, in that order.
+pub const KEYS: [&str; 5] = [
+    "state_dir",
+    "registries",
+    "permissions.deny",
+    "run.timeout_ms",
+    "run.log_level",
+];
+
+/// Contents of one settings file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingsFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_dir: Option<String>,
+    /// Registry files or `http(s)://` URLs, highest precedence first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registries: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "PermissionsPolicy::is_empty")]
+    pub permissions: PermissionsPolicy,
+    #[serde(default, skip_serializing_if = "RunDefaults::is_empty")]
+    pub run: RunDefaults,
+}
+
+/// Permissions no capsule gets, whatever its manifest asks for.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionsPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deny: Option<Vec<String>>,
+}
+
+impl PermissionsPolicy {
+    fn is_empty(&self) -> bool {
+        self.deny.is_none()
+    }
+}
+
+/// Defaults of `caeles run` limits, used when the flag is not given.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<LogLevel>,
+}
+
+impl RunDefaults {
+    fn is_empty(&self) -> bool {
+        self.timeout_ms.is_none() && self.log_level.is_none()
+    }
+}
+
+impl SettingsFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Falha ao ler configuração '{}'", path.display()))?;
+        let file: Self = toml::from_str(&text)
+            .with_context(|| format!("Configuração inválida em '{}'", path.display()))?;
+        file.check()
+            .with_context(|| format!("Configuração inválida em '{}'", path.display()))?;
+        Ok(file)
+    }
+
+    /// Writes the settings into `path`, keeping the comments and order of what is already there.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = if path.exists() {
+            fs::read_to_string(path)
+                .with_context(|| format!("Falha ao ler configuração '{}'", path.display()))?
+        } else {
+            String::new()
+        };
+        let mut document: DocumentMut = text
+            .parse()
+            .with_context(|| format!("Configuração inválida em '{}'", path.display()))?;
+        let strings = |items: &Option<Vec<String>>| {
+            items
+                .as_ref()
+                .map(|items| Value::Array(items.iter().collect()))
+        };
+        set_key(
+            document.as_table_mut(),
+            "state_dir",
+            self.state_dir.as_deref().map(Value::from),
+        );
+        set_key(
+            document.as_table_mut(),
+            "registries",
+            strings(&self.registries),
+        );
+        set_key(
+            table_mut(&mut document, "permissions"),
+            "deny",
+            strings(&self.permissions.deny),
+        );
+        let run = table_mut(&mut document, "run");
+        set_key(
+            run,
+            "timeout_ms",
+            self.run
+                .timeout_ms
+                .map(|ms| Value::from(i64::try_from(ms).unwrap_or(i64::MAX))),
+        );
+        set_key(
+            run,
+            "log_level",
+            self.run.log_level.map(|level| Value::from(level.as_str())),
+        );
+        for section in ["permissions", "run"] {
+            if document
+                .get(section)
+                .and_then(Item::as_table_like)
+                .is_some_and(|table| table.is_empty())
+            {
+                document.remove(section);
+            }
+        }
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, document.to_string())
+            .with_context(|| format!("Falha ao gravar configuração '{}'", path.display()))
+    }
+
+    fn check(&self) -> Result<()> {
+        for name in self.permissions.deny.iter().flatten() {
+            if !POLICY_PERMISSIONS.contains(&name.as_str()) {
+                bail!(
+                    "permissions.deny: '{name}' não é uma permissão (use: {})",
+                    POLICY_PERMISSIONS.join(", ")
+                );
+            }
+        }
+        if self.registries.as_ref().is_some_and(Vec::is_empty) {
+            bail!("registries: informe ao menos um registry");
+        }
+        Ok(())
+    }
+
+    /// Sets `key` from its command-line text; lists are comma-separated.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let list = || -> Vec<String> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        match key {
+            "state_dir" => self.state_dir = Some(value.to_string()),
+            "registries" => self.registries = Some(list()),
+            "permissions.deny" => self.permissions.deny = Some(list()),
+            "run.timeout_ms" => {
+                let timeout = value
+                    .parse()
+                    .with_context(|| format!("run.timeout_ms: '{value}' não é um número"))?;
+                self.run.timeout_ms = Some(timeout);
+            }
+            "run.log_level" => {
+                let level = <LogLevel as clap::ValueEnum>::from_str(value, true)
+                    .map_err(|_| anyhow::anyhow!("run.log_level: nível '{value}' inválido"))?;
+                self.run.log_level = Some(level);
+            }
+            _ => bail!("Chave desconhecida '{key}' (use: {})", KEYS.join(", ")),
+        }
+        self.check()
+    }
+}
+
+/// The `[name]` table of a settings document, created if missing.
+fn table_mut<'a>(document: &'a mut DocumentMut, name: &str) -> &'a mut dyn TableLike {
+    let item = document.entry(name).or_insert_with(toml_edit::table);
+    if item.as_table_like().is_none() {
+        *item = toml_edit::table();
+    }
+    item.as_table_like_mut()
+        .expect("item was just made a table")
+}
+
+/// Sets or removes `key`, keeping the comments around an existing value.
+fn set_key(table: &mut dyn TableLike, key: &str, value: Option<Value>) {
+    match value {
+        Some(mut value) => match table.get_mut(key) {
+            Some(item) => {
+                if let Some(old) = item.as_value() {
+                    *value.decor_mut() = old.decor().clone();
+                }
+                *item = Item::Value(value);
+            }
+            None => {
+                table.insert(key, Item::Value(value));
+            }
+        },
+        None => {
+            table.remove(key);
+        }
+    }
+}
+
+/// Where a setting came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+}
+
+impl Origin {
+    pub fn describe(&self) -> String {
+        match self {
+            Origin::Default => "padrão".to_string(),
+            Origin::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+/// A value with the file that set it.
+#[derive(Debug, Clone)]
+pub struct Setting<T> {
+    pub value: T,
+    pub origin: Origin,
+}
+
+impl<T> Setting<T> {
+    fn default(value: T) -> Self {
+        Self {
+            value,
+            origin: Origin::Default,
+        }
+    }
+}
+
+/// Settings in effect after merging the user and project files.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub user_file: Option<PathBuf>,
+    pub project_file: Option<PathBuf>,
+    /// Directory of the project file, or the working directory without one.
+    pub root: PathBuf,
+    pub state_dir: Setting<PathBuf>,
+    /// Paths are resolved; URLs are kept as written.
+    pub registries: Setting<Vec<String>>,
+    pub deny: Setting<Vec<String>>,
+    pub timeout_ms: Setting<Option<u64>>,
+    pub log_level: Setting<LogLevel>,
+}
+
+/// `$XDG_CONFIG_HOME/caeles/config.toml`, or `~/.config/caeles/config.toml`.
+pub fn user_file() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("caeles").join("config.toml"))
+}
+
+/// `caeles.toml` in the working directory or the nearest parent that has one.
+pub fn project_file() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+/// `location` relative to the directory of the file that declared it.
+fn resolve_path(file: &Path, location: &str) -> PathBuf {
+    let path = Path::new(location);
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    file.parent().unwrap_or_else(|| Path::new(".")).join(path)
+}
+
+impl Settings {
+    pub fn load() -> Result<Self> {
+        let mut settings = Self::defaults(user_file(), project_file());
+        let files = [settings.user_file.clone(), settings.project_file.clone()];
+        for path in files.into_iter().flatten() {
+            let file = SettingsFile::load(&path)?;
+            settings.apply(&file, &path);
+        }
+        Ok(settings)
+    }
+
+    fn defaults(user_file: Option<PathBuf>, project_file: Option<PathBuf>) -> Self {
+        let root = project_file
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        Settings {
+            user_file,
+            project_file,
+            state_dir: Setting::default(root.join(STATE_DIR)),
+            registries: Setting::default(vec![root.join(DEFAULT_REGISTRY).display().to_string()]),
+            root,
+            deny: Setting::default(Vec::new()),
+            timeout_ms: Setting::default(None),
+            log_level: Setting::default(LogLevel::Info),
+        }
+    }
+
+    fn apply(&mut self, file: &SettingsFile, path: &Path) {
+        let origin = Origin::File(path.to_path_buf());
+        if let Some(state_dir) = &file.state_dir {
+            self.state_dir = Setting {
+                value: resolve_path(path, state_dir),
+                origin: origin.clone(),
+            };
+        }
+        if let Some(registries) = &file.registries {
+            let value = registries
+                .iter()
+                .map(|location| {
+                    if is_url(location) {
+                        location.clone()
+                    } else {
+                        resolve_path(path, location).display().to_string()
+                    }
+                })
+                .collect();
+            self.registries = Setting {
+                value,
+                origin: origin.clone(),
+            };
+        }
+        if let Some(deny) = &file.permissions.deny {
+            self.deny = Setting {
+                value: deny.clone(),
+                origin: origin.clone(),
+            };
+        }
+        if let Some(timeout_ms) = file.run.timeout_ms {
+            self.timeout_ms = Setting {
+                value: Some(timeout_ms),
+                origin: origin.clone(),
+            };
+        }
+        if let Some(log_level) = file.run.log_level {
+            self.log_level = Setting {
+                value: log_level,
+                origin,
+            };
+        }
+    }
+
+    /// Where packaged and pulled images, their tags and blobs are kept: the
+    /// configured `state_dir`, or `.caeles/` at the project root.
+    pub fn store_dir(&self) -> PathBuf {
+        match self.state_dir.origin {
+            Origin::Default => self.root.join(DEFAULT_STORE_DIR),
+            Origin::File(_) => self.state_dir.value.clone(),
+        }
+    }
+
+    /// Effective value of `key` as text, with where it came from.
+    pub fn get(&self, key: &str) -> Result<(String, &Origin)> {
+        Ok(match key {
+            "state_dir" => (
+                self.state_dir.value.display().to_string(),
+                &self.state_dir.origin,
+            ),
+            "registries" => (self.registries.value.join(","), &self.registries.origin),
+            "permissions.deny" => (self.deny.value.join(","), &self.deny.origin),
+            "run.timeout_ms" => (
+                self.timeout_ms
+                    .value
+                    .map(|ms| ms.to_string())
+                    .unwrap_or_default(),
+                &self.timeout_ms.origin,
+            ),
+            "run.log_level" => (
+                self.log_level.value.as_str().to_string(),
+                &self.log_level.origin,
+            ),
+            _ => bail!("Chave desconhecida '{key}' (use: {})", KEYS.join(", ")),
+        })
+    }
+
+    /// Fails when the manifest asks for a permission in `permissions.deny`.
+    pub fn check_permissions(&self, manifest: &CapsuleManifest) -> Result<()> {
+        let denied: Vec<&str> = self
+            .deny
+            .value
+            .iter()
+            .map(String::as_str)
+            .filter(|name| manifest.permissions.is_granted(name))
+            .collect();
+        if !denied.is_empty() {
+            bail!(
+                "Permissão '{}' de '{}' negada pela configuração ({})",
+                denied.join("', '"),
+                manifest.id,
+                self.deny.origin.describe()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Origin, Settings, SettingsFile};
+    use crate::runtime::LogLevel;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn later_files_win_and_paths_are_relative_to_their_file() {
+        let user: SettingsFile = toml::from_str(
+            r#"
+state_dir = "/var/caeles"
+registries = ["shared/registry.json"]
+
+[run]
+timeout_ms = 500
+"#,
+        )
+        .expect("user settings should parse");
+        let project: SettingsFile = toml::from_str(
+            r#"
+registries = ["capsules/registry.json", "https://example.com/registry.json"]
+
+[permissions]
+deny = ["network"]
+
+[run]
+log_level = "debug"
+"#,
+        )
+        .expect("project settings should parse");
+
+        let mut settings = Settings::defaults(None, None);
+        settings.apply(&user, Path::new("/home/me/.config/caeles/config.toml"));
+        settings.apply(&project, Path::new("/work/app/caeles.toml"));
+
+        assert_eq!(settings.state_dir.value, PathBuf::from("/var/caeles"));
+        assert_eq!(
+            settings.registries.value,
+            vec![
+                "/work/app/capsules/registry.json",
+                "https://example.com/registry.json"
+            ]
+        );
+        assert_eq!(settings.timeout_ms.value, Some(500));
+        assert_eq!(
+            settings.timeout_ms.origin,
+            Origin::File(PathBuf::from("/home/me/.config/caeles/config.toml"))
+        );
+        assert_eq!(settings.log_level.value, LogLevel::Debug);
+        assert_eq!(
+            settings
+                .get("permissions.deny")
+                .expect("key should exist")
+                .0,
+            "network"
+        );
+        assert!(settings.get("run.fuel").is_err());
+        assert_eq!(settings.store_dir(), PathBuf::from("/var/caeles"));
+    }
+
+    #[test]
+    fn defaults_sit_at_the_project_root() {
+        let settings = Settings::defaults(None, Some(PathBuf::from("/work/app/caeles.toml")));
+        assert_eq!(
+            settings.state_dir.value,
+            PathBuf::from("/work/app/.caeles/state")
+        );
+        assert_eq!(settings.store_dir(), PathBuf::from("/work/app/.caeles"));
+        assert_eq!(
+            settings.registries.value,
+            vec!["/work/app/capsules/registry.json"]
+        );
+
+        let settings = Settings::defaults(None, None);
+        assert_eq!(settings.state_dir.value, PathBuf::from(".caeles/state"));
+        assert_eq!(settings.store_dir(), PathBuf::from(".caeles"));
+    }
+
+    #[test]
+    fn set_parses_values_and_rejects_unknown_keys_and_permissions() {
+        let mut file = SettingsFile::default();
+        file.set("registries", "a.json, https://example.com/r.json")
+            .expect("registries should be set");
+        file.set("run.log_level", "WARN")
+            .expect("log level should be set");
+        file.set("permissions.deny", "network,filesystem")
+            .expect("deny list should be set");
+        assert_eq!(
+            file.registries,
+            Some(vec![
+                "a.json".to_string(),
+                "https://example.com/r.json".to_string()
+            ])
+        );
+        assert_eq!(file.run.log_level, Some(LogLevel::Warn));
+
+        assert!(file.set("run.timeout_ms", "soon").is_err());
+        assert!(file.set("permissions.deny", "gpu").is_err());
+        assert!(file.set("colour", "auto").is_err());
+
+        let text = toml::to_string_pretty(&file).expect("settings should serialize");
+        let parsed: SettingsFile = toml::from_str(&text).expect("settings should parse back");
+        assert_eq!(parsed.run.log_level, Some(LogLevel::Warn));
+        assert!(toml::from_str::<SettingsFile>("colour = 1").is_err());
+    }
+
+    #[test]
+    fn save_keeps_comments_and_order() {
+        let dir = tempfile::TempDir::new().expect("temp directory should be created");
+        let path = dir.path().join("caeles.toml");
+        std::fs::write(
+            &path,
+            "# project settings\nregistries = [\"a.json\"] # local first\n\n[run]\nlog_level = \"info\" # chatty\n",
+        )
+        .expect("settings should be written");
+
+        let mut file = SettingsFile::load(&path).expect("settings should load");
+        file.set("run.log_level", "debug")
+            .expect("log level should be set");
+        file.set("state_dir", "state")
+            .expect("state dir should be set");
+        file.save(&path).expect("settings should be saved");
+
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("settings should be readable"),
+            "# project settings\nregistries = [\"a.json\"] # local first\nstate_dir = \"state\"\n\n[run]\nlog_level = \"debug\" # chatty\n"
+        );
+    }
+}
@@ -2,7 +2,9 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+/// State directory when no settings file sets `state_dir`.
 pub const STATE_DIR: &str = ".caeles/state";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -14,10 +16,41 @@ pub struct RunRecord {
     pub status: String,
     pub started_at_unix_ms: u128,
     pub finished_at_unix_ms: u128,
+    /// Host call trace written by `caeles run --record`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_path: Option<String>,
+    /// Seed of a `--deterministic` run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Bus message that started an `event` run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<RunTrigger>,
+    /// Local image the capsule was loaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<RunImage>,
 }
 
+/// Image behind a run: `<id>@<version>`, where it came from and its wasm digest.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RunImage {
+    pub reference: String,
+    pub source: String,
+    pub digest: String,
+}
+
+/// Message behind an `event` run; `parent_run_id` links the run to the one that published it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RunTrigger {
+    pub message_id: String,
+    pub topic: String,
+    pub publisher: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_run_id: Option<String>,
+}
+
+/// State directory from the settings (`state_dir`), created if missing.
 pub fn ensure_state_dirs() -> anyhow::Result<PathBuf> {
-    let base = PathBuf::from(STATE_DIR);
+    let base = crate::settings::Settings::load()?.state_dir.value;
     fs::create_dir_all(base.join("logs"))?;
     Ok(base)
 }
@@ -71,6 +104,11 @@ pub fn log_file_path(base: &Path, run_id: &str) -> PathBuf {
     base.join("logs").join(format!("{run_id}.log"))
 }
 
+/// Private directory of a capsule on the host, used to back WASI preopens.
+pub fn sandbox_dir(base: &Path, capsule_id: &str) -> PathBuf {
+    base.join("sandbox").join(capsule_id)
+}
+
 pub fn write_log_line(base: &Path, run_id: &str, message: &str) -> anyhow::Result<()> {
     let mut f = fs::OpenOptions::new()
         .create(true)
@@ -79,3 +117,215 @@ pub fn write_log_line(base: &Path, run_id: &str, message: &str) -> anyhow::Resul
     writeln!(f, "{message}")?;
     Ok(())
 }
+
+/// A run in progress, recorded so that `caeles rmi` and `caeles image prune`
+/// leave the images it loaded alone.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActiveRun {
+    pub run_id: String,
+    pub pid: u32,
+    pub capsule_id: String,
+    /// Manifest of the capsule and of each linked dependency.
+    pub manifests: Vec<String>,
+}
+
+fn active_run_path(base: &Path, run_id: &str) -> PathBuf {
+    base.join("active").join(format!("{run_id}.json"))
+}
+
+/// Removes the active-run entry when the run finishes, fails or panics.
+#[derive(Debug)]
+pub struct ActiveRunGuard {
+    path: PathBuf,
+}
+
+impl Drop for ActiveRunGuard {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub fn register_active_run(base: &Path, run: &ActiveRun) -> anyhow::Result<ActiveRunGuard> {
+    let path = active_run_path(base, &run.run_id);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string(run)?)?;
+    Ok(ActiveRunGuard { path })
+}
+
+/// Runs in progress. Entries left by processes that no longer exist are removed.
+pub fn load_active_runs(base: &Path) -> anyhow::Result<Vec<ActiveRun>> {
+    let dir = base.join("active");
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let Ok(run) = serde_json::from_str::<ActiveRun>(&fs::read_to_string(&path)?) else {
+            continue;
+        };
+        if process_alive(run.pid) {
+            runs.push(run);
+        } else {
+            let _ = fs::remove_file(&path);
+        }
+    }
+    runs.sort_by(|a, b| a.run_id.cmp(&b.run_id));
+    Ok(runs)
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Without `/proc`, entries are trusted until their run removes them.
+#[cfg(not(target_os = "linux"))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+/// Text shown in place of secret values.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// `secret` as written, and as it appears inside a JSON string: escaped by
+/// `serde_json`, and with non-ASCII characters as `\uXXXX` too.
+fn json_escaped_forms(secret: String) -> Vec<String> {
+    let json = serde_json::to_string(&secret).unwrap_or_default();
+    let escaped = json[1..json.len() - 1].to_string();
+    let mut ascii = String::new();
+    for c in escaped.chars() {
+        if c.is_ascii() {
+            ascii.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                ascii.push_str(&format!("\\u{unit:04x}"));
+            }
+        }
+    }
+    let mut forms = vec![secret];
+    for form in [escaped, ascii] {
+        if !forms.contains(&form) {
+            forms.push(form);
+        }
+    }
+    forms
+}
+
+/// Replaces secret values in text leaving the host (console, run log, traces).
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    secrets: Arc<Vec<String>>,
+}
+
+impl Redactor {
+    pub fn new(secrets: impl IntoIterator<Item = String>) -> Self {
+        let mut secrets: Vec<String> = secrets
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .flat_map(json_escaped_forms)
+            .collect();
+        secrets.dedup();
+        // Longest first, so a secret containing another is replaced whole.
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        Self {
+            secrets: Arc::new(secrets),
+        }
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in self.secrets.iter() {
+            if text.contains(secret.as_str()) {
+                text = text.replace(secret.as_str(), REDACTED);
+            }
+        }
+        text
+    }
+
+    /// Redacts every string inside a JSON value.
+    pub fn redact_json(&self, value: &serde_json::Value) -> serde_json::Value {
+        use serde_json::Value;
+        if self.secrets.is_empty() {
+            return value.clone();
+        }
+        match value {
+            Value::String(text) => Value::String(self.redact(text)),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|v| self.redact_json(v)).collect())
+            }
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), self.redact_json(v)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+/// Handle to the log file of a single run, shared with the runtime so host calls can write to it.
+#[derive(Debug, Clone)]
+pub struct RunLog {
+    base: PathBuf,
+    run_id: String,
+    redactor: Redactor,
+}
+
+impl RunLog {
+    pub fn new(base: &Path, run_id: &str) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            run_id: run_id.to_string(),
+            redactor: Redactor::default(),
+        }
+    }
+
+    /// Redacts these secret values from every line written to the log.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    pub fn line(&self, message: &str) -> anyhow::Result<()> {
+        write_log_line(&self.base, &self.run_id, &self.redactor.redact(message))
+    }
+
+    /// Appends a structured security event as an `audit {json}` line.
+    pub fn audit(&self, event: &str, details: serde_json::Value) {
+        let mut payload = serde_json::json!({ "event": event });
+        if let (Some(payload), serde_json::Value::Object(details)) =
+            (payload.as_object_mut(), details)
+        {
+            payload.extend(details);
+        }
+        if let Err(err) = self.line(&format!("audit {payload}")) {
+            eprintln!("[caeles-runtime] failed to write audit event: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redactor_hides_json_escaped_secrets() {
+        let redactor = Redactor::new(["pa\"ss\\wörd".to_string()]);
+
+        let line = redactor.redact(r#"raw=pa"ss\wörd json={"token":"pa\"ss\\wörd"}"#);
+        assert_eq!(
+            line,
+            format!(r#"raw={REDACTED} json={{"token":"{REDACTED}"}}"#)
+        );
+
+        let line = redactor.redact(r#"{"token":"pa\"ss\\w\u00f6rd"}"#);
+        assert_eq!(line, format!(r#"{{"token":"{REDACTED}"}}"#));
+    }
+}
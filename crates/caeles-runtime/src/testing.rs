@@ -0,0 +1,445 @@
+use crate::capsule_config;
+use crate::dependencies::ResolvedDependency;
+use crate::manifest::CapsuleManifest;
+use crate::replay::HostCallMode;
+use crate::runtime::{self, HttpFailure, HttpResponse, HttpStubs, LogLevel, RunOptions, RunTrace};
+use crate::state::{sandbox_dir, Redactor, RunLog};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Default test file looked up next to the manifest.
+pub const DEFAULT_TEST_FILE: &str = "caeles-test.json";
+
+/// Declarative test file for `caeles test`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestFile {
+    pub cases: Vec<TestCase>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestCase {
+    pub name: String,
+    /// JSON handed to the capsule like `caeles run --input`.
+    #[serde(default)]
+    pub input: Option<serde_json::Value>,
+    /// Overrides of the manifest permissions for this case.
+    #[serde(default)]
+    pub permissions: PermissionOverrides,
+    /// Stubbed responses by URL; requests to other URLs fail with a host error.
+    #[serde(default)]
+    pub http: BTreeMap<String, HttpStub>,
+    #[serde(default = "default_log_level")]
+    pub log_level: LogLevel,
+    /// Cases always run in deterministic mode, with this seed.
+    #[serde(default)]
+    pub seed: u64,
+    /// Run timeout of the case, like `caeles run --timeout-ms`.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Config values of the case, like `caeles run --config-file`; the config store is not used.
+    #[serde(default)]
+    pub config: BTreeMap<String, serde_json::Value>,
+    /// Secret values of the case; the secret store is not used.
+    #[serde(default)]
+    pub secrets: BTreeMap<String, String>,
+    #[serde(default)]
+    pub expect: Expectations,
+}
+
+fn default_log_level() -> LogLevel {
+    LogLevel::Info
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionOverrides {
+    pub notifications: Option<bool>,
+    pub network: Option<bool>,
+    pub network_hosts: Option<Vec<String>>,
+    pub filesystem: Option<bool>,
+    pub publish: Option<Vec<String>>,
+}
+
+/// `{"status": 200, "body": "..."}` or `{"error": "host_failure"}`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum HttpStub {
+    Error {
+        error: StubError,
+    },
+    Response {
+        #[serde(default = "default_status")]
+        status: u16,
+        #[serde(default)]
+        body: String,
+    },
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StubError {
+    InvalidRequest,
+    HostFailure,
+}
+
+/// Expected behavior; fields left out are not checked.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectations {
+    pub logs: Option<Vec<String>>,
+    pub notifications: Option<Vec<String>>,
+    /// A string is compared verbatim; any other JSON value is compared to the parsed output.
+    pub output: Option<serde_json::Value>,
+    /// Published messages as `topic: payload` lines.
+    pub published: Option<Vec<String>>,
+    pub exit_code: Option<i32>,
+}
+
+impl TestFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Falha ao ler arquivo de teste '{}'", path.display()))?;
+        let mut deserializer = serde_json::Deserializer::from_str(&text);
+        serde_path_to_error::deserialize(&mut deserializer).map_err(|err| {
+            anyhow::anyhow!(
+                "Arquivo de teste invalido '{}': campo '{}': {}",
+                path.display(),
+                err.path(),
+                err.inner()
+            )
+        })
+    }
+}
+
+/// Outcome of one test case.
+#[derive(Debug)]
+pub struct CaseResult {
+    pub name: String,
+    pub duration_secs: f64,
+    /// Human-readable mismatches; empty when the case passed.
+    pub failures: Vec<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+fn apply_permissions(
+    manifest: &CapsuleManifest,
+    overrides: &PermissionOverrides,
+) -> CapsuleManifest {
+    let mut manifest = manifest.clone();
+    if let Some(notifications) = overrides.notifications {
+        manifest.permissions.notifications = notifications;
+    }
+    if let Some(network) = overrides.network {
+        manifest.permissions.network = network;
+    }
+    if let Some(hosts) = &overrides.network_hosts {
+        manifest.permissions.network_hosts = hosts.clone();
+    }
+    if let Some(filesystem) = overrides.filesystem {
+        manifest.permissions.filesystem = filesystem;
+    }
+    if let Some(topics) = &overrides.publish {
+        manifest.permissions.publish = topics.clone();
+    }
+    manifest
+}
+
+fn http_stubs(stubs: &BTreeMap<String, HttpStub>) -> HttpStubs {
+    stubs
+        .iter()
+        .map(|(url, stub)| {
+            let result = match stub {
+                HttpStub::Response { status, body } => Ok(HttpResponse {
+                    status: *status,
+                    body: body.clone(),
+                }),
+                HttpStub::Error { error } => Err(match error {
+                    StubError::InvalidRequest => HttpFailure::InvalidRequest,
+                    StubError::HostFailure => HttpFailure::HostFailure,
+                }),
+            };
+            (url.clone(), result)
+        })
+        .collect()
+}
+
+/// Runs one case in a fresh runtime with its own run log (`<run_id>.log`).
+pub fn run_case(
+    manifest: &CapsuleManifest,
+    dependencies: &[ResolvedDependency],
+    case: &TestCase,
+    state_dir: &Path,
+    run_id: &str,
+) -> CaseResult {
+    let started = Instant::now();
+    let manifest = apply_permissions(manifest, &case.permissions);
+    let config =
+        match capsule_config::resolve_config(&manifest, &BTreeMap::new(), &case.config, &[]) {
+            Ok(config) => config,
+            Err(err) => {
+                return CaseResult {
+                    name: case.name.clone(),
+                    duration_secs: started.elapsed().as_secs_f64(),
+                    failures: vec![format!("config inválida: {err:#}")],
+                }
+            }
+        };
+    let options = RunOptions {
+        log: RunLog::new(state_dir, run_id)
+            .with_redactor(Redactor::new(case.secrets.values().cloned())),
+        sandbox_dir: sandbox_dir(state_dir, &manifest.id),
+        input: case
+            .input
+            .as_ref()
+            .map(|value| value.to_string().into_bytes())
+            .unwrap_or_default(),
+        log_level: case.log_level,
+        http_stubs: Some(http_stubs(&case.http)),
+        host_calls: HostCallMode::Off,
+        capability_salt: None,
+        seed: Some(case.seed),
+        timeout: case.timeout_ms.map(Duration::from_millis),
+        config,
+        secrets: case.secrets.clone(),
+        dependencies: dependencies.to_vec(),
+    };
+
+    let outcome = runtime::run_capsule(&manifest, &options);
+    let exit_code = runtime::exit_code(&outcome.result);
+    let mut failures = check(&case.expect, &outcome.trace, exit_code);
+    if let (Err(err), None) = (&outcome.result, case.expect.exit_code) {
+        failures.push(format!("execução falhou: {err:#}"));
+    }
+
+    CaseResult {
+        name: case.name.clone(),
+        duration_secs: started.elapsed().as_secs_f64(),
+        failures,
+    }
+}
+
+fn check(expect: &Expectations, trace: &RunTrace, exit_code: i32) -> Vec<String> {
+    let mut failures = Vec::new();
+    let published: Vec<String> = trace
+        .published
+        .iter()
+        .map(|message| format!("{}: {}", message.topic, message.payload))
+        .collect();
+
+    for (field, expected, actual) in [
+        ("logs", &expect.logs, &trace.logs),
+        ("notifications", &expect.notifications, &trace.notifications),
+        ("published", &expect.published, &published),
+    ] {
+        if let Some(expected) = expected {
+            if expected != actual {
+                let mut report = format!("{field}:");
+                for line in diff_lines(expected, actual) {
+                    report.push_str("\n  ");
+                    report.push_str(&line);
+                }
+                failures.push(report);
+            }
+        }
+    }
+
+    if let Some(expected) = &expect.output {
+        let matches = match (expected, &trace.output) {
+            (serde_json::Value::String(text), Some(actual)) => text == actual,
+            (value, Some(actual)) => {
+                serde_json::from_str::<serde_json::Value>(actual)
+                    .ok()
+                    .as_ref()
+                    == Some(value)
+            }
+            (_, None) => false,
+        };
+        if !matches {
+            let expected = match expected {
+                serde_json::Value::String(text) => text.clone(),
+                value => value.to_string(),
+            };
+            failures.push(format!(
+                "output:\n  - {expected}\n  + {}",
+                trace.output.as_deref().unwrap_or("<nenhum>")
+            ));
+        }
+    }
+
+    if let Some(expected) = expect.exit_code {
+        if expected != exit_code {
+            failures.push(format!(
+                "exit_code: esperado {expected}, obtido {exit_code}"
+            ));
+        }
+    }
+
+    failures
+}
+
+/// Line diff based on the longest common subsequence: `  ` kept, `- ` expected only, `+ ` actual only.
+pub fn diff_lines(expected: &[String], actual: &[String]) -> Vec<String> {
+    let (n, m) = (expected.len(), actual.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        } else {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        }
+    }
+    lines
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders the results as a JUnit XML report with one `<testsuite>` per capsule.
+pub fn junit_xml(suite: &str, results: &[CaseResult]) -> String {
+    let failures = results.iter().filter(|r| !r.passed()).count();
+    let time: f64 = results.iter().map(|r| r.duration_secs).sum();
+    let suite = xml_escape(suite);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites tests=\"{}\" failures=\"{failures}\">\n",
+        results.len()
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{suite}\" tests=\"{}\" failures=\"{failures}\" time=\"{time:.3}\">\n",
+        results.len()
+    ));
+    for result in results {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{suite}\" time=\"{:.3}\"",
+            xml_escape(&result.name),
+            result.duration_secs
+        ));
+        if result.passed() {
+            xml.push_str(" />\n");
+            continue;
+        }
+        let details = result.failures.join("\n");
+        xml.push_str(&format!(
+            ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+            xml_escape(result.failures[0].lines().next().unwrap_or_default()),
+            xml_escape(&details)
+        ));
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, diff_lines, junit_xml, CaseResult, Expectations, TestFile};
+    use crate::runtime::RunTrace;
+
+    fn lines(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn diff_lines_marks_missing_and_extra_lines() {
+        let diff = diff_lines(&lines(&["a", "b", "c"]), &lines(&["a", "x", "c"]));
+        assert_eq!(diff, lines(&["  a", "+ x", "- b", "  c"]));
+    }
+
+    #[test]
+    fn check_compares_json_output_and_exit_code() {
+        let expect: Expectations = serde_json::from_str(
+            r#"{ "output": { "ok": true }, "exit_code": 0, "logs": ["start"] }"#,
+        )
+        .expect("expectations should parse");
+        let trace = RunTrace {
+            logs: lines(&["start"]),
+            notifications: Vec::new(),
+            output: Some(r#"{"ok": true}"#.to_string()),
+            published: Vec::new(),
+        };
+        assert!(check(&expect, &trace, 0).is_empty());
+
+        let failures = check(&expect, &trace, 1);
+        assert_eq!(failures, ["exit_code: esperado 0, obtido 1"]);
+    }
+
+    #[test]
+    fn test_file_rejects_unknown_fields() {
+        let dir = tempfile::TempDir::new().expect("temp dir should be created");
+        let path = dir.path().join("caeles-test.json");
+        std::fs::write(
+            &path,
+            r#"{ "cases": [ { "name": "a", "expect": { "log": [] } } ] }"#,
+        )
+        .expect("test file should be written");
+
+        let err = TestFile::load(&path).expect_err("unknown field should be rejected");
+        assert!(err.to_string().contains("cases[0].expect"), "{err}");
+    }
+
+    #[test]
+    fn junit_xml_escapes_and_reports_failures() {
+        let results = [
+            CaseResult {
+                name: "ok <case>".to_string(),
+                duration_secs: 0.5,
+                failures: Vec::new(),
+            },
+            CaseResult {
+                name: "broken".to_string(),
+                duration_secs: 0.25,
+                failures: vec!["exit_code: esperado 0, obtido 2".to_string()],
+            },
+        ];
+        let xml = junit_xml("com.caeles.demo", &results);
+        assert!(xml
+            .contains(r#"<testsuite name="com.caeles.demo" tests="2" failures="1" time="0.750">"#));
+        assert!(xml.contains(r#"<testcase name="ok &lt;case&gt;""#));
+        assert!(xml.contains(r#"<failure message="exit_code: esperado 0, obtido 2">"#));
+    }
+}
@@ -0,0 +1,30 @@
+use caeles_sdk::{config, http_get, log};
+
+#[caeles_sdk::main]
+fn main() -> Result<(), String> {
+    let url = config("url").unwrap_or_else(|| "https://example.com".to_string());
+    http_get(&url).map_err(|err| format!("GET {url} failed: {err:?}"))?;
+    log(&format!("{{crate}}: GET {url} succeeded"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use caeles_sdk::mock::{self, MockCapability};
+
+    #[test]
+    fn requests_configured_url() {
+        mock::set_config("url", "https://example.com/status");
+
+        assert_eq!(super::main(), Ok(()));
+        assert_eq!(mock::http_requests(), ["https://example.com/status"]);
+    }
+
+    #[test]
+    fn fails_without_network_permission() {
+        mock::deny(MockCapability::Network);
+
+        assert!(super::main().is_err());
+        assert!(mock::http_requests().is_empty());
+    }
+}
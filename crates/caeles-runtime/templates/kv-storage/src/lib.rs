@@ -0,0 +1,54 @@
+//! Stores `{"key": .., "value": ..}` in the capsule's private directory and,
+//! given only `{"key": ..}`, returns the stored value as the run output.
+
+use caeles_sdk::{fs, log, set_output};
+
+#[derive(serde::Deserialize)]
+struct Input {
+    key: String,
+    value: Option<String>,
+}
+
+#[caeles_sdk::main]
+fn main(input: Input) -> Result<(), String> {
+    if input.key.is_empty() || input.key.starts_with('.') || input.key.contains(['/', '\\']) {
+        return Err(format!("invalid key '{}'", input.key));
+    }
+    let path = format!("kv/{}", input.key);
+    match input.value {
+        Some(value) => {
+            fs::write(&path, value).map_err(|err| format!("{path}: {err}"))?;
+            log(&format!("{{crate}}: stored '{}'", input.key));
+        }
+        None => set_output(&fs::read_to_string(&path).map_err(|err| format!("{path}: {err}"))?),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Input;
+    use caeles_sdk::mock;
+
+    fn input(key: &str, value: Option<&str>) -> Input {
+        Input {
+            key: key.to_string(),
+            value: value.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn stores_and_reads_back_values() {
+        assert_eq!(super::main(input("greeting", Some("hello"))), Ok(()));
+        assert_eq!(mock::file("kv/greeting").as_deref(), Some(&b"hello"[..]));
+
+        assert_eq!(super::main(input("greeting", None)), Ok(()));
+        assert_eq!(mock::output().as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn rejects_keys_outside_the_store() {
+        assert!(super::main(input("../secrets", Some("x"))).is_err());
+        assert!(super::main(input("missing", None)).is_err());
+    }
+}
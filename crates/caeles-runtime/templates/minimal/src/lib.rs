@@ -0,0 +1,18 @@
+use caeles_sdk::log;
+
+#[caeles_sdk::main]
+fn main() {
+    log("{{crate}}: hello from {{name}}");
+}
+
+#[cfg(test)]
+mod tests {
+    use caeles_sdk::mock;
+
+    #[test]
+    fn logs_greeting() {
+        super::main();
+
+        assert_eq!(mock::logs(), ["{{crate}}: hello from {{name}}"]);
+    }
+}
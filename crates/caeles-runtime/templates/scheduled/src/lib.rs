@@ -0,0 +1,40 @@
+//! Runs `tick` every `interval_ms`, `ticks` times per run. Pair it with
+//! `caeles run --timeout-ms` or an external scheduler to bound the run.
+
+use caeles_sdk::{config_as, log, now_ms, sleep};
+use std::time::Duration;
+
+#[caeles_sdk::main]
+fn main() {
+    let interval = Duration::from_millis(config_as("interval_ms").unwrap_or(1000));
+    let ticks: u32 = config_as("ticks").unwrap_or(3);
+    for n in 1..=ticks {
+        tick(n);
+        if n < ticks {
+            sleep(interval);
+        }
+    }
+}
+
+fn tick(n: u32) {
+    let at = now_ms().map_or_else(|| "?".to_string(), |ms| ms.to_string());
+    log(&format!("{{crate}}: tick {n} at {at}"));
+}
+
+#[cfg(test)]
+mod tests {
+    use caeles_sdk::mock;
+
+    #[test]
+    fn ticks_with_configured_interval() {
+        mock::set_config("ticks", "3");
+        mock::set_config("interval_ms", "250");
+        mock::set_now_ms(Some(1_000));
+
+        super::main();
+
+        assert_eq!(mock::sleeps(), [250, 250]);
+        assert_eq!(mock::logs().len(), 3);
+        assert!(mock::logs()[0].ends_with("tick 1 at 1000"));
+    }
+}
@@ -0,0 +1,262 @@
+mod common;
+
+use common::{
+    run_caeles, write_build_capsule_fixture, write_demo_capsule_wasm, write_demo_registry_fixture,
+    write_file,
+};
+use predicates::str::contains;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+#[test]
+fn cli_new_scaffolds_registers_and_adds_capsules_to_the_workspace() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+    write_file(
+        &temp.path().join("Cargo.toml"),
+        "[workspace]\nmembers = [\"crates/caeles-sdk\"]\nresolver = \"2\"\n",
+    );
+    write_file(
+        &temp.path().join("crates/caeles-sdk/Cargo.toml"),
+        "[package]\nname = \"caeles-sdk\"\n",
+    );
+
+    run_caeles(temp.path())
+        .args([
+            "new",
+            "weather-fetch",
+            "--id",
+            "com.caeles.test.weather",
+            "--template",
+            "http-client",
+        ])
+        .assert()
+        .success()
+        .stdout(contains(
+            "Cápsula 'com.caeles.test.weather' (Weather Fetch) criada em capsules/weather-fetch",
+        ))
+        .stdout(contains("caeles build capsules/weather-fetch --copy"));
+    let manifest = fs::read_to_string(temp.path().join("capsules/weather-fetch/manifest.json"))
+        .expect("manifest should be generated");
+    assert!(manifest.contains("\"entry\": \"weather_fetch.wasm\""));
+    let cargo = fs::read_to_string(temp.path().join("capsules/weather-fetch/Cargo.toml"))
+        .expect("Cargo.toml should be generated");
+    assert!(cargo.contains("name = \"weather-fetch\""));
+    assert!(cargo.contains("caeles-sdk = { path = \"../../crates/caeles-sdk\" }"));
+    let workspace =
+        fs::read_to_string(temp.path().join("Cargo.toml")).expect("workspace should be readable");
+    assert!(
+        workspace.contains("    \"capsules/weather-fetch\",\n"),
+        "{workspace}"
+    );
+
+    run_caeles(temp.path())
+        .args(["validate", "capsules/weather-fetch/manifest.json"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["registry", "search", "weather"])
+        .assert()
+        .success()
+        .stdout(contains("- com.caeles.test.weather (Weather Fetch)"));
+    run_caeles(temp.path())
+        .args(["new", "weather-fetch", "--id", "com.caeles.test.other"])
+        .assert()
+        .failure()
+        .stderr(contains("já existe"));
+    run_caeles(temp.path())
+        .args([
+            "new",
+            "cron-job",
+            "--id",
+            "com.caeles.test.cron",
+            "--template",
+            "cron",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Template 'cron' desconhecido"));
+
+    // Local templates use the same placeholders; the generated capsule runs as is.
+    let template = temp.path().join("my-template");
+    write_file(
+        &template.join("manifest.json"),
+        r#"{
+  "id": "{{id}}",
+  "name": "{{name}}",
+  "version": "0.1.0",
+  "entry": "{{lib}}.wasm",
+  "permissions": { "notifications": true, "network": false },
+  "lifecycle": { "kind": "on_demand" }
+}"#,
+    );
+    write_demo_capsule_wasm(&template.join("{{lib}}.wasm"));
+    write_file(&template.join("README.md"), "# {{name}} ({{crate}})\n");
+    run_caeles(temp.path())
+        .args([
+            "new",
+            "demo-tool",
+            "--id",
+            "com.caeles.test.tool",
+            "--display-name",
+            "Tool",
+            "--template-dir",
+            "my-template",
+            "--dir",
+            "tools",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp.path().join("tools/demo-tool/README.md"))
+            .expect("README should be generated"),
+        "# Tool (demo-tool)\n"
+    );
+    run_caeles(temp.path())
+        .args(["run", "com.caeles.test.tool"])
+        .assert()
+        .success()
+        .stdout(contains("integration-log"));
+}
+
+/// Stand-in for `cargo build` that records its arguments and reports
+/// `fixture.wasm` as the artifact, the way cargo's JSON messages do.
+#[cfg(unix)]
+fn write_fake_cargo(workdir: &Path) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let script = workdir.join("fake-cargo.sh");
+    write_file(
+        &script,
+        r#"#!/bin/sh
+echo "$@" > "$(dirname "$0")/cargo-args.txt"
+crate_dir=$(cd "$(dirname "$3")" && pwd)
+profile=debug
+case "$*" in *--release*) profile=release ;; esac
+out="$(dirname "$0")/target/$5/$profile"
+mkdir -p "$out"
+cp "$(dirname "$0")/fixture.wasm" "$out/build_capsule.wasm"
+echo '{"reason":"compiler-artifact","manifest_path":"/elsewhere/Cargo.toml","filenames":["/elsewhere/libdep.rlib"]}'
+echo "{\"reason\":\"compiler-artifact\",\"manifest_path\":\"$crate_dir/Cargo.toml\",\"filenames\":[\"$out/build_capsule.wasm\"]}"
+echo '{"reason":"build-finished","success":true}'
+"#,
+    );
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))
+        .expect("script should be executable");
+    script
+}
+
+#[cfg(unix)]
+#[test]
+fn cli_build_reports_and_wires_the_cargo_artifact() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_build_capsule_fixture(temp.path());
+    write_demo_capsule_wasm(&temp.path().join("fixture.wasm"));
+    let cargo = write_fake_cargo(temp.path());
+    let manifest_path = temp.path().join("capsules/build-capsule/manifest.json");
+    write_file(
+        &manifest_path,
+        r#"{
+  "id": "com.caeles.test.build",
+  "name": "Build Capsule",
+  "version": "0.1.0",
+  "entry": "old.wasm",
+  "permissions": { "notifications": true, "network": false },
+  "lifecycle": { "kind": "on_demand" }
+}
+"#,
+    );
+
+    run_caeles(temp.path())
+        .env("CARGO", &cargo)
+        .args(["build", "capsules/build-capsule", "--release"])
+        .assert()
+        .success()
+        .stdout(contains(
+            "wasm32-unknown-unknown/release/build_capsule.wasm (",
+        ))
+        .stdout(contains("> Exports: memory (memory), caeles_main (func)"))
+        .stdout(contains("> Imports: 3 de caeles"))
+        .stdout(contains(
+            "aviso: entry 'old.wasm' não é o artefato 'build_capsule.wasm'; use --update-entry",
+        ));
+    let cargo_args =
+        fs::read_to_string(temp.path().join("cargo-args.txt")).expect("cargo should be called");
+    assert!(cargo_args.contains("--target wasm32-unknown-unknown"));
+    assert!(cargo_args.contains("--message-format=json-render-diagnostics"));
+    assert!(cargo_args.contains("--release"));
+
+    run_caeles(temp.path())
+        .env("CARGO", &cargo)
+        .args(["build", "capsules/build-capsule", "--optimize"])
+        .assert()
+        .failure();
+    run_caeles(temp.path())
+        .env("CARGO", &cargo)
+        .args(["build", "capsules/build-capsule", "--update-entry"])
+        .assert()
+        .success()
+        .stdout(contains("atualizado: old.wasm -> build_capsule.wasm"))
+        .stdout(contains("> Copiado para"));
+    let manifest = fs::read_to_string(&manifest_path).expect("manifest should be readable");
+    let manifest: Value = serde_json::from_str(&manifest).expect("manifest should be json");
+    assert_eq!(manifest["entry"], "build_capsule.wasm");
+    assert_eq!(manifest["lifecycle"]["kind"], "on_demand");
+
+    run_caeles(temp.path())
+        .env("CARGO", &cargo)
+        .args(["build", "capsules/build-capsule"])
+        .assert()
+        .success()
+        .stdout(contains("entry 'build_capsule.wasm' está atualizado"));
+    run_caeles(temp.path())
+        .args(["run", "--manifest", "capsules/build-capsule/manifest.json"])
+        .assert()
+        .success()
+        .stdout(contains("integration-log"));
+
+    // An entry inside target/ is never overwritten: the copy goes next to the manifest.
+    let debug_artifact = temp
+        .path()
+        .join("target/wasm32-unknown-unknown/debug/build_capsule.wasm");
+    fs::write(&debug_artifact, b"debug build").expect("debug artifact should be written");
+    let root_manifest = temp.path().join("manifest.json");
+    write_file(
+        &root_manifest,
+        &fs::read_to_string(&manifest_path)
+            .expect("manifest should be readable")
+            .replace(
+                r#""entry": "build_capsule.wasm""#,
+                r#""entry": "target/wasm32-unknown-unknown/debug/build_capsule.wasm""#,
+            ),
+    );
+    let build_with_root_manifest = |flag: &str| {
+        run_caeles(temp.path())
+            .env("CARGO", &cargo)
+            .args(["build", "capsules/build-capsule", "--release", flag])
+            .arg("--manifest")
+            .arg(&root_manifest)
+            .assert()
+            .success()
+    };
+    build_with_root_manifest("--copy").stdout(contains(
+        "não é a cópia 'build_capsule.wasm'; use --update-entry",
+    ));
+    assert_eq!(
+        fs::read(&debug_artifact).expect("debug artifact should be readable"),
+        b"debug build"
+    );
+    assert!(temp.path().join("build_capsule.wasm").is_file());
+    build_with_root_manifest("--update-entry");
+    let manifest: Value = serde_json::from_str(
+        &fs::read_to_string(&root_manifest).expect("manifest should be readable"),
+    )
+    .expect("manifest should be json");
+    assert_eq!(manifest["entry"], "build_capsule.wasm");
+    assert_eq!(
+        fs::read(&debug_artifact).expect("debug artifact should be readable"),
+        b"debug build"
+    );
+}
@@ -1,128 +1,14 @@
-use assert_cmd::Command;
+mod common;
+
+use common::{
+    extract_run_id, run_caeles, write_build_capsule_fixture, write_demo_registry_fixture,
+    write_file, CAPSULE_ID, CAPSULE_VERSION,
+};
 use predicates::str::contains;
 use serde_json::Value;
 use std::fs;
-use std::path::Path;
 use tempfile::TempDir;
 
-const CAPSULE_ID: &str = "com.caeles.test.demo";
-const CAPSULE_NAME: &str = "Demo Capsule";
-const CAPSULE_VERSION: &str = "0.1.0";
-
-fn run_caeles(workdir: &Path) -> Command {
-    let mut cmd =
-        Command::cargo_bin("caeles").expect("caeles binary should be available for tests");
-    cmd.current_dir(workdir);
-    cmd
-}
-
-fn write_file(path: &Path, content: &str) {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).expect("parent directory should be created");
-    }
-    fs::write(path, content).expect("file should be written");
-}
-
-fn demo_wat_module() -> &'static str {
-    r#"(module
-  (import "caeles" "host_log" (func $host_log (param i32 i32)))
-  (import "caeles" "host_notify" (func $host_notify (param i32 i32)))
-  (import "caeles" "host_http_get" (func $host_http_get (param i32 i32) (result i32)))
-  (memory (export "memory") 1)
-  (data (i32.const 0) "integration-log")
-  (data (i32.const 64) "integration-notify")
-  (data (i32.const 128) "https://example.com")
-  (func (export "caeles_main")
-    i32.const 0
-    i32.const 15
-    call $host_log
-    i32.const 64
-    i32.const 18
-    call $host_notify
-    i32.const 128
-    i32.const 19
-    call $host_http_get
-    drop
-  )
-)
-"#
-}
-
-fn write_demo_capsule_wasm(path: &Path) {
-    let bytes = wat::parse_str(demo_wat_module()).expect("WAT should compile to valid wasm");
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).expect("wasm parent dir should be created");
-    }
-    fs::write(path, bytes).expect("wasm file should be written");
-}
-
-fn write_demo_registry_fixture(workdir: &Path, lifecycle_kind: &str) {
-    let manifest_path = workdir.join("capsules/demo/manifest.json");
-    let wasm_path = workdir.join("capsules/demo/demo.wasm");
-    let registry_path = workdir.join("capsules/registry.json");
-
-    write_demo_capsule_wasm(&wasm_path);
-
-    let manifest = serde_json::json!({
-        "id": CAPSULE_ID,
-        "name": CAPSULE_NAME,
-        "version": CAPSULE_VERSION,
-        "entry": "demo.wasm",
-        "permissions": {
-            "notifications": true,
-            "network": false
-        },
-        "lifecycle": {
-            "kind": lifecycle_kind
-        }
-    });
-    write_file(
-        &manifest_path,
-        &serde_json::to_string_pretty(&manifest).expect("manifest json should serialize"),
-    );
-
-    let registry = serde_json::json!([
-        {
-            "id": CAPSULE_ID,
-            "name": CAPSULE_NAME,
-            "manifest": "demo/manifest.json"
-        }
-    ]);
-    write_file(
-        &registry_path,
-        &serde_json::to_string_pretty(&registry).expect("registry json should serialize"),
-    );
-}
-
-fn write_build_capsule_fixture(workdir: &Path) {
-    let cargo_toml = r#"[package]
-name = "build-capsule"
-version = "0.1.0"
-edition = "2021"
-
-[lib]
-crate-type = ["cdylib"]
-"#;
-
-    let lib_rs = r#"#[no_mangle]
-pub extern "C" fn caeles_main() {}
-"#;
-
-    write_file(
-        &workdir.join("capsules/build-capsule/Cargo.toml"),
-        cargo_toml,
-    );
-    write_file(&workdir.join("capsules/build-capsule/src/lib.rs"), lib_rs);
-}
-
-fn extract_run_id(stdout: &str) -> String {
-    stdout
-        .split_whitespace()
-        .find(|token| token.starts_with("run-"))
-        .expect("run id should be present in command output")
-        .to_owned()
-}
-
 #[test]
 fn cli_definition_of_done_happy_path() {
     let temp = TempDir::new().expect("temp directory should be created");
@@ -208,6 +94,8 @@ fn cli_definition_of_done_happy_path() {
         serde_json::from_slice(&inspect_stdout).expect("inspect output should be json");
     assert_eq!(inspect["id"].as_str(), Some(CAPSULE_ID));
     assert_eq!(inspect["manifest_exists"].as_bool(), Some(true));
+    assert_eq!(inspect["abi"]["capsule_abi"].as_str(), Some("v0"));
+    assert_eq!(inspect["abi"]["compatible"].as_bool(), Some(true));
 
     run_caeles(temp.path())
         .args([
@@ -228,7 +116,7 @@ fn cli_definition_of_done_happy_path() {
     ));
     assert!(package_root.join("manifest.json").exists());
     assert!(package_root.join("capsule.wasm").exists());
-    assert!(package_root.join("package.json").exists());
+    assert!(package_root.join("image.json").exists());
 
     run_caeles(temp.path())
         .args([
@@ -248,6 +136,11 @@ fn cli_definition_of_done_happy_path() {
     ));
     assert!(pulled_root.join("manifest.json").exists());
     assert!(pulled_root.join("capsule.wasm").exists());
+    // Both images share the manifest and wasm blobs.
+    let blobs = fs::read_dir(temp.path().join(".caeles/blobs/sha256"))
+        .expect("blob store should exist")
+        .count();
+    assert_eq!(blobs, 2);
 
     let images_stdout = run_caeles(temp.path())
         .args([
@@ -266,6 +159,11 @@ fn cli_definition_of_done_happy_path() {
     let images: Value =
         serde_json::from_slice(&images_stdout).expect("images output should be valid json");
     assert_eq!(images.as_array().map(Vec::len), Some(2));
+    assert_eq!(images[0]["digest"], images[1]["digest"]);
+    assert!(images[0]["digest"]
+        .as_str()
+        .is_some_and(|digest| digest.starts_with("sha256:")));
+    assert!(images[0]["size"].as_u64().is_some_and(|size| size > 0));
 }
 
 #[test]
@@ -296,3 +194,36 @@ fn cli_run_fails_for_invalid_manifest_lifecycle() {
         .stderr(contains("Manifest invalido"))
         .stderr(contains("on_demand"));
 }
+
+#[test]
+fn cli_validate_reports_all_manifest_issues() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_file(
+        &temp.path().join("capsules/bad/manifest.json"),
+        r#"{
+  "id": "../../etc",
+  "name": "Bad",
+  "version": "banana",
+  "entry": "../escape.wasm",
+  "permissions": { "notifications": true, "network": false },
+  "lifecycle": { "kind": "on_demand" }
+}"#,
+    );
+
+    let stdout = run_caeles(temp.path())
+        .args(["validate", "capsules/bad/manifest.json", "--json"])
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+    let report: Value = serde_json::from_slice(&stdout).expect("validate output should be json");
+    assert_eq!(report["valid"].as_bool(), Some(false));
+    let fields: Vec<&str> = report["issues"]
+        .as_array()
+        .expect("issues should be an array")
+        .iter()
+        .filter_map(|issue| issue["field"].as_str())
+        .collect();
+    assert_eq!(fields, vec!["id", "version", "entry"]);
+}
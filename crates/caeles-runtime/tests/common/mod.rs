@@ -0,0 +1,169 @@
+#![allow(dead_code)]
+
+use assert_cmd::Command;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+
+pub const CAPSULE_ID: &str = "com.caeles.test.demo";
+
+pub const CAPSULE_NAME: &str = "Demo Capsule";
+
+pub const CAPSULE_VERSION: &str = "0.1.0";
+
+pub fn run_caeles(workdir: &Path) -> Command {
+    let mut cmd =
+        Command::cargo_bin("caeles").expect("caeles binary should be available for tests");
+    cmd.current_dir(workdir)
+        .env("XDG_CONFIG_HOME", workdir.join(".config"));
+    cmd
+}
+
+pub fn write_file(path: &Path, content: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).expect("parent directory should be created");
+    }
+    fs::write(path, content).expect("file should be written");
+}
+
+pub fn demo_wat_module() -> &'static str {
+    r#"(module
+  (import "caeles" "host_log" (func $host_log (param i32 i32)))
+  (import "caeles" "host_notify" (func $host_notify (param i32 i32)))
+  (import "caeles" "host_http_get" (func $host_http_get (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "integration-log")
+  (data (i32.const 64) "integration-notify")
+  (data (i32.const 128) "https://example.com")
+  (func (export "caeles_main")
+    i32.const 0
+    i32.const 15
+    call $host_log
+    i32.const 64
+    i32.const 18
+    call $host_notify
+    i32.const 128
+    i32.const 19
+    call $host_http_get
+    drop
+  )
+)
+"#
+}
+
+pub fn write_demo_capsule_wasm(path: &Path) {
+    let bytes = wat::parse_str(demo_wat_module()).expect("WAT should compile to valid wasm");
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).expect("wasm parent dir should be created");
+    }
+    fs::write(path, bytes).expect("wasm file should be written");
+}
+
+pub fn write_demo_registry_fixture(workdir: &Path, lifecycle_kind: &str) {
+    let manifest_path = workdir.join("capsules/demo/manifest.json");
+    let wasm_path = workdir.join("capsules/demo/demo.wasm");
+    let registry_path = workdir.join("capsules/registry.json");
+
+    write_demo_capsule_wasm(&wasm_path);
+
+    let manifest = serde_json::json!({
+        "id": CAPSULE_ID,
+        "name": CAPSULE_NAME,
+        "version": CAPSULE_VERSION,
+        "entry": "demo.wasm",
+        "permissions": {
+            "notifications": true,
+            "network": false
+        },
+        "lifecycle": {
+            "kind": lifecycle_kind
+        }
+    });
+    write_file(
+        &manifest_path,
+        &serde_json::to_string_pretty(&manifest).expect("manifest json should serialize"),
+    );
+
+    let registry = serde_json::json!([
+        {
+            "id": CAPSULE_ID,
+            "name": CAPSULE_NAME,
+            "manifest": "demo/manifest.json"
+        }
+    ]);
+    write_file(
+        &registry_path,
+        &serde_json::to_string_pretty(&registry).expect("registry json should serialize"),
+    );
+}
+
+pub fn write_build_capsule_fixture(workdir: &Path) {
+    let cargo_toml = r#"[package]
+name = "build-capsule"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+crate-type = ["cdylib"]
+"#;
+
+    let lib_rs = r#"#[no_mangle]
+pub extern "C" fn caeles_main() {}
+"#;
+
+    write_file(
+        &workdir.join("capsules/build-capsule/Cargo.toml"),
+        cargo_toml,
+    );
+    write_file(&workdir.join("capsules/build-capsule/src/lib.rs"), lib_rs);
+}
+
+pub fn extract_run_id(stdout: &str) -> String {
+    stdout
+        .split_whitespace()
+        .find(|token| token.starts_with("run-"))
+        .expect("run id should be present in command output")
+        .to_owned()
+}
+
+/// Answers HTTP requests with `respond(path)` until the test process exits.
+pub fn serve(respond: impl Fn(&str, &str) -> Vec<u8> + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("listener should bind");
+    let address = listener
+        .local_addr()
+        .expect("listener should have an address")
+        .to_string();
+    let base = format!("http://{address}");
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut request_line = String::new();
+            let mut reader = BufReader::new(&stream);
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            loop {
+                let mut header = String::new();
+                if reader.read_line(&mut header).is_err() || header.is_empty() || header == "\r\n" {
+                    break;
+                }
+            }
+            let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+            let _ = stream.write_all(&respond(&address, path));
+        }
+    });
+    base
+}
+
+pub fn http_response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
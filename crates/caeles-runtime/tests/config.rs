@@ -0,0 +1,142 @@
+mod common;
+
+use common::{run_caeles, write_demo_registry_fixture, CAPSULE_ID};
+use predicates::str::contains;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn cli_config_files_set_registries_state_dir_and_run_defaults() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+    let sub = temp.path().join("capsules/demo");
+
+    run_caeles(temp.path())
+        .args(["config", "set", "state_dir", "state"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["config", "set", "registries", "capsules/registry.json"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["config", "set", "run.timeout_ms", "200"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["config", "set", "run.fuel", "1"])
+        .assert()
+        .failure()
+        .stderr(contains("Chave desconhecida 'run.fuel'"));
+    let project = fs::read_to_string(temp.path().join("caeles.toml"))
+        .expect("project settings should be written");
+    assert!(project.contains("state_dir = \"state\""), "{project}");
+
+    // Paths in caeles.toml are relative to it, from any subdirectory.
+    run_caeles(&sub)
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(contains(CAPSULE_ID));
+    run_caeles(&sub)
+        .args(["run", CAPSULE_ID])
+        .assert()
+        .success();
+    assert!(temp.path().join("state/runs.jsonl").is_file());
+
+    // Images and blobs follow state_dir too.
+    run_caeles(&sub)
+        .args(["package", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success();
+    assert!(temp.path().join("state/packages").join(CAPSULE_ID).is_dir());
+    assert!(temp.path().join("state/blobs/sha256").is_dir());
+    run_caeles(&sub)
+        .args(["images", "--id", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains(CAPSULE_ID));
+    assert!(!sub.join(".caeles").exists());
+
+    // Manifests added from a subdirectory are stored relative to the registry.
+    run_caeles(&sub)
+        .args(["registry", "remove", CAPSULE_ID])
+        .assert()
+        .success();
+    run_caeles(&sub)
+        .args(["registry", "add", "manifest.json"])
+        .assert()
+        .success();
+    let registry = fs::read_to_string(temp.path().join("capsules/registry.json"))
+        .expect("registry should be readable");
+    assert!(
+        registry.contains("\"manifest\": \"demo/manifest.json\""),
+        "{registry}"
+    );
+    run_caeles(temp.path())
+        .args(["run", CAPSULE_ID])
+        .assert()
+        .success();
+
+    let busy = wat::parse_str(
+        r#"(module
+  (func (export "caeles_main")
+    (loop $spin (br $spin))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(sub.join("demo.wasm"), busy).expect("wasm should be written");
+    run_caeles(&sub)
+        .args(["run", CAPSULE_ID])
+        .timeout(std::time::Duration::from_secs(30))
+        .assert()
+        .failure()
+        .stderr(contains("capsule exceeded the run timeout of 200 ms"));
+
+    run_caeles(temp.path())
+        .args([
+            "config",
+            "set",
+            "--user",
+            "permissions.deny",
+            "notifications",
+        ])
+        .assert()
+        .success();
+    let user_config = temp.path().join(".config");
+    assert!(user_config.join("caeles/config.toml").is_file());
+    run_caeles(&sub)
+        .env("XDG_CONFIG_HOME", &user_config)
+        .args(["run", CAPSULE_ID])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Permissão 'notifications' de 'com.caeles.test.demo' negada pela configuração",
+        ));
+
+    run_caeles(&sub)
+        .env("XDG_CONFIG_HOME", &user_config)
+        .args(["config", "get", "run.timeout_ms"])
+        .assert()
+        .success()
+        .stdout("200\n");
+    let output = run_caeles(&sub)
+        .env("XDG_CONFIG_HOME", &user_config)
+        .args(["config", "list", "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let items: Value = serde_json::from_slice(&output).expect("config list should be json");
+    assert_eq!(items[0]["key"], "state_dir");
+    assert_eq!(items[2]["key"], "permissions.deny");
+    assert_eq!(items[2]["value"], "notifications");
+    assert!(items[2]["origin"]
+        .as_str()
+        .is_some_and(|o| o.ends_with("config.toml")));
+    assert_eq!(items[4]["value"], "info");
+    assert_eq!(items[4]["origin"], "padrão");
+}
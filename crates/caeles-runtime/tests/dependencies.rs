@@ -0,0 +1,140 @@
+mod common;
+
+use common::{
+    run_caeles, write_demo_registry_fixture, write_file, CAPSULE_ID, CAPSULE_NAME, CAPSULE_VERSION,
+};
+use predicates::str::contains;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn cli_run_links_library_dependencies() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let app = serde_json::json!({
+        "id": CAPSULE_ID,
+        "name": CAPSULE_NAME,
+        "version": CAPSULE_VERSION,
+        "entry": "demo.wasm",
+        "permissions": { "notifications": false, "network": false },
+        "lifecycle": { "kind": "on_demand" },
+        "dependencies": { "com.caeles.test.math": "^1.0" }
+    });
+    write_file(
+        &temp.path().join("capsules/demo/manifest.json"),
+        &serde_json::to_string_pretty(&app).expect("manifest json should serialize"),
+    );
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "dep:com.caeles.test.math" "add" (func $add (param i32 i32) (result i32)))
+  (import "caeles_v2" "host_set_output" (func $output (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main")
+    (i32.store8 (i32.const 0) (i32.add (i32.const 48) (call $add (i32.const 3) (i32.const 4))))
+    (drop (call $output (i32.const 0) (i32.const 1)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    for (version, dir) in [("1.2.0", "math-1.2"), ("1.10.0", "math-1.10")] {
+        let library = serde_json::json!({
+            "id": "com.caeles.test.math",
+            "name": "Math",
+            "version": version,
+            "entry": "math.wasm",
+            "permissions": { "notifications": false, "network": false },
+            "lifecycle": { "kind": "library" }
+        });
+        write_file(
+            &temp.path().join(format!("capsules/{dir}/manifest.json")),
+            &serde_json::to_string_pretty(&library).expect("manifest json should serialize"),
+        );
+        let wasm = wat::parse_str(format!(
+            r#"(module
+  (import "caeles_v2" "host_log" (func $log (param i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "math {version}")
+  (func (export "add") (param i32 i32) (result i32)
+    (call $log (i32.const 0) (i32.const {len}))
+    (i32.add (local.get 0) (local.get 1)))
+)"#,
+            len = 5 + version.len()
+        ))
+        .expect("WAT should compile to valid wasm");
+        fs::write(temp.path().join(format!("capsules/{dir}/math.wasm")), wasm)
+            .expect("wasm should be written");
+    }
+
+    let registry = serde_json::json!([
+        { "id": CAPSULE_ID, "name": CAPSULE_NAME, "manifest": "demo/manifest.json" },
+        { "id": "com.caeles.test.math", "name": "Math", "manifest": "math-1.2/manifest.json" }
+    ]);
+    write_file(
+        &temp.path().join("capsules/registry.json"),
+        &serde_json::to_string_pretty(&registry).expect("registry json should serialize"),
+    );
+
+    run_caeles(temp.path())
+        .args(["run", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("> Dependency: com.caeles.test.math 1.2.0"))
+        .stdout(contains("[capsule-log] math 1.2.0"))
+        .stdout(contains("> output: 7"));
+
+    run_caeles(temp.path())
+        .args(["package", "--manifest", "capsules/math-1.10/manifest.json"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["run", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("[capsule-log] math 1.10.0"));
+
+    let output = run_caeles(temp.path())
+        .args(["inspect", CAPSULE_ID, "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let view: Value = serde_json::from_slice(&output).expect("inspect output should be json");
+    assert_eq!(view["dependencies"][0]["id"], "com.caeles.test.math");
+    assert_eq!(view["dependencies"][0]["version"], "1.10.0");
+    assert_eq!(view["dependencies"][0]["source"], "package");
+
+    run_caeles(temp.path())
+        .args(["run", "--capsule-id", "com.caeles.test.math"])
+        .assert()
+        .failure()
+        .stderr(contains("is a library"));
+
+    // A dependency whose start function never returns is stopped by the timeout.
+    let looping = wat::parse_str(
+        r#"(module
+  (memory (export "memory") 1)
+  (func $start (loop $spin (br $spin)))
+  (start $start)
+  (func (export "add") (param i32 i32) (result i32)
+    (i32.add (local.get 0) (local.get 1)))
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/math-1.2/math.wasm"), looping)
+        .expect("wasm should be written");
+    run_caeles(temp.path())
+        .args(["rmi", "com.caeles.test.math"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["run", "--capsule-id", CAPSULE_ID, "--timeout-ms", "200"])
+        .timeout(std::time::Duration::from_secs(30))
+        .assert()
+        .failure()
+        .stderr(contains("capsule exceeded the run timeout of 200 ms"));
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::{
+    run_caeles, write_demo_registry_fixture, write_file, CAPSULE_ID, CAPSULE_NAME, CAPSULE_VERSION,
+};
+use predicates::str::contains;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn cli_publish_triggers_subscribed_event_capsules() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let publisher = serde_json::json!({
+        "id": CAPSULE_ID,
+        "name": CAPSULE_NAME,
+        "version": CAPSULE_VERSION,
+        "entry": "demo.wasm",
+        "permissions": { "notifications": false, "network": false, "publish": ["orders.*"] },
+        "lifecycle": { "kind": "on_demand" }
+    });
+    write_file(
+        &temp.path().join("capsules/demo/manifest.json"),
+        &serde_json::to_string_pretty(&publisher).expect("manifest json should serialize"),
+    );
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_publish" (func $publish (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "orders.created")
+  (data (i32.const 32) "order-42")
+  (data (i32.const 64) "audit")
+  (func (export "caeles_main")
+    (if (i32.ne (call $publish (i32.const 0) (i32.const 14) (i32.const 32) (i32.const 8)) (i32.const 0))
+      (then unreachable))
+    (if (i32.ne (call $publish (i32.const 64) (i32.const 5) (i32.const 32) (i32.const 8)) (i32.const -1))
+      (then unreachable))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    let subscriber = serde_json::json!({
+        "id": "com.caeles.test.listener",
+        "name": "Listener",
+        "version": CAPSULE_VERSION,
+        "entry": "listener.wasm",
+        "permissions": { "notifications": false, "network": false, "subscribe": ["orders.*"] },
+        "lifecycle": { "kind": "event" }
+    });
+    write_file(
+        &temp.path().join("capsules/listener/manifest.json"),
+        &serde_json::to_string_pretty(&subscriber).expect("manifest json should serialize"),
+    );
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_input_len" (func $input_len (result i32)))
+  (import "caeles_v2" "host_input_read" (func $input_read (param i32 i32) (result i32)))
+  (import "caeles_v2" "host_set_output" (func $output (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main")
+    (local $len i32)
+    (local.set $len (call $input_read (i32.const 0) (call $input_len)))
+    (drop (call $output (i32.const 0) (local.get $len)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/listener/listener.wasm"), wasm)
+        .expect("wasm should be written");
+
+    let registry = serde_json::json!([
+        { "id": CAPSULE_ID, "name": CAPSULE_NAME, "manifest": "demo/manifest.json" },
+        { "id": "com.caeles.test.listener", "name": "Listener", "manifest": "listener/manifest.json" }
+    ]);
+    write_file(
+        &temp.path().join("capsules/registry.json"),
+        &serde_json::to_string_pretty(&registry).expect("registry json should serialize"),
+    );
+
+    let stdout = run_caeles(temp.path())
+        .args(["run", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("[capsule-publish] orders.created: order-42"))
+        .stdout(contains("[capsule-publish BLOCKED] topic 'audit'"))
+        .stdout(contains("-> com.caeles.test.listener"))
+        .stdout(contains(r#""payload":"order-42""#))
+        .get_output()
+        .stdout
+        .clone();
+    let stdout = String::from_utf8_lossy(&stdout);
+    let run_ids: Vec<&str> = stdout
+        .split_whitespace()
+        .filter(|token| token.starts_with("run-"))
+        .collect();
+    assert_eq!(run_ids.len(), 2, "{stdout}");
+
+    let output = run_caeles(temp.path())
+        .args(["inspect-run", run_ids[1], "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let view: Value = serde_json::from_slice(&output).expect("inspect-run output should be json");
+    assert_eq!(view["capsule_id"], "com.caeles.test.listener");
+    assert_eq!(view["trigger"]["topic"], "orders.created");
+    assert_eq!(view["trigger"]["publisher"], CAPSULE_ID);
+    assert_eq!(view["trigger"]["parent_run_id"], run_ids[0]);
+
+    let output = run_caeles(temp.path())
+        .args(["inspect-run", run_ids[0], "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let view: Value = serde_json::from_slice(&output).expect("inspect-run output should be json");
+    assert_eq!(view["triggered_runs"][0], run_ids[1]);
+
+    run_caeles(temp.path())
+        .args(["publish", "orders.shipped", "--payload", "cli"])
+        .assert()
+        .success()
+        .stdout(contains("orders.shipped"))
+        .stdout(contains(r#""publisher":"cli""#));
+
+    run_caeles(temp.path())
+        .args(["publish", "billing.paid"])
+        .assert()
+        .success()
+        .stdout(contains("Nenhuma cápsula assina 'billing.paid'"));
+}
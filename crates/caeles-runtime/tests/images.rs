@@ -0,0 +1,337 @@
+mod common;
+
+use common::{
+    extract_run_id, run_caeles, write_demo_registry_fixture, write_file, CAPSULE_ID,
+    CAPSULE_VERSION,
+};
+use predicates::prelude::PredicateBooleanExt;
+use predicates::str::contains;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn cli_run_rejects_image_files_that_no_longer_match_their_digest() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    run_caeles(temp.path())
+        .args(["package", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("sha256:"));
+    let reference = format!("{CAPSULE_ID}@{CAPSULE_VERSION}");
+    run_caeles(temp.path())
+        .args(["run", &reference])
+        .assert()
+        .success();
+
+    run_caeles(temp.path())
+        .arg("images")
+        .assert()
+        .success()
+        .stdout(contains("DIGEST | SIZE"))
+        .stdout(contains("| sha256:"));
+
+    let wasm = temp.path().join(format!(
+        ".caeles/packages/{CAPSULE_ID}/{CAPSULE_VERSION}/capsule.wasm"
+    ));
+    let mut bytes = fs::read(&wasm).expect("image wasm should be readable");
+    bytes.extend_from_slice(b"\0tampered");
+    fs::remove_file(&wasm).expect("image wasm should be removed");
+    fs::write(&wasm, bytes).expect("image wasm should be written");
+
+    run_caeles(temp.path())
+        .args(["run", &reference])
+        .assert()
+        .failure()
+        .stderr(contains("corrompida"));
+
+    // Packaging again restores the image from the store.
+    run_caeles(temp.path())
+        .args(["package", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("já estava no store"));
+    run_caeles(temp.path())
+        .args(["run", &reference])
+        .assert()
+        .success();
+}
+
+#[test]
+fn cli_images_resolve_semver_ranges_and_tags() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+    let manifest_path = temp.path().join("capsules/demo/manifest.json");
+
+    for version in ["0.1.0", "0.10.0", "0.9.0"] {
+        let manifest = fs::read_to_string(&manifest_path)
+            .expect("manifest should be readable")
+            .replace(
+                r#""version": "0.1.0""#,
+                &format!(r#""version": "{version}""#),
+            );
+        let versioned = temp
+            .path()
+            .join(format!("capsules/demo/manifest-{version}.json"));
+        fs::write(&versioned, manifest).expect("manifest should be written");
+        run_caeles(temp.path())
+            .args(["package", "--manifest"])
+            .arg(&versioned)
+            .assert()
+            .success();
+    }
+
+    let output = run_caeles(temp.path())
+        .args(["images", "--id", CAPSULE_ID, "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let images: Value = serde_json::from_slice(&output).expect("images output should be json");
+    let versions: Vec<&str> = images
+        .as_array()
+        .expect("images should be an array")
+        .iter()
+        .map(|image| {
+            image["version"]
+                .as_str()
+                .expect("version should be a string")
+        })
+        .collect();
+    assert_eq!(versions, vec!["0.1.0", "0.9.0", "0.10.0"]);
+    assert_eq!(images[2]["tags"], serde_json::json!(["latest"]));
+
+    run_caeles(temp.path())
+        .args(["images", "--id", "com.caeles.test.other"])
+        .assert()
+        .success()
+        .stdout(contains("Nenhuma imagem local encontrada."));
+
+    run_caeles(temp.path())
+        .args(["tag", &format!("{CAPSULE_ID}@0.9.0"), "stable"])
+        .assert()
+        .success()
+        .stdout(contains(format!("Tag 'stable' -> {CAPSULE_ID}@0.9.0")));
+
+    run_caeles(temp.path())
+        .args(["run", &format!("{CAPSULE_ID}@stable")])
+        .assert()
+        .success()
+        .stdout(contains(format!("> Imagem: {CAPSULE_ID}@0.9.0")))
+        .stdout(contains("[capsule-log] integration-log"));
+    run_caeles(temp.path())
+        .args(["run", &format!("{CAPSULE_ID}@^0.1")])
+        .assert()
+        .success()
+        .stdout(contains(format!("> Imagem: {CAPSULE_ID}@0.1.0")));
+    run_caeles(temp.path())
+        .args(["run", &format!("{CAPSULE_ID}@^1")])
+        .assert()
+        .failure()
+        .stderr(contains("disponíveis: 0.1.0, 0.9.0, 0.10.0"));
+
+    run_caeles(temp.path())
+        .args(["tag", CAPSULE_ID, "stable", "--remove"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["run", &format!("{CAPSULE_ID}@stable")])
+        .assert()
+        .failure()
+        .stderr(contains("Tag 'stable' não existe"));
+}
+
+#[test]
+fn cli_rmi_and_image_prune_remove_unused_images_and_blobs() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+    let manifest_path = temp.path().join("capsules/demo/manifest.json");
+    for version in ["0.1.0", "0.9.0", "0.10.0"] {
+        let manifest = fs::read_to_string(&manifest_path)
+            .expect("manifest should be readable")
+            .replace(
+                r#""version": "0.1.0""#,
+                &format!(r#""version": "{version}""#),
+            );
+        let versioned = temp
+            .path()
+            .join(format!("capsules/demo/manifest-{version}.json"));
+        fs::write(&versioned, manifest).expect("manifest should be written");
+        run_caeles(temp.path())
+            .args(["package", "--manifest"])
+            .arg(&versioned)
+            .assert()
+            .success();
+    }
+    run_caeles(temp.path())
+        .args(["pull", CAPSULE_ID])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["run", &format!("{CAPSULE_ID}@0.9.0")])
+        .assert()
+        .success();
+    let blob_count = || {
+        fs::read_dir(temp.path().join(".caeles/blobs/sha256"))
+            .expect("blob store should exist")
+            .count()
+    };
+    // One wasm and a manifest per version.
+    assert_eq!(blob_count(), 4);
+
+    // 0.1.0 is the registry version and 0.9.0 was just run.
+    run_caeles(temp.path())
+        .args(["image", "prune", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(contains(format!("Removeria: package {CAPSULE_ID}@0.10.0")))
+        .stdout(contains("1 imagem(ns), 1 blob(s)"))
+        .stdout(contains("0.9.0").not());
+    assert_eq!(blob_count(), 4);
+    run_caeles(temp.path())
+        .args(["image", "prune"])
+        .assert()
+        .success()
+        .stdout(contains(format!("Removida: package {CAPSULE_ID}@0.10.0")));
+    assert_eq!(blob_count(), 3);
+    assert!(!temp
+        .path()
+        .join(format!(".caeles/packages/{CAPSULE_ID}/0.10.0"))
+        .exists());
+
+    // An image loaded by a capsule that is still running cannot be removed,
+    // even when the run recorded its manifest by absolute path.
+    let active = temp.path().join(".caeles/state/active/run-busy.json");
+    let busy_manifest = temp
+        .path()
+        .join(format!(".caeles/pulled/{CAPSULE_ID}/0.1.0/manifest.json"));
+    write_file(
+        &active,
+        &serde_json::json!({
+            "run_id": "run-busy",
+            "pid": std::process::id(),
+            "capsule_id": CAPSULE_ID,
+            "manifests": [busy_manifest]
+        })
+        .to_string(),
+    );
+    run_caeles(temp.path())
+        .args(["rmi", &format!("{CAPSULE_ID}@0.1.0")])
+        .assert()
+        .failure()
+        .stderr(contains("em uso"))
+        .stderr(contains("run-busy"));
+    run_caeles(temp.path())
+        .args(["rmi", &format!("{CAPSULE_ID}@0.1.0"), "--source", "package"])
+        .assert()
+        .success()
+        .stdout(contains(format!("Removida: package {CAPSULE_ID}@0.1.0")))
+        .stdout(contains("0 blob(s)"));
+    fs::remove_file(&active).expect("active run should be removed");
+
+    run_caeles(temp.path())
+        .args(["tag", &format!("{CAPSULE_ID}@0.9.0"), "stable"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["rmi", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("tag 'stable' removida"));
+    assert!(!temp
+        .path()
+        .join(format!(".caeles/packages/{CAPSULE_ID}"))
+        .exists());
+    assert_eq!(blob_count(), 0);
+    run_caeles(temp.path())
+        .args(["rmi", CAPSULE_ID])
+        .assert()
+        .failure()
+        .stderr(contains("Nenhuma imagem local"));
+}
+
+#[test]
+fn cli_run_loads_capsules_from_the_image_store() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let package_stdout = run_caeles(temp.path())
+        .args(["package", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let package_stdout = String::from_utf8_lossy(&package_stdout);
+    let digest = package_stdout
+        .split(['(', ')'])
+        .find(|part| part.starts_with("sha256:"))
+        .expect("package output should show the wasm digest")
+        .to_string();
+
+    let run_stdout = run_caeles(temp.path())
+        .args(["run", "--image", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains(format!(
+            "> Imagem: {CAPSULE_ID}@{CAPSULE_VERSION}"
+        )))
+        .stdout(contains("[capsule-log] integration-log"))
+        .get_output()
+        .stdout
+        .clone();
+    let run_id = extract_run_id(&String::from_utf8_lossy(&run_stdout));
+    let inspect_stdout = run_caeles(temp.path())
+        .args(["inspect-run", &run_id, "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let view: Value = serde_json::from_slice(&inspect_stdout).expect("inspect-run should be json");
+    assert_eq!(
+        view["image"]["reference"].as_str(),
+        Some(format!("{CAPSULE_ID}@{CAPSULE_VERSION}").as_str())
+    );
+    assert_eq!(view["image"]["source"].as_str(), Some("package"));
+    assert_eq!(view["image"]["digest"].as_str(), Some(digest.as_str()));
+    assert!(view["manifest_path"]
+        .as_str()
+        .is_some_and(|path| path.contains(".caeles/packages")));
+
+    // Registry runs do not record an image.
+    let run_stdout = run_caeles(temp.path())
+        .args(["run", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let run_id = extract_run_id(&String::from_utf8_lossy(&run_stdout));
+    run_caeles(temp.path())
+        .args(["inspect-run", &run_id])
+        .assert()
+        .success()
+        .stdout(contains("image:").not());
+
+    // Without a registry entry, the id falls back to the local images.
+    write_file(&temp.path().join("capsules/registry.json"), "[]");
+    run_caeles(temp.path())
+        .args(["run", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("não está no registry; usando imagem local"))
+        .stdout(contains("[capsule-log] integration-log"));
+    run_caeles(temp.path())
+        .args(["run", "--capsule-id", "com.caeles.test.missing"])
+        .assert()
+        .failure()
+        .stderr(contains("não encontrado no registry"));
+    run_caeles(temp.path())
+        .args(["run", "--image", CAPSULE_ID, "--capsule-id", CAPSULE_ID])
+        .assert()
+        .failure();
+}
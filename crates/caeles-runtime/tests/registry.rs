@@ -0,0 +1,166 @@
+mod common;
+
+use common::{
+    http_response, run_caeles, serve, write_demo_capsule_wasm, write_demo_registry_fixture,
+    write_file, CAPSULE_ID, CAPSULE_NAME,
+};
+use predicates::prelude::PredicateBooleanExt;
+use predicates::str::contains;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+#[test]
+fn cli_registry_commands_edit_and_verify_the_registry() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+    let registry_path = temp.path().join("capsules/registry.json");
+
+    let tool_manifest = temp.path().join("capsules/tool/manifest.json");
+    let manifest = fs::read_to_string(temp.path().join("capsules/demo/manifest.json"))
+        .expect("manifest should be readable")
+        .replace(CAPSULE_ID, "com.caeles.test.tool")
+        .replace(CAPSULE_NAME, "Search Tool");
+    write_file(&tool_manifest, &manifest);
+    fs::copy(
+        temp.path().join("capsules/demo/demo.wasm"),
+        temp.path().join("capsules/tool/demo.wasm"),
+    )
+    .expect("wasm should be copied");
+
+    run_caeles(temp.path())
+        .args(["registry", "add", "capsules/tool/manifest.json"])
+        .assert()
+        .success()
+        .stdout(contains("'com.caeles.test.tool' (Search Tool) adicionada"));
+    run_caeles(temp.path())
+        .args(["registry", "add", "capsules/tool/manifest.json"])
+        .assert()
+        .failure()
+        .stderr(contains("já está no registry"));
+    let registry = fs::read_to_string(&registry_path).expect("registry should be readable");
+    assert!(registry.ends_with("}\n]\n"));
+    assert!(registry.contains("    \"manifest\": \"tool/manifest.json\"\n"));
+
+    run_caeles(temp.path())
+        .args(["registry", "search", "search"])
+        .assert()
+        .success()
+        .stdout(contains("- com.caeles.test.tool (Search Tool)"))
+        .stdout(contains(CAPSULE_ID).not());
+    run_caeles(temp.path())
+        .args(["run", "--capsule-id", "com.caeles.test.tool"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["registry", "verify"])
+        .assert()
+        .success()
+        .stdout(contains("Registry válido"));
+
+    // Drift between the registry and the manifests is reported.
+    write_file(
+        &tool_manifest,
+        &manifest.replace("Search Tool", "Renamed Tool"),
+    );
+    fs::remove_file(temp.path().join("capsules/demo/manifest.json"))
+        .expect("manifest should be removed");
+    let output = run_caeles(temp.path())
+        .args(["registry", "verify", "--json"])
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+    let view: Value = serde_json::from_slice(&output).expect("verify output should be json");
+    assert_eq!(view["valid"], false);
+    assert_eq!(view["entries"], 2);
+    assert_eq!(view["issues"][0]["id"], CAPSULE_ID);
+    assert!(view["issues"][0]["message"]
+        .as_str()
+        .is_some_and(|m| m.contains("manifest não encontrado")));
+    assert!(view["issues"][1]["message"]
+        .as_str()
+        .is_some_and(|m| m.contains("difere do manifest")));
+
+    run_caeles(temp.path())
+        .args(["registry", "remove", CAPSULE_ID])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["registry", "remove", CAPSULE_ID])
+        .assert()
+        .failure()
+        .stderr(contains("não encontrado no registry"));
+    run_caeles(temp.path())
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(contains("com.caeles.test.tool"))
+        .stdout(contains(CAPSULE_ID).not());
+}
+
+/// Serves the files under `root` over HTTP until the test process exits.
+fn serve_dir(root: PathBuf) -> String {
+    serve(
+        move |_, path| match fs::read(root.join(path.trim_start_matches('/'))) {
+            Ok(body) => http_response("200 OK", "", &body),
+            Err(_) => http_response("404 Not Found", "", b""),
+        },
+    )
+}
+
+#[test]
+fn cli_run_resolves_capsules_from_remote_registries_in_precedence_order() {
+    let remote = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(remote.path(), "on_demand");
+    let url = serve_dir(remote.path().to_path_buf());
+
+    let temp = TempDir::new().expect("temp directory should be created");
+    let tool = fs::read_to_string(remote.path().join("capsules/demo/manifest.json"))
+        .expect("manifest should be readable")
+        .replace(CAPSULE_ID, "com.caeles.test.tool")
+        .replace(CAPSULE_NAME, "Local Tool");
+    write_file(&temp.path().join("capsules/tool/manifest.json"), &tool);
+    write_demo_capsule_wasm(&temp.path().join("capsules/tool/demo.wasm"));
+    write_file(
+        &temp.path().join("capsules/registry.json"),
+        r#"[
+  { "id": "com.caeles.test.tool", "name": "Local Tool", "manifest": "tool/manifest.json" },
+  { "id": "com.caeles.test.demo", "name": "Shadowed", "manifest": "missing/manifest.json" }
+]"#,
+    );
+    write_file(
+        &temp.path().join("caeles.toml"),
+        &format!("registries = [\"capsules/registry.json\", \"{url}/capsules/registry.json\"]\n"),
+    );
+
+    // The local registry comes first, so its (broken) entry for the demo wins.
+    run_caeles(temp.path())
+        .args(["run", CAPSULE_ID])
+        .assert()
+        .failure();
+
+    write_file(
+        &temp.path().join("caeles.toml"),
+        &format!("registries = [\"{url}/capsules/registry.json\", \"capsules/registry.json\"]\n"),
+    );
+    run_caeles(temp.path())
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(contains("- com.caeles.test.demo (Demo Capsule)"))
+        .stdout(contains("- com.caeles.test.tool (Local Tool)"));
+    run_caeles(temp.path())
+        .args(["run", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("integration-log"));
+    run_caeles(temp.path())
+        .args(["registry", "verify"])
+        .assert()
+        .failure()
+        .stdout(contains("manifest não encontrado"));
+    assert!(temp.path().join(".caeles/state/registries").is_dir());
+}
@@ -0,0 +1,278 @@
+mod common;
+
+use common::{extract_run_id, run_caeles, write_demo_registry_fixture, write_file};
+use predicates::str::contains;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn cli_record_requires_deterministic_mode_for_wasip1_capsules() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    // Prints the low byte of the WASI realtime clock, which a trace cannot reproduce.
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "wasi_snapshot_preview1" "clock_time_get"
+    (func $clock (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "_start")
+    (drop (call $clock (i32.const 0) (i64.const 1) (i32.const 32)))
+    (i32.store8 (i32.const 32) (i32.add (i32.const 65) (i32.rem_u (i32.load8_u (i32.const 32)) (i32.const 26))))
+    (i32.store8 (i32.const 33) (i32.const 10))
+    (i32.store (i32.const 0) (i32.const 32))
+    (i32.store (i32.const 4) (i32.const 2))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    write_file(&temp.path().join("capsules/clock/placeholder"), "");
+    fs::write(temp.path().join("capsules/clock/clock.wasm"), wasm).expect("wasm should be written");
+    let manifest = serde_json::json!({
+        "id": "com.caeles.test.clock",
+        "name": "Clock",
+        "version": "0.1.0",
+        "entry": "clock.wasm",
+        "target": "wasm32-wasip1",
+        "permissions": { "notifications": false, "network": false, "wall_clock": true },
+        "lifecycle": { "kind": "on_demand" }
+    });
+    write_file(
+        &temp.path().join("capsules/clock/manifest.json"),
+        &serde_json::to_string_pretty(&manifest).expect("manifest json should serialize"),
+    );
+
+    run_caeles(temp.path())
+        .args([
+            "run",
+            "--manifest",
+            "capsules/clock/manifest.json",
+            "--record",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "imports wasi_snapshot_preview1, whose clocks and random values are not traced",
+        ));
+
+    let run_stdout = run_caeles(temp.path())
+        .args([
+            "run",
+            "--manifest",
+            "capsules/clock/manifest.json",
+            "--record",
+            "--deterministic",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let run_stdout = String::from_utf8(run_stdout).expect("run output should be utf-8");
+    let run_id = extract_run_id(&run_stdout);
+    let printed = run_stdout
+        .lines()
+        .find(|line| line.starts_with("[capsule-stdout]"))
+        .expect("capsule should print")
+        .to_string();
+    run_caeles(temp.path())
+        .args(["replay", &run_id])
+        .assert()
+        .success()
+        .stdout(contains(printed))
+        .stdout(contains("conferiu com o trace"));
+}
+
+#[test]
+fn cli_replay_reproduces_recorded_run_and_flags_divergence() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_log" (func $log (param i32 i32)))
+  (import "caeles_v2" "host_cap_acquire" (func $acquire (param i32) (result i32)))
+  (import "caeles_v2" "host_notify_cap" (func $notify (param i32 i32 i32) (result i32)))
+  (import "caeles_v2" "host_input_len" (func $input_len (result i32)))
+  (import "caeles_v2" "host_input_read" (func $input_read (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "hello")
+  (func (export "caeles_main")
+    (local $len i32)
+    (local.set $len (call $input_read (i32.const 64) (call $input_len)))
+    (call $log (i32.const 64) (local.get $len))
+    (drop (call $notify (call $acquire (i32.const 2)) (i32.const 0) (i32.const 5)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    let run_stdout = run_caeles(temp.path())
+        .args([
+            "run",
+            "--manifest",
+            "capsules/demo/manifest.json",
+            "--input",
+            r#"{"n":1}"#,
+            "--record",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("> trace: "))
+        .get_output()
+        .stdout
+        .clone();
+    let run_id = extract_run_id(&String::from_utf8_lossy(&run_stdout));
+
+    let trace_path = temp
+        .path()
+        .join(".caeles/state/traces")
+        .join(format!("{run_id}.json"));
+    let trace: Value =
+        serde_json::from_str(&fs::read_to_string(&trace_path).expect("trace should exist"))
+            .expect("trace should be valid JSON");
+    let functions: Vec<&str> = trace["calls"]
+        .as_array()
+        .expect("calls should be an array")
+        .iter()
+        .map(|call| {
+            call["function"]
+                .as_str()
+                .expect("function should be a string")
+        })
+        .collect();
+    assert_eq!(
+        functions,
+        [
+            "host_input_len",
+            "host_input_read",
+            "host_log",
+            "host_cap_acquire",
+            "host_notify_cap"
+        ]
+    );
+
+    run_caeles(temp.path())
+        .args(["inspect-run", &run_id])
+        .assert()
+        .success()
+        .stdout(contains("trace_path: "));
+
+    run_caeles(temp.path())
+        .args(["replay", &run_id])
+        .assert()
+        .success()
+        .stdout(contains("[capsule-notify] hello"))
+        .stdout(contains("conferiu com o trace"));
+
+    let mut edited = trace.clone();
+    edited["calls"][2]["args"] = serde_json::json!(["{\"n\":2}"]);
+    let edited_path = temp.path().join("edited-trace.json");
+    fs::write(&edited_path, edited.to_string()).expect("edited trace should be written");
+
+    run_caeles(temp.path())
+        .args(["replay", "edited-trace.json"])
+        .assert()
+        .failure()
+        .stderr(contains("Reprodução divergiu do trace"))
+        .stderr(contains(r#"call #2: expected host_log(["{\"n\":2}"])"#));
+}
+
+#[test]
+fn cli_run_deterministic_mode_repeats_clock_and_random_values() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "wasi_snapshot_preview1" "random_get" (func $random (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get"
+    (func $clock (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "0123456789abcdef")
+  (func (export "_start")
+    (local $i i32)
+    (local $byte i32)
+    (drop (call $random (i32.const 104) (i32.const 8)))
+    (drop (call $clock (i32.const 0) (i64.const 1) (i32.const 112)))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (i32.const 16)))
+        (local.set $byte (i32.load8_u (i32.add (i32.const 104) (local.get $i))))
+        (i32.store8 (i32.add (i32.const 200) (i32.shl (local.get $i) (i32.const 1)))
+          (i32.load8_u (i32.shr_u (local.get $byte) (i32.const 4))))
+        (i32.store8 (i32.add (i32.const 201) (i32.shl (local.get $i) (i32.const 1)))
+          (i32.load8_u (i32.and (local.get $byte) (i32.const 15))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (i32.store8 (i32.const 232) (i32.const 10))
+    (i32.store (i32.const 300) (i32.const 200))
+    (i32.store (i32.const 304) (i32.const 33))
+    (drop (call $fd_write (i32.const 1) (i32.const 300) (i32.const 1) (i32.const 308)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    write_file(&temp.path().join("capsules/wasi/placeholder"), "");
+    fs::write(temp.path().join("capsules/wasi/wasi.wasm"), wasm).expect("wasm should be written");
+    let manifest = serde_json::json!({
+        "id": "com.caeles.test.wasi",
+        "name": "WASI Capsule",
+        "version": "0.1.0",
+        "entry": "wasi.wasm",
+        "target": "wasm32-wasip1",
+        "permissions": { "notifications": false, "network": false },
+        "lifecycle": { "kind": "on_demand" }
+    });
+    write_file(
+        &temp.path().join("capsules/wasi/manifest.json"),
+        &serde_json::to_string_pretty(&manifest).expect("manifest json should serialize"),
+    );
+
+    let run = |extra: &[&str]| -> (String, String) {
+        let stdout = run_caeles(temp.path())
+            .args(["run", "--manifest", "capsules/wasi/manifest.json"])
+            .args(extra)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let stdout = String::from_utf8(stdout).expect("run output should be utf-8");
+        let values = stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("[capsule-stdout] "))
+            .expect("capsule should print its values")
+            .to_owned();
+        (values, extract_run_id(&stdout))
+    };
+
+    let (first, run_id) = run(&["--deterministic", "--seed", "42"]);
+    let (second, _) = run(&["--deterministic", "--seed", "42"]);
+    let (other_seed, _) = run(&["--deterministic", "--seed", "43"]);
+    assert_eq!(first, second);
+    assert_ne!(first, other_seed);
+    // Same virtual clock for every seed: 2024-01-01T00:00:00.001Z in nanoseconds.
+    assert_eq!(first[16..], other_seed[16..]);
+    assert_eq!(&first[16..], "404274011710a617");
+
+    run_caeles(temp.path())
+        .args(["inspect-run", &run_id])
+        .assert()
+        .success()
+        .stdout(contains("seed: 42"));
+
+    run_caeles(temp.path())
+        .args([
+            "run",
+            "--manifest",
+            "capsules/wasi/manifest.json",
+            "--seed",
+            "1",
+        ])
+        .assert()
+        .failure();
+}
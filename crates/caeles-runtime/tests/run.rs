@@ -0,0 +1,366 @@
+mod common;
+
+use common::{extract_run_id, run_caeles, write_demo_registry_fixture, write_file, CAPSULE_ID};
+use predicates::str::contains;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn cli_run_rejects_capsule_with_undeclared_imports() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main")))"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    run_caeles(temp.path())
+        .args(["run", "--manifest", "capsules/demo/manifest.json"])
+        .assert()
+        .failure()
+        .stderr(contains("wasi_snapshot_preview1::fd_write"));
+
+    run_caeles(temp.path())
+        .args(["validate", "capsules/demo/manifest.json"])
+        .assert()
+        .failure()
+        .stdout(contains("[erro] wasi_snapshot_preview1::fd_write"));
+}
+
+#[test]
+fn cli_run_wasip1_capsule_captures_stdout_into_run_log() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "hello-wasi\n")
+  (func (export "_start")
+    (i32.store (i32.const 0) (i32.const 16))
+    (i32.store (i32.const 4) (i32.const 11))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    write_file(&temp.path().join("capsules/wasi/placeholder"), "");
+    fs::write(temp.path().join("capsules/wasi/wasi.wasm"), wasm).expect("wasm should be written");
+
+    let manifest = serde_json::json!({
+        "id": "com.caeles.test.wasi",
+        "name": "WASI Capsule",
+        "version": "0.1.0",
+        "entry": "wasi.wasm",
+        "target": "wasm32-wasip1",
+        "wasi": {
+            "preopens": [{ "path": "data", "guest": "/data", "mode": "read_write" }],
+            "env": ["CAELES_TEST_VISIBLE"]
+        },
+        "permissions": { "notifications": false, "network": false },
+        "lifecycle": { "kind": "on_demand" }
+    });
+    write_file(
+        &temp.path().join("capsules/wasi/manifest.json"),
+        &serde_json::to_string_pretty(&manifest).expect("manifest json should serialize"),
+    );
+
+    let run_stdout = run_caeles(temp.path())
+        .args(["run", "--manifest", "capsules/wasi/manifest.json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let run_stdout = String::from_utf8(run_stdout).expect("run output should be utf-8");
+    assert!(run_stdout.contains("[capsule-stdout] hello-wasi"));
+    let run_id = extract_run_id(&run_stdout);
+
+    let log = fs::read_to_string(temp.path().join(format!(".caeles/state/logs/{run_id}.log")))
+        .expect("run log should exist");
+    assert!(log.contains("wasi_stdout: hello-wasi"), "{log}");
+    assert!(temp
+        .path()
+        .join(".caeles/state/sandbox/com.caeles.test.wasi/data")
+        .is_dir());
+}
+
+fn component_wat(message: &str) -> String {
+    format!(
+        r#"(component
+  (import "caeles:capsule/log@0.1.0" (instance $log
+    (export "log" (func (param "message" string)))
+  ))
+  (alias export $log "log" (func $log_fn))
+
+  (core module $mem (memory (export "memory") 1))
+  (core instance $mem_instance (instantiate $mem))
+  (alias core export $mem_instance "memory" (core memory $memory))
+  (core func $log_lowered (canon lower (func $log_fn) (memory $memory)))
+
+  (core module $capsule
+    (import "host" "log" (func $log (param i32 i32)))
+    (import "host" "memory" (memory 1))
+    (data (i32.const 0) "{message}")
+    (func (export "run") (result i32)
+      (call $log (i32.const 0) (i32.const {len}))
+      (i32.store8 (i32.const 256) (i32.const 0))
+      (i32.const 256))
+  )
+  (core instance $capsule_instance (instantiate $capsule
+    (with "host" (instance
+      (export "log" (func $log_lowered))
+      (export "memory" (memory $memory))
+    ))
+  ))
+
+  (func (export "run") (result (result (error string)))
+    (canon lift (core func $capsule_instance "run") (memory $memory)))
+)"#,
+        len = message.len()
+    )
+}
+
+#[test]
+fn cli_run_detects_and_executes_component_capsules() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let component =
+        wat::parse_str(component_wat("hello-component")).expect("component WAT should compile");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), component)
+        .expect("component should be written");
+
+    run_caeles(temp.path())
+        .args(["run", "--manifest", "capsules/demo/manifest.json"])
+        .assert()
+        .success()
+        .stdout(contains("Capsule format: component"))
+        .stdout(contains("[capsule-log] hello-component"));
+
+    let inspect_stdout = run_caeles(temp.path())
+        .args(["inspect", CAPSULE_ID, "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let inspect: Value =
+        serde_json::from_slice(&inspect_stdout).expect("inspect output should be json");
+    assert_eq!(inspect["abi"]["format"].as_str(), Some("component"));
+    assert_eq!(inspect["abi"]["compatible"].as_bool(), Some(true));
+}
+
+#[test]
+fn cli_run_reports_capsule_panic_message() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_panic" (func $panic (param i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "boom at src/lib.rs:3:5")
+  (func (export "caeles_main")
+    (call $panic (i32.const 0) (i32.const 22))
+    unreachable
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    run_caeles(temp.path())
+        .args(["run", "--manifest", "capsules/demo/manifest.json"])
+        .assert()
+        .failure()
+        .stderr(contains("capsule panicked: boom at src/lib.rs:3:5"));
+}
+
+#[test]
+fn cli_run_passes_input_and_reports_exit_code() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_log" (func $log (param i32 i32)))
+  (import "caeles_v2" "host_exit" (func $exit (param i32 i32 i32)))
+  (import "caeles_v2" "host_input_len" (func $input_len (result i32)))
+  (import "caeles_v2" "host_input_read" (func $input_read (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "bad input")
+  (func (export "caeles_main")
+    (local $len i32)
+    (local.set $len (call $input_read (i32.const 64) (call $input_len)))
+    (call $log (i32.const 64) (local.get $len))
+    (call $exit (i32.const 1) (i32.const 0) (i32.const 9))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    run_caeles(temp.path())
+        .args([
+            "run",
+            "--manifest",
+            "capsules/demo/manifest.json",
+            "--input",
+            r#"{"name":"caeles"}"#,
+        ])
+        .assert()
+        .failure()
+        .stdout(contains(r#"[capsule-log] {"name":"caeles"}"#))
+        .stderr(contains("capsule exited with code 1: bad input"));
+
+    run_caeles(temp.path())
+        .args([
+            "run",
+            "--manifest",
+            "capsules/demo/manifest.json",
+            "--input",
+            "not json",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Input JSON inválido"));
+}
+
+#[test]
+fn cli_run_filters_capsule_logs_by_level_and_stores_fields() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_log_v2"
+    (func $log (param i32 i32 i32 i32 i32 i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "demo::net")
+  (data (i32.const 16) "request failed")
+  (data (i32.const 32) "verbose detail")
+  (data (i32.const 64) "{\22status\22:\22503\22}")
+  (func (export "caeles_main")
+    (call $log (i32.const 2) (i32.const 0) (i32.const 9) (i32.const 16) (i32.const 14)
+      (i32.const 64) (i32.const 16))
+    (call $log (i32.const 4) (i32.const 0) (i32.const 9) (i32.const 32) (i32.const 14)
+      (i32.const 0) (i32.const 0))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    let run_stdout = run_caeles(temp.path())
+        .args(["run", "--manifest", "capsules/demo/manifest.json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let run_stdout = String::from_utf8(run_stdout).expect("run output should be utf-8");
+    assert!(run_stdout.contains("[capsule-log WARN demo::net] request failed status=503"));
+    assert!(!run_stdout.contains("verbose detail"));
+
+    let run_id = extract_run_id(&run_stdout);
+    let log = fs::read_to_string(temp.path().join(format!(".caeles/state/logs/{run_id}.log")))
+        .expect("run log should exist");
+    assert!(
+        log.contains(r#"capsule_log {"fields":{"status":"503"},"level":"warn","message":"request failed","target":"demo::net"}"#),
+        "{log}"
+    );
+
+    run_caeles(temp.path())
+        .args([
+            "run",
+            "--manifest",
+            "capsules/demo/manifest.json",
+            "--log-level",
+            "debug",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("[capsule-log DEBUG demo::net] verbose detail"));
+}
+
+#[test]
+fn cli_test_runs_cases_with_stubs_and_writes_junit() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_log" (func $log (param i32 i32)))
+  (import "caeles_v2" "host_cap_acquire" (func $acquire (param i32) (result i32)))
+  (import "caeles_v2" "host_http_get_cap" (func $get (param i32 i32 i32) (result i32)))
+  (import "caeles_v2" "host_set_output" (func $output (param i32 i32) (result i32)))
+  (import "caeles_v2" "host_input_len" (func $input_len (result i32)))
+  (import "caeles_v2" "host_input_read" (func $input_read (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "https://api.test/ok")
+  (data (i32.const 32) "ok")
+  (data (i32.const 48) "fail")
+  (func (export "caeles_main")
+    (local $len i32)
+    (if (i32.eqz (call $get (call $acquire (i32.const 1)) (i32.const 0) (i32.const 19)))
+      (then (call $log (i32.const 32) (i32.const 2)))
+      (else (call $log (i32.const 48) (i32.const 4))))
+    (local.set $len (call $input_read (i32.const 128) (call $input_len)))
+    (drop (call $output (i32.const 128) (local.get $len)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    let cases = serde_json::json!({
+        "cases": [
+            {
+                "name": "granted",
+                "input": { "n": 1 },
+                "permissions": { "network": true },
+                "http": { "https://api.test/ok": { "status": 200, "body": "pong" } },
+                "expect": { "logs": ["ok"], "output": { "n": 1 }, "exit_code": 0 }
+            },
+            {
+                "name": "denied",
+                "expect": { "logs": ["ok"] }
+            }
+        ]
+    });
+    write_file(
+        &temp.path().join("capsules/demo/caeles-test.json"),
+        &serde_json::to_string_pretty(&cases).expect("test file should serialize"),
+    );
+
+    let output = run_caeles(temp.path())
+        .args([
+            "test",
+            "capsules/demo/manifest.json",
+            "--junit",
+            "report.xml",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("1 caso(s) de teste falharam"))
+        .get_output()
+        .stdout
+        .clone();
+    let stdout = String::from_utf8(output).expect("test output should be utf-8");
+    assert!(stdout.contains("caso 'granted' ... ok"), "{stdout}");
+    assert!(stdout.contains("caso 'denied' ... FALHOU"), "{stdout}");
+    assert!(stdout.contains("+ fail"), "{stdout}");
+    assert!(stdout.contains("- ok"), "{stdout}");
+
+    let junit =
+        fs::read_to_string(temp.path().join("report.xml")).expect("junit report should exist");
+    assert!(junit.contains(r#"tests="2" failures="1""#), "{junit}");
+    assert!(junit.contains(r#"<testcase name="denied""#), "{junit}");
+}
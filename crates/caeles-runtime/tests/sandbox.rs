@@ -0,0 +1,389 @@
+mod common;
+
+use common::{
+    extract_run_id, http_response, run_caeles, serve, write_demo_registry_fixture, write_file,
+    CAPSULE_ID, CAPSULE_NAME, CAPSULE_VERSION,
+};
+use predicates::prelude::PredicateBooleanExt;
+use predicates::str::contains;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn cli_run_audits_rejected_capability_handles() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_cap_acquire" (func $acquire (param i32) (result i32)))
+  (import "caeles_v2" "host_cap_release" (func $release (param i32) (result i32)))
+  (import "caeles_v2" "host_notify_cap" (func $notify (param i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "granted")
+  (data (i32.const 16) "forged")
+  (func (export "caeles_main")
+    (local $handle i32)
+    (local.set $handle (call $acquire (i32.const 2)))
+    (drop (call $notify (local.get $handle) (i32.const 0) (i32.const 7)))
+    (drop (call $notify (i32.const 12345) (i32.const 16) (i32.const 6)))
+    (drop (call $acquire (i32.const 1)))
+    (drop (call $release (local.get $handle)))
+    (drop (call $notify (local.get $handle) (i32.const 16) (i32.const 6)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    let run_stdout = run_caeles(temp.path())
+        .args(["run", "--manifest", "capsules/demo/manifest.json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let run_stdout = String::from_utf8(run_stdout).expect("run output should be utf-8");
+    assert!(run_stdout.contains("[capsule-notify] granted"));
+    assert!(!run_stdout.contains("[capsule-notify] forged"));
+    let run_id = extract_run_id(&run_stdout);
+
+    let log = fs::read_to_string(temp.path().join(format!(".caeles/state/logs/{run_id}.log")))
+        .expect("run log should exist");
+    assert!(log.contains(r#""event":"permission_denied""#), "{log}");
+    assert!(log.contains(r#""reason":"unknown_handle""#), "{log}");
+    assert!(log.contains(r#""event":"capability_revoked""#), "{log}");
+    assert!(log.contains(r#""reason":"revoked_handle""#), "{log}");
+}
+
+#[test]
+fn cli_run_enforces_timeout_and_wall_clock_permission() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_now_ms" (func $now (result i64)))
+  (import "caeles_v2" "host_sleep_ms" (func $sleep (param i32) (result i32)))
+  (import "caeles_v2" "host_random_bytes" (func $random (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "caeles_main")
+    (if (i64.ne (call $now) (i64.const -1)) (then unreachable))
+    (if (call $random (i32.const 0) (i32.const 32)) (then unreachable))
+    (if (call $sleep (i32.const 10)) (then unreachable))
+    (drop (call $sleep (i32.const 60000)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    let stdout = run_caeles(temp.path())
+        .args([
+            "run",
+            "--manifest",
+            "capsules/demo/manifest.json",
+            "--timeout-ms",
+            "1000",
+        ])
+        .assert()
+        .failure()
+        .stdout(contains(
+            "[capsule-clock BLOCKED] permission 'wall_clock' = false",
+        ))
+        .stderr(contains("capsule exceeded the run timeout of 1000 ms"))
+        .get_output()
+        .stdout
+        .clone();
+    let run_id = extract_run_id(&String::from_utf8_lossy(&stdout));
+    let log = fs::read_to_string(temp.path().join(format!(".caeles/state/logs/{run_id}.log")))
+        .expect("run log should exist");
+    assert!(
+        log.contains(r#""function":"host_now_ms","kind":"wall_clock""#),
+        "{log}"
+    );
+
+    let busy = wat::parse_str(
+        r#"(module
+  (func (export "caeles_main")
+    (loop $spin (br $spin))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), busy).expect("wasm should be written");
+
+    run_caeles(temp.path())
+        .args([
+            "run",
+            "--manifest",
+            "capsules/demo/manifest.json",
+            "--timeout-ms",
+            "200",
+        ])
+        .timeout(std::time::Duration::from_secs(30))
+        .assert()
+        .failure()
+        .stderr(contains("capsule exceeded the run timeout of 200 ms"));
+}
+
+#[test]
+fn cli_run_serves_config_and_redacts_secrets() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let manifest = serde_json::json!({
+        "id": CAPSULE_ID,
+        "name": CAPSULE_NAME,
+        "version": CAPSULE_VERSION,
+        "entry": "demo.wasm",
+        "permissions": { "notifications": true, "network": false },
+        "lifecycle": { "kind": "on_demand" },
+        "config": {
+            "greeting": { "type": "string", "default": "from-default" },
+            "retries": { "type": "integer" }
+        },
+        "secrets": ["api_token"]
+    });
+    write_file(
+        &temp.path().join("capsules/demo/manifest.json"),
+        &serde_json::to_string_pretty(&manifest).expect("manifest json should serialize"),
+    );
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_config_get" (func $config (param i32 i32 i32 i32) (result i32)))
+  (import "caeles_v2" "host_secret_get" (func $secret (param i32 i32 i32 i32) (result i32)))
+  (import "caeles_v2" "host_log" (func $log (param i32 i32)))
+  (import "caeles_v2" "host_set_output" (func $output (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "greeting")
+  (data (i32.const 16) "api_token")
+  (func (export "caeles_main")
+    (local $len i32)
+    (local.set $len (call $config (i32.const 0) (i32.const 8) (i32.const 64) (i32.const 64)))
+    (drop (call $output (i32.const 64) (local.get $len)))
+    (local.set $len (call $secret (i32.const 16) (i32.const 9) (i32.const 128) (i32.const 64)))
+    (call $log (i32.const 128) (local.get $len))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    run_caeles(temp.path())
+        .args([
+            "secret",
+            "set",
+            CAPSULE_ID,
+            "api_token",
+            "--value",
+            "s3cr3t-token",
+        ])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["capsule-config", "set", CAPSULE_ID, "greeting=from-store"])
+        .assert()
+        .success();
+    run_caeles(temp.path())
+        .args(["capsule-config", "set", CAPSULE_ID, "retries=many"])
+        .assert()
+        .failure()
+        .stderr(contains("'many' nao e um valor integer"));
+
+    run_caeles(temp.path())
+        .args(["run", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("> output: from-store"));
+
+    let stdout = run_caeles(temp.path())
+        .args([
+            "run",
+            "--capsule-id",
+            CAPSULE_ID,
+            "--config",
+            "greeting=from-cli",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("> output: from-cli"))
+        .stdout(contains("[capsule-log] [REDACTED]"))
+        .stdout(contains("s3cr3t-token").not())
+        .get_output()
+        .stdout
+        .clone();
+    let run_id = extract_run_id(&String::from_utf8_lossy(&stdout));
+    let log = fs::read_to_string(temp.path().join(format!(".caeles/state/logs/{run_id}.log")))
+        .expect("run log should exist");
+    assert!(log.contains(r#""name":"api_token""#), "{log}");
+    assert!(!log.contains("s3cr3t-token"), "{log}");
+
+    let sealed = fs::read_to_string(
+        temp.path()
+            .join(format!(".caeles/state/secrets/{CAPSULE_ID}.json")),
+    )
+    .expect("secret store should exist");
+    assert!(!sealed.contains("s3cr3t-token"), "{sealed}");
+
+    run_caeles(temp.path())
+        .args(["run", "--capsule-id", CAPSULE_ID, "--config", "colour=red"])
+        .assert()
+        .failure()
+        .stderr(contains("Chave de config 'colour' não declarada"));
+}
+
+#[test]
+fn cli_run_keeps_capsule_files_inside_sandbox_and_quota() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    write_demo_registry_fixture(temp.path(), "on_demand");
+
+    let manifest = serde_json::json!({
+        "id": CAPSULE_ID,
+        "name": CAPSULE_NAME,
+        "version": CAPSULE_VERSION,
+        "entry": "demo.wasm",
+        "permissions": { "notifications": true, "network": false, "filesystem": true },
+        "lifecycle": { "kind": "on_demand" },
+        "filesystem": { "quota_bytes": 64, "assets": ["static"] }
+    });
+    write_file(
+        &temp.path().join("capsules/demo/manifest.json"),
+        &serde_json::to_string_pretty(&manifest).expect("manifest json should serialize"),
+    );
+    write_file(
+        &temp.path().join("capsules/demo/static/readme.txt"),
+        "bundled asset",
+    );
+
+    let wasm = wat::parse_str(
+        r#"(module
+  (import "caeles_v2" "host_fs_open" (func $open (param i32 i32 i32) (result i32)))
+  (import "caeles_v2" "host_fs_read" (func $read (param i32 i32 i32) (result i32)))
+  (import "caeles_v2" "host_fs_write" (func $write (param i32 i32 i32) (result i32)))
+  (import "caeles_v2" "host_fs_close" (func $close (param i32) (result i32)))
+  (import "caeles_v2" "host_log" (func $log (param i32 i32)))
+  (import "caeles_v2" "host_set_output" (func $output (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "notes/a.txt")
+  (data (i32.const 32) "hello fs")
+  (data (i32.const 64) "../x")
+  (data (i32.const 80) "assets/static/readme.txt")
+  (data (i32.const 256) "big.bin")
+  (func (export "caeles_main")
+    (local $fd i32)
+    (local $len i32)
+    (local.set $fd (call $open (i32.const 0) (i32.const 11) (i32.const 1)))
+    (if (i32.le_s (local.get $fd) (i32.const 0)) (then unreachable))
+    (if (i32.ne (call $write (local.get $fd) (i32.const 32) (i32.const 8)) (i32.const 8))
+      (then unreachable))
+    (drop (call $close (local.get $fd)))
+
+    (local.set $fd (call $open (i32.const 0) (i32.const 11) (i32.const 0)))
+    (local.set $len (call $read (local.get $fd) (i32.const 128) (i32.const 64)))
+    (drop (call $output (i32.const 128) (local.get $len)))
+    (drop (call $close (local.get $fd)))
+
+    (if (i32.ne (call $open (i32.const 64) (i32.const 4) (i32.const 0)) (i32.const -2))
+      (then unreachable))
+    (if (i32.ne (call $open (i32.const 80) (i32.const 24) (i32.const 1)) (i32.const -5))
+      (then unreachable))
+    (local.set $fd (call $open (i32.const 80) (i32.const 24) (i32.const 0)))
+    (local.set $len (call $read (local.get $fd) (i32.const 512) (i32.const 64)))
+    (call $log (i32.const 512) (local.get $len))
+    (drop (call $close (local.get $fd)))
+
+    (local.set $fd (call $open (i32.const 256) (i32.const 7) (i32.const 1)))
+    (if (i32.ne (call $write (local.get $fd) (i32.const 0) (i32.const 100)) (i32.const -4))
+      (then unreachable))
+    (drop (call $close (local.get $fd)))
+  )
+)"#,
+    )
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsules/demo/demo.wasm"), wasm).expect("wasm should be written");
+
+    let stdout = run_caeles(temp.path())
+        .args(["run", "--capsule-id", CAPSULE_ID])
+        .assert()
+        .success()
+        .stdout(contains("> output: hello fs"))
+        .stdout(contains("[capsule-log] bundled asset"))
+        .stdout(contains("[capsule-fs REJECTED] host_fs_open: '../x'"))
+        .stdout(contains("[capsule-fs QUOTA]"))
+        .get_output()
+        .stdout
+        .clone();
+    let run_id = extract_run_id(&String::from_utf8_lossy(&stdout));
+    let log = fs::read_to_string(temp.path().join(format!(".caeles/state/logs/{run_id}.log")))
+        .expect("run log should exist");
+    assert!(log.contains(r#""event":"fs_rejected""#), "{log}");
+
+    let output = run_caeles(temp.path())
+        .args(["inspect", CAPSULE_ID, "--json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let view: serde_json::Value =
+        serde_json::from_slice(&output).expect("inspect output should be json");
+    assert_eq!(view["disk"]["used_bytes"], 8);
+    assert_eq!(view["disk"]["quota_bytes"], 64);
+}
+
+#[test]
+fn cli_run_checks_every_redirect_against_network_hosts() {
+    let temp = TempDir::new().expect("temp directory should be created");
+    let base = serve(|address, path| {
+        let port = address.rsplit(':').next().unwrap_or_default();
+        match path {
+            "/inside" => http_response("302 Found", "Location: /ok\r\n", b""),
+            "/outside" => http_response(
+                "302 Found",
+                &format!("Location: http://localhost:{port}/ok\r\n"),
+                b"",
+            ),
+            _ => http_response("200 OK", "", b"ok"),
+        }
+    });
+    let inside = format!("{base}/inside");
+    let outside = format!("{base}/outside");
+    let wasm = wat::parse_str(format!(
+        r#"(module
+  (import "caeles" "host_http_get" (func $get (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{inside}")
+  (data (i32.const 256) "{outside}")
+  (func (export "caeles_main")
+    (drop (call $get (i32.const 0) (i32.const {})))
+    (drop (call $get (i32.const 256) (i32.const {})))
+  )
+)"#,
+        inside.len(),
+        outside.len()
+    ))
+    .expect("WAT should compile to valid wasm");
+    fs::write(temp.path().join("capsule.wasm"), wasm).expect("wasm should be written");
+    write_file(
+        &temp.path().join("manifest.json"),
+        r#"{
+  "id": "com.caeles.test.redirects",
+  "name": "Redirects",
+  "version": "0.1.0",
+  "entry": "capsule.wasm",
+  "permissions": { "notifications": false, "network": true, "network_hosts": ["127.0.0.1"] },
+  "lifecycle": { "kind": "on_demand" }
+}"#,
+    );
+
+    run_caeles(temp.path())
+        .args(["run", "--manifest", "manifest.json"])
+        .assert()
+        .success()
+        .stdout(contains(format!("[capsule-network] GET {inside} -> 200")))
+        .stdout(contains(format!(
+            "[capsule-network BLOCKED] GET {outside} redirected to host 'localhost'"
+        )));
+}
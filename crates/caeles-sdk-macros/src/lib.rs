@@ -0,0 +1,124 @@
+//! Procedural macros re-exported by `caeles-sdk`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse2, Error, FnArg, ItemFn};
+
+/// Marks the capsule entrypoint and generates the `caeles_main` export.
+///
+/// The function may return `()` or `Result<(), E>` with `E: Debug`; an `Err`
+/// is reported to the host as exit code 1. A panic hook sends the panic
+/// message and location to the host before the capsule traps, and with the
+/// `log` feature of `caeles-sdk` the host logger is installed. With one
+/// argument, the input passed through `caeles run --input` is deserialized
+/// into it (requires the `input` feature of `caeles-sdk`); invalid input is
+/// reported as exit code 2.
+#[proc_macro_attribute]
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(attr.into(), item.into())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(attr: TokenStream2, item: TokenStream2) -> syn::Result<TokenStream2> {
+    if !attr.is_empty() {
+        return Err(Error::new_spanned(
+            attr,
+            "#[caeles_sdk::main] does not take arguments",
+        ));
+    }
+
+    let function: ItemFn = parse2(item)?;
+    let sig = &function.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(Error::new_spanned(
+            asyncness,
+            "capsule entrypoint cannot be async",
+        ));
+    }
+    if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "capsule entrypoint cannot be generic",
+        ));
+    }
+    if sig.ident == "caeles_main" {
+        return Err(Error::new_spanned(
+            &sig.ident,
+            "entrypoint cannot be named `caeles_main`; that name is generated by the macro",
+        ));
+    }
+
+    let name = &sig.ident;
+    let call = match sig.inputs.len() {
+        0 => quote! { ::caeles_sdk::rt::run(#name) },
+        1 => {
+            if let Some(FnArg::Receiver(receiver)) = sig.inputs.first() {
+                return Err(Error::new_spanned(
+                    receiver,
+                    "capsule entrypoint cannot take `self`",
+                ));
+            }
+            quote! { ::caeles_sdk::rt::run_with_input(#name) }
+        }
+        _ => {
+            return Err(Error::new_spanned(
+                &sig.inputs,
+                "capsule entrypoint takes at most one argument (the typed input)",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        #function
+
+        #[no_mangle]
+        pub extern "C" fn caeles_main() {
+            ::caeles_sdk::rt::init();
+            #call
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand;
+    use quote::quote;
+
+    #[test]
+    fn generates_caeles_main_export() {
+        let output = expand(
+            quote! {},
+            quote! { fn main() -> Result<(), String> { Ok(()) } },
+        )
+        .expect("expansion should succeed")
+        .to_string();
+
+        assert!(output.contains("extern \"C\" fn caeles_main"));
+        assert!(output.contains(":: caeles_sdk :: rt :: run (main)"));
+        assert!(output.contains(":: caeles_sdk :: rt :: init ()"));
+    }
+
+    #[test]
+    fn single_argument_reads_typed_input() {
+        let output = expand(quote! {}, quote! { fn start(config: Config) {} })
+            .expect("expansion should succeed")
+            .to_string();
+
+        assert!(output.contains(":: caeles_sdk :: rt :: run_with_input (start)"));
+    }
+
+    #[test]
+    fn rejects_unsupported_signatures() {
+        for item in [
+            quote! { async fn main() {} },
+            quote! { fn main<T>() {} },
+            quote! { fn main(a: u32, b: u32) {} },
+            quote! { fn caeles_main() {} },
+        ] {
+            assert!(expand(quote! {}, item).is_err());
+        }
+        assert!(expand(quote! { input }, quote! { fn main() {} }).is_err());
+    }
+}
@@ -1,15 +1,721 @@
-#[link(wasm_import_module = "caeles")]
+/// Host ABI version this SDK targets; imports come from the `caeles_v{ABI_VERSION}` module.
+pub const ABI_VERSION: u32 = 2;
+
+/// Version marker read by the runtime from the `caeles_abi` custom section.
+///
+/// Lets the runtime report mismatches even for capsules that import no host functions.
+#[cfg(target_arch = "wasm32")]
+#[used]
+#[link_section = "caeles_abi"]
+static ABI_VERSION_MARKER: [u8; 4] = ABI_VERSION.to_le_bytes();
+
+#[cfg(any(target_arch = "wasm32", not(feature = "mock-host")))]
+#[link(wasm_import_module = "caeles_v2")]
 extern "C" {
     fn host_log(ptr: *const u8, len: u32);
-    fn host_notify(ptr: *const u8, len: u32);
-    fn host_http_get(ptr: *const u8, len: u32) -> i32;
+    fn host_log_v2(
+        level: i32,
+        target_ptr: *const u8,
+        target_len: u32,
+        msg_ptr: *const u8,
+        msg_len: u32,
+        fields_ptr: *const u8,
+        fields_len: u32,
+    );
+    fn host_log_level() -> i32;
+    fn host_cap_acquire(kind: i32) -> i32;
+    fn host_cap_release(handle: i32) -> i32;
+    fn host_notify_cap(handle: i32, ptr: *const u8, len: u32) -> i32;
+    fn host_http_get_cap(handle: i32, ptr: *const u8, len: u32) -> i32;
+    fn host_panic(ptr: *const u8, len: u32);
+    fn host_exit(code: i32, ptr: *const u8, len: u32);
+    fn host_set_output(ptr: *const u8, len: u32) -> i32;
+    fn host_input_len() -> i32;
+    fn host_input_read(ptr: *mut u8, len: u32) -> i32;
+    fn host_now_ms() -> i64;
+    fn host_monotonic_ns() -> i64;
+    fn host_sleep_ms(ms: i32) -> i32;
+    fn host_random_bytes(ptr: *mut u8, len: u32) -> i32;
+    fn host_config_get(key_ptr: *const u8, key_len: u32, buf_ptr: *mut u8, buf_len: u32) -> i32;
+    fn host_secret_get(name_ptr: *const u8, name_len: u32, buf_ptr: *mut u8, buf_len: u32) -> i32;
+    fn host_fs_open(path_ptr: *const u8, path_len: u32, mode: i32) -> i32;
+    fn host_fs_read(fd: i32, ptr: *mut u8, len: u32) -> i32;
+    fn host_fs_write(fd: i32, ptr: *const u8, len: u32) -> i32;
+    fn host_fs_close(fd: i32) -> i32;
+    fn host_fs_list(path_ptr: *const u8, path_len: u32, buf_ptr: *mut u8, buf_len: u32) -> i32;
+    fn host_fs_delete(path_ptr: *const u8, path_len: u32) -> i32;
+    fn host_publish(
+        topic_ptr: *const u8,
+        topic_len: u32,
+        payload_ptr: *const u8,
+        payload_len: u32,
+    ) -> i32;
 }
 
+#[cfg(all(not(target_arch = "wasm32"), feature = "mock-host"))]
+pub mod mock;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "mock-host"))]
+use mock::abi::{
+    host_cap_acquire, host_cap_release, host_config_get, host_exit, host_fs_close, host_fs_delete,
+    host_fs_list, host_fs_open, host_fs_read, host_fs_write, host_http_get_cap, host_input_len,
+    host_input_read, host_log, host_log_level, host_log_v2, host_monotonic_ns, host_notify_cap,
+    host_now_ms, host_panic, host_publish, host_random_bytes, host_secret_get, host_set_output,
+    host_sleep_ms,
+};
+
+pub use caeles_sdk_macros::main;
+use core::time::Duration;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NetworkError {
     BlockedByPermission,
     InvalidRequest,
     HostFailure,
+    /// The capability handle was revoked or rejected by the runtime.
+    InvalidCapability,
+}
+
+/// Typed wrappers around capability handles issued by the runtime.
+///
+/// Handles are only obtainable through [`Network::acquire`] and
+/// [`Notifications::acquire`]; the raw value is private so capsule code cannot
+/// build one from an arbitrary integer.
+pub mod capability {
+    use super::NetworkError;
+    use super::{host_cap_acquire, host_cap_release, host_http_get_cap, host_notify_cap};
+
+    const KIND_NETWORK: i32 = 1;
+    const KIND_NOTIFICATIONS: i32 = 2;
+
+    fn acquire(kind: i32) -> Option<i32> {
+        let handle = unsafe { host_cap_acquire(kind) };
+        (handle > 0).then_some(handle)
+    }
+
+    /// Network access, scoped by the runtime to `permissions.network_hosts`.
+    #[derive(Debug)]
+    pub struct Network {
+        handle: i32,
+    }
+
+    impl Network {
+        /// Returns `None` when `permissions.network` is not granted.
+        pub fn acquire() -> Option<Self> {
+            acquire(KIND_NETWORK).map(|handle| Self { handle })
+        }
+
+        /// Perform a host-mediated HTTP GET request.
+        pub fn get(&self, url: &str) -> Result<(), NetworkError> {
+            let code = unsafe { host_http_get_cap(self.handle, url.as_ptr(), url.len() as u32) };
+            match code {
+                0 => Ok(()),
+                1 => Err(NetworkError::BlockedByPermission),
+                2 => Err(NetworkError::InvalidRequest),
+                4 => Err(NetworkError::InvalidCapability),
+                _ => Err(NetworkError::HostFailure),
+            }
+        }
+
+        /// Revokes the handle; later `acquire` calls in this run return `None`.
+        pub fn release(self) {
+            unsafe {
+                host_cap_release(self.handle);
+            }
+        }
+    }
+
+    /// Permission to send notifications to the host.
+    #[derive(Debug)]
+    pub struct Notifications {
+        handle: i32,
+    }
+
+    impl Notifications {
+        /// Returns `None` when `permissions.notifications` is not granted.
+        pub fn acquire() -> Option<Self> {
+            acquire(KIND_NOTIFICATIONS).map(|handle| Self { handle })
+        }
+
+        /// Returns `false` if the runtime rejected the handle.
+        pub fn send(&self, msg: &str) -> bool {
+            unsafe { host_notify_cap(self.handle, msg.as_ptr(), msg.len() as u32) == 0 }
+        }
+
+        /// Revokes the handle; later `acquire` calls in this run return `None`.
+        pub fn release(self) {
+            unsafe {
+                host_cap_release(self.handle);
+            }
+        }
+    }
+}
+
+/// Support code for the `#[caeles_sdk::main]` macro; not a stable API.
+#[doc(hidden)]
+pub mod rt {
+    use super::{host_exit, host_panic};
+    use std::fmt;
+
+    /// Exit code reported when `main` returns `Err`.
+    pub const EXIT_ERROR: i32 = 1;
+    /// Exit code reported when the run input cannot be deserialized.
+    pub const EXIT_INVALID_INPUT: i32 = 2;
+
+    /// Return types accepted by `#[caeles_sdk::main]`.
+    pub trait Report {
+        fn report(self) -> Result<(), String>;
+    }
+
+    impl Report for () {
+        fn report(self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl<E: fmt::Debug> Report for Result<(), E> {
+        fn report(self) -> Result<(), String> {
+            self.map_err(|err| format!("{err:?}"))
+        }
+    }
+
+    fn exit(code: i32, message: &str) {
+        unsafe { host_exit(code, message.as_ptr(), message.len() as u32) }
+    }
+
+    /// Runs before `main`: installs the panic hook and, with the `log` feature, the logger.
+    pub fn init() {
+        install_panic_hook();
+        #[cfg(feature = "log")]
+        super::logger::init();
+    }
+
+    /// Forwards panics to the host before the capsule traps.
+    fn install_panic_hook() {
+        #[cfg(not(target_arch = "wasm32"))]
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let payload = info.payload();
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("Box<dyn Any>");
+            let report = match info.location() {
+                Some(location) => format!("{message} at {location}"),
+                None => message.to_string(),
+            };
+            unsafe { host_panic(report.as_ptr(), report.len() as u32) }
+            #[cfg(not(target_arch = "wasm32"))]
+            previous(info);
+        }));
+    }
+
+    pub fn run<R: Report>(main: impl FnOnce() -> R) {
+        if let Err(message) = main().report() {
+            exit(EXIT_ERROR, &message);
+        }
+    }
+
+    #[cfg(feature = "input")]
+    pub fn run_with_input<T: serde::de::DeserializeOwned, R: Report>(main: impl FnOnce(T) -> R) {
+        match super::input::<T>() {
+            Ok(input) => run(move || main(input)),
+            Err(err) => exit(EXIT_INVALID_INPUT, &err.to_string()),
+        }
+    }
+}
+
+/// Sets the result of the run, shown by `caeles run` and checked by `caeles test`.
+///
+/// Calling it again replaces the previous value.
+pub fn set_output(value: &str) {
+    unsafe {
+        host_set_output(value.as_ptr(), value.len() as u32);
+    }
+}
+
+/// Raw bytes passed with `caeles run --input`; empty when no input was given.
+pub fn input_bytes() -> Vec<u8> {
+    let len = unsafe { host_input_len() }.max(0) as usize;
+    let mut buf = vec![0u8; len];
+    let read = unsafe { host_input_read(buf.as_mut_ptr(), len as u32) };
+    buf.truncate(read.max(0) as usize);
+    buf
+}
+
+/// Deserializes the JSON passed with `caeles run --input`.
+#[cfg(feature = "input")]
+pub fn input<T: serde::de::DeserializeOwned>() -> Result<T, InputError> {
+    let bytes = input_bytes();
+    if bytes.is_empty() {
+        return Err(InputError::Missing);
+    }
+    serde_json::from_slice(&bytes).map_err(|err| InputError::Invalid(err.to_string()))
+}
+
+/// Why [`input`] could not produce a value.
+#[cfg(feature = "input")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputError {
+    /// The run was started without `--input`.
+    Missing,
+    Invalid(String),
+}
+
+#[cfg(feature = "input")]
+impl std::fmt::Display for InputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputError::Missing => f.write_str("no input provided (use `caeles run --input`)"),
+            InputError::Invalid(err) => write!(f, "invalid input: {err}"),
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch, or `None` when the manifest does not
+/// grant `permissions.wall_clock`.
+pub fn now_ms() -> Option<u64> {
+    u64::try_from(unsafe { host_now_ms() }).ok()
+}
+
+/// Time since the run started; use it to measure durations.
+pub fn monotonic() -> Duration {
+    Duration::from_nanos(unsafe { host_monotonic_ns() }.max(0) as u64)
+}
+
+/// Pauses the capsule. The run fails if the sleep would outlast `--timeout-ms`.
+pub fn sleep(duration: Duration) {
+    let ms = duration.as_millis().min(i32::MAX as u128) as i32;
+    unsafe {
+        host_sleep_ms(ms);
+    }
+}
+
+/// Largest buffer the host fills in one `host_random_bytes` call.
+const RANDOM_CHUNK: usize = 64 * 1024;
+
+/// The host could not fill the buffer passed to [`random_bytes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RandomError;
+
+/// Fills `buf` with random bytes from the host; seeded in deterministic runs.
+pub fn random_bytes(buf: &mut [u8]) -> Result<(), RandomError> {
+    for chunk in buf.chunks_mut(RANDOM_CHUNK) {
+        if unsafe { host_random_bytes(chunk.as_mut_ptr(), chunk.len() as u32) } != 0 {
+            return Err(RandomError);
+        }
+    }
+    Ok(())
+}
+
+/// Value of a key declared in the manifest `config` section, as text; `None`
+/// when the key has no default and no value was given for the run.
+pub fn config(key: &str) -> Option<String> {
+    lookup(key, |key, key_len, buf, buf_len| unsafe {
+        host_config_get(key, key_len, buf, buf_len)
+    })
+}
+
+/// [`config`] parsed as `T`; `None` when unset or not a valid `T`.
+pub fn config_as<T: core::str::FromStr>(key: &str) -> Option<T> {
+    config(key)?.parse().ok()
+}
+
+/// Secret declared in the manifest `secrets` list and saved with `caeles secret set`.
+/// The host redacts its value from logs, notifications and output.
+pub fn secret(name: &str) -> Option<String> {
+    lookup(name, |name, name_len, buf, buf_len| unsafe {
+        host_secret_get(name, name_len, buf, buf_len)
+    })
+}
+
+/// Why [`publish`] did not send a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishError {
+    /// The topic is not covered by `permissions.publish`.
+    NotAllowed,
+    /// Topics are `.`-separated segments of `a-z`, `0-9`, `_` and `-`.
+    InvalidTopic,
+    /// The payload is larger than 64 KiB.
+    TooLarge,
+    HostFailure,
+}
+
+impl std::fmt::Display for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for PublishError {}
+
+/// Sends a message to the host bus. After the run, every `event` capsule
+/// subscribed to `topic` runs once with the message as its input.
+pub fn publish(topic: &str, payload: &str) -> Result<(), PublishError> {
+    let status = unsafe {
+        host_publish(
+            topic.as_ptr(),
+            topic.len() as u32,
+            payload.as_ptr(),
+            payload.len() as u32,
+        )
+    };
+    match status {
+        0 => Ok(()),
+        -1 => Err(PublishError::NotAllowed),
+        -2 => Err(PublishError::InvalidTopic),
+        -3 => Err(PublishError::TooLarge),
+        _ => Err(PublishError::HostFailure),
+    }
+}
+
+/// Calls a `host_*_get` function, growing the buffer when the value does not fit.
+fn lookup(key: &str, get: impl Fn(*const u8, u32, *mut u8, u32) -> i32) -> Option<String> {
+    lookup_raw(key, get).ok()
+}
+
+/// [`lookup`] keeping the negative status returned by the host.
+fn lookup_raw(key: &str, get: impl Fn(*const u8, u32, *mut u8, u32) -> i32) -> Result<String, i32> {
+    let mut buf = vec![0u8; 256];
+    loop {
+        let status = get(
+            key.as_ptr(),
+            key.len() as u32,
+            buf.as_mut_ptr(),
+            buf.len() as u32,
+        );
+        let len = usize::try_from(status).map_err(|_| status)?;
+        if len <= buf.len() {
+            buf.truncate(len);
+            return String::from_utf8(buf).map_err(|_| -2);
+        }
+        buf.resize(len, 0);
+    }
+}
+
+/// Files of the capsule, granted by `permissions.filesystem`.
+///
+/// Paths are relative and `/`-separated. They point into the private
+/// directory of the capsule, except `assets/<dir>/...`, which reads the asset
+/// directories declared in the manifest.
+pub mod fs {
+    use super::{
+        host_fs_close, host_fs_delete, host_fs_list, host_fs_open, host_fs_read, host_fs_write,
+        lookup_raw,
+    };
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum FsError {
+        /// `permissions.filesystem` is not granted.
+        PermissionDenied,
+        /// The path is absolute, uses `..` or leaves the capsule file area.
+        InvalidPath,
+        NotFound,
+        /// The write would take the private directory over `filesystem.quota_bytes`.
+        QuotaExceeded,
+        /// Assets, or a file opened for reading, cannot be written.
+        ReadOnly,
+        BadHandle,
+        TooManyOpenFiles,
+        HostFailure,
+    }
+
+    impl FsError {
+        fn from_code(code: i32) -> Self {
+            match code {
+                -1 => FsError::PermissionDenied,
+                -2 => FsError::InvalidPath,
+                -3 => FsError::NotFound,
+                -4 => FsError::QuotaExceeded,
+                -5 => FsError::ReadOnly,
+                -6 => FsError::BadHandle,
+                -7 => FsError::TooManyOpenFiles,
+                _ => FsError::HostFailure,
+            }
+        }
+    }
+
+    impl std::fmt::Display for FsError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            std::fmt::Debug::fmt(self, f)
+        }
+    }
+
+    impl std::error::Error for FsError {}
+
+    fn status(code: i32) -> Result<usize, FsError> {
+        usize::try_from(code).map_err(|_| FsError::from_code(code))
+    }
+
+    /// Open file; closed when dropped.
+    #[derive(Debug)]
+    pub struct File {
+        fd: i32,
+    }
+
+    impl File {
+        fn open_with(path: &str, mode: i32) -> Result<Self, FsError> {
+            let fd = unsafe { host_fs_open(path.as_ptr(), path.len() as u32, mode) };
+            status(fd).map(|_| Self { fd })
+        }
+
+        /// Opens a file for reading.
+        pub fn open(path: &str) -> Result<Self, FsError> {
+            Self::open_with(path, 0)
+        }
+
+        /// Creates or truncates a file, and its missing parent directories.
+        pub fn create(path: &str) -> Result<Self, FsError> {
+            Self::open_with(path, 1)
+        }
+
+        /// Opens a file for writing at its end, creating it if needed.
+        pub fn append(path: &str) -> Result<Self, FsError> {
+            Self::open_with(path, 2)
+        }
+
+        /// Reads up to `buf.len()` bytes; `Ok(0)` at the end of the file.
+        pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+            status(unsafe { host_fs_read(self.fd, buf.as_mut_ptr(), buf.len() as u32) })
+        }
+
+        pub fn read_to_end(&mut self) -> Result<Vec<u8>, FsError> {
+            let mut data = Vec::new();
+            let mut chunk = vec![0u8; 64 * 1024];
+            loop {
+                match self.read(&mut chunk)? {
+                    0 => return Ok(data),
+                    count => data.extend_from_slice(&chunk[..count]),
+                }
+            }
+        }
+
+        pub fn write_all(&mut self, data: &[u8]) -> Result<(), FsError> {
+            status(unsafe { host_fs_write(self.fd, data.as_ptr(), data.len() as u32) }).map(|_| ())
+        }
+    }
+
+    impl Drop for File {
+        fn drop(&mut self) {
+            unsafe {
+                host_fs_close(self.fd);
+            }
+        }
+    }
+
+    impl std::io::Read for File {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            File::read(self, buf).map_err(std::io::Error::other)
+        }
+    }
+
+    impl std::io::Write for File {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.write_all(buf).map_err(std::io::Error::other)?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    pub fn read(path: &str) -> Result<Vec<u8>, FsError> {
+        File::open(path)?.read_to_end()
+    }
+
+    pub fn read_to_string(path: &str) -> Result<String, FsError> {
+        String::from_utf8(read(path)?).map_err(|_| FsError::HostFailure)
+    }
+
+    /// Replaces the contents of a file.
+    pub fn write(path: &str, data: impl AsRef<[u8]>) -> Result<(), FsError> {
+        File::create(path)?.write_all(data.as_ref())
+    }
+
+    /// Entries of a directory, sorted; subdirectories end with `/`. `""` is the private directory.
+    pub fn list(dir: &str) -> Result<Vec<String>, FsError> {
+        let text = lookup_raw(dir, |path, path_len, buf, buf_len| unsafe {
+            host_fs_list(path, path_len, buf, buf_len)
+        })
+        .map_err(FsError::from_code)?;
+        Ok(text.lines().map(str::to_string).collect())
+    }
+
+    /// Removes a file or an empty directory.
+    pub fn remove(path: &str) -> Result<(), FsError> {
+        status(unsafe { host_fs_delete(path.as_ptr(), path.len() as u32) }).map(|_| ())
+    }
+}
+
+/// `getrandom` 0.2 backend for `wasm32-unknown-unknown`, so crates such as
+/// `rand` and `uuid` work inside capsules.
+#[cfg(all(feature = "getrandom", target_arch = "wasm32"))]
+mod getrandom_backend {
+    use core::num::NonZeroU32;
+
+    fn caeles_getrandom(buf: &mut [u8]) -> Result<(), getrandom::Error> {
+        super::random_bytes(buf).map_err(|_| {
+            NonZeroU32::new(getrandom::Error::CUSTOM_START)
+                .expect("custom error codes are non-zero")
+                .into()
+        })
+    }
+
+    getrandom::register_custom_getrandom!(caeles_getrandom);
+}
+
+/// Guest bindings for component capsules implementing the `caeles:capsule` world.
+///
+/// Implement [`component::Guest`] and register it with
+/// `caeles_sdk::component::export!(MyCapsule);`. The host interfaces are
+/// available under `component::caeles::capsule::{log, notify, http}`.
+#[cfg(feature = "component")]
+pub mod component {
+    wit_bindgen::generate!({
+        path: "../../wit",
+        world: "capsule",
+        pub_export_macro: true,
+        default_bindings_module: "caeles_sdk::component",
+    });
+}
+
+/// Severity of a log record; records below the run's `--log-level` are dropped by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+/// Most verbose level the host keeps in this run.
+pub fn max_level() -> Level {
+    match unsafe { host_log_level() } {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+fn push_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Send a structured log record with a level, target and key-value fields.
+pub fn log_record(level: Level, target: &str, msg: &str, fields: &[(&str, &str)]) {
+    let mut json = String::new();
+    if !fields.is_empty() {
+        json.push('{');
+        for (index, (key, value)) in fields.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            push_json_string(&mut json, key);
+            json.push(':');
+            push_json_string(&mut json, value);
+        }
+        json.push('}');
+    }
+    unsafe {
+        host_log_v2(
+            level as i32,
+            target.as_ptr(),
+            target.len() as u32,
+            msg.as_ptr(),
+            msg.len() as u32,
+            json.as_ptr(),
+            json.len() as u32,
+        );
+    }
+}
+
+/// [`log`](https://docs.rs/log) backend forwarding records to `host_log_v2`.
+///
+/// Key-value pairs (`log::info!(user = "x"; "msg")`) become structured fields.
+#[cfg(feature = "log")]
+pub mod logger {
+    use super::Level;
+
+    struct HostLogger;
+
+    static LOGGER: HostLogger = HostLogger;
+
+    struct Fields(Vec<(String, String)>);
+
+    impl<'kvs> ::log::kv::VisitSource<'kvs> for Fields {
+        fn visit_pair(
+            &mut self,
+            key: ::log::kv::Key<'kvs>,
+            value: ::log::kv::Value<'kvs>,
+        ) -> Result<(), ::log::kv::Error> {
+            self.0.push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+
+    fn level(level: ::log::Level) -> Level {
+        match level {
+            ::log::Level::Error => Level::Error,
+            ::log::Level::Warn => Level::Warn,
+            ::log::Level::Info => Level::Info,
+            ::log::Level::Debug => Level::Debug,
+            ::log::Level::Trace => Level::Trace,
+        }
+    }
+
+    impl ::log::Log for HostLogger {
+        fn enabled(&self, metadata: &::log::Metadata) -> bool {
+            level(metadata.level()) <= super::max_level()
+        }
+
+        fn log(&self, record: &::log::Record) {
+            let mut fields = Fields(Vec::new());
+            let _ = record.key_values().visit(&mut fields);
+            let fields: Vec<(&str, &str)> = fields
+                .0
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect();
+            super::log_record(
+                level(record.level()),
+                record.target(),
+                &record.args().to_string(),
+                &fields,
+            );
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Installs the host logger with the run's level; a no-op if a logger is already set.
+    pub fn init() {
+        if ::log::set_logger(&LOGGER).is_ok() {
+            ::log::set_max_level(match super::max_level() {
+                Level::Error => ::log::LevelFilter::Error,
+                Level::Warn => ::log::LevelFilter::Warn,
+                Level::Info => ::log::LevelFilter::Info,
+                Level::Debug => ::log::LevelFilter::Debug,
+                Level::Trace => ::log::LevelFilter::Trace,
+            });
+        }
+    }
 }
 
 /// Send a log line to the CAELES host runtime.
@@ -20,9 +726,11 @@ pub fn log(msg: &str) {
 }
 
 /// Send a notification to the CAELES host runtime.
+///
+/// Dropped by the runtime when `permissions.notifications` is not granted.
 pub fn notify(msg: &str) {
-    unsafe {
-        host_notify(msg.as_ptr(), msg.len() as u32);
+    if let Some(notifications) = capability::Notifications::acquire() {
+        notifications.send(msg);
     }
 }
 
@@ -30,11 +738,7 @@ pub fn notify(msg: &str) {
 ///
 /// The runtime enforces the `permissions.network` flag from the manifest.
 pub fn http_get(url: &str) -> Result<(), NetworkError> {
-    let code = unsafe { host_http_get(url.as_ptr(), url.len() as u32) };
-    match code {
-        0 => Ok(()),
-        1 => Err(NetworkError::BlockedByPermission),
-        2 => Err(NetworkError::InvalidRequest),
-        _ => Err(NetworkError::HostFailure),
-    }
+    capability::Network::acquire()
+        .ok_or(NetworkError::BlockedByPermission)?
+        .get(url)
 }
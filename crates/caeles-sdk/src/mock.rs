@@ -0,0 +1,725 @@
+//! In-process host for testing capsule logic natively with `cargo test`.
+//!
+//! Enabled by the `mock-host` feature on non-wasm targets. Every host call made
+//! through the SDK is recorded in thread-local state, so each `#[test]` (which
+//! runs on its own thread) starts from a clean host. Capabilities are granted
+//! and HTTP requests succeed unless a test says otherwise:
+//!
+//! ```ignore
+//! use caeles_sdk::mock::{self, MockCapability};
+//!
+//! mock::deny(MockCapability::Network);
+//! my_capsule_logic();
+//! assert_eq!(mock::logs(), ["network blocked"]);
+//! ```
+
+use crate::{Level, NetworkError};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// Capabilities a test can deny with [`deny`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockCapability {
+    Network,
+    Notifications,
+}
+
+impl MockCapability {
+    fn from_code(code: i32) -> Option<Self> {
+        match code {
+            1 => Some(MockCapability::Network),
+            2 => Some(MockCapability::Notifications),
+            _ => None,
+        }
+    }
+
+    fn handle(self) -> i32 {
+        match self {
+            MockCapability::Network => 101,
+            MockCapability::Notifications => 102,
+        }
+    }
+}
+
+/// Structured record sent with [`crate::log_record`] or the `log` backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    /// Fields as the raw JSON object sent to the host; empty when there were none.
+    pub fields: String,
+}
+
+#[derive(Debug)]
+struct MockHost {
+    logs: Vec<String>,
+    records: Vec<LogRecord>,
+    notifications: Vec<String>,
+    http_requests: Vec<String>,
+    http_responses: HashMap<String, Result<(), NetworkError>>,
+    denied: Vec<MockCapability>,
+    released: Vec<MockCapability>,
+    log_level: Level,
+    input: Vec<u8>,
+    output: Option<String>,
+    panic: Option<String>,
+    exit: Option<(i32, String)>,
+    now_ms: Option<u64>,
+    monotonic_ns: u64,
+    sleeps: Vec<u64>,
+    random_state: u64,
+    config: HashMap<String, String>,
+    secrets: HashMap<String, String>,
+    filesystem: bool,
+    files: BTreeMap<String, Vec<u8>>,
+    open_files: HashMap<i32, MockFile>,
+    next_fd: i32,
+    publish_topics: Option<Vec<String>>,
+    published: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+struct MockFile {
+    path: String,
+    position: usize,
+    writable: bool,
+}
+
+impl Default for MockHost {
+    fn default() -> Self {
+        Self {
+            logs: Vec::new(),
+            records: Vec::new(),
+            notifications: Vec::new(),
+            http_requests: Vec::new(),
+            http_responses: HashMap::new(),
+            denied: Vec::new(),
+            released: Vec::new(),
+            log_level: Level::Trace,
+            input: Vec::new(),
+            output: None,
+            panic: None,
+            exit: None,
+            now_ms: Some(DEFAULT_NOW_MS),
+            monotonic_ns: 0,
+            sleeps: Vec::new(),
+            random_state: DEFAULT_RANDOM_SEED,
+            config: HashMap::new(),
+            secrets: HashMap::new(),
+            filesystem: true,
+            files: BTreeMap::new(),
+            open_files: HashMap::new(),
+            next_fd: 1,
+            publish_topics: None,
+            published: Vec::new(),
+        }
+    }
+}
+
+/// Wall-clock time of a fresh mock host (2024-01-01T00:00:00Z).
+pub const DEFAULT_NOW_MS: u64 = 1_704_067_200_000;
+
+const DEFAULT_RANDOM_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+thread_local! {
+    static HOST: RefCell<MockHost> = RefCell::new(MockHost::default());
+}
+
+fn with_host<T>(f: impl FnOnce(&mut MockHost) -> T) -> T {
+    HOST.with(|host| f(&mut host.borrow_mut()))
+}
+
+/// Clears everything recorded and scripted on the current thread.
+pub fn reset() {
+    with_host(|host| *host = MockHost::default());
+}
+
+/// Makes `acquire` for `capability` fail, as if the manifest did not grant it.
+pub fn deny(capability: MockCapability) {
+    with_host(|host| host.denied.push(capability));
+}
+
+/// Scripts the result of HTTP GETs to `url`; unscripted URLs succeed.
+pub fn respond(url: &str, result: Result<(), NetworkError>) {
+    with_host(|host| {
+        host.http_responses.insert(url.to_string(), result);
+    });
+}
+
+/// Sets the value returned by [`crate::max_level`].
+pub fn set_log_level(level: Level) {
+    with_host(|host| host.log_level = level);
+}
+
+/// Sets the bytes returned by [`crate::input_bytes`], like `caeles run --input`.
+pub fn set_input(input: &str) {
+    with_host(|host| host.input = input.as_bytes().to_vec());
+}
+
+/// Sets the time returned by [`crate::now_ms`]; `None` acts as if
+/// `permissions.wall_clock` was not granted.
+pub fn set_now_ms(now_ms: Option<u64>) {
+    with_host(|host| host.now_ms = now_ms);
+}
+
+/// Seeds the bytes returned by [`crate::random_bytes`]; the default seed is fixed too.
+pub fn set_random_seed(seed: u64) {
+    with_host(|host| host.random_state = seed | 1);
+}
+
+/// Sets the value returned by [`crate::config`], like `caeles run --config key=value`.
+pub fn set_config(key: &str, value: &str) {
+    with_host(|host| {
+        host.config.insert(key.to_string(), value.to_string());
+    });
+}
+
+/// Sets the value returned by [`crate::secret`].
+pub fn set_secret(name: &str, value: &str) {
+    with_host(|host| {
+        host.secrets.insert(name.to_string(), value.to_string());
+    });
+}
+
+/// Stores a file for [`crate::fs`]; use `assets/<dir>/...` paths for read-only assets.
+pub fn set_file(path: &str, contents: impl AsRef<[u8]>) {
+    with_host(|host| {
+        host.files.insert(
+            path.trim_matches('/').to_string(),
+            contents.as_ref().to_vec(),
+        );
+    });
+}
+
+/// Contents of a file written through [`crate::fs`] or [`set_file`].
+pub fn file(path: &str) -> Option<Vec<u8>> {
+    with_host(|host| host.files.get(path.trim_matches('/')).cloned())
+}
+
+/// Makes every [`crate::fs`] call fail, as if `permissions.filesystem` was not granted.
+pub fn deny_filesystem() {
+    with_host(|host| host.filesystem = false);
+}
+
+/// Limits [`crate::publish`] to `topics`, like `permissions.publish`; every
+/// topic is allowed until this is called.
+pub fn allow_publish(topics: &[&str]) {
+    with_host(|host| host.publish_topics = Some(topics.iter().map(|t| t.to_string()).collect()));
+}
+
+/// `(topic, payload)` of every message accepted by [`crate::publish`].
+pub fn published() -> Vec<(String, String)> {
+    with_host(|host| host.published.clone())
+}
+
+/// Milliseconds of every [`crate::sleep`]; sleeping only advances the mock clocks.
+pub fn sleeps() -> Vec<u64> {
+    with_host(|host| host.sleeps.clone())
+}
+
+/// Messages logged so far, plain and structured, in order.
+pub fn logs() -> Vec<String> {
+    with_host(|host| host.logs.clone())
+}
+
+/// Structured records logged so far.
+pub fn log_records() -> Vec<LogRecord> {
+    with_host(|host| host.records.clone())
+}
+
+/// Notifications accepted by the host.
+pub fn notifications() -> Vec<String> {
+    with_host(|host| host.notifications.clone())
+}
+
+/// URLs of every HTTP GET that reached the host, including failed ones.
+pub fn http_requests() -> Vec<String> {
+    with_host(|host| host.http_requests.clone())
+}
+
+/// Value set with [`crate::set_output`].
+pub fn output() -> Option<String> {
+    with_host(|host| host.output.clone())
+}
+
+/// Message reported by the panic hook installed by `#[caeles_sdk::main]`.
+pub fn panic_message() -> Option<String> {
+    with_host(|host| host.panic.clone())
+}
+
+/// Non-zero exit code and message reported by `#[caeles_sdk::main]`.
+pub fn exit() -> Option<(i32, String)> {
+    with_host(|host| host.exit.clone())
+}
+
+/// Native stand-ins for the `caeles_v2` imports, with the same signatures.
+#[allow(clippy::missing_safety_doc)]
+pub(crate) mod abi {
+    use super::{with_host, LogRecord, MockCapability};
+    use crate::{Level, NetworkError};
+    use std::collections::BTreeSet;
+
+    unsafe fn string(ptr: *const u8, len: u32) -> String {
+        let bytes = std::slice::from_raw_parts(ptr, len as usize);
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    fn capability(handle: i32, expected: MockCapability) -> bool {
+        handle == expected.handle() && with_host(|host| !host.released.contains(&expected))
+    }
+
+    pub unsafe fn host_log(ptr: *const u8, len: u32) {
+        let msg = string(ptr, len);
+        with_host(|host| host.logs.push(msg));
+    }
+
+    pub unsafe fn host_log_v2(
+        level: i32,
+        target_ptr: *const u8,
+        target_len: u32,
+        msg_ptr: *const u8,
+        msg_len: u32,
+        fields_ptr: *const u8,
+        fields_len: u32,
+    ) {
+        let level = match level {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            _ => Level::Trace,
+        };
+        let record = LogRecord {
+            level,
+            target: string(target_ptr, target_len),
+            message: string(msg_ptr, msg_len),
+            fields: string(fields_ptr, fields_len),
+        };
+        with_host(|host| {
+            if level <= host.log_level {
+                host.logs.push(record.message.clone());
+                host.records.push(record);
+            }
+        });
+    }
+
+    pub unsafe fn host_log_level() -> i32 {
+        with_host(|host| host.log_level as i32)
+    }
+
+    pub unsafe fn host_cap_acquire(kind: i32) -> i32 {
+        let Some(capability) = MockCapability::from_code(kind) else {
+            return -2;
+        };
+        with_host(|host| {
+            if host.denied.contains(&capability) || host.released.contains(&capability) {
+                -1
+            } else {
+                capability.handle()
+            }
+        })
+    }
+
+    pub unsafe fn host_cap_release(handle: i32) -> i32 {
+        let Some(capability) = [MockCapability::Network, MockCapability::Notifications]
+            .into_iter()
+            .find(|c| capability(handle, *c))
+        else {
+            return -1;
+        };
+        with_host(|host| host.released.push(capability));
+        0
+    }
+
+    pub unsafe fn host_notify_cap(handle: i32, ptr: *const u8, len: u32) -> i32 {
+        if !capability(handle, MockCapability::Notifications) {
+            return 4;
+        }
+        let msg = string(ptr, len);
+        with_host(|host| host.notifications.push(msg));
+        0
+    }
+
+    pub unsafe fn host_http_get_cap(handle: i32, ptr: *const u8, len: u32) -> i32 {
+        if !capability(handle, MockCapability::Network) {
+            return 4;
+        }
+        let url = string(ptr, len);
+        with_host(|host| {
+            let result = host.http_responses.get(&url).copied().unwrap_or(Ok(()));
+            host.http_requests.push(url);
+            match result {
+                Ok(()) => 0,
+                Err(NetworkError::BlockedByPermission) => 1,
+                Err(NetworkError::InvalidRequest) => 2,
+                Err(NetworkError::HostFailure) => 3,
+                Err(NetworkError::InvalidCapability) => 4,
+            }
+        })
+    }
+
+    pub unsafe fn host_panic(ptr: *const u8, len: u32) {
+        let msg = string(ptr, len);
+        with_host(|host| host.panic = Some(msg));
+    }
+
+    pub unsafe fn host_exit(code: i32, ptr: *const u8, len: u32) {
+        let msg = string(ptr, len);
+        if code != 0 {
+            with_host(|host| host.exit = Some((code, msg)));
+        }
+    }
+
+    pub unsafe fn host_set_output(ptr: *const u8, len: u32) -> i32 {
+        let output = string(ptr, len);
+        with_host(|host| host.output = Some(output));
+        0
+    }
+
+    pub unsafe fn host_input_len() -> i32 {
+        with_host(|host| host.input.len() as i32)
+    }
+
+    pub unsafe fn host_input_read(ptr: *mut u8, len: u32) -> i32 {
+        with_host(|host| {
+            let count = host.input.len().min(len as usize);
+            std::ptr::copy_nonoverlapping(host.input.as_ptr(), ptr, count);
+            count as i32
+        })
+    }
+
+    pub unsafe fn host_now_ms() -> i64 {
+        with_host(|host| host.now_ms.map_or(-1, |ms| ms as i64))
+    }
+
+    pub unsafe fn host_monotonic_ns() -> i64 {
+        with_host(|host| host.monotonic_ns as i64)
+    }
+
+    pub unsafe fn host_sleep_ms(ms: i32) -> i32 {
+        let Ok(ms) = u64::try_from(ms) else {
+            return 2;
+        };
+        with_host(|host| {
+            host.sleeps.push(ms);
+            host.monotonic_ns += ms * 1_000_000;
+            if let Some(now) = host.now_ms.as_mut() {
+                *now += ms;
+            }
+        });
+        0
+    }
+
+    unsafe fn lookup(value: Option<String>, buf_ptr: *mut u8, buf_len: u32) -> i32 {
+        let Some(value) = value else {
+            return -1;
+        };
+        if value.len() <= buf_len as usize {
+            std::ptr::copy_nonoverlapping(value.as_ptr(), buf_ptr, value.len());
+        }
+        value.len() as i32
+    }
+
+    pub unsafe fn host_config_get(
+        key_ptr: *const u8,
+        key_len: u32,
+        buf_ptr: *mut u8,
+        buf_len: u32,
+    ) -> i32 {
+        let key = string(key_ptr, key_len);
+        lookup(
+            with_host(|host| host.config.get(&key).cloned()),
+            buf_ptr,
+            buf_len,
+        )
+    }
+
+    pub unsafe fn host_secret_get(
+        name_ptr: *const u8,
+        name_len: u32,
+        buf_ptr: *mut u8,
+        buf_len: u32,
+    ) -> i32 {
+        let name = string(name_ptr, name_len);
+        lookup(
+            with_host(|host| host.secrets.get(&name).cloned()),
+            buf_ptr,
+            buf_len,
+        )
+    }
+
+    // Same codes as the runtime: -1 denied, -2 invalid path, -3 not found,
+    // -5 read-only, -6 bad handle. Quotas are not simulated.
+    fn fs_path(path: &str) -> Result<String, i32> {
+        let parts: Vec<&str> = path
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect();
+        if parts.is_empty() || parts.contains(&"..") || path.contains('\\') {
+            return Err(-2);
+        }
+        Ok(parts.join("/"))
+    }
+
+    fn fs_enabled() -> Result<(), i32> {
+        if with_host(|host| host.filesystem) {
+            Ok(())
+        } else {
+            Err(-1)
+        }
+    }
+
+    pub unsafe fn host_fs_open(path_ptr: *const u8, path_len: u32, mode: i32) -> i32 {
+        let opened = fs_enabled().and_then(|()| fs_path(&string(path_ptr, path_len)));
+        let path = match opened {
+            Ok(path) => path,
+            Err(code) => return code,
+        };
+        with_host(|host| {
+            let writable = mode != 0;
+            if writable && path.starts_with("assets/") {
+                return -5;
+            }
+            match mode {
+                0 if !host.files.contains_key(&path) => return -3,
+                1 => {
+                    host.files.insert(path.clone(), Vec::new());
+                }
+                2 => {
+                    host.files.entry(path.clone()).or_default();
+                }
+                0 => {}
+                _ => return -2,
+            }
+            let position = if mode == 2 {
+                host.files[&path].len()
+            } else {
+                0
+            };
+            let fd = host.next_fd;
+            host.next_fd += 1;
+            host.open_files.insert(
+                fd,
+                super::MockFile {
+                    path,
+                    position,
+                    writable,
+                },
+            );
+            fd
+        })
+    }
+
+    pub unsafe fn host_fs_read(fd: i32, ptr: *mut u8, len: u32) -> i32 {
+        with_host(|host| {
+            let Some(file) = host.open_files.get_mut(&fd) else {
+                return -6;
+            };
+            let data = host.files.get(&file.path).map_or(&[][..], Vec::as_slice);
+            let start = file.position.min(data.len());
+            let count = (data.len() - start).min(len as usize);
+            std::ptr::copy_nonoverlapping(data[start..].as_ptr(), ptr, count);
+            file.position += count;
+            count as i32
+        })
+    }
+
+    pub unsafe fn host_fs_write(fd: i32, ptr: *const u8, len: u32) -> i32 {
+        let bytes = std::slice::from_raw_parts(ptr, len as usize);
+        with_host(|host| {
+            let Some(file) = host.open_files.get_mut(&fd) else {
+                return -6;
+            };
+            if !file.writable {
+                return -5;
+            }
+            let data = host.files.entry(file.path.clone()).or_default();
+            let end = file.position + bytes.len();
+            if data.len() < end {
+                data.resize(end, 0);
+            }
+            data[file.position..end].copy_from_slice(bytes);
+            file.position = end;
+            len as i32
+        })
+    }
+
+    pub unsafe fn host_fs_close(fd: i32) -> i32 {
+        with_host(|host| host.open_files.remove(&fd).map_or(-6, |_| 0))
+    }
+
+    pub unsafe fn host_fs_list(
+        path_ptr: *const u8,
+        path_len: u32,
+        buf_ptr: *mut u8,
+        buf_len: u32,
+    ) -> i32 {
+        if let Err(code) = fs_enabled() {
+            return code;
+        }
+        let dir = string(path_ptr, path_len);
+        let dir = dir.trim_matches('/');
+        let prefix = if dir.is_empty() {
+            String::new()
+        } else {
+            format!("{dir}/")
+        };
+        let names: BTreeSet<String> = with_host(|host| {
+            host.files
+                .keys()
+                .filter_map(|path| path.strip_prefix(&prefix))
+                .map(|rest| match rest.split_once('/') {
+                    Some((dir, _)) => format!("{dir}/"),
+                    None => rest.to_string(),
+                })
+                .collect()
+        });
+        if names.is_empty() && !dir.is_empty() {
+            return -3;
+        }
+        let names: Vec<String> = names.into_iter().collect();
+        lookup(Some(names.join("\n")), buf_ptr, buf_len)
+    }
+
+    pub unsafe fn host_fs_delete(path_ptr: *const u8, path_len: u32) -> i32 {
+        let path = match fs_enabled().and_then(|()| fs_path(&string(path_ptr, path_len))) {
+            Ok(path) => path,
+            Err(code) => return code,
+        };
+        if path.starts_with("assets/") {
+            return -5;
+        }
+        with_host(|host| host.files.remove(&path).map_or(-3, |_| 0))
+    }
+
+    pub unsafe fn host_publish(
+        topic_ptr: *const u8,
+        topic_len: u32,
+        payload_ptr: *const u8,
+        payload_len: u32,
+    ) -> i32 {
+        let topic = string(topic_ptr, topic_len);
+        let payload = string(payload_ptr, payload_len);
+        let valid = topic.split('.').all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-'))
+        });
+        if !valid {
+            return -2;
+        }
+        if payload.len() > 64 * 1024 {
+            return -3;
+        }
+        with_host(|host| {
+            let allowed = host.publish_topics.as_ref().is_none_or(|patterns| {
+                patterns
+                    .iter()
+                    .any(|pattern| match pattern.strip_suffix('*') {
+                        Some(prefix) => topic.starts_with(prefix) && topic.len() > prefix.len(),
+                        None => *pattern == topic,
+                    })
+            });
+            if !allowed {
+                return -1;
+            }
+            host.published.push((topic, payload));
+            0
+        })
+    }
+
+    pub unsafe fn host_random_bytes(ptr: *mut u8, len: u32) -> i32 {
+        let buf = std::slice::from_raw_parts_mut(ptr, len as usize);
+        with_host(|host| {
+            for byte in buf {
+                // xorshift64: reproducible, not meant to be secure.
+                let mut x = host.random_state;
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                host.random_state = x;
+                *byte = x as u8;
+            }
+        });
+        0
+    }
+}
+
+#[cfg(all(test, feature = "mock-host"))]
+mod tests {
+    use super::{self as mock, LogRecord, MockCapability};
+    use crate::fs::FsError;
+    use crate::{Level, NetworkError, PublishError};
+
+    #[test]
+    fn logs_and_records_are_kept_in_order() {
+        crate::log("plain");
+        crate::log_record(Level::Warn, "capsule", "structured", &[("key", "value")]);
+
+        assert_eq!(mock::logs(), ["plain", "structured"]);
+        assert_eq!(
+            mock::log_records(),
+            [LogRecord {
+                level: Level::Warn,
+                target: "capsule".to_string(),
+                message: "structured".to_string(),
+                fields: r#"{"key":"value"}"#.to_string(),
+            }]
+        );
+        mock::reset();
+        assert!(mock::logs().is_empty());
+    }
+
+    #[test]
+    fn files_config_and_secrets_round_trip() {
+        crate::fs::write("notes/today.txt", "hello").expect("file should be written");
+        assert_eq!(crate::fs::read("notes/today.txt"), Ok(b"hello".to_vec()));
+        assert_eq!(mock::file("notes/today.txt"), Some(b"hello".to_vec()));
+
+        mock::set_file("assets/static/readme.txt", "asset");
+        assert_eq!(
+            crate::fs::read_to_string("assets/static/readme.txt"),
+            Ok("asset".to_string())
+        );
+        assert_eq!(crate::fs::read("missing.txt"), Err(FsError::NotFound));
+
+        mock::set_config("retries", "3");
+        mock::set_secret("api_token", "s3cr3t");
+        assert_eq!(crate::config_as::<u32>("retries"), Some(3));
+        assert_eq!(crate::secret("api_token").as_deref(), Some("s3cr3t"));
+        assert_eq!(crate::config("missing"), None);
+    }
+
+    #[test]
+    fn denied_capabilities_block_calls_before_the_host() {
+        crate::notify("granted");
+        assert_eq!(crate::http_get("https://example.com"), Ok(()));
+        assert_eq!(mock::notifications(), ["granted"]);
+
+        mock::deny(MockCapability::Network);
+        mock::deny(MockCapability::Notifications);
+        mock::deny_filesystem();
+        mock::allow_publish(&["allowed"]);
+
+        crate::notify("dropped");
+        assert_eq!(
+            crate::http_get("https://example.com/blocked"),
+            Err(NetworkError::BlockedByPermission)
+        );
+        assert_eq!(
+            crate::fs::write("a.txt", "x"),
+            Err(FsError::PermissionDenied)
+        );
+        assert_eq!(crate::publish("other", "{}"), Err(PublishError::NotAllowed));
+        assert_eq!(crate::publish("allowed", "{}"), Ok(()));
+
+        assert_eq!(mock::notifications(), ["granted"]);
+        assert_eq!(mock::http_requests(), ["https://example.com"]);
+        assert_eq!(
+            mock::published(),
+            [("allowed".to_string(), "{}".to_string())]
+        );
+    }
+}